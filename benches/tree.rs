@@ -0,0 +1,397 @@
+//! Criterion benchmarks for parsing and traversal, run with `cargo bench`. 
+//! Synthetic inputs are generated once per run, from fixed seeds, and written
+//! to a temp directory. The `memory` group times nothing; it prints the 
+//! memory the FSDir and FsArena back ends hold for the same tree, and at 
+//! peak while parsing it, and how many allocations some queries over the 
+//! 500k-node tree make. Apart from checking that the two back ends agree on
+//! the puzzle's answers, nothing is asserted.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::fs;
+use std::hint::black_box;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use no_space_left_on_device::analysis::*;
+use no_space_left_on_device::arena::*;
+use no_space_left_on_device::fsobject::*;
+use no_space_left_on_device::parser::*;
+use no_space_left_on_device::streaming::*;
+use no_space_left_on_device::testing::*;
+
+/// The number of bytes currently allocated, kept by Counting.
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+/// The most bytes allocated at once since held() last reset it, kept by 
+/// Counting.
+/// 
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+/// The number of allocations made so far, kept by Counting. Reallocations
+/// count too.
+/// 
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+/// The system allocator, counting the bytes it hands out in ALLOCATED, the
+/// most of them at once in PEAK, and the allocations in ALLOCATIONS.
+/// 
+struct Counting;
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let now = ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        PEAK.fetch_max(now + layout.size(), Ordering::Relaxed);
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+        System.dealloc(ptr, layout)
+    }
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) 
+        -> *mut u8 
+    {
+        let now = ALLOCATED.fetch_add(new_size, Ordering::Relaxed);
+        PEAK.fetch_max(now + new_size, Ordering::Relaxed);
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+/// The synthetic inputs, made once per run.
+struct Inputs {
+    /// A session of about a million lines.
+    session : PathBuf,
+    /// The number of lines in `session`.
+    lines   : usize,
+    /// A tree of 500k nodes.
+    tree    : FSDir,
+    /// A tree of 100k directories, see wide_tree().
+    wide    : FSDir,
+    /// A session for a tree of a million nodes, to compare the back ends.
+    million : PathBuf,
+}
+impl Inputs {
+    fn new() -> Self {
+        let dir = std::env::temp_dir().join("no-space-left-on-device-bench");
+        fs::create_dir_all(&dir).expect("can't create the bench directory");
+
+        let big     = FSDir::random(1, TreeSpec { max_depth : 8, 
+                                                  children  : 5..=30,
+                                                  nodes     : 800_000,
+                                                  ..Default::default() });
+        let session = dir.join("session-1m.txt");
+        fs::write(&session, big.to_session())
+            .expect("can't write the session");
+        let lines   = fs::read_to_string(&session).unwrap().lines().count();
+
+        let tree    = FSDir::random(2, TreeSpec { max_depth : 8,
+                                                  children  : 5..=30,
+                                                  nodes     : 500_000,
+                                                  ..Default::default() });
+
+        let million = FSDir::random(3, TreeSpec { max_depth : 8,
+                                                  children  : 5..=30,
+                                                  nodes     : 1_000_000,
+                                                  ..Default::default() });
+        let path    = dir.join("session-1m-nodes.txt");
+        fs::write(&path, million.to_session())
+            .expect("can't write the session");
+        Inputs { session, lines, tree, wide: wide_tree(), million: path }
+    }
+}
+
+thread_local! {
+    static INPUTS: Inputs = Inputs::new();
+}
+
+fn parsing(c: &mut Criterion) {
+    INPUTS.with(|inputs| {
+        let mut group = c.benchmark_group("parsing");
+        group.sample_size(10);
+        group.bench_function(format!("build_fs ({} lines)", inputs.lines), 
+                             |b| {
+            b.iter(|| build_fs_from_path(&inputs.session).unwrap())
+        });
+        group.bench_function("build_fs with progress callback", |b| {
+            b.iter(|| {
+                let calls = Rc::new(Cell::new(0));
+                let count = calls.clone();
+                let opts  = ParserOptions { 
+                    progress : Some(Box::new(move |_| {
+                        count.set(count.get() + 1)
+                    })),
+                    ..Default::default()
+                };
+                build_fs_from_path_with(&inputs.session, opts).unwrap();
+                calls.get()
+            })
+        });
+        group.finish();
+    });
+}
+
+fn traversal(c: &mut Criterion) {
+    INPUTS.with(|inputs| {
+        let tree      = &inputs.tree;
+        let mut group = c.benchmark_group("traversal (500k nodes)");
+        group.bench_function("find_dirs_recurs_by", |b| {
+            b.iter(|| tree.find_dirs_recurs_by(|d| d.size() <= 100_000).len())
+        });
+        // The first match is about half way through the walk, which the lazy
+        // search stops at and the collecting one goes past.
+        let wide = &inputs.wide;
+        let big  = |d: &FSDir| d.size() >= 1_000_000;
+        group.bench_function("first match: lazy (100k dirs)", |b| {
+            b.iter(|| wide.find_first_dir_by(big))
+        });
+        group.bench_function("first match: collect (100k dirs)", |b| {
+            b.iter(|| wide.find_dirs_recurs_by(big).into_iter().next())
+        });
+        group.bench_function("name()", |b| {
+            b.iter(|| name_bytes(tree, |e| e.name().len()))
+        });
+        group.bench_function("name_ref()", |b| {
+            b.iter(|| name_bytes(tree, |e| e.name_ref().len()))
+        });
+        group.finish();
+    });
+}
+
+fn removal(c: &mut Criterion) {
+    INPUTS.with(|inputs| {
+        let tree      = &inputs.tree;
+        let mut group = c.benchmark_group("bulk remove");
+        group.sample_size(10);
+        group.bench_function("eager sizes (500k)", |b| {
+            b.iter_batched_ref(|| tree.deep_clone(), 
+                               |root| remove_small_dirs(root),
+                               BatchSize::LargeInput)
+        });
+        group.bench_function("lazy sizes (500k)", |b| {
+            b.iter_batched_ref(|| {
+                                   let copy = tree.deep_clone();
+                                   copy.set_lazy_sizes(true);
+                                   copy
+                               }, 
+                               |root| remove_small_dirs(root),
+                               BatchSize::LargeInput)
+        });
+        group.bench_function("1000 deep, eager sizes", |b| {
+            b.iter_batched_ref(|| deep_tree(false), 
+                               |root| remove_small_dirs(root),
+                               BatchSize::LargeInput)
+        });
+        group.bench_function("1000 deep, lazy sizes", |b| {
+            b.iter_batched_ref(|| deep_tree(true), 
+                               |root| remove_small_dirs(root),
+                               BatchSize::LargeInput)
+        });
+        group.finish();
+    });
+}
+
+fn solvers(c: &mut Criterion) {
+    INPUTS.with(|inputs| {
+        let mut group = c.benchmark_group("part 1 and 2");
+        group.sample_size(10);
+        group.bench_function("tree (500k nodes)", |b| {
+            b.iter(|| answers(&inputs.tree))
+        });
+        group.bench_function("parse + tree", |b| {
+            b.iter(|| answers(&build_fs_from_path(&inputs.session).unwrap()))
+        });
+        group.bench_function("streaming", |b| {
+            b.iter(|| {
+                solve_streaming(reader(&inputs.session), 100_000, 
+                                70_000_000, 30_000_000).unwrap()
+            })
+        });
+        group.finish();
+    });
+}
+
+fn back_ends(c: &mut Criterion) {
+    INPUTS.with(|inputs| {
+        let input     = &inputs.million;
+        let rc_tree   = build_fs_from_path(input).unwrap();
+        let arena     = build_fs_arena(reader(input)).unwrap();
+        assert_eq!(answers(&rc_tree), arena_answers(&arena), 
+                   "the tree back ends disagree");
+        let mut group = c.benchmark_group("back ends (1M nodes)");
+        group.sample_size(10);
+        group.bench_function("build_fs, Rc tree", |b| {
+            b.iter(|| build_fs_from_path(input).unwrap())
+        });
+        group.bench_function("build_fs_arena", |b| {
+            b.iter(|| build_fs_arena(reader(input)).unwrap())
+        });
+        group.bench_function("find_dirs_recurs_by, Rc tree", |b| {
+            b.iter(|| {
+                rc_tree.find_dirs_recurs_by(|d| d.size() <= 100_000).len()
+            })
+        });
+        group.bench_function("find_dirs_recurs_by, arena", |b| {
+            b.iter(|| {
+                arena.find_dirs_recurs_by(arena.root(), 
+                                          |d| arena.size(d) <= 100_000)
+                     .len()
+            })
+        });
+        group.bench_function("part 1 and 2, Rc tree", |b| {
+            b.iter(|| answers(&rc_tree))
+        });
+        group.bench_function("part 1 and 2, arena", |b| {
+            b.iter(|| arena_answers(&arena))
+        });
+        group.finish();
+    });
+}
+
+fn memory(_: &mut Criterion) {
+    INPUTS.with(|inputs| {
+        let input = &inputs.million;
+        let (rc_tree, rc_bytes, rc_peak) = held(|| {
+            build_fs_from_path(input).unwrap()
+        });
+        drop(rc_tree);
+        let (arena, arena_bytes, arena_peak) = held(|| {
+            build_fs_arena(reader(input)).unwrap()
+        });
+        drop(arena);
+        println!("memory held for 1M nodes: Rc tree {:.1} MB, arena {:.1} MB",
+                 rc_bytes as f64 / 1e6, arena_bytes as f64 / 1e6);
+        println!("peak memory while parsing them: Rc tree {:.1} MB, \
+                  arena {:.1} MB",
+                 rc_peak as f64 / 1e6, arena_peak as f64 / 1e6);
+        let tree = &inputs.tree;
+        let dirs = tree.find_dirs_recurs_by(|_| true);
+        println!("allocations over 500k nodes: walk {}, name() {}, \
+                  name_ref() {}, contains() and get_dir() {}",
+                 allocations(|| tree.walk().count()),
+                 allocations(|| name_bytes(tree, |e| e.name().len())),
+                 allocations(|| name_bytes(tree, |e| e.name_ref().len())),
+                 allocations(|| {
+                     dirs.iter().filter(|d| d.contains("a") || 
+                                            d.get_dir("b").is_some())
+                                .count()
+                 }));
+    });
+}
+
+criterion_group!(benches, parsing, traversal, removal, solvers, back_ends, 
+                 memory);
+criterion_main!(benches);
+
+/// Returns what `f` returns, the number of bytes it left allocated, and the
+/// most bytes it had allocated at once along the way.
+/// 
+fn held<T, F: FnOnce() -> T>(f: F) -> (T, usize, usize) {
+    let before = ALLOCATED.load(Ordering::Relaxed);
+    PEAK.store(before, Ordering::Relaxed);
+    let value  = f();
+    (value, 
+     ALLOCATED.load(Ordering::Relaxed).saturating_sub(before),
+     PEAK.load(Ordering::Relaxed).saturating_sub(before))
+}
+
+/// Returns the number of allocations `f` makes.
+fn allocations<T, F: FnOnce() -> T>(f: F) -> usize {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    black_box(f());
+    ALLOCATIONS.load(Ordering::Relaxed) - before
+}
+
+/// Adds up `len` over the children of every directory below `root`, one
+/// directory at a time, so that the traversal itself allocates little.
+/// 
+fn name_bytes<F: Fn(&FSEntry) -> usize>(root: &FSDir, len: F) -> usize {
+    root.iter_dirs_recurs()
+        .map(|d| d.children().iter().map(&len).sum::<usize>())
+        .sum()
+}
+
+/// The answers to both parts of the puzzle for the tree at `root`.
+fn answers(root: &FSDir) -> (u64, u64) {
+    (sum_dirs_at_most(root, 100_000),
+     smallest_dir_to_free(root, 70_000_000, 30_000_000).map_or(0, |d| d.size()))
+}
+
+/// The answers to both parts of the puzzle for `arena`, worked out as 
+/// answers() does.
+/// 
+fn arena_answers(arena: &FsArena) -> (u64, u64) {
+    let root   = arena.root();
+    let sizes  = arena.find_dirs_recurs_by(root, |_| true)
+                      .into_iter()
+                      .map(|d| arena.size(d))
+                      .collect::<Vec<_>>();
+    let part_1 = sizes.iter().filter(|&&s| s <= 100_000).sum();
+    let taken  = arena.size(root);
+    let need   = if taken > 70_000_000 {
+        30_000_000 + (taken - 70_000_000)
+    } else {
+        30_000_000_u64.saturating_sub(70_000_000 - taken)
+    };
+    let part_2 = match need {
+        0 => 0,
+        _ => sizes.into_iter().filter(|&s| s >= need).min().unwrap_or(0),
+    };
+    (part_1, part_2)
+}
+
+/// Removes every directory below `root` of at most 100,000 bytes, one at a
+/// time, then returns the size left, which brings lazy sizes up to date.
+/// 
+fn remove_small_dirs(root: &FSDir) -> u64 {
+    for dir in root.find_dirs_recurs_by(|d| d.size() <= 100_000) {
+        if let Some(parent) = dir.parent() {
+            parent.remove(&dir.name_ref());
+        }
+    }
+    root.size()
+}
+
+/// Returns a chain of 1000 directories with 20,000 small directories at the
+/// bottom, each holding one file, with lazy sizes if `lazy` is set.
+/// 
+fn deep_tree(lazy: bool) -> FSDir {
+    let root = FSDir::new("/".into());
+    let leaf = root.make_dirs(&vec!["d"; 1000].join("/"));
+    for i in 0..20_000 {
+        leaf.add_dir(FSDir::new(format!("s{}", i)))
+            .add_file(FSFile::new("f".into(), 1));
+    }
+    leaf.add_file(FSFile::new("big".into(), 1_000_000));
+    root.set_lazy_sizes(lazy);
+    root
+}
+
+/// Returns a tree of 100 directories, each holding 999 directories that hold
+/// one small file, so 100,000 directories in all. The one big file is in 
+/// "/d50/s500", and so only "/d50" and "/d50/s500" are 1 MB or over.
+/// 
+fn wide_tree() -> FSDir {
+    let root = FSDir::new("/".into());
+    for i in 0..100 {
+        let dir = root.add_dir(FSDir::new(format!("d{}", i)));
+        for j in 0..999 {
+            let size = if (i, j) == (50, 500) { 1_000_000 } else { 1 };
+            dir.add_dir(FSDir::new(format!("s{}", j)))
+               .add_file(FSFile::new("f".into(), size));
+        }
+    }
+    root
+}
+
+/// Open `path` for the streaming solver.
+fn reader(path: &Path) -> BufReader<fs::File> {
+    BufReader::new(fs::File::open(path).unwrap())
+}
@@ -0,0 +1,114 @@
+//! This module implements FSDir::fold(), which reduces the entries below a
+//! directory to a single value, and a few summaries of a tree built on it,
+//! like the average file size and a histogram of file sizes.
+
+use crate::fsobject::*;
+
+impl FSDir {
+    /// Folds `f` over every entry below this directory, files and directories
+    /// alike, in the order walk() visits them, starting from `init`. `f` gets
+    /// the value so far, the entry's full path, and the entry, and returns
+    /// the new value. This directory itself isn't visited.
+    /// 
+    pub fn fold<A, F>(&self, init: A, mut f: F) -> A
+    where
+        F: FnMut(A, &str, &FSEntry) -> A
+    {
+        self.walk().fold(init, |acc, (path, entry)| f(acc, &path, &entry))
+    }
+    /// Returns the depth of the deepest entry below this directory, where
+    /// this directory is at depth 0, so it's 0 for an empty directory.
+    /// 
+    pub fn max_depth(&self) -> usize {
+        self.stats().max_depth
+    }
+    /// Returns the total size of the files below this directory, counted
+    /// from the files themselves. It's the same as size(), and doesn't
+    /// include anything a DiskModel would add.
+    /// 
+    pub fn total_file_bytes(&self) -> u64 {
+        self.fold(0, |total, _, entry| match entry {
+            FSEntry::File(file) => total + file.size(),
+            _                   => total,
+        })
+    }
+    /// Returns the average size of the files below this directory, or None
+    /// if there are none.
+    /// 
+    pub fn average_file_size(&self) -> Option<f64> {
+        let (files, bytes) = self.fold((0usize, 0u64), |(n, bytes), _, e| {
+            match e {
+                FSEntry::File(file) => (n + 1, bytes + file.size()),
+                _                   => (n, bytes),
+            }
+        });
+        (files > 0).then(|| bytes as f64 / files as f64)
+    }
+    /// Counts the files below this directory by size, with `bounds` as the
+    /// boundaries between buckets. The result has one more count than there
+    /// are bounds: the first counts the files smaller than `bounds[0]`, the
+    /// one after counts those from `bounds[0]` up to but not including
+    /// `bounds[1]`, and so on, with the last counting those of `bounds[n-1]`
+    /// or more. So a file whose size equals a bound is counted in the bucket
+    /// above it. Every count is 0 if there are no files.
+    /// 
+    /// # Panics
+    /// If `bounds` isn't in strictly ascending order.
+    /// 
+    pub fn size_histogram(&self, bounds: &[u64]) -> Vec<usize> {
+        assert!(bounds.windows(2).all(|w| w[0] < w[1]),
+                "histogram bounds must be strictly ascending: {:?}", bounds);
+        self.fold(vec![0; bounds.len() + 1], |mut counts, _, entry| {
+            if let FSEntry::File(file) = entry {
+                counts[bounds.partition_point(|&b| b <= file.size())] += 1;
+            }
+            counts
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::*;
+
+    fn sample() -> FSDir {
+        build_fs_from_str(include_str!("../data/sample.txt")).unwrap()
+    }
+
+    #[test]
+    fn the_sample_aggregates() {
+        let root  = sample();
+        let paths = root.fold(vec![], |mut paths, path, _| {
+            paths.push(path.to_string());
+            paths
+        });
+        assert_eq!(paths.len(), 13);
+        assert_eq!(paths[..3], ["/a", "/a/e", "/a/e/i"]);
+        assert_eq!(root.max_depth(), 3);
+        assert_eq!(root.total_file_bytes(), 48381165);
+        assert_eq!(root.average_file_size(), Some(4838116.5));
+        // Files of exactly 584 and 8033020 bytes count in the bucket above.
+        assert_eq!(root.size_histogram(&[584, 10_000, 1_000_000, 8033020]),
+                   [0, 2, 2, 3, 3]);
+        assert_eq!(root.size_histogram(&[]), [10]);
+        let d = root.get_dir("d").unwrap();
+        assert_eq!((d.max_depth(), d.total_file_bytes()), (1, 24933642));
+        assert_eq!(d.average_file_size(), Some(24933642.0 / 4.0));
+    }
+
+    #[test]
+    fn an_empty_tree_aggregates_to_nothing() {
+        let root = FSDir::new("/".into());
+        assert_eq!(root.fold(0, |n, _, _| n + 1), 0);
+        assert_eq!((root.max_depth(), root.total_file_bytes()), (0, 0));
+        assert_eq!(root.average_file_size(), None);
+        assert_eq!(root.size_histogram(&[10, 100]), [0, 0, 0]);
+    }
+
+    #[test]
+    #[should_panic(expected = "strictly ascending")]
+    fn histogram_bounds_must_ascend() {
+        sample().size_histogram(&[10, 10]);
+    }
+}
@@ -0,0 +1,584 @@
+//! This module implements the puzzle's questions, and others like them, as
+//! functions over an already built file system tree.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use crate::fsobject::*;
+
+/// Find all the directories below `root` with a size less than or equal to
+/// `threshold` and return the sum of their sizes. As the puzzle allows, a file
+/// is counted once for every matching directory it's in.
+/// 
+pub fn sum_dirs_at_most(root: &FSDir, threshold: u64) -> u64 {
+    let dirs = root.find_dirs_recurs_by(|d| d.size() <= threshold);
+    dirs.iter().map(|d| d.size()).sum()
+}
+
+/// Returns how many bytes must be freed on a device of `device` bytes, 
+/// holding the tree at `root`, to fit an update of `needed` bytes. If the 
+/// tree is already bigger than the device, the overage has to be freed too.
+/// 
+pub fn space_to_free(root: &FSDir, device: u64, needed: u64) -> u64 {
+    let taken = root.size();
+    if taken > device {
+        needed.saturating_add(taken - device)
+    } else {
+        needed.saturating_sub(device - taken)
+    }
+}
+
+/// Find the smallest directory below `root` that would free up enough space
+/// on a device of `device` bytes to fit an update of `needed` bytes. Returns
+/// None if no directory is large enough, and also if there's already enough
+/// free space, in which case nothing needs deleting. Use space_to_free() to 
+/// tell the two apart.
+/// 
+pub fn smallest_dir_to_free(root   : &FSDir,
+                            device : u64,
+                            needed : u64) -> Option<FSDir>
+{
+    let need = space_to_free(root, device, needed);
+    if need == 0 {
+        return None;
+    }
+    root.iter_dirs_recurs()
+        .filter(|d| d.size() >= need)
+        .min_by_key(|d| d.size())
+}
+
+/// What a device would look like after deleting a directory, as worked out by
+/// after_delete().
+/// 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeletionOutcome {
+    /// The bytes deleting the directory would free.
+    pub freed       : u64,
+    /// The bytes that would still be used.
+    pub used        : u64,
+    /// The bytes that would be free, or 0 if the tree would still be bigger
+    /// than the device.
+    pub free        : u64,
+    /// The size of the device.
+    pub device_size : u64,
+}
+impl DeletionOutcome {
+    /// Returns true if an update of `update_size` bytes would fit.
+    pub fn fits(&self, update_size: u64) -> bool {
+        self.free >= update_size
+    }
+}
+
+/// Returned when a deletion can't be simulated.
+/// 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeleteError {
+    /// Nothing exists at the given path.
+    NotFound(String),
+    /// The entry at the given path is a file or a symbolic link.
+    NotADirectory(String),
+    /// The directory at the given path isn't part of the tree.
+    NotInTree(String),
+}
+impl Display for DeleteError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DeleteError::NotFound(path) => {
+                write!(f, "no directory at '{}'", path)
+            },
+            DeleteError::NotADirectory(path) => {
+                write!(f, "'{}' isn't a directory", path)
+            },
+            DeleteError::NotInTree(path) => {
+                write!(f, "'{}' isn't in this tree", path)
+            },
+        }
+    }
+}
+impl Error for DeleteError {}
+
+/// Works out what a device of `device_size` bytes, holding the tree at 
+/// `root`, would look like if `victim` were deleted. The tree isn't changed.
+/// Fails if `victim` isn't in the same tree as `root`, at `root` or below it.
+/// 
+pub fn after_delete(root        : &FSDir, 
+                    victim      : &FSDir, 
+                    device_size : u64) -> Result<DeletionOutcome, DeleteError>
+{
+    // Paths are unique within a tree, so once the trees are known to be the
+    // same, the paths tell whether `victim` is in `root`'s subtree.
+    let in_tree = victim.root().ptr_eq(&root.root())
+                  && is_ancestor_path(&root.path(), &victim.path());
+    if !in_tree {
+        return Err(DeleteError::NotInTree(victim.path()));
+    }
+    let freed = victim.size();
+    let used  = root.size() - freed;
+    Ok(DeletionOutcome { freed,
+                         used,
+                         free: device_size.saturating_sub(used),
+                         device_size })
+}
+
+/// Like after_delete(), with the directory to delete given by its path from
+/// `root`, as FSDir::find_by_path() resolves it.
+/// 
+pub fn simulate_delete_path(root        : &FSDir, 
+                            path        : &str, 
+                            device_size : u64) 
+    -> Result<DeletionOutcome, DeleteError>
+{
+    match root.find_by_path(path) {
+        Some(FSEntry::Dir(dir)) => after_delete(root, &dir, device_size),
+        Some(_)                 => Err(DeleteError::NotADirectory(path.into())),
+        None                    => Err(DeleteError::NotFound(path.into())),
+    }
+}
+
+/// How many steps deletion_plan() searches for before settling for the best
+/// plan found so far.
+const PLAN_SEARCH_STEPS: usize = 1_000_000;
+
+/// Returns true if the directory at path `a` is `b` or one of its ancestors.
+/// 
+fn is_ancestor_path(a: &str, b: &str) -> bool {
+    b.starts_with(a) && (a.ends_with('/') || b[a.len()..].is_empty()
+                                          || b[a.len()..].starts_with('/'))
+}
+
+/// Plans which directories below `root` to delete to free at least `need` 
+/// bytes, deleting as few bytes as possible. Only directories `candidates`
+/// accepts are considered, and no directory is picked along with one of its
+/// ancestors, since deleting the ancestor deletes it too. The root itself is
+/// never picked.
+/// 
+/// The plan is found by a depth-first search over the candidates, largest 
+/// first, that skips branches that can't beat the best plan so far. The 
+/// result is the same from run to run. Returns an empty plan if `need` is 0
+/// or the candidates can't free enough.
+/// 
+/// The search stops after 1,000,000 steps, which only very many candidates 
+/// take. The best plan found by then is returned, so it may delete more 
+/// bytes than needed, or be empty if no plan was found in time.
+/// 
+pub fn deletion_plan(root       : &FSDir, 
+                     need       : u64,
+                     candidates : &dyn Fn(&FSDir) -> bool) -> Vec<FSDir>
+{
+    if need == 0 {
+        return vec![];
+    }
+    let mut cands = root.iter_dirs_recurs()
+                        .filter(|d| d.size() > 0 && candidates(d))
+                        .map(|d| (d.path(), d))
+                        .collect::<Vec<_>>();
+    cands.sort_by(|a, b| {
+        b.1.size().cmp(&a.1.size()).then_with(|| a.0.cmp(&b.0))
+    });
+    let     sizes  = cands.iter().map(|(_, d)| d.size()).collect::<Vec<_>>();
+    let mut suffix = vec![0_u64; sizes.len() + 1];
+    for i in (0..sizes.len()).rev() {
+        suffix[i] = suffix[i + 1].saturating_add(sizes[i]);
+    }
+
+    // Start from the smallest single directory that's big enough, if any.
+    let mut best: Option<(u64, Vec<usize>)> = sizes.iter()
+        .rposition(|&s| s >= need)
+        .map(|i| (sizes[i], vec![i]));
+
+    // Each step is (next candidate, total so far, length of the chosen list
+    // to go back to, candidate to add to it).
+    let mut chosen = Vec::<usize>::new();
+    let mut stack  = vec![(0, 0_u64, 0, None)];
+    let mut steps  = 0;
+    while let Some((i, total, len, add)) = stack.pop() {
+        steps += 1;
+        if steps > PLAN_SEARCH_STEPS {
+            break;
+        }
+        chosen.truncate(len);
+        chosen.extend(add);
+        if best.as_ref().is_some_and(|(b, _)| total >= *b) {
+            continue;
+        }
+        if total >= need {
+            best = Some((total, chosen.clone()));
+            continue;
+        }
+        if i == sizes.len() || total.saturating_add(suffix[i]) < need {
+            continue;
+        }
+        stack.push((i + 1, total, chosen.len(), None));
+        let overlaps = chosen.iter().any(|&c| {
+            is_ancestor_path(&cands[c].0, &cands[i].0) 
+            || is_ancestor_path(&cands[i].0, &cands[c].0)
+        });
+        if !overlaps {
+            stack.push((i + 1, total + sizes[i], chosen.len(), Some(i)));
+        }
+    }
+    let mut plan = best.map_or(vec![], |(_, chosen)| chosen);
+    plan.sort_unstable();
+    plan.into_iter().map(|i| cands[i].1.clone()).collect()
+}
+
+/// The key size_by_extension() files the sizes of files without an extension
+/// under.
+pub const NO_EXTENSION: &str = "";
+
+/// The total size and number of the files with some extension.
+/// 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ExtStats {
+    /// The total size of the files.
+    pub bytes : u64,
+    /// The number of files.
+    pub files : usize,
+}
+
+/// Totals the sizes of the files below `root` by extension, as 
+/// FSFile::extension() finds it. Files without an extension are totaled 
+/// under NO_EXTENSION. Extensions are case-sensitive; see
+/// size_by_extension_with() to fold them.
+/// 
+pub fn size_by_extension(root: &FSDir) -> BTreeMap<String, ExtStats> {
+    size_by_extension_with(root, false)
+}
+
+/// Like size_by_extension(), but if `fold_case` is true, extensions that only
+/// differ in case, like "LOG" and "log", are totaled together, in lowercase.
+/// 
+pub fn size_by_extension_with(root      : &FSDir, 
+                              fold_case : bool) -> BTreeMap<String, ExtStats> 
+{
+    let mut stats = BTreeMap::<String, ExtStats>::new();
+    for (_, entry) in root.walk() {
+        if let FSEntry::File(file) = entry {
+            let mut ext = file.extension()
+                              .unwrap_or_else(|| NO_EXTENSION.into());
+            if fold_case {
+                ext = ext.to_lowercase();
+            }
+            let entry    = stats.entry(ext).or_default();
+            entry.bytes += file.size();
+            entry.files += 1;
+        }
+    }
+    stats
+}
+
+/// A set of files below a directory that share a name and a size, and so are
+/// likely copies of each other.
+/// 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateGroup {
+    /// The name the files share.
+    pub name  : String,
+    /// The size of each copy.
+    pub size  : u64,
+    /// The paths of the copies, in order.
+    pub paths : Vec<String>,
+}
+impl DuplicateGroup {
+    /// Returns the bytes that would be freed by keeping only one copy.
+    pub fn wasted(&self) -> u64 {
+        self.size * (self.paths.len() as u64 - 1)
+    }
+}
+
+/// Finds the files below `root` that share both their name and their size 
+/// with another file, grouped by the two, with the groups that waste the 
+/// most space first. Files are only compared by name and size, not content.
+/// 
+pub fn find_duplicates(root: &FSDir) -> Vec<DuplicateGroup> {
+    find_duplicates_with(root, false)
+}
+
+/// Like find_duplicates(), but if `skip_empty` is true, empty files are left
+/// out.
+/// 
+pub fn find_duplicates_with(root       : &FSDir, 
+                            skip_empty : bool) -> Vec<DuplicateGroup> 
+{
+    let mut groups = BTreeMap::<(String, u64), Vec<String>>::new();
+    for (path, entry) in root.walk() {
+        if let FSEntry::File(file) = entry {
+            if skip_empty && file.size() == 0 {
+                continue;
+            }
+            groups.entry((file.name(), file.size())).or_default().push(path);
+        }
+    }
+    let mut groups = groups.into_iter()
+                           .filter(|(_, paths)| paths.len() > 1)
+                           .map(|((name, size), paths)| {
+                               DuplicateGroup { name, size, paths }
+                           })
+                           .collect::<Vec<_>>();
+    groups.sort_by(|a, b| {
+        b.wasted().cmp(&a.wasted())
+                  .then_with(|| a.name.cmp(&b.name))
+                  .then_with(|| a.size.cmp(&b.size))
+    });
+    groups
+}
+
+/// Finds the files below `root` last modified before `older_than`, in seconds
+/// since the Unix epoch, that are at least `min_size` bytes: the biggest and
+/// oldest candidates for cleaning up. They're ordered largest first, then 
+/// oldest first, then by path. Files without a modification time are left
+/// out.
+/// 
+pub fn stale_candidates(root       : &FSDir, 
+                        older_than : u64, 
+                        min_size   : u64) -> Vec<FSFile> 
+{
+    let mut stale = root.walk()
+                        .filter_map(|(path, entry)| match entry {
+                            FSEntry::File(file) => {
+                                let mtime = file.mtime()?;
+                                (mtime < older_than && file.size() >= min_size)
+                                    .then_some((path, mtime, file))
+                            },
+                            _ => None,
+                        })
+                        .collect::<Vec<_>>();
+    stale.sort_by(|(pa, ma, a), (pb, mb, b)| {
+        b.size().cmp(&a.size()).then_with(|| ma.cmp(mb))
+                               .then_with(|| pa.cmp(pb))
+    });
+    stale.into_iter().map(|(_, _, file)| file).collect()
+}
+
+/// A directory with a quota, as quota_report() lists it.
+/// 
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuotaStatus {
+    /// The directory's path.
+    pub path    : String,
+    /// The directory's quota, in bytes.
+    pub quota   : u64,
+    /// The total size of everything below the directory.
+    pub usage   : u64,
+    /// The usage as a percentage of the quota, which can go over 100. 
+    /// Infinite for a quota of 0 over a directory that isn't empty.
+    pub percent : f64,
+}
+
+/// Lists `root` and every directory below it that has a quota, with how 
+/// much of its quota it's using, in path order. Directories over their 
+/// quotas are listed too, whether or not quotas are enforced.
+/// 
+pub fn quota_report(root: &FSDir) -> Vec<QuotaStatus> {
+    let status = |path: String, dir: &FSDir| {
+        dir.quota().map(|quota| {
+            let usage   = dir.size();
+            let percent = if usage == 0 {
+                0.0
+            } else {
+                usage as f64 / quota as f64 * 100.0
+            };
+            QuotaStatus { path, quota, usage, percent }
+        })
+    };
+    let mut report = status(root.path(), root).into_iter()
+                                              .collect::<Vec<_>>();
+    report.extend(root.walk().filter_map(|(path, entry)| match entry {
+        FSEntry::Dir(dir) => status(path, &dir),
+        _                 => None,
+    }));
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quota_report_lists_directories_with_quotas() {
+        let root = FSDir::new("/".into());
+        root.make_dirs("a/b").add_file(FSFile::new("f".into(), 50));
+        root.make_dirs("c");
+        root.get_dir("a").unwrap().set_quota(200);
+        root.get_dir("c").unwrap().set_quota(10);
+        let report = quota_report(&root);
+        assert_eq!(report, vec![
+            QuotaStatus { path: "/a".into(), quota: 200, usage: 50, 
+                          percent: 25.0 },
+            QuotaStatus { path: "/c".into(), quota: 10, usage: 0, 
+                          percent: 0.0 },
+        ]);
+    }
+
+    /// Builds /big (600 bytes, holding /big/small, 150) and /other (200).
+    fn device_tree() -> FSDir {
+        let root = FSDir::new("/".into());
+        root.add_file_at_path("big/f", 450);
+        root.add_file_at_path("big/small/f", 150);
+        root.add_file_at_path("other/f", 200);
+        root
+    }
+
+    #[test]
+    fn a_tree_bigger_than_the_device_frees_the_overage_too() {
+        let root = device_tree();
+        // 800 bytes on a 500 byte device: 300 over, plus 100 for the update.
+        assert_eq!(space_to_free(&root, 500, 100), 400);
+        let dir = smallest_dir_to_free(&root, 500, 100).unwrap();
+        assert_eq!(dir.path(), "/big");
+        assert!(smallest_dir_to_free(&root, 0, 100).is_none());
+        assert_eq!(space_to_free(&root, 0, u64::MAX), u64::MAX);
+    }
+
+    #[test]
+    fn a_tree_with_enough_free_space_needs_nothing_freed() {
+        let root = device_tree();
+        assert_eq!(space_to_free(&root, 1000, 200), 0);
+        assert!(smallest_dir_to_free(&root, 1000, 200).is_none());
+        // One byte more and the smallest directory that frees it is picked.
+        assert_eq!(space_to_free(&root, 1000, 201), 1);
+        let dir = smallest_dir_to_free(&root, 1000, 201).unwrap();
+        assert_eq!(dir.path(), "/big/small");
+    }
+
+    #[test]
+    fn deletions_are_simulated_without_changing_the_tree() {
+        let root    = device_tree();
+        let outcome = simulate_delete_path(&root, "/big", 1000).unwrap();
+        assert_eq!(outcome, DeletionOutcome { freed       : 600,
+                                              used        : 200,
+                                              free        : 800,
+                                              device_size : 1000 });
+        assert!(outcome.fits(800) && !outcome.fits(801));
+        let small = root.get_dir("big").unwrap().get_dir("small").unwrap();
+        assert_eq!(after_delete(&root, &small, 500).unwrap().free, 0);
+        assert_eq!(after_delete(&root, &root, 500).unwrap().free, 500);
+        assert_eq!(root.size(), 800);
+    }
+
+    #[test]
+    fn deletions_outside_the_tree_are_errors() {
+        let root  = device_tree();
+        let other = device_tree();
+        let big   = other.get_dir("big").unwrap();
+        assert_eq!(after_delete(&root, &big, 1000),
+                   Err(DeleteError::NotInTree("/big".into())));
+        // Nor is the root of a tree in one of its subtrees.
+        let big = root.get_dir("big").unwrap();
+        assert_eq!(after_delete(&big, &root, 1000),
+                   Err(DeleteError::NotInTree("/".into())));
+        assert_eq!(simulate_delete_path(&root, "/nope", 1000),
+                   Err(DeleteError::NotFound("/nope".into())));
+        assert_eq!(simulate_delete_path(&root, "/big/f", 1000),
+                   Err(DeleteError::NotADirectory("/big/f".into())));
+        assert_eq!(simulate_delete_path(&root, "/big/f", 1000)
+                       .unwrap_err().to_string(),
+                   "'/big/f' isn't a directory");
+    }
+
+    /// Returns the paths and sizes of the directories in `plan`.
+    fn plan_paths(plan: &[FSDir]) -> Vec<(String, u64)> {
+        plan.iter().map(|d| (d.path(), d.size())).collect()
+    }
+
+    #[test]
+    fn the_best_plan_can_skip_the_largest_candidate() {
+        let root = FSDir::new("/".into());
+        root.add_file_at_path("big/f", 150);
+        root.add_file_at_path("x/f", 60);
+        root.add_file_at_path("y/f", 45);
+        root.add_file_at_path("z/f", 30);
+        let all  = |_: &FSDir| true;
+        // /big alone would do, but /x and /y free 105 bytes, not 150.
+        assert_eq!(plan_paths(&deletion_plan(&root, 100, &all)),
+                   [("/x".into(), 60), ("/y".into(), 45)]);
+        // Exactly enough beats more than enough.
+        assert_eq!(plan_paths(&deletion_plan(&root, 90, &all)),
+                   [("/x".into(), 60), ("/z".into(), 30)]);
+        assert_eq!(plan_paths(&deletion_plan(&root, 150, &all)),
+                   [("/big".into(), 150)]);
+        // Only the candidates are picked from.
+        let not_x = |d: &FSDir| d.name() != "x";
+        assert_eq!(plan_paths(&deletion_plan(&root, 100, &not_x)),
+                   [("/big".into(), 150)]);
+        assert!(deletion_plan(&root, 0, &all).is_empty());
+        assert!(deletion_plan(&root, 286, &all).is_empty());
+    }
+
+    #[test]
+    fn plans_never_pick_a_directory_with_its_ancestor() {
+        // /a is 100 bytes, 70 of them in /a/b.
+        let root = FSDir::new("/".into());
+        root.add_file_at_path("a/f", 30);
+        root.add_file_at_path("a/b/f", 70);
+        root.add_file_at_path("c/f", 60);
+        let all  = |_: &FSDir| true;
+        // /a and /a/b would seem to free 170 bytes, but /a and /c, all that
+        // can be freed, are only 160.
+        assert!(deletion_plan(&root, 165, &all).is_empty());
+        assert_eq!(plan_paths(&deletion_plan(&root, 160, &all)),
+                   [("/a".into(), 100), ("/c".into(), 60)]);
+        assert_eq!(plan_paths(&deletion_plan(&root, 125, &all)),
+                   [("/a/b".into(), 70), ("/c".into(), 60)]);
+    }
+
+    #[test]
+    fn sizes_are_totaled_by_extension() {
+        let root = FSDir::new("/".into());
+        root.add_file_at_path("a.txt", 10);
+        root.add_file_at_path("d/b.txt", 20);
+        root.add_file_at_path("d/c.TXT", 5);
+        root.add_file_at_path("d/e/archive.tar.gz", 100);
+        root.add_file_at_path("Makefile", 7);
+        root.add_file_at_path(".bashrc", 3);
+        root.add_file_at_path("d/trailing.", 1);
+        let stats = |bytes, files| ExtStats { bytes, files };
+        assert_eq!(size_by_extension(&root), BTreeMap::from([
+            (NO_EXTENSION.to_string(), stats(11, 3)),
+            ("TXT".to_string(),        stats(5, 1)),
+            ("gz".to_string(),         stats(100, 1)),
+            ("txt".to_string(),        stats(30, 2)),
+        ]));
+        assert_eq!(size_by_extension_with(&root, true), BTreeMap::from([
+            (NO_EXTENSION.to_string(), stats(11, 3)),
+            ("gz".to_string(),         stats(100, 1)),
+            ("txt".to_string(),        stats(35, 3)),
+        ]));
+        // Only what's below the directory given is counted.
+        let d = root.get_dir("d").unwrap();
+        assert_eq!(size_by_extension(&d), BTreeMap::from([
+            (NO_EXTENSION.to_string(), stats(1, 1)),
+            ("TXT".to_string(),        stats(5, 1)),
+            ("gz".to_string(),         stats(100, 1)),
+            ("txt".to_string(),        stats(20, 1)),
+        ]));
+    }
+
+    #[test]
+    fn duplicates_share_a_name_and_a_size() {
+        let root = FSDir::new("/".into());
+        root.add_file_at_path("a/photo.jpg", 500);
+        root.add_file_at_path("b/photo.jpg", 500);
+        root.add_file_at_path("b/c/photo.jpg", 500);
+        root.add_file_at_path("a/notes.txt", 40);
+        root.add_file_at_path("b/notes.txt", 41);
+        root.add_file_at_path("a/empty", 0);
+        root.add_file_at_path("b/empty", 0);
+        root.add_file_at_path("b/c/other.jpg", 500);
+        let photos = DuplicateGroup { 
+            name  : "photo.jpg".into(), 
+            size  : 500,
+            paths : vec!["/a/photo.jpg".into(), "/b/c/photo.jpg".into(), 
+                         "/b/photo.jpg".into()],
+        };
+        let empty  = DuplicateGroup {
+            name  : "empty".into(),
+            size  : 0,
+            paths : vec!["/a/empty".into(), "/b/empty".into()],
+        };
+        // The two notes.txt differ in size, so they aren't copies.
+        assert_eq!(find_duplicates(&root), [photos.clone(), empty]);
+        assert_eq!(photos.wasted(), 1000);
+        assert_eq!(find_duplicates_with(&root, true), [photos]);
+    }
+}
@@ -0,0 +1,418 @@
+//! This module implements FsArena, an alternative to the FSDir tree for big
+//! inputs. All the nodes live in one Vec and are addressed by NodeId, parents
+//! and children are stored as ids, and names are interned, so there's no
+//! reference counting, no RefCell, and no per-node name allocation. Queries
+//! hand out borrowed names instead of cloned Strings.
+//! 
+//! An arena only grows: entries can be added, and files resized, but nothing
+//! is removed. FsArena::from_fsdir() and to_fsdir() convert to and from the
+//! FSDir tree for everything else.
+
+use std::collections::{BTreeMap, HashMap};
+use std::rc::Rc;
+use crate::fsobject::*;
+
+/// The handle of a file or directory in an FsArena. It's only meaningful for
+/// the arena it came from.
+/// 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeId(u32);
+impl NodeId {
+    /// Returns the position of the node in its arena, counting from 0 for
+    /// the root. Nodes are numbered in the order they were added.
+    /// 
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// A file or directory in an FsArena. Files have no children. A directory's
+/// children are keyed by their interned names, as in FSDir, so that they're
+/// in name order however they're added.
+/// 
+#[derive(Debug, Clone)]
+struct Node {
+    name     : u32,
+    parent   : Option<NodeId>,
+    size     : u64,
+    is_dir   : bool,
+    children : BTreeMap<Rc<str>, NodeId>,
+}
+
+/// A file system tree stored in an arena. The root is a directory named "/".
+/// Each directory's children are kept in name order, as in FSDir.
+/// 
+#[derive(Debug, Clone)]
+pub struct FsArena {
+    nodes    : Vec<Node>,
+    names    : Vec<Rc<str>>,
+    name_ids : HashMap<Rc<str>, u32>,
+}
+impl Default for FsArena {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl FsArena {
+    /// Creates an arena holding only the root directory.
+    pub fn new() -> Self {
+        let mut arena = Self { nodes    : vec![],
+                               names    : vec![],
+                               name_ids : HashMap::new() };
+        let name = arena.intern("/");
+        arena.nodes.push(Node { name,
+                                parent   : None,
+                                size     : 0,
+                                is_dir   : true,
+                                children : BTreeMap::new() });
+        arena
+    }
+    /// Returns the root directory.
+    pub fn root(&self) -> NodeId {
+        NodeId(0)
+    }
+    /// Returns the number of files and directories, including the root.
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+    /// Returns the number of distinct names.
+    pub fn name_count(&self) -> usize {
+        self.names.len()
+    }
+    /// Returns the name of the node.
+    pub fn name(&self, id: NodeId) -> &str {
+        &self.names[self.node(id).name as usize]
+    }
+    /// Returns the size of the node. A directory's size is the total size of
+    /// the files below it.
+    /// 
+    pub fn size(&self, id: NodeId) -> u64 {
+        self.node(id).size
+    }
+    /// Returns the directory holding the node, or None for the root.
+    pub fn parent(&self, id: NodeId) -> Option<NodeId> {
+        self.node(id).parent
+    }
+    /// Returns true if the node is a directory.
+    pub fn is_dir(&self, id: NodeId) -> bool {
+        self.node(id).is_dir
+    }
+    /// Returns the children of the node in name order. Files have none.
+    pub fn children(&self, id: NodeId) 
+        -> impl DoubleEndedIterator<Item = NodeId> + '_ 
+    {
+        self.node(id).children.values().copied()
+    }
+    /// Returns the child of `dir` named `name`, if there is one.
+    pub fn get(&self, dir: NodeId, name: &str) -> Option<NodeId> {
+        self.node(dir).children.get(name).copied()
+    }
+    /// Returns the full path of the node, like FSObject::path().
+    pub fn path(&self, id: NodeId) -> String {
+        let mut names = vec![self.name(id)];
+        let mut cur   = self.parent(id);
+        while let Some(dir) = cur {
+            names.push(self.name(dir));
+            cur = self.parent(dir);
+        }
+        join_names(names.iter().rev())
+    }
+    /// Returns the subdirectory of `dir` named `name`, creating it first if
+    /// it doesn't exist.
+    /// 
+    /// # Panics
+    /// If `dir` has a file with the given name, or `dir` is a file.
+    /// 
+    pub fn dir_entry(&mut self, dir: NodeId, name: &str) -> NodeId {
+        match self.get(dir, name) {
+            Some(id) if self.is_dir(id) => id,
+            Some(_) => panic!("'{}' is a file, not a directory", name),
+            None    => self.push(dir, name, true, 0),
+        }
+    }
+    /// Adds a file of the given size to `dir` and returns its handle. If
+    /// `dir` already has a file by that name, its size is replaced.
+    /// 
+    /// # Panics
+    /// If `dir` has a directory with the given name, or `dir` is a file, or
+    /// the size of `dir` or one of its parents would overflow.
+    /// try_add_file() returns an error for overflow instead.
+    /// 
+    pub fn add_file(&mut self, dir: NodeId, name: &str, size: u64) -> NodeId {
+        self.try_add_file(dir, name, size).unwrap_or_else(|e| panic!("{}", e))
+    }
+    /// Like add_file(), but fails, leaving the arena as it was, if the size
+    /// of `dir` or one of its parents would overflow.
+    /// 
+    pub fn try_add_file(&mut self, dir: NodeId, name: &str, size: u64)
+        -> Result<NodeId, SizeOverflow>
+    {
+        let existing = self.get(dir, name);
+        let replaced = match existing {
+            Some(id) if self.is_dir(id) => {
+                panic!("'{}' is a directory, not a file", name);
+            },
+            Some(id) => self.size(id),
+            None     => 0,
+        };
+        let mut cur = Some(dir);
+        while let Some(id) = cur {
+            if (self.size(id) - replaced).checked_add(size).is_none() {
+                return Err(SizeOverflow { path: self.path(id), size });
+            }
+            cur = self.parent(id);
+        }
+        let file = match existing {
+            Some(id) => { self.nodes[id.index()].size = size; id },
+            None     => self.push(dir, name, false, size),
+        };
+        let mut cur = Some(dir);
+        while let Some(id) = cur {
+            let node  = &mut self.nodes[id.index()];
+            node.size = node.size - replaced + size;
+            cur       = node.parent;
+        }
+        Ok(file)
+    }
+    /// Returns the subdirectories of `dir` that satisfy `pred`, in name
+    /// order.
+    /// 
+    pub fn find_dirs_by<F>(&self, dir: NodeId, mut pred: F) -> Vec<NodeId>
+    where
+        F: FnMut(NodeId) -> bool
+    {
+        self.children(dir).filter(|&id| self.is_dir(id) && pred(id))
+                          .collect()
+    }
+    /// Returns all the directories below `dir` that satisfy `pred`,
+    /// depth-first in name order.
+    /// 
+    pub fn find_dirs_recurs_by<F>(&self, dir: NodeId, mut pred: F)
+        -> Vec<NodeId>
+    where
+        F: FnMut(NodeId) -> bool
+    {
+        self.walk(dir).filter(|&id| self.is_dir(id) && pred(id)).collect()
+    }
+    /// Returns all the files below `dir` that satisfy `pred`, depth-first in
+    /// name order.
+    /// 
+    pub fn find_files_recurs_by<F>(&self, dir: NodeId, mut pred: F)
+        -> Vec<NodeId>
+    where
+        F: FnMut(NodeId) -> bool
+    {
+        self.walk(dir).filter(|&id| !self.is_dir(id) && pred(id)).collect()
+    }
+    /// Returns a depth-first, pre-order iterator over the descendants of
+    /// `dir`, in name order, like FSDir::walk() without the paths.
+    /// 
+    pub fn walk(&self, dir: NodeId) -> ArenaWalk<'_> {
+        let stack = self.children(dir).rev().collect();
+        ArenaWalk { arena: self, stack }
+    }
+    /// Copies the tree rooted at `root` into a new arena. The arena's root
+    /// is always named "/". Symbolic links are left out, as an arena only
+    /// holds files and directories.
+    /// 
+    pub fn from_fsdir(root: &FSDir) -> Self {
+        let mut arena = Self::new();
+        let mut stack = vec![(root.clone(), arena.root())];
+        while let Some((dir, id)) = stack.pop() {
+            for child in dir.children() {
+                match child {
+                    FSEntry::Dir(sub) => {
+                        let sub_id = arena.dir_entry(id, &sub.name_ref());
+                        stack.push((sub, sub_id));
+                    },
+                    FSEntry::File(file) => {
+                        arena.add_file(id, &file.name_ref(), file.size());
+                    },
+                    FSEntry::Link(_) => {},
+                }
+            }
+        }
+        arena
+    }
+    /// Copies the tree rooted at the directory `dir` into a new FSDir tree.
+    /// 
+    pub fn to_fsdir(&self, dir: NodeId) -> FSDir {
+        let root      = FSDir::new(self.name(dir).into());
+        let mut stack = vec![(dir, root.clone())];
+        while let Some((id, dir)) = stack.pop() {
+            for child in self.children(id) {
+                let name = self.name(child).to_string();
+                if self.is_dir(child) {
+                    stack.push((child, dir.add_dir(FSDir::new(name))));
+                } else {
+                    dir.add_file(FSFile::new(name, self.size(child)));
+                }
+            }
+        }
+        root
+    }
+    /// Returns the node behind `id`.
+    fn node(&self, id: NodeId) -> &Node {
+        &self.nodes[id.index()]
+    }
+    /// Returns the id of `name`, adding it to the names if it's new.
+    fn intern(&mut self, name: &str) -> u32 {
+        if let Some(&id) = self.name_ids.get(name) {
+            return id;
+        }
+        let id   = self.names.len() as u32;
+        let name = Rc::<str>::from(name);
+        self.names.push(name.clone());
+        self.name_ids.insert(name, id);
+        id
+    }
+    /// Adds a new child named `name` to `dir` and returns its id. Sizes
+    /// aren't updated.
+    /// 
+    /// # Panics
+    /// If `dir` is a file, or the arena already holds u32::MAX nodes.
+    /// 
+    fn push(&mut self, dir: NodeId, name: &str, is_dir: bool, size: u64)
+        -> NodeId
+    {
+        assert!(self.is_dir(dir), "'{}' is a file", self.name(dir));
+        let id = u32::try_from(self.nodes.len())
+                     .map(NodeId)
+                     .expect("the arena is full");
+        let name = self.intern(name);
+        self.nodes.push(Node { name,
+                               parent   : Some(dir),
+                               size,
+                               is_dir,
+                               children : BTreeMap::new() });
+        let key = self.names[name as usize].clone();
+        self.nodes[dir.index()].children.insert(key, id);
+        id
+    }
+}
+
+/// A depth-first, pre-order iterator over the descendants of a directory in
+/// an FsArena, returned by FsArena::walk().
+/// 
+pub struct ArenaWalk<'a> {
+    arena : &'a FsArena,
+    stack : Vec<NodeId>,
+}
+impl Iterator for ArenaWalk<'_> {
+    type Item = NodeId;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let id = self.stack.pop()?;
+        self.stack.extend(self.arena.children(id).rev());
+        Some(id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the names of the children of `dir`.
+    fn names(arena: &FsArena, dir: NodeId) -> Vec<&str> {
+        arena.children(dir).map(|c| arena.name(c)).collect()
+    }
+
+    #[test]
+    fn overflowing_adds_leave_the_arena_as_it_was() {
+        let mut arena = FsArena::new();
+        let root      = arena.root();
+        let a         = arena.dir_entry(root, "a");
+        arena.add_file(a, "big", u64::MAX - 10);
+        let err = arena.try_add_file(a, "more", 11).unwrap_err();
+        assert_eq!(err, SizeOverflow { path: "/a".into(), size: 11 });
+        assert_eq!((arena.node_count(), arena.name_count()), (3, 3));
+        assert_eq!(names(&arena, a), ["big"]);
+        assert_eq!((arena.size(a), arena.size(root)), (u64::MAX - 10, 
+                                                       u64::MAX - 10));
+        // Replacing the big file takes its old size off first.
+        arena.add_file(a, "big", u64::MAX);
+        assert_eq!(arena.size(root), u64::MAX);
+    }
+
+    #[test]
+    fn adding_a_file_again_replaces_its_size() {
+        let mut arena = FsArena::new();
+        let root      = arena.root();
+        let a         = arena.dir_entry(root, "a");
+        let f         = arena.add_file(a, "f", 100);
+        arena.add_file(root, "g", 5);
+        assert_eq!(arena.add_file(a, "f", 30), f);
+        assert_eq!((arena.size(f), arena.size(a), arena.size(root)), 
+                   (30, 30, 35));
+        assert_eq!(arena.node_count(), 4);
+    }
+
+    #[test]
+    #[should_panic(expected = "'f' is a file, not a directory")]
+    fn dir_entry_refuses_files() {
+        let mut arena = FsArena::new();
+        let root      = arena.root();
+        arena.add_file(root, "f", 1);
+        arena.dir_entry(root, "f");
+    }
+
+    #[test]
+    #[should_panic(expected = "'d' is a directory, not a file")]
+    fn add_file_refuses_directories() {
+        let mut arena = FsArena::new();
+        let root      = arena.root();
+        arena.dir_entry(root, "d");
+        arena.add_file(root, "d", 1);
+    }
+
+    #[test]
+    fn paths_are_built_from_the_parents() {
+        let mut arena = FsArena::new();
+        let root      = arena.root();
+        let a         = arena.dir_entry(root, "a");
+        let b         = arena.dir_entry(a, "b");
+        let f         = arena.add_file(b, "f.txt", 1);
+        assert_eq!(arena.path(root), "/");
+        assert_eq!(arena.path(a), "/a");
+        assert_eq!(arena.path(f), "/a/b/f.txt");
+        assert_eq!(arena.parent(f), Some(b));
+        assert_eq!(arena.get(a, "b"), Some(b));
+    }
+
+    #[test]
+    fn big_flat_directories_stay_in_name_order() {
+        let mut arena = FsArena::new();
+        let root      = arena.root();
+        for i in (0..100_000).rev() {
+            arena.add_file(root, &format!("{:06}", i), 1);
+        }
+        let want = (0..100_000).map(|i| format!("{:06}", i))
+                               .collect::<Vec<_>>();
+        assert_eq!(names(&arena, root), want);
+        assert_eq!(arena.size(root), 100_000);
+    }
+
+    #[test]
+    fn trees_round_trip_without_their_links() {
+        let tree = FSDir::new("/".into());
+        tree.make_dirs("a/b").add_file(FSFile::new("f".into(), 10));
+        tree.make_dirs("c").add_file(FSFile::new("g".into(), 5));
+        let link = FSLink::new_with("l".into(), "b/f".into(), 
+                                    LinkSize::TargetLength);
+        tree.get_dir("a").unwrap().add_link(link);
+        assert_eq!(tree.size(), 18);
+
+        let arena = FsArena::from_fsdir(&tree);
+        let a     = arena.get(arena.root(), "a").unwrap();
+        assert_eq!(names(&arena, a), ["b"]);
+        assert_eq!((arena.size(a), arena.size(arena.root())), (10, 15));
+
+        let back = arena.to_fsdir(arena.root());
+        assert_eq!(back.size(), 15);
+        assert!(!back.get_dir("a").unwrap().contains("l"));
+        tree.get_dir("a").unwrap().remove("l");
+        assert!(back.structural_eq(&tree));
+        let b = arena.get(a, "b").unwrap();
+        assert_eq!(arena.to_fsdir(b).path(), "b");
+    }
+}
@@ -0,0 +1,296 @@
+//! This module implements export of the file system tree as a Graphviz DOT
+//! digraph, with a node per directory and file and an edge from each
+//! directory to each of its children.
+//! 
+//! ```text
+//! digraph fs {
+//!   node [fontname="monospace"];
+//!   n0 [label="/\n48381165", shape=folder];
+//!   n1 [label="a\n94853", shape=folder];
+//!   n0 -> n1;
+//!   ...
+//! }
+//! ```
+
+use crate::fsobject::*;
+
+/// Options controlling what FSDir::to_dot() includes in the graph.
+/// 
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DotOptions {
+    /// Don't include entries deeper than this. The directory being exported
+    /// is at depth 0.
+    pub max_depth      : Option<usize>,
+    /// Leave files out, so only the directory hierarchy is drawn.
+    pub skip_files     : bool,
+    /// Fill the nodes of entries bigger than this in red.
+    pub highlight_over : Option<u64>,
+}
+
+/// Quote `text` as a DOT string, escaping quotes and backslashes.
+/// 
+fn dot_string(text: &str) -> String {
+    let mut out = String::with_capacity(text.len() + 2);
+    out.push('"');
+    for c in text.chars() {
+        match c {
+            '"' | '\\' => { out.push('\\'); out.push(c); },
+            '\n'       => out.push_str("\\n"),
+            _          => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+impl FSDir {
+    /// Exports the tree rooted at this directory as a DOT digraph. Each node
+    /// is labeled with the entry's name and size on separate lines. Nodes are
+    /// identified by a counter, so entries with the same name in different
+    /// directories get distinct nodes.
+    /// 
+    pub fn to_dot(&self, opts: DotOptions) -> String {
+        let mut out     = String::from("digraph fs {\n");
+        let mut next_id = 0;
+        let mut stack   = vec![(None, 0, FSEntry::Dir(self.clone()))];
+
+        out.push_str("  node [fontname=\"monospace\"];\n");
+        while let Some((parent, depth, entry)) = stack.pop() {
+            let id    = next_id;
+            let shape = match entry {
+                FSEntry::File(_) => "note",
+                FSEntry::Dir(_)  => "folder",
+                FSEntry::Link(_) => "rarrow",
+            };
+            let label = format!("{}\n{}", entry.name(), entry.size());
+            next_id  += 1;
+
+            out.push_str(&format!("  n{} [label={}, shape={}", 
+                                  id, dot_string(&label), shape));
+            if opts.highlight_over.is_some_and(|t| entry.size() > t) {
+                out.push_str(", style=filled, fillcolor=\"#ff9999\"");
+            }
+            out.push_str("];\n");
+            if let Some(parent) = parent {
+                out.push_str(&format!("  n{} -> n{};\n", parent, id));
+            }
+            if let FSEntry::Dir(dir) = entry {
+                if opts.max_depth.is_some_and(|max| depth >= max) {
+                    continue;
+                }
+                for child in dir.children().into_iter().rev() {
+                    if opts.skip_files && matches!(child, FSEntry::File(_)) {
+                        continue;
+                    }
+                    stack.push((Some(id), depth + 1, child));
+                }
+            }
+        }
+        out.push_str("}\n");
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use crate::parser::*;
+
+    #[derive(Debug, Clone, PartialEq)]
+    enum Token { Id(String), Str(String), Arrow, Punct(char) }
+
+    /// Splits the subset of DOT that to_dot() writes into tokens, undoing
+    /// the escapes in quoted strings.
+    /// 
+    fn tokens(text: &str) -> Result<Vec<Token>, String> {
+        let mut out   = vec![];
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                c if c.is_whitespace() => {},
+                '{' | '}' | '[' | ']' | '=' | ';' | ',' => {
+                    out.push(Token::Punct(c));
+                },
+                '-' if chars.next_if_eq(&'>').is_some() => {
+                    out.push(Token::Arrow);
+                },
+                '"' => {
+                    let mut s = String::new();
+                    loop {
+                        match chars.next().ok_or("unterminated string")? {
+                            '"'  => break,
+                            '\\' => match chars.next() {
+                                Some('n')        => s.push('\n'),
+                                Some(c @ ('"' | '\\')) => s.push(c),
+                                other => {
+                                    return Err(format!("bad escape {:?}", 
+                                                       other));
+                                },
+                            },
+                            c    => s.push(c),
+                        }
+                    }
+                    out.push(Token::Str(s));
+                },
+                c if c.is_ascii_alphanumeric() || c == '_' => {
+                    let mut id = c.to_string();
+                    while let Some(c) = chars.next_if(|c| {
+                        c.is_ascii_alphanumeric() || *c == '_'
+                    }) {
+                        id.push(c);
+                    }
+                    out.push(Token::Id(id));
+                },
+                c => return Err(format!("unexpected {:?}", c)),
+            }
+        }
+        Ok(out)
+    }
+
+    /// A digraph read back by validate(): each node's attributes, and the
+    /// edges in order.
+    /// 
+    #[derive(Debug, Default)]
+    struct Graph {
+        nodes : HashMap<String, HashMap<String, String>>,
+        edges : Vec<(String, String)>,
+    }
+
+    /// Checks that `text` is a digraph of node and edge statements, with no
+    /// node declared twice, and every edge between declared nodes, and reads
+    /// it back.
+    /// 
+    fn validate(text: &str) -> Result<Graph, String> {
+        let tokens    = tokens(text)?;
+        let mut iter  = tokens.into_iter();
+        let mut next  = || iter.next().ok_or("unexpected end".to_string());
+        let mut graph = Graph::default();
+        let id        = |t: Token| match t {
+            Token::Id(id) => Ok(id),
+            t             => Err(format!("expected an ID, got {:?}", t)),
+        };
+        let punct     = |t: Token, c: char| match t {
+            Token::Punct(p) if p == c => Ok(()),
+            t => Err(format!("expected {:?}, got {:?}", c, t)),
+        };
+        if id(next()?)? != "digraph" {
+            return Err("not a digraph".into());
+        }
+        id(next()?)?;
+        punct(next()?, '{')?;
+        loop {
+            let name = match next()? {
+                Token::Punct('}') => break,
+                t                 => id(t)?,
+            };
+            match next()? {
+                Token::Arrow => {
+                    let to = id(next()?)?;
+                    punct(next()?, ';')?;
+                    for end in [&name, &to] {
+                        if !graph.nodes.contains_key(end) {
+                            return Err(format!("edge to unknown {}", end));
+                        }
+                    }
+                    graph.edges.push((name, to));
+                },
+                Token::Punct('[') => {
+                    let mut attrs = HashMap::new();
+                    loop {
+                        let key = id(next()?)?;
+                        punct(next()?, '=')?;
+                        let value = match next()? {
+                            Token::Id(v) | Token::Str(v) => v,
+                            t => return Err(format!("bad value {:?}", t)),
+                        };
+                        attrs.insert(key, value);
+                        match next()? {
+                            Token::Punct(',') => {},
+                            Token::Punct(']') => break,
+                            t => return Err(format!("unexpected {:?}", t)),
+                        }
+                    }
+                    punct(next()?, ';')?;
+                    if name != "node" 
+                        && graph.nodes.insert(name.clone(), attrs).is_some()
+                    {
+                        return Err(format!("{} declared twice", name));
+                    }
+                },
+                t => return Err(format!("unexpected {:?}", t)),
+            }
+        }
+        match next() {
+            Err(_) => Ok(graph),
+            Ok(t)  => Err(format!("{:?} after the graph", t)),
+        }
+    }
+
+    /// Returns the labels of the nodes in `graph` that have `attr` set to
+    /// `value`, sorted.
+    /// 
+    fn labels_with(graph: &Graph, attr: &str, value: &str) -> Vec<String> {
+        let mut labels = graph.nodes.values()
+                              .filter(|a| a.get(attr).is_some_and(|v| {
+                                  v == value
+                              }))
+                              .map(|a| a["label"].clone())
+                              .collect::<Vec<_>>();
+        labels.sort();
+        labels
+    }
+
+    #[test]
+    fn the_sample_exports_a_valid_graph() {
+        let root  = build_fs_from_str(include_str!("../data/sample.txt"))
+                        .unwrap();
+        let graph = validate(&root.to_dot(DotOptions::default())).unwrap();
+        assert_eq!((graph.nodes.len(), graph.edges.len()), (14, 13));
+        assert_eq!(labels_with(&graph, "shape", "folder"), 
+                   ["/\n48381165", "a\n94853", "d\n24933642", "e\n584"]);
+        // Each node but the root has one parent.
+        let mut targets = graph.edges.iter()
+                               .map(|(_, to)| to)
+                               .collect::<Vec<_>>();
+        targets.sort();
+        targets.dedup();
+        assert_eq!(targets.len(), 13);
+
+        let opts  = DotOptions { skip_files: true, ..Default::default() };
+        let graph = validate(&root.to_dot(opts)).unwrap();
+        assert_eq!((graph.nodes.len(), graph.edges.len()), (4, 3));
+        let opts  = DotOptions { max_depth: Some(1), ..Default::default() };
+        let graph = validate(&root.to_dot(opts)).unwrap();
+        assert_eq!((graph.nodes.len(), graph.edges.len()), (5, 4));
+        let opts  = DotOptions { highlight_over: Some(10_000_000), 
+                                 ..Default::default() };
+        let graph = validate(&root.to_dot(opts)).unwrap();
+        assert_eq!(labels_with(&graph, "style", "filled"), 
+                   ["/\n48381165", "b.txt\n14848514", "d\n24933642"]);
+    }
+
+    #[test]
+    fn quotes_and_backslashes_in_names_are_escaped() {
+        let root = FSDir::new("/".into());
+        let name = r#"say "hi" \ bye\"#;
+        root.make_dirs("x").add_file(FSFile::new(name.into(), 5));
+        root.make_dirs("y").add_file(FSFile::new(name.into(), 6));
+        let dot  = root.to_dot(DotOptions::default());
+        assert!(dot.contains(r#"label="say \"hi\" \\ bye\\\n5""#), "{}", dot);
+        let graph = validate(&dot).unwrap();
+        assert_eq!((graph.nodes.len(), graph.edges.len()), (5, 4));
+        assert_eq!(labels_with(&graph, "shape", "note"), 
+                   [format!("{}\n5", name), format!("{}\n6", name)]);
+    }
+
+    #[test]
+    fn the_validator_rejects_broken_graphs() {
+        for text in ["digraph fs {\n  n0 [label=\"a];\n}\n",
+                     "digraph fs {\n  n0 [label=\"a\"];\n  n0 -> n1;\n}\n",
+                     "digraph fs {\n  n0 [label=\"a\"];\n",
+                     "digraph fs {\n  n0 [label=\"a\\q\"];\n}\n"] {
+            assert!(validate(text).is_err(), "{}", text);
+        }
+    }
+}
@@ -0,0 +1,206 @@
+//! This module implements ways of narrowing a tree down to the entries that
+//! pass a test: the retain functions remove the rest in place, adjusting
+//! sizes as they go, and filter_clone() copies the entries that pass into a
+//! new tree, leaving the original alone.
+
+use std::collections::{HashMap, HashSet};
+use std::iter;
+use crate::fsobject::*;
+
+impl FSDir {
+    /// Removes every file below this directory that doesn't satisfy `pred`,
+    /// and returns how many were removed. Directories are kept, even if
+    /// they're left empty; retain_files_with() can remove those too.
+    /// 
+    pub fn retain_files<F>(&self, pred: F) -> usize
+    where
+        F: FnMut(&FSFile) -> bool
+    {
+        self.retain_files_with(pred, false)
+    }
+    /// Like retain_files(), but with `prune` set, directories left empty by
+    /// the removal are removed as well, along with any directory above them
+    /// that's left empty in turn. Directories that were empty to begin with,
+    /// and this directory itself, are kept. The count returned includes the
+    /// directories removed.
+    /// 
+    pub fn retain_files_with<F>(&self, mut pred: F, prune: bool) -> usize
+    where
+        F: FnMut(&FSFile) -> bool
+    {
+        let mut removed = 0;
+        let mut emptied = HashSet::new();
+        // Children come before their parents in reverse pre-order, so a
+        // parent is only looked at once everything below it is done.
+        for dir in self.dirs_pre_order().iter().rev() {
+            for child in dir.children() {
+                if let FSEntry::File(file) = child {
+                    if !pred(&file) {
+                        dir.remove(&file.name_ref());
+                        emptied.insert(dir.id());
+                        removed += 1;
+                    }
+                }
+            }
+            if !prune || dir.ptr_eq(self) || !dir.is_empty()
+                || !emptied.contains(&dir.id())
+            {
+                continue;
+            }
+            if let Some(parent) = dir.parent() {
+                parent.remove(&dir.name_ref());
+                emptied.insert(parent.id());
+                removed += 1;
+            }
+        }
+        removed
+    }
+    /// Removes every directory below this one that doesn't satisfy `pred`,
+    /// along with everything in it, and returns how many were removed, not
+    /// counting their contents. The directories inside a removed directory
+    /// aren't tested.
+    /// 
+    pub fn retain_dirs<F>(&self, mut pred: F) -> usize
+    where
+        F: FnMut(&FSDir) -> bool
+    {
+        let mut removed = 0;
+        let mut stack   = vec![self.clone()];
+        while let Some(dir) = stack.pop() {
+            for sub in dir.find_dirs_by(|_| true) {
+                if pred(&sub) {
+                    stack.push(sub);
+                } else {
+                    dir.remove(&sub.name_ref());
+                    removed += 1;
+                }
+            }
+        }
+        removed
+    }
+    /// Returns a new tree holding only the files below this directory that
+    /// satisfy `pred`, and the directories on the way to them. The copy's
+    /// root has this directory's name, and is returned even if nothing
+    /// matches. This tree isn't changed.
+    /// 
+    pub fn filter_clone<F>(&self, mut pred: F) -> FSDir
+    where
+        F: FnMut(&FSFile) -> bool
+    {
+        let mut kept   = HashMap::new();
+        let mut needed = HashSet::new();
+        for dir in self.dirs_pre_order().iter().rev() {
+            let files = dir.children()
+                           .into_iter()
+                           .filter_map(|child| match child {
+                               FSEntry::File(f) if pred(&f) => Some(f),
+                               _                            => None,
+                           })
+                           .collect::<Vec<_>>();
+            if !files.is_empty()
+                || dir.any_subdir(|sub| needed.contains(&sub.id()))
+            {
+                needed.insert(dir.id());
+            }
+            kept.insert(dir.id(), files);
+        }
+        let root      = FSDir::new_shared(self.name_ref());
+        let mut stack = vec![(self.clone(), root.clone())];
+        while let Some((src, dst)) = stack.pop() {
+            dst.set_mtime(src.own_mtime());
+            for file in &kept[&src.id()] {
+                let copy = FSFile::new_shared(file.name_ref(), file.size());
+                copy.set_link_id(file.link_id());
+                copy.set_mtime(file.mtime());
+                dst.add_file(copy);
+            }
+            for sub in src.find_dirs_by(|d| needed.contains(&d.id())) {
+                let copy = dst.add_dir(FSDir::new_shared(sub.name_ref()));
+                stack.push((sub, copy));
+            }
+        }
+        root
+    }
+    /// Returns this directory and every directory below it, in pre-order.
+    fn dirs_pre_order(&self) -> Vec<FSDir> {
+        iter::once(self.clone()).chain(self.iter_dirs_recurs()).collect()
+    }
+    /// Returns true if a directory directly in this one satisfies `pred`.
+    fn any_subdir<F>(&self, mut pred: F) -> bool
+    where
+        F: FnMut(&FSDir) -> bool
+    {
+        self.children().iter().any(|child| match child {
+            FSEntry::Dir(sub) => pred(sub),
+            _                 => false,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::*;
+    use crate::testing::*;
+
+    fn sample() -> FSDir {
+        build_fs_from_str(include_str!("../data/sample.txt")).unwrap()
+    }
+
+    fn paths(root: &FSDir) -> Vec<String> {
+        root.walk().map(|(path, _)| path).collect()
+    }
+
+    fn is_log(file: &FSFile) -> bool {
+        file.extension().as_deref() == Some("log")
+    }
+
+    #[test]
+    fn retaining_in_place_keeps_sizes_consistent() {
+        let root = sample();
+        assert_eq!(root.retain_files(is_log), 9);
+        assert_eq!(root.verify_sizes(), Ok(()));
+        assert_eq!(paths(&root), ["/a", "/a/e", "/d", "/d/d.log"]);
+        assert_eq!(root.size(), 8033020);
+
+        let root = sample();
+        assert_eq!(root.retain_files_with(is_log, true), 11);
+        assert_eq!(root.verify_sizes(), Ok(()));
+        assert_eq!(paths(&root), ["/d", "/d/d.log"]);
+
+        let root = sample();
+        assert_eq!(root.retain_dirs(|dir| dir.name_ref().as_ref() != "a"), 1);
+        assert_eq!(root.verify_sizes(), Ok(()));
+        assert_eq!(root.size(), 48381165 - 94853);
+
+        for seed in 0..10 {
+            let spec = TreeSpec { nodes: 300, ..Default::default() };
+            let root = FSDir::random(seed, spec.clone());
+            root.retain_files_with(|f| f.size() % 3 != 0, seed % 2 == 0);
+            assert_eq!(root.verify_sizes(), Ok(()), "seed {}", seed);
+            let root = FSDir::random(seed, spec);
+            root.retain_dirs(|d| d.size() % 2 == 0);
+            assert_eq!(root.verify_sizes(), Ok(()), "seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn filtered_clones_keep_sizes_consistent() {
+        let root = sample();
+        let logs = root.filter_clone(is_log);
+        assert_eq!(logs.verify_sizes(), Ok(()));
+        assert_eq!(paths(&logs), ["/d", "/d/d.log"]);
+        assert_eq!(logs.size(), 8033020);
+        assert!(root.structural_eq(&sample()));
+        assert_eq!(root.filter_clone(|_| false).size(), 0);
+
+        for seed in 0..10 {
+            let spec = TreeSpec { nodes: 300, ..Default::default() };
+            let root = FSDir::random(seed, spec);
+            let copy = root.filter_clone(|f| f.size() % 3 != 0);
+            assert_eq!(copy.verify_sizes(), Ok(()), "seed {}", seed);
+            root.retain_files(|f| f.size() % 3 != 0);
+            assert_eq!(copy.size(), root.size(), "seed {}", seed);
+        }
+    }
+}
@@ -0,0 +1,131 @@
+//! This module implements export of the file system tree as "collapsed 
+//! stacks", the input format of flamegraph.pl and inferno, so a disk can be
+//! viewed as a flame graph. Each line is a `;` separated stack of frames and
+//! the bytes attributed to it:
+//! 
+//! ```text
+//! /;b.txt 14848514
+//! /;c.dat 8504156
+//! /;a;f 29116
+//! ```
+
+use crate::fsobject::*;
+use crate::visit::*;
+
+/// Selects what the leaf frames of FSDir::to_collapsed_stacks() are.
+/// 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Leaf {
+    /// Every file gets a frame of its own, on top of its directory's stack.
+    #[default]
+    File,
+    /// Files get no frames; the bytes of the files directly in a directory
+    /// are attributed to the directory's own frame.
+    Dir,
+}
+
+/// Makes `name` safe to use as a frame. Semicolons separate frames and line
+/// breaks separate stacks, and neither format has a way of escaping them, so
+/// they're replaced with underscores.
+/// 
+fn frame(name: &str) -> String {
+    name.replace([';', '\r', '\n'], "_")
+}
+
+impl FSDir {
+    /// Exports the tree rooted at this directory as collapsed stacks, one
+    /// line per stack in depth-first order. A directory's files come before
+    /// its subdirectories, each in name order. 
+    /// Stacks with no bytes are left out, and the values of the lines add up
+    /// to this directory's size.
+    /// 
+    pub fn to_collapsed_stacks(&self, leaf_granularity: Leaf) -> String {
+        let mut writer = StackWriter { out    : String::new(), 
+                                       frames : vec![], 
+                                       leaf   : leaf_granularity };
+        self.accept(&mut writer);
+        writer.out
+    }
+}
+
+/// The Visitor behind FSDir::to_collapsed_stacks(). `frames` holds the stack
+/// of each directory entered and not yet left.
+/// 
+struct StackWriter {
+    out    : String,
+    frames : Vec<String>,
+    leaf   : Leaf,
+}
+impl Visitor for StackWriter {
+    fn enter_dir(&mut self, _path: &str, dir: &FSDir) -> VisitFlow {
+        let frames = match self.frames.last() {
+            Some(above) => format!("{};{}", above, frame(&dir.name_ref())),
+            None        => frame(&dir.name_ref()),
+        };
+        // A directory's files are written as it's entered, so they come
+        // before anything in its subdirectories.
+        let mut own = 0;
+        for file in dir.find_files_by(|_| true) {
+            match self.leaf {
+                Leaf::File if file.size() > 0 => {
+                    self.out.push_str(&format!("{};{} {}\n", frames, 
+                                               frame(&file.name_ref()), 
+                                               file.size()));
+                },
+                Leaf::File => {},
+                Leaf::Dir  => own += file.size(),
+            }
+        }
+        if own > 0 {
+            self.out.push_str(&format!("{} {}\n", frames, own));
+        }
+        self.frames.push(frames);
+        VisitFlow::Continue
+    }
+    fn leave_dir(&mut self, _path: &str, _dir: &FSDir) -> VisitFlow {
+        self.frames.pop();
+        VisitFlow::Continue
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::*;
+
+    /// Returns the sum of the values on the lines of `stacks`.
+    fn total(stacks: &str) -> u64 {
+        stacks.lines()
+              .map(|line| line.rsplit_once(' ').unwrap().1.parse::<u64>())
+              .sum::<Result<_, _>>()
+              .unwrap()
+    }
+
+    #[test]
+    fn the_sample_stacks_add_up_to_its_size() {
+        let root = build_fs_from_str(include_str!("../data/sample.txt"))
+                       .unwrap();
+        for leaf in [Leaf::File, Leaf::Dir] {
+            let stacks = root.to_collapsed_stacks(leaf);
+            assert_eq!(total(&stacks), root.size(), "{:?}", leaf);
+        }
+        assert_eq!(root.to_collapsed_stacks(Leaf::File).lines().count(), 10);
+        assert_eq!(root.to_collapsed_stacks(Leaf::Dir), "\
+/ 23352670
+/;a 94269
+/;a;e 584
+/;d 24933642
+");
+        let d = root.get_dir("d").unwrap();
+        assert_eq!(total(&d.to_collapsed_stacks(Leaf::File)), d.size());
+    }
+
+    #[test]
+    fn separators_in_names_are_replaced() {
+        let root = FSDir::new("/".into());
+        root.make_dirs("a;b").add_file(FSFile::new("c\nd;e".into(), 3));
+        root.add_file(FSFile::new("empty".into(), 0));
+        assert_eq!(root.to_collapsed_stacks(Leaf::File), "/;a_b;c_d_e 3\n");
+        assert_eq!(root.to_collapsed_stacks(Leaf::Dir), "/;a_b 3\n");
+    }
+}
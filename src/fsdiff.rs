@@ -0,0 +1,195 @@
+//! This module implements comparing two trees, such as scans of the same disk
+//! taken a week apart, to find out where space was gained or lost.
+
+use std::collections::BTreeSet;
+use crate::fsobject::*;
+
+/// How an entry differs between the old and the new tree.
+/// 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DiffKind {
+    /// Only the new tree has the entry.
+    Added,
+    /// Only the old tree has the entry.
+    Removed,
+    /// Both trees have the entry, with different sizes.
+    SizeChanged,
+}
+
+/// One difference between two trees.
+/// 
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DiffEntry {
+    /// The entry's path, from the root of the tree.
+    pub path             : String,
+    /// How the entry differs.
+    pub kind             : DiffKind,
+    /// Whether the entry is a directory.
+    pub is_dir           : bool,
+    /// The entry's size in the old tree, or 0 if it was added.
+    pub old_size         : u64,
+    /// The entry's size in the new tree, or 0 if it was removed.
+    pub new_size         : u64,
+    /// For a directory whose size changed, true if none of its own files or
+    /// links were added, removed, or resized, so the change is all further
+    /// down.
+    pub descendants_only : bool,
+}
+impl DiffEntry {
+    /// Returns how much the entry grew, or shrank if negative.
+    pub fn delta(&self) -> i128 {
+        self.new_size as i128 - self.old_size as i128
+    }
+}
+
+/// Compares `old` and `new`, walking them in step by name, and returns their
+/// differences in path order. An added or removed directory is reported once,
+/// without its contents. A path that's a file in one tree and a directory in
+/// the other is reported as removed and added.
+/// 
+pub fn diff(old: &FSDir, new: &FSDir) -> Vec<DiffEntry> {
+    let mut diffs = vec![];
+    let mut stack = vec![(old.path(), old.clone(), new.clone())];
+
+    while let Some((path, old, new)) = stack.pop() {
+        let this  = diffs.len();
+        let names = old.children().iter()
+                       .chain(new.children().iter())
+                       .map(|c| c.name_ref())
+                       .collect::<BTreeSet<_>>();
+        let mut own_change = false;
+        let mut subdirs    = vec![];
+        for name in names {
+            let child_path = join_path(&path, &name);
+            let entry = |kind, e: &FSEntry, old_size, new_size| {
+                DiffEntry { path             : child_path.clone(), 
+                            kind, 
+                            is_dir           : matches!(e, FSEntry::Dir(_)),
+                            old_size, 
+                            new_size, 
+                            descendants_only : false }
+            };
+            match (old.get(&name), new.get(&name)) {
+                (Some(FSEntry::Dir(o)), Some(FSEntry::Dir(n))) => {
+                    subdirs.push((child_path, o, n));
+                },
+                (Some(o @ FSEntry::File(_)), Some(n @ FSEntry::File(_))) => {
+                    if o.size() != n.size() {
+                        diffs.push(entry(DiffKind::SizeChanged, &o, 
+                                         o.size(), n.size()));
+                        own_change = true;
+                    }
+                },
+                (Some(FSEntry::Link(o)), Some(FSEntry::Link(n))) 
+                    if o.target() == n.target() => {},
+                (o, n) => {
+                    if let Some(o) = o {
+                        own_change |= !matches!(o, FSEntry::Dir(_));
+                        diffs.push(entry(DiffKind::Removed, &o, o.size(), 0));
+                    }
+                    if let Some(n) = n {
+                        own_change |= !matches!(n, FSEntry::Dir(_));
+                        diffs.push(entry(DiffKind::Added, &n, 0, n.size()));
+                    }
+                },
+            }
+        }
+        if old.size() != new.size() {
+            diffs.insert(this, DiffEntry { 
+                path             : path.clone(),
+                kind             : DiffKind::SizeChanged,
+                is_dir           : true,
+                old_size         : old.size(),
+                new_size         : new.size(),
+                descendants_only : !own_change,
+            });
+        }
+        // Pushed in reverse so they're popped in name order.
+        stack.extend(subdirs.into_iter().rev());
+    }
+    // Sort by path component, so each directory is followed by its contents.
+    diffs.sort_by(|a, b| a.path.split('/').cmp(b.path.split('/')));
+    diffs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(dir: &FSDir, name: &str, size: u64) {
+        dir.add_file(FSFile::new(name.into(), size));
+    }
+
+    /// The trees before and after: /a/f2 and /a/b/g grow, /a/f3 is added, 
+    /// /c removed and /d added, /e grows only through /e/q/z, /x turns from
+    /// a file into a directory, and the link /l changes target, while /s and
+    /// the link /m stay as they were.
+    fn trees() -> (FSDir, FSDir) {
+        let old = FSDir::new("/".into());
+        let a   = old.make_dirs("a");
+        file(&a, "f1", 10);
+        file(&a, "f2", 20);
+        file(&old.make_dirs("a/b"), "g", 5);
+        file(&old.make_dirs("c"), "h", 1);
+        file(&old.make_dirs("e/q"), "z", 1);
+        file(&old, "x", 3);
+        file(&old, "s", 7);
+        old.add_link(FSLink::new("l".into(), "a".into()));
+        old.add_link(FSLink::new("m".into(), "a".into()));
+
+        let new = FSDir::new("/".into());
+        let a   = new.make_dirs("a");
+        file(&a, "f1", 10);
+        file(&a, "f2", 25);
+        file(&a, "f3", 4);
+        file(&new.make_dirs("a/b"), "g", 6);
+        file(&new.make_dirs("d"), "k", 2);
+        file(&new.make_dirs("e/q"), "z", 2);
+        file(&new.make_dirs("x"), "y", 1);
+        file(&new, "s", 7);
+        new.add_link(FSLink::new("l".into(), "d".into()));
+        new.add_link(FSLink::new("m".into(), "a".into()));
+        (old, new)
+    }
+
+    fn entry(path: &str, kind: DiffKind, is_dir: bool, old_size: u64, 
+             new_size: u64, descendants_only: bool) -> DiffEntry 
+    {
+        DiffEntry { path: path.into(), kind, is_dir, old_size, new_size, 
+                    descendants_only }
+    }
+
+    #[test]
+    fn every_kind_of_change_is_reported_in_path_order() {
+        use DiffKind::*;
+        let (old, new) = trees();
+        assert_eq!(diff(&old, &new), [
+            entry("/",      SizeChanged, true,  47, 57, false),
+            entry("/a",     SizeChanged, true,  35, 45, false),
+            entry("/a/b",   SizeChanged, true,  5,  6,  false),
+            entry("/a/b/g", SizeChanged, false, 5,  6,  false),
+            entry("/a/f2",  SizeChanged, false, 20, 25, false),
+            entry("/a/f3",  Added,       false, 0,  4,  false),
+            entry("/c",     Removed,     true,  1,  0,  false),
+            entry("/d",     Added,       true,  0,  2,  false),
+            entry("/e",     SizeChanged, true,  1,  2,  true),
+            entry("/e/q",   SizeChanged, true,  1,  2,  false),
+            entry("/e/q/z", SizeChanged, false, 1,  2,  false),
+            entry("/l",     Removed,     false, 0,  0,  false),
+            entry("/l",     Added,       false, 0,  0,  false),
+            entry("/x",     Removed,     false, 3,  0,  false),
+            entry("/x",     Added,       true,  0,  1,  false),
+        ]);
+        let deltas = diff(&new, &old).iter()
+                                     .map(|e| e.delta())
+                                     .collect::<Vec<_>>();
+        assert_eq!(deltas, [-10, -10, -1, -1, -5, -4, 1, -2, -1, -1, -1, 
+                            0, 0, -1, 3]);
+    }
+
+    #[test]
+    fn equal_trees_have_no_differences() {
+        let (old, _) = trees();
+        assert!(diff(&old, &old.deep_clone()).is_empty());
+    }
+}
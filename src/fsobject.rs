@@ -1,43 +1,467 @@
 #![allow(dead_code)]
 
-/// This module defines the FSObject trait and the FSDir and FSFile structs.
-/// The objects implemented in this module are used to represent the file 
-/// system.
+//! This module defines the FSObject trait and the FSDir and FSFile structs.
+//! The objects implemented in this module are used to represent the file 
+//! system.
 
-use std::cell::{RefCell, RefMut, Ref};
-use std::collections::BTreeMap;
-use std::fmt::{Formatter, Debug};
-use std::rc::Rc;
+use std::cell::{Cell, RefCell, RefMut, Ref};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BinaryHeap, HashMap, HashSet, VecDeque};
+use std::error::Error;
+use std::fmt::{Formatter, Debug, Display};
+use std::rc::{Rc, Weak};
 
 macro_rules! pwrap { ($e:expr) => { Rc::new(RefCell::new($e)) } }
 
 /// A trait for objects in the file system.
 /// 
 pub trait FSObject {
-    /// Return the name of the object.
-    fn name(&self) -> String;
+    /// Return the name of the object as a new String. name_ref() returns
+    /// the same name without allocating.
+    /// 
+    fn name(&self) -> String {
+        self.name_ref().to_string()
+    }
+    /// Return a shared handle to the name of the object. This only bumps a
+    /// reference count, so it's the one to use in hot loops.
+    /// 
+    fn name_ref(&self) -> Rc<str>;
     /// Return the size of the object.
-    fn size(&self) -> usize;
-    /// Return the parent of the object.
+    fn size(&self) -> u64;
+    /// Return the parent of the object. Parents are held weakly, so this is 
+    /// None for the root, and for any object whose tree has been dropped.
+    /// 
     fn parent(&self) -> Option<FSDir>;
+    /// Return the full path of the object, built by walking up the parent 
+    /// chain and joining the names with '/'. The root directory, named "/",
+    /// yields "/", and its children yield "/name".
+    /// 
+    fn path(&self) -> String {
+        let mut names = vec![self.name_ref()];
+        let mut cur   = self.parent();
+        while let Some(dir) = cur {
+            names.push(dir.name_ref());
+            cur = dir.parent();
+        }
+        join_names(names.iter().rev())
+    }
+    /// Return how far below the root of its tree the object is. The root is
+    /// at depth 0 and its children at depth 1.
+    /// 
+    fn depth(&self) -> usize {
+        self.ancestors().count()
+    }
+    /// Return an iterator over the directories above the object, from its
+    /// parent up to the root.
+    /// 
+    fn ancestors(&self) -> FSAncestors {
+        FSAncestors { next: self.parent() }
+    }
+}
+
+/// Appends a name to a path, adding a '/' separator unless the path is empty
+/// or already ends with one.
+/// 
+pub(crate) fn join_path(path: &str, name: &str) -> String {
+    let mut full = String::with_capacity(path.len() + 1 + name.len());
+    full.push_str(path);
+    push_name(&mut full, name);
+    full
+}
+
+/// Joins `names`, from the root down, into a path, as join_path() joins them
+/// one at a time.
+/// 
+pub(crate) fn join_names<I, S>(names: I) -> String 
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>
+{
+    let mut path = String::new();
+    for name in names {
+        push_name(&mut path, name.as_ref());
+    }
+    path
+}
+
+/// Like join_path(), but appends to `path` in place.
+/// 
+fn push_name(path: &mut String, name: &str) {
+    if !path.is_empty() && !path.ends_with('/') {
+        path.push('/');
+    }
+    path.push_str(name);
+}
+
+/// Returned when a file or directory can't be renamed.
+/// 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RenameError {
+    /// The parent directory already has a child with the requested name.
+    AlreadyExists(String),
+}
+impl Display for RenameError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RenameError::AlreadyExists(name) => {
+                write!(f, "an entry named '{}' already exists", name)
+            },
+        }
+    }
+}
+impl Error for RenameError {}
+
+/// Returned when a child can't be moved from one directory to another.
+/// 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MoveError {
+    /// The source directory has no child with the given name.
+    NotFound(String),
+    /// The destination directory already has a child with the given name.
+    AlreadyExists(String),
+    /// The destination is the directory being moved, or one of its 
+    /// descendants.
+    IntoDescendant(String),
+    /// Quotas are enforced, and the move would take a directory over its
+    /// quota.
+    QuotaExceeded(QuotaExceeded),
+}
+impl Display for MoveError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MoveError::NotFound(name) => {
+                write!(f, "no entry named '{}' to move", name)
+            },
+            MoveError::AlreadyExists(name) => {
+                write!(f, "destination already has an entry named '{}'", name)
+            },
+            MoveError::IntoDescendant(name) => {
+                write!(f, "can't move '{}' into itself or a descendant", name)
+            },
+            MoveError::QuotaExceeded(e) => write!(f, "can't move: {}", e),
+        }
+    }
+}
+impl Error for MoveError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            MoveError::QuotaExceeded(e) => Some(e),
+            _                           => None,
+        }
+    }
+}
+
+/// Returned when two trees can't be merged.
+/// 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MergeError {
+    /// Both trees have a file at the path, with different sizes.
+    SizeConflict { path: String, ours: u64, theirs: u64 },
+    /// The path is a different kind of entry in each tree: a file in one and
+    /// a directory in the other, or a link in only one of them.
+    KindConflict(String),
+    /// Both trees have a link at the path, with different targets.
+    LinkTargetConflict { path: String, ours: String, theirs: String },
+}
+impl Display for MergeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MergeError::SizeConflict { path, ours, theirs } => {
+                write!(f, "'{}' has size {} in one tree and {} in the other",
+                       path, ours, theirs)
+            },
+            MergeError::KindConflict(path) => {
+                write!(f, "'{}' is a different kind of entry in each tree", 
+                       path)
+            },
+            MergeError::LinkTargetConflict { path, ours, theirs } => {
+                write!(f, "'{}' links to '{}' in one tree and '{}' in the \
+                           other", path, ours, theirs)
+            },
+        }
+    }
+}
+impl Error for MergeError {}
+
+/// Returned when adding an entry to a directory, or resizing a file, would
+/// make the size of the directory, or of one of its parents, overflow.
+/// 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SizeOverflow {
+    /// The path of the directory whose size would overflow, or of the file,
+    /// if FSFile::grow() would overflow its own size.
+    /// 
+    pub path : String,
+    /// The size of the entry being added, or the new size of the file, or
+    /// with FSFile::grow(), the number of bytes to grow by.
+    /// 
+    pub size : u64,
+}
+impl Display for SizeOverflow {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "adding {} bytes would overflow the size of '{}'", 
+               self.size, self.path)
+    }
+}
+impl Error for SizeOverflow {}
+
+/// Returned by FSDir::check_quotas(), and, when quotas are enforced, by 
+/// FSDir::move_child() and in a GrowthError by the try_ functions, when a 
+/// change would take a directory over its quota.
+/// 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuotaExceeded {
+    /// The path of the directory whose quota would be exceeded.
+    pub path    : String,
+    /// The directory's quota.
+    pub quota   : u64,
+    /// How many bytes over its quota the directory would be.
+    pub overage : u64,
+}
+impl Display for QuotaExceeded {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "'{}' would be {} bytes over its quota of {} bytes", 
+               self.path, self.overage, self.quota)
+    }
+}
+impl Error for QuotaExceeded {}
+
+/// Returned by the try_ variants of the functions that add to a directory
+/// or resize a file, when the change can't be made.
+/// 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GrowthError {
+    /// The size of a directory, or of the file, would overflow.
+    Overflow(SizeOverflow),
+    /// Quotas are enforced, and a directory would go over its quota.
+    QuotaExceeded(QuotaExceeded),
 }
+impl Display for GrowthError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GrowthError::Overflow(e)      => write!(f, "{}", e),
+            GrowthError::QuotaExceeded(e) => write!(f, "{}", e),
+        }
+    }
+}
+impl Error for GrowthError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            GrowthError::Overflow(e)      => Some(e),
+            GrowthError::QuotaExceeded(e) => Some(e),
+        }
+    }
+}
+impl From<SizeOverflow> for GrowthError {
+    fn from(e: SizeOverflow) -> Self {
+        GrowthError::Overflow(e)
+    }
+}
+impl From<QuotaExceeded> for GrowthError {
+    fn from(e: QuotaExceeded) -> Self {
+        GrowthError::QuotaExceeded(e)
+    }
+}
+
+/// Returned when shrinking a file by more than its size.
+/// 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SizeUnderflow {
+    /// The path of the file.
+    pub path : String,
+    /// The size of the file.
+    pub size : u64,
+    /// How much it was to shrink by.
+    pub by   : u64,
+}
+impl Display for SizeUnderflow {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "can't shrink '{}' by {} bytes; it only has {}", 
+               self.path, self.by, self.size)
+    }
+}
+impl Error for SizeUnderflow {}
+
+/// Returned by FSDir::verify_sizes() when a directory's cached size isn't 
+/// the total size of the files below it.
+/// 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SizeMismatch {
+    /// The path of the directory.
+    pub path   : String,
+    /// The size the directory has cached.
+    pub cached : u64,
+    /// The total size of the files below the directory.
+    pub actual : u64,
+}
+impl Display for SizeMismatch {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "'{}' has size {}, but the files below it add up to {}",
+               self.path, self.cached, self.actual)
+    }
+}
+impl Error for SizeMismatch {}
 
-/// Holds a file or dir in the file system.
+/// Holds a file or dir in the file system. This is what a directory's 
+/// children are stored as, and what's handed out when callers need to look at
+/// the contents of a directory without knowing in advance what kind of object
+/// each child is.
 /// 
-#[derive(Debug)]
-enum FSDirOrFile {
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FSEntry {
     File(FSFile),
     Dir(FSDir),
+    Link(FSLink),
+}
+impl FSObject for FSEntry {
+    fn name_ref(&self) -> Rc<str> {
+        match self {
+            FSEntry::File(file) => file.name_ref(),
+            FSEntry::Dir(dir)   => dir.name_ref(),
+            FSEntry::Link(link) => link.name_ref(),
+        }
+    }
+    fn size(&self) -> u64 {
+        match self {
+            FSEntry::File(file) => file.size(),
+            FSEntry::Dir(dir)   => dir.size(),
+            FSEntry::Link(link) => link.size(),
+        }
+    }
+    fn parent(&self) -> Option<FSDir> {
+        match self {
+            FSEntry::File(file) => file.parent(),
+            FSEntry::Dir(dir)   => dir.parent(),
+            FSEntry::Link(link) => link.parent(),
+        }
+    }
+}
+impl From<FSFile> for FSEntry {
+    fn from(file: FSFile) -> Self {
+        FSEntry::File(file)
+    }
+}
+impl From<FSDir> for FSEntry {
+    fn from(dir: FSDir) -> Self {
+        FSEntry::Dir(dir)
+    }
+}
+impl From<FSLink> for FSEntry {
+    fn from(link: FSLink) -> Self {
+        FSEntry::Link(link)
+    }
+}
+
+/// How much a symbolic link adds to the size of the directories above it.
+/// 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LinkSize {
+    /// Nothing, so sizes count only files.
+    #[default]
+    Zero,
+    /// The length of its target, as `ls -l` and `du` report a link's size.
+    TargetLength,
+}
+impl LinkSize {
+    /// Returns the size of a link to `target`.
+    pub fn of(self, target: &str) -> u64 {
+        match self {
+            LinkSize::Zero         => 0,
+            LinkSize::TargetLength => target.len() as u64,
+        }
+    }
+}
+
+/// The most links FSDir::resolve_link() follows before giving up, as it 
+/// would on a loop. Linux gives up after the same number.
+/// 
+pub const MAX_LINK_HOPS: usize = 40;
+
+/// Summary statistics for a directory tree, as returned by FSDir::stats().
+/// 
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FSStats {
+    /// The number of files below the directory.
+    pub files     : usize,
+    /// The number of directories below the directory, not counting itself.
+    pub dirs      : usize,
+    /// The number of symbolic links below the directory.
+    pub links     : usize,
+    /// The total size of the directory.
+    pub size      : u64,
+    /// The depth of the deepest entry, where the directory itself is 0.
+    pub max_depth : usize,
+}
+
+/// What FSDir::children_sorted() orders children by.
+/// 
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SortKey {
+    /// By name.
+    #[default]
+    Name,
+    /// By size, with entries of the same size in name order.
+    Size,
+}
+
+/// Which way FSDir::children_sorted() orders children.
+/// 
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SortDir {
+    /// Smallest, or first by name, first.
+    #[default]
+    Ascending,
+    /// Largest, or last by name, first.
+    Descending,
+}
+impl SortDir {
+    /// Returns the opposite direction.
+    pub fn reversed(self) -> Self {
+        match self {
+            SortDir::Ascending  => SortDir::Descending,
+            SortDir::Descending => SortDir::Ascending,
+        }
+    }
+}
+
+/// An estimate of the heap memory a directory tree uses, as returned by 
+/// FSDir::memory_stats().
+/// 
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryStats {
+    /// The number of files and directories, the directory itself included.
+    pub nodes        : usize,
+    /// The number of distinct names.
+    pub unique_names : usize,
+    /// The number of allocations holding names. Entries that share an 
+    /// interned name share its allocation, so this is unique_names for a 
+    /// fully interned tree, and nodes for one that isn't interned at all.
+    pub name_allocs  : usize,
+    /// Roughly how many bytes the nodes, their names, and the directories'
+    /// entries take. The overhead of the allocator and of the maps' internal
+    /// nodes isn't counted.
+    pub approx_bytes : usize,
 }
 
 /// The impl data for a FS directory. This is wrapped in a RefCell so that
-/// we can have multiple references to the same directory.
+/// we can have multiple references to the same directory. When `dirty` is
+/// set, `size` is out of date and is recomputed the next time it's asked 
+/// for; every directory above a dirty one is dirty too. `lazy` says which 
+/// way changes below the directory are accounted for; see FSDir::new_lazy().
+/// `quotas` is the quota enforcement switch of the tree the directory is in,
+/// shared by every directory in it, or None if enforcement has never been 
+/// switched on for that tree.
 /// 
 struct FSDirImpl  { 
-    name     : String, 
-    size     : usize,
-    children : BTreeMap<String, FSDirOrFile>, 
-    parent   : Option<FSDir>,
+    name     : Rc<str>, 
+    size     : u64,
+    children : BTreeMap<Rc<str>, FSEntry>, 
+    parent   : Option<FSWeakDir>,
+    dirty    : bool,
+    lazy     : bool,
+    mtime    : Option<u64>,
+    quota    : Option<u64>,
+    quotas   : Option<Rc<Cell<bool>>>,
 }
 impl Debug for FSDirImpl {
     /// This is a custom debug impl to avoid infinite recursion.
@@ -46,11 +470,52 @@ impl Debug for FSDirImpl {
         f.debug_struct("FSDirImpl")
             .field("name", &self.name)
             .field("size", &self.size)
+            .field("dirty", &self.dirty)
+            .field("mtime", &self.mtime)
+            .field("quota", &self.quota)
+            .field("quotas", &self.quotas.as_ref().map(|on| on.get()))
             .field("children", &self.children)
             //.field("parent", "skipped..")
             .finish()
     }
 }
+impl Drop for FSDirImpl {
+    /// Frees the subdirectories with a work list rather than letting each
+    /// one's drop recurse into its own, so that dropping a very deep tree
+    /// can't overflow the stack. Subdirectories something else still holds
+    /// are left alone, but for leaving the tree's quota switch behind, and 
+    /// directories without subdirectories take the plain path.
+    /// 
+    fn drop(&mut self) {
+        let quotas = self.quotas.is_some();
+        let has_subdirs = |children: &BTreeMap<Rc<str>, FSEntry>| {
+            children.values().any(|c| matches!(c, FSEntry::Dir(_)))
+        };
+        if !has_subdirs(&self.children) {
+            return;
+        }
+        let mut stack = vec![std::mem::take(&mut self.children)];
+        while let Some(children) = stack.pop() {
+            for child in children.into_values() {
+                if let FSEntry::Dir(dir) = child {
+                    if Rc::strong_count(&dir.pimpl) > 1 {
+                        if quotas {
+                            dir.share_quotas(None);
+                        }
+                        continue;
+                    }
+                    let taken = match dir.pimpl.try_borrow_mut() {
+                        Ok(mut pimpl) if has_subdirs(&pimpl.children) => {
+                            std::mem::take(&mut pimpl.children)
+                        },
+                        _ => continue,
+                    };
+                    stack.push(taken);
+                }
+            }
+        }
+    }
+}
 
 /// A directory in the FS file system. Internally this is a RefCell so that
 /// we can have multiple references to the same directory.
@@ -62,160 +527,2664 @@ pub struct FSDir {
 impl FSDir {
     /// Create a new directory with the given name.
     pub fn new(name: String) -> Self {
+        Self::new_shared(name.into())
+    }
+    /// Like new(), but takes a shared name, such as one from a NameInterner,
+    /// which the directory then shares instead of holding its own copy.
+    /// 
+    pub fn new_shared(name: Rc<str>) -> Self {
         Self {
             pimpl: pwrap!(FSDirImpl { 
                 name, 
                 children: BTreeMap::new(), 
                 parent: None, 
-                size: 0 
+                size: 0,
+                dirty: false,
+                lazy: false,
+                mtime: None,
+                quota: None,
+                quotas: None,
             }),
         }
     }
-    /// Returns true if the directory contains a child with the given name.
-    pub fn contains(&self, name: &str) -> bool {
-        self.get_ref().children.contains_key(name)
+    /// Creates a directory that accounts for sizes lazily. Normally, adding 
+    /// or removing an entry adds or subtracts its size all the way up to the
+    /// root. Below a lazy directory, a change only marks the directories 
+    /// above it as out of date, stopping at the first one that already is, 
+    /// and size() adds their sizes up again when it's next called. That 
+    /// saves work on bulk edits, like removing thousands of directories, at
+    /// the cost of a slower first size() afterwards. New subdirectories made
+    /// by dir_entry() and make_dirs() are lazy too.
+    /// 
+    pub fn new_lazy(name: String) -> Self {
+        let dir = Self::new(name);
+        dir.get_mut().lazy = true;
+        dir
     }
-    /// Adds a directory to this directory.
-    pub fn add_dir(&self, dir: FSDir)  {
-        dir.set_parent(self.clone());
-        self.incr_size(dir.size());
-        self.get_mut().children.insert(dir.name(), FSDirOrFile::Dir(dir));
+    /// Switches this directory and every directory below it to lazy size 
+    /// accounting, as new_lazy() describes, or back to the normal kind.
+    /// 
+    pub fn set_lazy_sizes(&self, lazy: bool) {
+        self.get_mut().lazy = lazy;
+        for dir in self.iter_dirs_recurs() {
+            dir.get_mut().lazy = lazy;
+        }
     }
-    /// Adds a file to this directory.
-    pub fn add_file(&self, file: FSFile) {
-        file.set_parent(self.clone());
-        self.incr_size(file.size());
-        self.get_mut().children.insert(file.name(), FSDirOrFile::File(file));
+    /// Returns true if this directory accounts for sizes lazily.
+    pub fn is_lazy(&self) -> bool {
+        self.get_ref().lazy
     }
-    /// Returns the directory object with the given name.
-    pub fn get_dir(&self, name: &str) -> Option<FSDir> {
-        match self.get_ref().children.get(name) {
-            Some(FSDirOrFile::Dir(dir)) => Some(dir.clone()),
-            _ => None,
+    /// Recomputes the size of this directory and every directory below it 
+    /// from the sizes of their files, in one bottom-up pass, and clears 
+    /// their out of date marks. If this directory's size changes, the 
+    /// directories above it are marked out of date.
+    /// 
+    /// # Panics
+    /// If a size overflows.
+    /// 
+    pub fn recompute_sizes(&self) {
+        let before = self.get_ref().size;
+        let dirs   = self.walk_bfs().map(|(_, dir)| dir).collect::<Vec<_>>();
+        for dir in dirs.iter().rev() {
+            let total = dir.child_total(|sub| sub.get_ref().size);
+            let mut pimpl = dir.get_mut();
+            pimpl.size  = total;
+            pimpl.dirty = false;
+        }
+        if self.get_ref().size != before {
+            if let Some(parent) = self.parent() {
+                parent.mark_dirty();
+            }
         }
     }
-    /// Returns a list of all the files in this directory that match the 
-    /// predicate.
+    /// Checks that the cached size of this directory and of every directory 
+    /// below it is the total size of the files below it, and returns the 
+    /// first that isn't, in breadth-first order. Sizes that are out of date 
+    /// and waiting to be recomputed aren't checked. Nothing is changed.
     /// 
-    pub fn find_dirs_by<F>(&self, pred: &F)  -> Vec<FSDir> 
-    where 
-        F: Fn(&FSDir) -> bool
-    {
-        let mut dirs = vec![];
-        for (_, child) in self.get_ref().children.iter() {
-            if let FSDirOrFile::Dir(dir) = child {
-                if pred(dir) {
-                    dirs.push(dir.clone());
+    pub fn verify_sizes(&self) -> Result<(), SizeMismatch> {
+        let dirs   = self.walk_bfs().map(|(_, dir)| dir).collect::<Vec<_>>();
+        let mut actual = HashMap::with_capacity(dirs.len());
+        for dir in dirs.iter().rev() {
+            let total = dir.child_total(|sub| actual[&sub.id()]);
+            actual.insert(dir.id(), total);
+        }
+        for dir in &dirs {
+            let pimpl = dir.get_ref();
+            if !pimpl.dirty && pimpl.size != actual[&dir.id()] {
+                return Err(SizeMismatch { path   : dir.path(), 
+                                          cached : pimpl.size, 
+                                          actual : actual[&dir.id()] });
+            }
+        }
+        Ok(())
+    }
+    /// Returns the directory at the top of this directory's tree, following
+    /// parents up from it, or this directory if it has no parent. A subtree
+    /// that's been removed is a tree of its own, with its own top.
+    /// 
+    pub fn root(&self) -> FSDir {
+        self.ancestors().last().unwrap_or_else(|| self.clone())
+    }
+    /// Returns true if this directory has no parent, being the root of a 
+    /// tree, or a subtree that's been removed from one.
+    /// 
+    pub fn is_root(&self) -> bool {
+        self.parent().is_none()
+    }
+    /// Returns true if `self` and `other` are handles to the same directory.
+    pub fn ptr_eq(&self, other: &FSDir) -> bool {
+        Rc::ptr_eq(&self.pimpl, &other.pimpl)
+    }
+    /// Returns the deepest directory that is, or is above, both `a` and `b`,
+    /// so if one is above the other, that one. Returns None if they're in
+    /// different trees. Directories are compared by identity, not by name.
+    /// 
+    pub fn common_ancestor(a: &FSDir, b: &FSDir) -> Option<FSDir> {
+        let chain = |dir: &FSDir| {
+            let mut chain = vec![dir.clone()];
+            chain.extend(dir.ancestors());
+            chain
+        };
+        let (chain_a, chain_b) = (chain(a), chain(b));
+        chain_a.iter()
+               .rev()
+               .zip(chain_b.iter().rev())
+               .take_while(|(x, y)| x.ptr_eq(y))
+               .last()
+               .map(|(x, _)| x.clone())
+    }
+    /// Returns a number that tells this directory apart from every other one
+    /// for as long as it's alive.
+    /// 
+    pub(crate) fn id(&self) -> usize {
+        Rc::as_ptr(&self.pimpl) as usize
+    }
+    /// Returns true if `self` and `other` have the same name and size, and 
+    /// their children are structurally equal, all the way down. Parents 
+    /// aren't compared, so a subtree can be compared against a detached copy.
+    /// The comparison is iterative, so deep trees are safe.
+    /// 
+    pub fn structural_eq(&self, other: &FSDir) -> bool {
+        let mut stack = vec![(self.clone(), other.clone())];
+        while let Some((a, b)) = stack.pop() {
+            if a.ptr_eq(&b) {
+                continue;
+            }
+            let same = a.name_ref() == b.name_ref() 
+                    && a.size() == b.size() 
+                    && a.len()  == b.len();
+            if !same {
+                return false;
+            }
+            for (ca, cb) in a.children().into_iter().zip(b.children()) {
+                match (ca, cb) {
+                    (FSEntry::File(fa), FSEntry::File(fb)) => {
+                        if fa != fb {
+                            return false;
+                        }
+                    },
+                    (FSEntry::Dir(da), FSEntry::Dir(db)) => {
+                        stack.push((da, db));
+                    },
+                    (FSEntry::Link(la), FSEntry::Link(lb)) => {
+                        if la != lb {
+                            return false;
+                        }
+                    },
+                    _ => return false,
                 }
             }
         }
-        dirs
+        true
     }
-    /// Returns a list of all the files in this directory that match the
-    /// predicate. The directory structure is traversed recursively.
+    /// Returns a copy of the tree rooted at this directory, built from fresh
+    /// nodes that share nothing with the original. The copy is detached; it
+    /// has no parent. The copy is made iteratively, so deep trees are safe.
     /// 
-    pub fn find_dirs_recurs_by<F>(&self, pred: &F) -> Vec<FSDir> 
-    where
-        F: Fn(&FSDir) -> bool
-    {
-        let mut dirs = vec![];
-        for (_, child) in self.get_ref().children.iter() {
-            if let FSDirOrFile::Dir(dir) = child {
-                if pred(dir) {
-                    dirs.push(dir.clone());
+    pub fn deep_clone(&self) -> FSDir {
+        let root      = FSDir::new(self.name());
+        let mut stack = vec![(self.clone(), root.clone())];
+        while let Some((src, dst)) = stack.pop() {
+            dst.set_mtime(src.own_mtime());
+            dst.get_mut().quota = src.quota();
+            for child in src.children() {
+                match child {
+                    FSEntry::File(file) => {
+                        dst.add_file(file.deep_clone());
+                    },
+                    FSEntry::Dir(dir) => {
+                        let copy = dst.add_dir(FSDir::new(dir.name()));
+                        stack.push((dir, copy));
+                    },
+                    FSEntry::Link(link) => {
+                        dst.add_link(link.deep_clone());
+                    },
                 }
-                dirs.extend(dir.find_dirs_recurs_by(pred));
             }
         }
-        dirs
+        root
     }
-    /// Returns a mutable reference to the internal RefCell.
-    fn get_mut(&self) -> RefMut<FSDirImpl> {
-        self.pimpl.borrow_mut()
+    /// Returns true if the directory contains a child with the given name.
+    pub fn contains(&self, name: &str) -> bool {
+        self.get_ref().children.contains_key(name)
     }
-    /// Returns a reference to the internal RefCell.
-    fn get_ref(&self) -> Ref<FSDirImpl> {
-        self.pimpl.borrow()
+    /// Adds a directory to this directory and returns a handle to it. If a 
+    /// child with the same name already exists, it's replaced, and its size
+    /// is subtracted before the new directory's size is added.
+    /// 
+    /// # Panics
+    /// If the size of this directory or one of its parents would overflow,
+    /// or, if quotas are enforced, if a directory would go over its quota.
+    /// try_add_dir() returns an error instead.
+    /// 
+    pub fn add_dir(&self, dir: FSDir) -> FSDir {
+        self.enforce_quotas(&dir.name_ref(), dir.size())
+            .unwrap_or_else(|e| panic!("{}", e));
+        self.insert_entry(FSEntry::Dir(dir.clone()));
+        dir
     }
-    /// Sets the parent of this directory.
-    fn set_parent(&self, parent: FSDir) {
-        self.get_mut().parent = Some(parent);
+    /// Adds a file to this directory and returns a handle to it. If a child
+    /// with the same name already exists, it's replaced, and its size is 
+    /// subtracted before the new file's size is added.
+    /// 
+    /// # Panics
+    /// If the size of this directory or one of its parents would overflow,
+    /// or, if quotas are enforced, if a directory would go over its quota.
+    /// try_add_file() returns an error instead.
+    /// 
+    pub fn add_file(&self, file: FSFile) -> FSFile {
+        self.enforce_quotas(&file.name_ref(), file.size())
+            .unwrap_or_else(|e| panic!("{}", e));
+        self.insert_entry(FSEntry::File(file.clone()));
+        file
     }
-    /// Increments the size of this directory and all its parents.
+    /// Like add_dir(), but fails, leaving this directory as it was, if the 
+    /// size of this directory or one of its parents would overflow, or if 
+    /// quotas are enforced and a directory would go over its quota.
     /// 
-    fn incr_size(&self, size: usize) {
-        let mut pimpl = self.get_mut();
-        pimpl.size += size;
-        if let Some(parent) = pimpl.parent.clone() {
-            parent.incr_size(size);
-        }
+    pub fn try_add_dir(&self, dir: FSDir) -> Result<FSDir, GrowthError> {
+        self.check_growth(&dir.name_ref(), dir.size())?;
+        self.enforce_quotas(&dir.name_ref(), dir.size())?;
+        self.insert_entry(FSEntry::Dir(dir.clone()));
+        Ok(dir)
     }
-}
-impl FSObject for FSDir {
-    fn name(&self) -> String {
-        self.get_ref().name.clone()
+    /// Like add_file(), but fails, leaving this directory as it was, if the 
+    /// size of this directory or one of its parents would overflow, or if 
+    /// quotas are enforced and a directory would go over its quota.
+    /// 
+    pub fn try_add_file(&self, file: FSFile) -> Result<FSFile, GrowthError> {
+        self.check_growth(&file.name_ref(), file.size())?;
+        self.enforce_quotas(&file.name_ref(), file.size())?;
+        self.insert_entry(FSEntry::File(file.clone()));
+        Ok(file)
     }
-    fn size(&self) -> usize {
-        self.get_ref().size
+    /// Adds a symbolic link to this directory and returns a handle to it, 
+    /// replacing any child with the same name, as add_file() does.
+    /// 
+    /// # Panics
+    /// If the size of this directory or one of its parents would overflow,
+    /// or, if quotas are enforced, if a directory would go over its quota.
+    /// try_add_link() returns an error instead.
+    /// 
+    pub fn add_link(&self, link: FSLink) -> FSLink {
+        self.enforce_quotas(&link.name_ref(), link.size())
+            .unwrap_or_else(|e| panic!("{}", e));
+        self.insert_entry(FSEntry::Link(link.clone()));
+        link
     }
-    fn parent(&self) -> Option<FSDir> {
-        self.get_ref().parent.clone()
+    /// Like add_link(), but fails, leaving this directory as it was, if the 
+    /// size of this directory or one of its parents would overflow, or if 
+    /// quotas are enforced and a directory would go over its quota.
+    /// 
+    pub fn try_add_link(&self, link: FSLink) -> Result<FSLink, GrowthError> {
+        self.check_growth(&link.name_ref(), link.size())?;
+        self.enforce_quotas(&link.name_ref(), link.size())?;
+        self.insert_entry(FSEntry::Link(link.clone()));
+        Ok(link)
     }
-}
-
-/// The impl data for a FS file.
-/// 
-struct FSFileImpl { 
-    name: String, 
-    size: usize,
-    parent: Option<FSDir>,
-}
-impl Debug for FSFileImpl {
-    /// This is a custom debug impl to avoid infinite recursion.
+    /// Returns the child directory with the given name, creating it first if
+    /// it doesn't exist.
     /// 
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("FSFileImpl")
-            .field("name", &self.name)
-            .field("size", &self.size)
-            //.field("parent", "skipped..")
-            .finish()
+    /// # Panics
+    /// If this directory has a file with the given name.
+    /// 
+    pub fn dir_entry(&self, name: &str) -> FSDir {
+        if let Some(dir) = self.get_dir(name) {
+            dir
+        } else if self.contains(name) {
+            panic!("'{}' is a file, not a directory", name);
+        } else {
+            let dir = FSDir::new(name.into());
+            dir.get_mut().lazy = self.is_lazy();
+            self.add_dir(dir)
+        }
     }
-}
-
-/// A file in the FS file system.
-/// 
-#[derive(Debug, Clone)]
-pub struct FSFile {
-    pimpl: Rc<RefCell<FSFileImpl>>,
-}
-impl FSFile {
-    /// Creates a new file.
-    pub fn new(name: String, size: usize) -> Self {
-        Self {
-            pimpl: pwrap!(FSFileImpl { name, size, parent: None }),
+    /// Removes the child with the given name from this directory and returns
+    /// it. The child's size is subtracted from this directory and all its 
+    /// parents, and the child is detached so it can be used as a standalone
+    /// subtree. Returns None if there is no such child.
+    /// 
+    pub fn remove(&self, name: &str) -> Option<FSEntry> {
+        let entry = self.get_mut().children.remove(name)?;
+        match &entry {
+            FSEntry::File(file) => file.clear_parent(),
+            FSEntry::Dir(dir)   => {
+                dir.clear_parent();
+                dir.share_quotas(None);
+            },
+            FSEntry::Link(link) => link.clear_parent(),
         }
+        self.decr_size(entry.size());
+        Some(entry)
     }
-    /// Returns a mutable reference to the internal RefCell.
-    fn get_mut(&self) -> RefMut<FSFileImpl> {
-        self.pimpl.borrow_mut()
+    /// Moves the child with the given name from this directory into `dest`.
+    /// Sizes are decremented up this directory's ancestor chain and 
+    /// incremented up `dest`'s. Moving a directory into itself or one of its
+    /// own descendants is rejected, as is, when quotas are enforced, a move
+    /// that takes a directory above `dest` over its quota. Directories above
+    /// both this one and `dest` don't change size, so their quotas can't be.
+    /// 
+    pub fn move_child(&self, name: &str, dest: &FSDir) 
+        -> Result<(), MoveError> 
+    {
+        let entry = self.get(name)
+                        .ok_or_else(|| MoveError::NotFound(name.into()))?;
+        if self.ptr_eq(dest) {
+            return Ok(());
+        }
+        if dest.contains(name) {
+            return Err(MoveError::AlreadyExists(name.into()));
+        }
+        if let FSEntry::Dir(dir) = &entry {
+            let mut cur = Some(dest.clone());
+            while let Some(d) = cur {
+                if d.ptr_eq(dir) {
+                    return Err(MoveError::IntoDescendant(name.into()));
+                }
+                cur = d.parent();
+            }
+        }
+        self.remove(name);
+        if dest.enforces_quotas() {
+            if let Err(e) = dest.check_quotas(name, entry.size()) {
+                self.insert_entry(entry);
+                return Err(MoveError::QuotaExceeded(e));
+            }
+        }
+        dest.insert_entry(entry);
+        Ok(())
     }
-    /// Returns a reference to the internal RefCell.
-    fn get_ref(&self) -> Ref<FSFileImpl> {
-        self.pimpl.borrow()
+    /// Merges the tree rooted at `other` into this directory. Directories at
+    /// the same path are merged, and files at the same path with the same 
+    /// size are kept once. Entries only `other` has are copied in, so the two
+    /// trees stay independent. The names of the two roots aren't compared.
+    /// 
+    /// Fails if a path is a different kind of entry in each tree, a file in
+    /// both with different sizes, or a link in both with different targets.
+    /// The trees are checked before anything is changed, so on failure this
+    /// directory is left as it was.
+    /// 
+    pub fn merge(&self, other: &FSDir) -> Result<(), MergeError> {
+        if self.ptr_eq(other) {
+            return Ok(());
+        }
+        let mut stack = vec![(self.clone(), other.clone())];
+        while let Some((ours, theirs)) = stack.pop() {
+            for child in theirs.children() {
+                let path = || join_path(&ours.path(), &child.name_ref());
+                match (ours.get(&child.name_ref()), &child) {
+                    (None, _) => {},
+                    (Some(FSEntry::Dir(d)), FSEntry::Dir(t)) => {
+                        stack.push((d, t.clone()));
+                    },
+                    (Some(FSEntry::File(f)), FSEntry::File(t)) => {
+                        if f.size() != t.size() {
+                            return Err(MergeError::SizeConflict {
+                                path: path(), ours: f.size(), theirs: t.size(),
+                            });
+                        }
+                    },
+                    (Some(FSEntry::Link(l)), FSEntry::Link(t)) => {
+                        if l.target() != t.target() {
+                            return Err(MergeError::LinkTargetConflict {
+                                path   : path(), 
+                                ours   : l.target(), 
+                                theirs : t.target(),
+                            });
+                        }
+                    },
+                    _ => return Err(MergeError::KindConflict(path())),
+                }
+            }
+        }
+        let mut stack = vec![(self.clone(), other.clone())];
+        while let Some((ours, theirs)) = stack.pop() {
+            for child in theirs.children() {
+                match (ours.get(&child.name_ref()), child) {
+                    (Some(FSEntry::Dir(d)), FSEntry::Dir(t)) => {
+                        stack.push((d, t));
+                    },
+                    (Some(_), _) => {},
+                    (None, FSEntry::Dir(t)) => {
+                        ours.add_dir(t.deep_clone());
+                    },
+                    (None, FSEntry::File(t)) => {
+                        ours.add_file(t.deep_clone());
+                    },
+                    (None, FSEntry::Link(t)) => {
+                        ours.add_link(t.deep_clone());
+                    },
+                }
+            }
+        }
+        Ok(())
+    }
+    /// Returns when this directory was last modified, in seconds since the 
+    /// Unix epoch: the time set with set_mtime() if there is one, and 
+    /// otherwise the latest of its children's, worked out the same way for
+    /// the directories among them. None if nothing below it has a time.
+    /// 
+    pub fn mtime(&self) -> Option<u64> {
+        let mut latest = None;
+        let mut stack  = vec![self.clone()];
+        while let Some(dir) = stack.pop() {
+            if let Some(mtime) = dir.own_mtime() {
+                latest = latest.max(Some(mtime));
+                continue;
+            }
+            for child in dir.get_ref().children.values() {
+                match child {
+                    FSEntry::File(file) => latest = latest.max(file.mtime()),
+                    FSEntry::Dir(sub)   => stack.push(sub.clone()),
+                    FSEntry::Link(_)    => {},
+                }
+            }
+        }
+        latest
+    }
+    /// Returns the time set on this directory with set_mtime(), if any, 
+    /// without looking at its children.
+    /// 
+    pub fn own_mtime(&self) -> Option<u64> {
+        self.get_ref().mtime
+    }
+    /// Sets when this directory was last modified, in seconds since the Unix
+    /// epoch. With None, mtime() goes back to taking the latest of its 
+    /// children's.
+    /// 
+    pub fn set_mtime(&self, mtime: Option<u64>) {
+        self.get_mut().mtime = mtime;
+    }
+    /// Limits how many bytes this directory and everything below it may 
+    /// hold. The limit only stops anything when quotas are enforced, see 
+    /// set_enforce_quotas(); either way it's checked by check_quotas() and 
+    /// reported by analysis::quota_report(). A directory already over its 
+    /// new quota stays as it is.
+    /// 
+    pub fn set_quota(&self, bytes: u64) {
+        self.get_mut().quota = Some(bytes);
+    }
+    /// Removes this directory's quota, if it has one.
+    pub fn clear_quota(&self) {
+        self.get_mut().quota = None;
+    }
+    /// Returns this directory's quota, if it has one.
+    pub fn quota(&self) -> Option<u64> {
+        self.get_ref().quota
+    }
+    /// Turns quota enforcement on or off for the whole tree this directory 
+    /// is in. Every directory in the tree shares one switch, so a subtree 
+    /// added elsewhere follows its new tree's switch, and a subtree removed
+    /// from a tree has enforcement off. It's off by default; while it's on, 
+    /// the try_ variants of add_file(), add_dir(), add_link() and 
+    /// FSFile::set_size() fail with GrowthError::QuotaExceeded, and 
+    /// move_child() with MoveError::QuotaExceeded, rather than take a 
+    /// directory over its quota. The plain variants panic instead.
+    /// 
+    pub fn set_enforce_quotas(&self, enforce: bool) {
+        let switch = self.get_ref().quotas.clone();
+        match switch {
+            Some(switch) => switch.set(enforce),
+            None if enforce => {
+                self.root().share_quotas(Some(Rc::new(Cell::new(true))));
+            },
+            None => {},
+        }
+    }
+    /// Returns true if quotas are enforced in the tree this directory is in.
+    pub fn enforces_quotas(&self) -> bool {
+        self.get_ref().quotas.as_ref().is_some_and(|on| on.get())
+    }
+    /// Checks that replacing the child named `name`, if there is one, with
+    /// an entry of `size` bytes leaves this directory and every directory 
+    /// above it within its quota, whether or not quotas are enforced. A 
+    /// change that doesn't add bytes always passes, even under a directory 
+    /// that's already over its quota. Usage equal to a quota is within it.
+    /// 
+    pub fn check_quotas(&self, name: &str, size: u64) 
+        -> Result<(), QuotaExceeded> 
+    {
+        let replaced = self.get(name).map_or(0, |e| e.size());
+        if size <= replaced {
+            return Ok(());
+        }
+        let mut cur = Some(self.clone());
+        while let Some(dir) = cur {
+            if let Some(quota) = dir.quota() {
+                let usage = (dir.size() - replaced).saturating_add(size);
+                if usage > quota {
+                    return Err(QuotaExceeded { path    : dir.path(), 
+                                               quota, 
+                                               overage : usage - quota });
+                }
+            }
+            cur = dir.parent();
+        }
+        Ok(())
+    }
+    /// Renames this directory. If the directory has a parent, the parent's
+    /// entry for it is updated to the new name. Fails if the parent already
+    /// has a child with the new name.
+    /// 
+    pub fn rename(&self, new_name: &str) -> Result<(), RenameError> {
+        let old_name = self.name_ref();
+        if &*old_name == new_name {
+            return Ok(());
+        }
+        let new_name = Rc::<str>::from(new_name);
+        if let Some(parent) = self.parent() {
+            parent.rekey_child(&old_name, &new_name)?;
+        }
+        self.get_mut().name = new_name;
+        Ok(())
+    }
+    /// Returns the directory object with the given name.
+    pub fn get_dir(&self, name: &str) -> Option<FSDir> {
+        match self.get_ref().children.get(name) {
+            Some(FSEntry::Dir(dir)) => Some(dir.clone()),
+            _ => None,
+        }
+    }
+    /// Returns the file object with the given name. Returns None if there is
+    /// no such child, or if the child is a directory.
+    /// 
+    pub fn get_file(&self, name: &str) -> Option<FSFile> {
+        match self.get_ref().children.get(name) {
+            Some(FSEntry::File(file)) => Some(file.clone()),
+            _ => None,
+        }
+    }
+    /// Returns the symbolic link with the given name. Returns None if there
+    /// is no such child, or if the child isn't a link.
+    /// 
+    pub fn get_link(&self, name: &str) -> Option<FSLink> {
+        match self.get_ref().children.get(name) {
+            Some(FSEntry::Link(link)) => Some(link.clone()),
+            _ => None,
+        }
+    }
+    /// Returns the child, file or directory, with the given name.
+    pub fn get(&self, name: &str) -> Option<FSEntry> {
+        self.get_ref().children.get(name).cloned()
+    }
+    /// Like get(), for when the child is known to be there. This stands in
+    /// for an Index impl, which can't hand out a reference into the
+    /// directory, as the child could be removed while it's held.
+    /// 
+    /// # Panics
+    /// If there's no child named `name`, with the name and this directory's
+    /// path in the message.
+    /// 
+    pub fn child(&self, name: &str) -> FSEntry {
+        self.get(name).unwrap_or_else(|| {
+            panic!("no entry named '{}' in '{}'", name, self.path())
+        })
+    }
+    /// Returns true if this directory has no children.
+    pub fn is_empty(&self) -> bool {
+        self.get_ref().children.is_empty()
+    }
+    /// Returns the number of direct children of this directory.
+    pub fn len(&self) -> usize {
+        self.get_ref().children.len()
+    }
+    /// Returns the number of files directly in this directory.
+    pub fn num_files(&self) -> usize {
+        self.get_ref().children.values()
+            .filter(|c| matches!(c, FSEntry::File(_)))
+            .count()
+    }
+    /// Returns the number of directories directly in this directory.
+    pub fn num_subdirs(&self) -> usize {
+        self.get_ref().children.values()
+            .filter(|c| matches!(c, FSEntry::Dir(_)))
+            .count()
+    }
+    /// Returns the children of this directory in name order.
+    pub fn children(&self) -> Vec<FSEntry> {
+        self.get_ref().children.values().cloned().collect()
+    }
+    /// Returns the children of this directory ordered by `key`, in the 
+    /// direction `dir`. Entries of the same size are always in name order, 
+    /// whichever the direction, so the order is fully determined.
+    /// 
+    pub fn children_sorted(&self, key: SortKey, dir: SortDir) -> Vec<FSEntry> {
+        let mut buf = Vec::with_capacity(self.len());
+        self.children_sorted_into(&mut buf, key, dir);
+        buf
+    }
+    /// Like children_sorted(), but fills `buf`, which is cleared first, so
+    /// that one buffer can serve many directories without reallocating.
+    /// 
+    pub fn children_sorted_into(&self, 
+                                buf : &mut Vec<FSEntry>, 
+                                key : SortKey, 
+                                dir : SortDir) 
+    {
+        buf.clear();
+        buf.extend(self.get_ref().children.values().cloned());
+        // The children come in name order, and the sorts are stable, so 
+        // entries of the same size stay in name order.
+        match (key, dir) {
+            (SortKey::Name, SortDir::Ascending)  => {},
+            (SortKey::Name, SortDir::Descending) => buf.reverse(),
+            (SortKey::Size, SortDir::Ascending)  => {
+                buf.sort_by_key(|c| c.size());
+            },
+            (SortKey::Size, SortDir::Descending) => {
+                buf.sort_by_key(|c| std::cmp::Reverse(c.size()));
+            },
+        }
+    }
+    /// Looks up the entry at the given path relative to this directory. A 
+    /// leading '/' resolves from this directory as the root, "." and ".." 
+    /// are honored (".." at this directory stays put), and repeated slashes
+    /// are collapsed. Returns None if any component is missing, or if an 
+    /// intermediate component is a file. Symbolic links aren't followed, so
+    /// one can only be the last component; see resolve_link().
+    /// 
+    pub fn find_by_path(&self, path: &str) -> Option<FSEntry> {
+        self.find_by_path_from(self, path)
+    }
+    /// Like find_by_path(), but a path not starting with '/' resolves from 
+    /// `cwd`, a directory at or below this one. ".." still stays put at this
+    /// directory, not at `cwd`.
+    /// 
+    pub(crate) fn find_by_path_from(&self, cwd: &FSDir, path: &str) 
+        -> Option<FSEntry> 
+    {
+        let start     = if path.starts_with('/') { self } else { cwd };
+        let mut entry = FSEntry::Dir(start.clone());
+        for part in path.split('/') {
+            let dir = match &entry {
+                FSEntry::Dir(dir) => dir.clone(),
+                _                 => return None,
+            };
+            entry = match part {
+                "" | "." => continue,
+                ".." if dir.ptr_eq(self) => FSEntry::Dir(dir),
+                ".."     => FSEntry::Dir(dir.parent().unwrap_or(dir)),
+                name     => dir.get(name)?,
+            };
+        }
+        Some(entry)
+    }
+    /// Looks up the entry `link` points to, within the tree rooted at this 
+    /// directory. A target starting with '/' resolves from this directory,
+    /// as find_by_path() does, and any other from the directory holding the
+    /// link, and ".." at this directory stays put. Links met on the way, 
+    /// including at the end, are followed in turn, up to MAX_LINK_HOPS in 
+    /// all, so the result is never a link. Returns None if the target 
+    /// doesn't exist in the tree, runs through a file, or takes too many 
+    /// hops, as a loop of links does.
+    /// 
+    pub fn resolve_link(&self, link: &FSLink) -> Option<FSEntry> {
+        let mut dir   = self.clone();
+        let mut parts = vec![];
+        let mut hops  = 0;
+        let mut next  = Some(link.clone());
+        while let Some(link) = next.take() {
+            hops += 1;
+            if hops > MAX_LINK_HOPS {
+                return None;
+            }
+            let target = link.target();
+            if target.starts_with('/') {
+                dir = self.clone();
+            } else if let Some(parent) = link.parent() {
+                dir = parent;
+            }
+            // Parts are popped from the end, so the target goes on reversed.
+            parts.extend(target.split('/')
+                               .filter(|p| !p.is_empty() && *p != ".")
+                               .map(String::from)
+                               .rev());
+            while let Some(part) = parts.pop() {
+                if part == ".." {
+                    if !dir.ptr_eq(self) {
+                        dir = dir.parent().unwrap_or(dir);
+                    }
+                    continue;
+                }
+                match dir.get(&part)? {
+                    FSEntry::Dir(sub)   => dir = sub,
+                    FSEntry::File(file) => {
+                        return parts.is_empty().then_some(file.into());
+                    },
+                    FSEntry::Link(link) => {
+                        next = Some(link);
+                        break;
+                    },
+                }
+            }
+        }
+        Some(FSEntry::Dir(dir))
+    }
+    /// Returns every entry below this directory, files and directories alike,
+    /// whose name contains `needle`, matching case exactly, with its full
+    /// path. The results are sorted by path.
+    /// 
+    pub fn find_by_name(&self, needle: &str) -> Vec<(String, FSEntry)> {
+        let mut found = self.walk()
+                            .filter(|(_, e)| e.name_ref().contains(needle))
+                            .collect::<Vec<_>>();
+        found.sort_by(|a, b| a.0.cmp(&b.0));
+        found
+    }
+    /// Returns every entry below this directory, files and directories alike,
+    /// whose name `regex` matches anywhere, with its full path. The results
+    /// are sorted by path. Like find_by_name(), but with a pattern; it's 
+    /// built with the `regex` feature.
+    /// 
+    #[cfg(feature = "regex")]
+    pub fn find_by_regex(&self, regex: &regex::Regex) 
+        -> Vec<(String, FSEntry)> 
+    {
+        let mut found = self.walk()
+                            .filter(|(_, e)| regex.is_match(&e.name_ref()))
+                            .collect::<Vec<_>>();
+        found.sort_by(|a, b| a.0.cmp(&b.0));
+        found
+    }
+    /// Creates any missing directories along the given path, relative to this
+    /// directory, and returns the last one, like `mkdir -p`. Existing 
+    /// directories along the way are reused. As with find_by_path(), ".." at
+    /// this directory stays put, so nothing is made outside it.
+    /// 
+    /// # Panics
+    /// If a component of the path names an existing file.
+    /// 
+    pub fn make_dirs(&self, path: &str) -> FSDir {
+        let mut dir = self.clone();
+        for part in path.split('/') {
+            dir = match part {
+                "" | "." => continue,
+                ".." if dir.ptr_eq(self) => continue,
+                ".."     => dir.parent().unwrap_or(dir),
+                name     => dir.dir_entry(name),
+            };
+        }
+        dir
+    }
+    /// Adds a file of the given size at the given path, relative to this
+    /// directory, creating any missing parent directories first. Returns the
+    /// new file.
+    /// 
+    /// # Panics
+    /// If a parent component of the path names an existing file.
+    /// 
+    pub fn add_file_at_path(&self, path: &str, size: u64) -> FSFile {
+        let (dir_path, name) = path.rsplit_once('/').unwrap_or(("", path));
+        self.make_dirs(dir_path).add_file(FSFile::new(name.into(), size))
+    }
+    /// Returns a lazy iterator over every descendant of this directory, files
+    /// and directories alike, paired with its full path. The traversal is 
+    /// depth-first pre-order with children visited in name order.
+    /// 
+    pub fn walk(&self) -> FSWalk {
+        FSWalk::new(self, None)
+    }
+    /// Like walk(), but entries deeper than `max_depth` below this directory
+    /// are skipped, without being visited. This directory is at depth 0 and
+    /// its children at depth 1, so a `max_depth` of 0 yields nothing, and 1
+    /// yields the children alone. Depths count from this directory, not the
+    /// root of its tree.
+    /// 
+    pub fn walk_to_depth(&self, max_depth: usize) -> FSWalk {
+        FSWalk::new(self, Some(max_depth))
+    }
+    /// Returns a lazy iterator over this directory and all its subdirectories,
+    /// level by level, paired with their depth. This directory is yielded 
+    /// first at depth 0, and each level is visited in name order.
+    /// 
+    pub fn walk_bfs(&self) -> FSWalkBfs {
+        FSWalkBfs::new(self)
+    }
+    /// Returns the number of files below this directory, at any depth.
+    pub fn file_count(&self) -> usize {
+        self.walk().filter(|(_, e)| matches!(e, FSEntry::File(_))).count()
+    }
+    /// Returns the number of directories below this directory, at any depth.
+    /// This directory itself isn't counted.
+    /// 
+    pub fn dir_count(&self) -> usize {
+        self.walk().filter(|(_, e)| matches!(e, FSEntry::Dir(_))).count()
+    }
+    /// Returns the file and directory counts, total size, and maximum depth
+    /// of the tree rooted at this directory.
+    /// 
+    pub fn stats(&self) -> FSStats {
+        let mut stats = FSStats { size: self.size(), ..Default::default() };
+        for (depth, dir) in self.walk_bfs() {
+            for child in dir.children() {
+                match child {
+                    FSEntry::File(_) => stats.files += 1,
+                    FSEntry::Dir(_)  => stats.dirs  += 1,
+                    FSEntry::Link(_) => stats.links += 1,
+                }
+                stats.max_depth = stats.max_depth.max(depth + 1);
+            }
+        }
+        stats
+    }
+    /// Returns an estimate of the memory the tree rooted at this directory
+    /// uses, along with how many of its names are shared.
+    /// 
+    pub fn memory_stats(&self) -> MemoryStats {
+        let rc_box    = |value: usize| 2 * size_of::<usize>() + value;
+        let mut allocs = HashSet::new();
+        let mut unique = HashSet::new();
+        let mut stats  = MemoryStats::default();
+        let mut count  = |stats: &mut MemoryStats, name: Rc<str>, node| {
+            stats.nodes        += 1;
+            stats.approx_bytes += node;
+            if allocs.insert(Rc::as_ptr(&name) as *const u8 as usize) {
+                stats.approx_bytes += rc_box(name.len());
+            }
+            unique.insert(name);
+        };
+        let dir_node  = rc_box(size_of::<RefCell<FSDirImpl>>());
+        let file_node = rc_box(size_of::<RefCell<FSFileImpl>>());
+        let link_node = rc_box(size_of::<RefCell<FSLinkImpl>>());
+        let entry     = size_of::<Rc<str>>() + size_of::<FSEntry>();
+        count(&mut stats, self.name_ref(), dir_node);
+        for (_, dir) in self.walk_bfs() {
+            for (name, child) in dir.get_ref().children.iter() {
+                let node = match child {
+                    FSEntry::Dir(_)  => dir_node,
+                    FSEntry::File(_) => file_node,
+                    FSEntry::Link(_) => link_node,
+                };
+                count(&mut stats, name.clone(), node + entry);
+            }
+        }
+        stats.name_allocs  = allocs.len();
+        stats.unique_names = unique.len();
+        stats
+    }
+    /// Returns this directory's share of its parent's size, from 0.0 to 1.0.
+    /// Returns None for a directory without a parent, or when the parent's
+    /// size is 0.
+    /// 
+    pub fn fraction_of_parent(&self) -> Option<f64> {
+        let parent = self.parent()?;
+        fraction(self.size(), parent.size())
+    }
+    /// Returns this directory's share of the size of the root of its tree,
+    /// found by walking up its parents. The root itself is 1.0, and every
+    /// directory of a tree whose size is 0 is 0.0.
+    /// 
+    pub fn fraction_of_root(&self) -> f64 {
+        fraction(self.size(), self.root().size()).unwrap_or(0.0)
+    }
+    /// Returns a lazy iterator over all the directories below this one, in 
+    /// the same order as find_dirs_recurs_by(). This directory itself isn't
+    /// included.
+    /// 
+    pub fn iter_dirs_recurs(&self) -> FSDirIter {
+        FSDirIter::new(self)
+    }
+    /// Returns the first directory below this one that matches the predicate,
+    /// stopping the search as soon as it's found.
+    /// 
+    pub fn find_first_dir_by<F>(&self, mut pred: F) -> Option<FSDir>
+    where
+        F: FnMut(&FSDir) -> bool
+    {
+        self.iter_dirs_recurs().find(|d| pred(d))
+    }
+    /// Returns true if any directory below this one matches the predicate.
+    pub fn any_dir_matches<F>(&self, mut pred: F) -> bool
+    where
+        F: FnMut(&FSDir) -> bool
+    {
+        self.iter_dirs_recurs().any(|d| pred(&d))
+    }
+    /// Returns the number of directories below this one that match the 
+    /// predicate.
+    /// 
+    pub fn count_dirs_by<F>(&self, mut pred: F) -> usize
+    where
+        F: FnMut(&FSDir) -> bool
+    {
+        self.iter_dirs_recurs().filter(|d| pred(d)).count()
+    }
+    /// Returns a list of all the files in this directory that match the 
+    /// predicate.
+    /// 
+    pub fn find_dirs_by<F>(&self, mut pred: F)  -> Vec<FSDir> 
+    where 
+        F: FnMut(&FSDir) -> bool
+    {
+        let mut dirs = vec![];
+        for (_, child) in self.get_ref().children.iter() {
+            if let FSEntry::Dir(dir) = child {
+                if pred(dir) {
+                    dirs.push(dir.clone());
+                }
+            }
+        }
+        dirs
+    }
+    /// Returns a list of all the directories below this one that match the
+    /// predicate. The directory structure is traversed recursively. This 
+    /// directory itself is never considered, even if it matches; use 
+    /// find_dirs_recurs_incl_self_by() for that.
+    /// 
+    pub fn find_dirs_recurs_by<F>(&self, mut pred: F) -> Vec<FSDir> 
+    where
+        F: FnMut(&FSDir) -> bool
+    {
+        self.iter_dirs_recurs().filter(|d| pred(d)).collect()
+    }
+    /// Like find_dirs_recurs_by(), but directories deeper than `max_depth`
+    /// below this one are neither tried nor descended into. Depths count as
+    /// they do for walk_to_depth(): a `max_depth` of 0 finds nothing, and 1
+    /// tries the directories directly in this one.
+    /// 
+    pub fn find_dirs_to_depth_by<F>(&self, max_depth: usize, mut pred: F)
+        -> Vec<FSDir>
+    where
+        F: FnMut(&FSDir) -> bool
+    {
+        let mut dirs  = vec![];
+        let mut stack = vec![(self.clone(), 0)];
+        while let Some((dir, depth)) = stack.pop() {
+            if depth > 0 && pred(&dir) {
+                dirs.push(dir.clone());
+            }
+            if depth < max_depth {
+                let subs = dir.find_dirs_by(|_| true);
+                stack.extend(subs.into_iter().rev().map(|d| (d, depth + 1)));
+            }
+        }
+        dirs
+    }
+    /// Like find_dirs_recurs_by(), but the predicate is tried on this 
+    /// directory first, and it's included in the results if it matches.
+    /// 
+    pub fn find_dirs_recurs_incl_self_by<F>(&self, mut pred: F) -> Vec<FSDir>
+    where
+        F: FnMut(&FSDir) -> bool
+    {
+        let mut dirs = vec![];
+        if pred(self) {
+            dirs.push(self.clone());
+        }
+        dirs.extend(self.find_dirs_recurs_by(pred));
+        dirs
+    }
+    /// Like find_dirs_recurs_by(), but each directory is paired with its 
+    /// path, which is built up during the traversal rather than by walking
+    /// back up the parent chain.
+    /// 
+    pub fn find_dirs_with_paths_by<F>(&self, mut pred: F) 
+        -> Vec<(String, FSDir)> 
+    where
+        F: FnMut(&FSDir) -> bool
+    {
+        self.walk()
+            .filter_map(|(path, entry)| match entry {
+                FSEntry::Dir(dir) if pred(&dir) => Some((path, dir)),
+                _ => None,
+            })
+            .collect()
+    }
+    /// Returns a list of all the files in this directory that match the
+    /// predicate.
+    /// 
+    pub fn find_files_by<F>(&self, mut pred: F) -> Vec<FSFile>
+    where
+        F: FnMut(&FSFile) -> bool
+    {
+        let mut files = vec![];
+        for (_, child) in self.get_ref().children.iter() {
+            if let FSEntry::File(file) = child {
+                if pred(file) {
+                    files.push(file.clone());
+                }
+            }
+        }
+        files
+    }
+    /// Returns a list of all the files in this directory that match the
+    /// predicate. The directory structure is traversed depth first, in the
+    /// same order as find_dirs_recurs_by(), over an explicit stack so that
+    /// deep trees don't overflow the call stack.
+    /// 
+    pub fn find_files_recurs_by<F>(&self, mut pred: F) -> Vec<FSFile>
+    where
+        F: FnMut(&FSFile) -> bool
+    {
+        let mut files = vec![];
+        let mut stack = self.children();
+        stack.reverse();
+        while let Some(child) = stack.pop() {
+            match child {
+                FSEntry::File(file) => {
+                    if pred(&file) {
+                        files.push(file);
+                    }
+                },
+                FSEntry::Dir(dir)   => {
+                    stack.extend(dir.children().into_iter().rev());
+                },
+                FSEntry::Link(_)    => {},
+            }
+        }
+        files
+    }
+    /// Returns the `n` largest directories below this one, largest first, 
+    /// with ties broken by path. This directory itself isn't considered.
+    /// 
+    pub fn largest_dirs(&self, n: usize) -> Vec<FSDir> {
+        top_n(n, self.walk().filter_map(|(path, entry)| match entry {
+            FSEntry::Dir(dir) => Some((path, dir)),
+            _                 => None,
+        }))
+    }
+    /// Returns the `n` largest files below this directory, largest first,
+    /// with ties broken by path.
+    /// 
+    pub fn largest_files(&self, n: usize) -> Vec<FSFile> {
+        top_n(n, self.walk().filter_map(|(path, entry)| match entry {
+            FSEntry::File(file) => Some((path, file)),
+            _                   => None,
+        }))
+    }
+    /// Returns all the directories below this one that have no children.
+    pub fn find_empty_dirs_recurs(&self) -> Vec<FSDir> {
+        self.find_dirs_recurs_by(|d| d.is_empty())
+    }
+    /// Removes all empty directories below this one, bottom-up, so that a 
+    /// directory left empty by the removal of its own empty children is also
+    /// removed. This directory itself is never removed. Returns the number of
+    /// directories removed.
+    /// 
+    pub fn prune_empty(&self) -> usize {
+        let mut removed = 0;
+        for dir in self.find_dirs_by(|_| true) {
+            removed += dir.prune_empty();
+            if dir.is_empty() {
+                self.remove(&dir.name_ref());
+                removed += 1;
+            }
+        }
+        removed
+    }
+    /// Returns a mutable reference to the internal RefCell.
+    fn get_mut(&self) -> RefMut<'_, FSDirImpl> {
+        self.pimpl.borrow_mut()
+    }
+    /// Returns a reference to the internal RefCell.
+    fn get_ref(&self) -> Ref<'_, FSDirImpl> {
+        self.pimpl.borrow()
+    }
+    /// Returns a weak handle to this directory.
+    fn downgrade(&self) -> FSWeakDir {
+        FSWeakDir { pimpl: Rc::downgrade(&self.pimpl) }
+    }
+    /// Sets the parent of this directory.
+    fn set_parent(&self, parent: FSDir) {
+        self.get_mut().parent = Some(parent.downgrade());
+    }
+    /// Moves the child stored under `old_name` so it is stored under 
+    /// `new_name`. Fails if `new_name` is already taken.
+    /// 
+    fn rekey_child(&self, old_name: &str, new_name: &Rc<str>) 
+        -> Result<(), RenameError> 
+    {
+        let mut pimpl = self.get_mut();
+        if pimpl.children.contains_key(new_name) {
+            return Err(RenameError::AlreadyExists(new_name.to_string()));
+        }
+        if let Some(child) = pimpl.children.remove(old_name) {
+            pimpl.children.insert(new_name.clone(), child);
+        }
+        Ok(())
+    }
+    /// Detaches this directory from its parent.
+    fn clear_parent(&self) {
+        self.get_mut().parent = None;
+    }
+    /// Hands `quotas`, the quota enforcement switch of the tree this 
+    /// directory is now in, to it and every directory below it, unless 
+    /// they have it already.
+    /// 
+    fn share_quotas(&self, quotas: Option<Rc<Cell<bool>>>) {
+        let same = match (&self.get_ref().quotas, &quotas) {
+            (Some(ours), Some(theirs)) => Rc::ptr_eq(ours, theirs),
+            (ours, theirs)             => ours.is_none() && theirs.is_none(),
+        };
+        if same {
+            return;
+        }
+        self.get_mut().quotas = quotas.clone();
+        for dir in self.iter_dirs_recurs() {
+            dir.get_mut().quotas = quotas.clone();
+        }
+    }
+    /// Adds `entry` to this directory, replacing any child with the same 
+    /// name, and adds its size to this directory and its parents. Quotas 
+    /// aren't checked.
+    /// 
+    pub(crate) fn insert_entry(&self, entry: FSEntry) {
+        let name = entry.name_ref();
+        self.remove(&name);
+        match &entry {
+            FSEntry::File(file) => file.set_parent(self.clone()),
+            FSEntry::Dir(dir)   => {
+                dir.set_parent(self.clone());
+                dir.share_quotas(self.get_ref().quotas.clone());
+            },
+            FSEntry::Link(link) => link.set_parent(self.clone()),
+        }
+        self.incr_size(entry.size());
+        self.get_mut().children.insert(name, entry);
+    }
+    /// Like check_quotas(), but only if quotas are enforced.
+    /// 
+    fn enforce_quotas(&self, name: &str, size: u64) 
+        -> Result<(), QuotaExceeded> 
+    {
+        match self.enforces_quotas() {
+            true  => self.check_quotas(name, size),
+            false => Ok(()),
+        }
+    }
+    /// Checks that replacing the child named `name`, if there is one, with an
+    /// entry of size `size` won't make the size of this directory or one of
+    /// its parents overflow.
+    /// 
+    fn check_growth(&self, name: &str, size: u64) 
+        -> Result<(), SizeOverflow> 
+    {
+        let replaced = self.get(name).map_or(0, |entry| entry.size());
+        let mut cur  = Some(self.clone());
+        while let Some(dir) = cur {
+            if (dir.size() - replaced).checked_add(size).is_none() {
+                return Err(SizeOverflow { path: dir.path(), size });
+            }
+            cur = dir.parent();
+        }
+        Ok(())
+    }
+    /// Increments the size of this directory and all its parents. The 
+    /// parents are walked in a loop, so deep trees can't overflow the stack.
+    /// The walk stops at the first directory that's out of date, and at the
+    /// first lazy one, which is marked out of date instead.
+    /// 
+    /// # Panics
+    /// If a size overflows.
+    /// 
+    fn incr_size(&self, size: u64) {
+        let mut cur = Some(self.clone()).filter(|_| size > 0);
+        while let Some(dir) = cur {
+            let mut pimpl = dir.get_mut();
+            if pimpl.dirty || pimpl.lazy {
+                drop(pimpl);
+                dir.mark_dirty();
+                break;
+            }
+            pimpl.size = pimpl.size.checked_add(size).unwrap_or_else(|| {
+                panic!("adding {} bytes overflows the size of '{}'", 
+                       size, pimpl.name)
+            });
+            cur = pimpl.parent.as_ref().and_then(|p| p.upgrade());
+        }
+    }
+    /// Decrements the size of this directory and all its parents, stopping
+    /// as incr_size() does.
+    /// 
+    fn decr_size(&self, size: u64) {
+        let mut cur = Some(self.clone()).filter(|_| size > 0);
+        while let Some(dir) = cur {
+            let mut pimpl = dir.get_mut();
+            if pimpl.dirty || pimpl.lazy {
+                drop(pimpl);
+                dir.mark_dirty();
+                break;
+            }
+            pimpl.size -= size;
+            cur = pimpl.parent.as_ref().and_then(|p| p.upgrade());
+        }
+    }
+    /// Marks this directory and the directories above it as out of date, 
+    /// stopping at the first that already is, since those above it are too.
+    /// 
+    fn mark_dirty(&self) {
+        let mut cur = Some(self.clone());
+        while let Some(dir) = cur {
+            let mut pimpl = dir.get_mut();
+            if pimpl.dirty {
+                break;
+            }
+            pimpl.dirty = true;
+            cur = pimpl.parent.as_ref().and_then(|p| p.upgrade());
+        }
+    }
+    /// Recomputes the sizes of this directory and the out of date 
+    /// directories below it, children before parents, and clears their 
+    /// marks. Directories that aren't out of date have nothing out of date 
+    /// below them, so they're left alone.
+    /// 
+    /// # Panics
+    /// If a size overflows.
+    /// 
+    fn refresh_size(&self) {
+        let mut stack = vec![(self.clone(), false)];
+        while let Some((dir, ready)) = stack.pop() {
+            if ready {
+                let total = dir.child_total(|sub| sub.get_ref().size);
+                let mut pimpl = dir.get_mut();
+                pimpl.size  = total;
+                pimpl.dirty = false;
+                continue;
+            }
+            stack.push((dir.clone(), true));
+            for child in dir.get_ref().children.values() {
+                if let FSEntry::Dir(sub) = child {
+                    if sub.get_ref().dirty {
+                        stack.push((sub.clone(), false));
+                    }
+                }
+            }
+        }
+    }
+    /// Returns the total size of this directory's children, taking each
+    /// subdirectory's size from `dir_size`.
+    /// 
+    /// # Panics
+    /// If the total overflows.
+    /// 
+    fn child_total<F: Fn(&FSDir) -> u64>(&self, dir_size: F) -> u64 {
+        let pimpl = self.get_ref();
+        pimpl.children.values().fold(0u64, |total, child| {
+            let size = match child {
+                FSEntry::File(file) => file.size(),
+                FSEntry::Dir(sub)   => dir_size(sub),
+                FSEntry::Link(link) => link.size(),
+            };
+            total.checked_add(size).unwrap_or_else(|| {
+                panic!("adding {} bytes overflows the size of '{}'", 
+                       size, pimpl.name)
+            })
+        })
+    }
+}
+/// Directories compare equal when they're structurally equal. Use ptr_eq() to
+/// check whether two handles refer to the same directory.
+/// 
+impl PartialEq for FSDir {
+    fn eq(&self, other: &Self) -> bool {
+        self.structural_eq(other)
+    }
+}
+impl Eq for FSDir {}
+
+/// Iterating over a directory yields its children in name order, as 
+/// children() returns them.
+/// 
+impl IntoIterator for &FSDir {
+    type Item     = FSEntry;
+    type IntoIter = std::vec::IntoIter<FSEntry>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.children().into_iter()
+    }
+}
+
+impl FSObject for FSDir {
+    fn name_ref(&self) -> Rc<str> {
+        self.get_ref().name.clone()
+    }
+    fn size(&self) -> u64 {
+        if self.get_ref().dirty {
+            self.refresh_size();
+        }
+        self.get_ref().size
+    }
+    fn parent(&self) -> Option<FSDir> {
+        self.get_ref().parent.as_ref().and_then(|p| p.upgrade())
+    }
+}
+
+/// Returns `part / whole`, or None when `whole` is 0.
+/// 
+fn fraction(part: u64, whole: u64) -> Option<f64> {
+    (whole != 0).then(|| part as f64 / whole as f64)
+}
+
+/// Returns the `n` largest objects from `iter`, largest first, with ties 
+/// broken by path. Only `n` objects are held at any time, so this is cheap on
+/// large trees.
+/// 
+fn top_n<T, I>(n: usize, iter: I) -> Vec<T>
+where
+    T: FSObject,
+    I: Iterator<Item = (String, T)>,
+{
+    top_n_by(n, iter.map(|(path, obj)| (path, obj.size(), obj)))
+        .into_iter()
+        .map(|(obj, _)| obj)
+        .collect()
+}
+
+/// Like top_n(), but objects are ranked by the size paired with them rather
+/// than their own, and are returned with it.
+/// 
+pub(crate) fn top_n_by<T, I>(n: usize, iter: I) -> Vec<(T, u64)>
+where
+    I: Iterator<Item = (String, u64, T)>,
+{
+    let mut heap = BinaryHeap::with_capacity(n + 1);
+    for (path, size, obj) in iter {
+        heap.push(Ranked { size, path, obj });
+        if heap.len() > n {
+            heap.pop();
+        }
+    }
+    heap.into_sorted_vec().into_iter().map(|r| (r.obj, r.size)).collect()
+}
+
+/// An object paired with the keys it's ranked by in top_n(). The ordering is
+/// reversed so that the heap's top is the smallest object, the one to evict.
+/// 
+struct Ranked<T> {
+    size : u64,
+    path : String,
+    obj  : T,
+}
+impl<T> Ord for Ranked<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.size.cmp(&self.size).then_with(|| self.path.cmp(&other.path))
+    }
+}
+impl<T> PartialOrd for Ranked<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl<T> PartialEq for Ranked<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+impl<T> Eq for Ranked<T> {}
+
+/// A non-owning handle to a directory. Children refer to their parents 
+/// through this so that a tree doesn't form Rc cycles and is freed when the
+/// last handle to its root goes away.
+/// 
+#[derive(Clone)]
+struct FSWeakDir {
+    pimpl: Weak<RefCell<FSDirImpl>>,
+}
+impl FSWeakDir {
+    /// Returns a strong handle to the directory, if it's still alive.
+    fn upgrade(&self) -> Option<FSDir> {
+        self.pimpl.upgrade().map(|pimpl| FSDir { pimpl })
+    }
+}
+
+/// The impl data for a FS file.
+/// 
+struct FSFileImpl { 
+    name: Rc<str>, 
+    size: u64,
+    link_id: Option<u64>,
+    mtime: Option<u64>,
+    parent: Option<FSWeakDir>,
+}
+impl Debug for FSFileImpl {
+    /// This is a custom debug impl to avoid infinite recursion.
+    /// 
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FSFileImpl")
+            .field("name", &self.name)
+            .field("size", &self.size)
+            .field("link_id", &self.link_id)
+            .field("mtime", &self.mtime)
+            //.field("parent", "skipped..")
+            .finish()
+    }
+}
+
+/// A file in the FS file system.
+/// 
+#[derive(Debug, Clone)]
+pub struct FSFile {
+    pimpl: Rc<RefCell<FSFileImpl>>,
+}
+impl FSFile {
+    /// Creates a new file.
+    pub fn new(name: String, size: u64) -> Self {
+        Self::new_shared(name.into(), size)
+    }
+    /// Like new(), but takes a shared name, such as one from a NameInterner,
+    /// which the file then shares instead of holding its own copy.
+    /// 
+    pub fn new_shared(name: Rc<str>, size: u64) -> Self {
+        Self {
+            pimpl: pwrap!(FSFileImpl { name, size, link_id: None, 
+                                       mtime: None, parent: None }),
+        }
+    }
+    /// Returns true if `self` and `other` are handles to the same file.
+    pub fn ptr_eq(&self, other: &FSFile) -> bool {
+        Rc::ptr_eq(&self.pimpl, &other.pimpl)
+    }
+    /// Renames this file. If the file has a parent, the parent's entry for it
+    /// is updated to the new name. Fails if the parent already has a child
+    /// with the new name.
+    /// 
+    pub fn rename(&self, new_name: &str) -> Result<(), RenameError> {
+        let old_name = self.name_ref();
+        if &*old_name == new_name {
+            return Ok(());
+        }
+        let new_name = Rc::<str>::from(new_name);
+        if let Some(parent) = self.parent() {
+            parent.rekey_child(&old_name, &new_name)?;
+        }
+        self.get_mut().name = new_name;
+        Ok(())
+    }
+    /// Changes the size of this file to `size`, and the sizes of the 
+    /// directories above it by the difference.
+    /// 
+    /// # Panics
+    /// If the size of a directory above the file would overflow, or, if 
+    /// quotas are enforced, if a directory above the file would go over its
+    /// quota. try_set_size() returns an error instead.
+    /// 
+    pub fn set_size(&self, size: u64) {
+        self.try_set_size(size).unwrap_or_else(|e| panic!("{}", e));
+    }
+    /// Like set_size(), but fails, leaving the tree as it was, if the size of
+    /// a directory above the file would overflow, or if quotas are enforced
+    /// and a directory above the file would go over its quota.
+    /// 
+    pub fn try_set_size(&self, size: u64) -> Result<(), GrowthError> {
+        let old    = self.size();
+        let parent = self.parent();
+        if let Some(parent) = &parent {
+            parent.check_growth(&self.name_ref(), size)?;
+            parent.enforce_quotas(&self.name_ref(), size)?;
+        }
+        self.get_mut().size = size;
+        if let Some(parent) = &parent {
+            if size > old {
+                parent.incr_size(size - old);
+            } else {
+                parent.decr_size(old - size);
+            }
+        }
+        Ok(())
+    }
+    /// Adds `by` bytes to the size of this file, as set_size() would. Fails,
+    /// leaving the tree as it was, if the size of the file or of a directory
+    /// above it would overflow, or, as try_set_size() does, over an enforced
+    /// quota.
+    /// 
+    pub fn grow(&self, by: u64) -> Result<(), GrowthError> {
+        match self.size().checked_add(by) {
+            Some(size) => self.try_set_size(size),
+            None       => {
+                Err(SizeOverflow { path: self.path(), size: by }.into())
+            },
+        }
+    }
+    /// Takes `by` bytes off the size of this file, as set_size() would. 
+    /// Fails, leaving the tree as it was, if the file has fewer than `by` 
+    /// bytes.
+    /// 
+    pub fn shrink(&self, by: u64) -> Result<(), SizeUnderflow> {
+        let size = self.size();
+        match size.checked_sub(by) {
+            Some(size) => { self.set_size(size); Ok(()) },
+            None       => Err(SizeUnderflow { path: self.path(), size, by }),
+        }
+    }
+    /// Returns the identity this file shares with its other hard links, if
+    /// it's known. Files with the same id are the same data on disk, listed
+    /// under several names; FSDir::deduplicated_size() counts them once.
+    /// 
+    pub fn link_id(&self) -> Option<u64> {
+        self.get_ref().link_id
+    }
+    /// Sets the identity this file shares with its other hard links. Sizes
+    /// aren't affected; each directory entry still counts in full.
+    /// 
+    pub fn set_link_id(&self, link_id: Option<u64>) {
+        self.get_mut().link_id = link_id;
+    }
+    /// Returns when this file was last modified, in seconds since the Unix
+    /// epoch, if that's known.
+    /// 
+    pub fn mtime(&self) -> Option<u64> {
+        self.get_ref().mtime
+    }
+    /// Sets when this file was last modified, in seconds since the Unix 
+    /// epoch.
+    /// 
+    pub fn set_mtime(&self, mtime: Option<u64>) {
+        self.get_mut().mtime = mtime;
+    }
+    /// Returns a detached copy of this file, with the same name, size, link
+    /// id, and modification time, that shares nothing with it.
+    /// 
+    pub fn deep_clone(&self) -> FSFile {
+        let pimpl = self.get_ref();
+        FSFile { 
+            pimpl: pwrap!(FSFileImpl { name    : (*pimpl.name).into(), 
+                                       size    : pimpl.size, 
+                                       link_id : pimpl.link_id,
+                                       mtime   : pimpl.mtime,
+                                       parent  : None }),
+        }
+    }
+    /// Returns the file's extension: the text after the last '.' in its name.
+    /// Names without a '.', names ending in one, and hidden files like 
+    /// ".bashrc", whose only '.' leads, have none. "archive.tar.gz" has "gz".
+    /// 
+    pub fn extension(&self) -> Option<String> {
+        let name = &self.get_ref().name;
+        match name.rfind('.') {
+            Some(i) if i > 0 && i + 1 < name.len() => {
+                Some(name[i + 1..].into())
+            },
+            _ => None,
+        }
+    }
+    /// Returns a mutable reference to the internal RefCell.
+    fn get_mut(&self) -> RefMut<'_, FSFileImpl> {
+        self.pimpl.borrow_mut()
+    }
+    /// Returns a reference to the internal RefCell.
+    fn get_ref(&self) -> Ref<'_, FSFileImpl> {
+        self.pimpl.borrow()
     }
     /// Sets the parent of this file.
     fn set_parent(&self, parent: FSDir) {
-        self.get_mut().parent = Some(parent);
+        self.get_mut().parent = Some(parent.downgrade());
+    }
+    /// Detaches this file from its parent.
+    fn clear_parent(&self) {
+        self.get_mut().parent = None;
+    }
+}
+/// Files compare equal when they have the same name and size. Use ptr_eq() 
+/// to check whether two handles refer to the same file.
+/// 
+impl PartialEq for FSFile {
+    fn eq(&self, other: &Self) -> bool {
+        self.ptr_eq(other) 
+            || (self.name_ref() == other.name_ref() 
+                && self.size() == other.size())
     }
 }
+impl Eq for FSFile {}
+
 impl FSObject for FSFile {
-    fn name(&self) -> String {
+    fn name_ref(&self) -> Rc<str> {
+        self.pimpl.borrow().name.clone()
+    }
+    fn size(&self) -> u64 {
+        self.pimpl.borrow().size
+    }
+    fn parent(&self) -> Option<FSDir> {
+        self.pimpl.borrow().parent.as_ref().and_then(|p| p.upgrade())
+    }
+}
+
+/// The impl data for a FS symbolic link.
+/// 
+struct FSLinkImpl { 
+    name     : Rc<str>, 
+    target   : Rc<str>,
+    size     : u64,
+    resolved : Option<bool>,
+    parent   : Option<FSWeakDir>,
+}
+impl Debug for FSLinkImpl {
+    /// This is a custom debug impl to avoid infinite recursion.
+    /// 
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FSLinkImpl")
+            .field("name", &self.name)
+            .field("target", &self.target)
+            .field("size", &self.size)
+            .field("resolved", &self.resolved)
+            //.field("parent", "skipped..")
+            .finish()
+    }
+}
+
+/// A symbolic link in the FS file system. It holds the path of its target,
+/// as given, which FSDir::resolve_link() looks up. A link is neither a file
+/// nor a directory, so it's never counted or searched as one.
+/// 
+#[derive(Debug, Clone)]
+pub struct FSLink {
+    pimpl: Rc<RefCell<FSLinkImpl>>,
+}
+impl FSLink {
+    /// Creates a new link to `target`, with a size of 0.
+    pub fn new(name: String, target: String) -> Self {
+        Self::new_with(name, target, LinkSize::Zero)
+    }
+    /// Like new(), with the size of the link decided by `size`.
+    pub fn new_with(name: String, target: String, size: LinkSize) -> Self {
+        Self {
+            pimpl: pwrap!(FSLinkImpl { name     : name.into(), 
+                                       size     : size.of(&target),
+                                       target   : target.into(), 
+                                       resolved : None,
+                                       parent   : None }),
+        }
+    }
+    /// Returns true if `self` and `other` are handles to the same link.
+    pub fn ptr_eq(&self, other: &FSLink) -> bool {
+        Rc::ptr_eq(&self.pimpl, &other.pimpl)
+    }
+    /// Returns the path the link points to, as it was given.
+    pub fn target(&self) -> String {
+        self.get_ref().target.to_string()
+    }
+    /// Returns whether the target was found when the link was made, if 
+    /// that's known. Scanning a real disk records it; parsing a session 
+    /// doesn't, as the session only names the target.
+    /// 
+    pub fn resolved(&self) -> Option<bool> {
+        self.get_ref().resolved
+    }
+    /// Records whether the target was found.
+    pub fn set_resolved(&self, resolved: Option<bool>) {
+        self.get_mut().resolved = resolved;
+    }
+    /// Renames this link. If the link has a parent, the parent's entry for it
+    /// is updated to the new name. Fails if the parent already has a child
+    /// with the new name.
+    /// 
+    pub fn rename(&self, new_name: &str) -> Result<(), RenameError> {
+        let old_name = self.name_ref();
+        if &*old_name == new_name {
+            return Ok(());
+        }
+        let new_name = Rc::<str>::from(new_name);
+        if let Some(parent) = self.parent() {
+            parent.rekey_child(&old_name, &new_name)?;
+        }
+        self.get_mut().name = new_name;
+        Ok(())
+    }
+    /// Returns a detached copy of this link that shares nothing with it.
+    pub fn deep_clone(&self) -> FSLink {
+        let pimpl = self.get_ref();
+        FSLink { 
+            pimpl: pwrap!(FSLinkImpl { name     : (*pimpl.name).into(),
+                                       target   : (*pimpl.target).into(),
+                                       size     : pimpl.size,
+                                       resolved : pimpl.resolved,
+                                       parent   : None }),
+        }
+    }
+    /// Returns a mutable reference to the internal RefCell.
+    fn get_mut(&self) -> RefMut<'_, FSLinkImpl> {
+        self.pimpl.borrow_mut()
+    }
+    /// Returns a reference to the internal RefCell.
+    fn get_ref(&self) -> Ref<'_, FSLinkImpl> {
+        self.pimpl.borrow()
+    }
+    /// Sets the parent of this link.
+    fn set_parent(&self, parent: FSDir) {
+        self.get_mut().parent = Some(parent.downgrade());
+    }
+    /// Detaches this link from its parent.
+    fn clear_parent(&self) {
+        self.get_mut().parent = None;
+    }
+}
+/// Links compare equal when they have the same name, target, and size. Use
+/// ptr_eq() to check whether two handles refer to the same link.
+/// 
+impl PartialEq for FSLink {
+    fn eq(&self, other: &Self) -> bool {
+        self.ptr_eq(other) 
+            || (self.name_ref() == other.name_ref() 
+                && self.get_ref().target == other.get_ref().target
+                && self.size() == other.size())
+    }
+}
+impl Eq for FSLink {}
+
+impl FSObject for FSLink {
+    fn name_ref(&self) -> Rc<str> {
         self.pimpl.borrow().name.clone()
     }
-    fn size(&self) -> usize {
+    fn size(&self) -> u64 {
         self.pimpl.borrow().size
     }
     fn parent(&self) -> Option<FSDir> {
-        self.pimpl.borrow().parent.clone()
+        self.pimpl.borrow().parent.as_ref().and_then(|p| p.upgrade())
+    }
+}
+
+/// An iterator over the directories above an object, nearest first, as
+/// returned by FSObject::ancestors().
+/// 
+pub struct FSAncestors {
+    next: Option<FSDir>,
+}
+impl Iterator for FSAncestors {
+    type Item = FSDir;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let dir   = self.next.take()?;
+        self.next = dir.parent();
+        Some(dir)
+    }
+}
+
+/// A depth-first, pre-order iterator over the descendants of a directory. It
+/// uses an explicit stack rather than recursion so deep trees are safe, and it
+/// clones handles as it goes so no borrows are held between calls to next().
+/// 
+pub struct FSWalk {
+    stack     : Vec<(String, FSEntry, usize)>,
+    max_depth : Option<usize>,
+}
+impl FSWalk {
+    /// Create a new walk over the descendants of `dir`, no deeper than 
+    /// `max_depth`, if given.
+    /// 
+    fn new(dir: &FSDir, max_depth: Option<usize>) -> Self {
+        let mut walk = Self { stack: vec![], max_depth };
+        walk.push_children(&dir.path(), dir, 0);
+        walk
+    }
+    /// Push the children of `dir`, which is at `depth`, so they'll be popped
+    /// in name order, unless they'd be too deep.
+    /// 
+    fn push_children(&mut self, path: &str, dir: &FSDir, depth: usize) {
+        if self.max_depth.is_some_and(|max| depth >= max) {
+            return;
+        }
+        for child in dir.children().into_iter().rev() {
+            self.stack.push((join_path(path, &child.name_ref()), child, 
+                             depth + 1));
+        }
+    }
+}
+impl Iterator for FSWalk {
+    type Item = (String, FSEntry);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (path, entry, depth) = self.stack.pop()?;
+        if let FSEntry::Dir(dir) = &entry {
+            self.push_children(&path, dir, depth);
+        }
+        Some((path, entry))
+    }
+}
+
+/// A depth-first, pre-order iterator over the directories below a directory.
+/// Like FSWalk, but it skips files and doesn't build paths.
+/// 
+pub struct FSDirIter {
+    stack: Vec<FSDir>,
+}
+impl FSDirIter {
+    /// Create a new iterator over the directories below `dir`.
+    fn new(dir: &FSDir) -> Self {
+        let mut iter = Self { stack: vec![] };
+        iter.push_subdirs(dir);
+        iter
+    }
+    /// Push the subdirectories of `dir` so they'll be popped in name order.
+    fn push_subdirs(&mut self, dir: &FSDir) {
+        let start = self.stack.len();
+        self.stack.extend(dir.find_dirs_by(|_| true));
+        self.stack[start..].reverse();
+    }
+}
+impl Iterator for FSDirIter {
+    type Item = FSDir;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let dir = self.stack.pop()?;
+        self.push_subdirs(&dir);
+        Some(dir)
+    }
+}
+
+/// A breadth-first iterator over a directory and its subdirectories. Like 
+/// FSWalk, it never recurses and holds no borrows between calls to next().
+/// 
+pub struct FSWalkBfs {
+    queue: VecDeque<(usize, FSDir)>,
+}
+impl FSWalkBfs {
+    /// Create a new breadth-first walk starting at `dir`.
+    fn new(dir: &FSDir) -> Self {
+        Self { queue: VecDeque::from([(0, dir.clone())]) }
+    }
+}
+impl Iterator for FSWalkBfs {
+    type Item = (usize, FSDir);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (depth, dir) = self.queue.pop_front()?;
+        for child in dir.children() {
+            if let FSEntry::Dir(sub) = child {
+                self.queue.push_back((depth + 1, sub));
+            }
+        }
+        Some((depth, dir))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds /a/b/file.txt (100 bytes) with no links.
+    fn small_tree() -> FSDir {
+        let root = FSDir::new("/".into());
+        root.make_dirs("a/b").add_file(FSFile::new("file.txt".into(), 100));
+        root
+    }
+
+    fn resolved_path(root: &FSDir, link: &FSLink) -> Option<String> {
+        root.resolve_link(link).map(|entry| entry.path())
+    }
+
+    /// Checks that every directory's size, this one included, is the sum 
+    /// of its children's.
+    fn assert_sizes_add_up(root: &FSDir) {
+        for dir in root.iter_dirs_recurs().chain([root.clone()]) {
+            let sum = dir.children().iter().map(FSEntry::size).sum::<u64>();
+            assert_eq!(dir.size(), sum, "{}", dir.path());
+        }
+    }
+
+    #[test]
+    fn links_resolve_through_chains() {
+        let root  = small_tree();
+        let a     = root.get_dir("a").unwrap();
+        let first = root.add_link(FSLink::new("l1".into(), "l2".into()));
+        root.add_link(FSLink::new("l2".into(), "a/l3".into()));
+        a.add_link(FSLink::new("l3".into(), "../a/b/file.txt".into()));
+        let to_dir = a.add_link(FSLink::new("up".into(), "/a/b".into()));
+        assert_eq!(resolved_path(&root, &first).as_deref(), 
+                   Some("/a/b/file.txt"));
+        assert_eq!(resolved_path(&root, &to_dir).as_deref(), Some("/a/b"));
+        assert_eq!(root.size(), 100);
+    }
+
+    #[test]
+    fn dangling_links_resolve_to_nothing() {
+        let root = small_tree();
+        let gone = root.add_link(FSLink::new("gone".into(), "a/zzz".into()));
+        let thru = root.add_link(FSLink::new("thru".into(), 
+                                             "a/b/file.txt/x".into()));
+        let chain = root.add_link(FSLink::new("chain".into(), "gone".into()));
+        assert!(root.resolve_link(&gone).is_none());
+        assert!(root.resolve_link(&thru).is_none());
+        assert!(root.resolve_link(&chain).is_none());
+    }
+
+    #[test]
+    fn link_loops_give_up() {
+        let root = small_tree();
+        let this = root.add_link(FSLink::new("self".into(), "self".into()));
+        let ping = root.add_link(FSLink::new("ping".into(), "a/pong".into()));
+        root.get_dir("a").unwrap()
+            .add_link(FSLink::new("pong".into(), "/ping".into()));
+        assert!(root.resolve_link(&this).is_none());
+        assert!(root.resolve_link(&ping).is_none());
+    }
+
+    #[test]
+    fn links_count_their_configured_size() {
+        let root = small_tree();
+        root.add_link(FSLink::new_with("l".into(), "a/b".into(), 
+                                       LinkSize::TargetLength));
+        assert_eq!(root.size(), 103);
+        assert_eq!(root.stats().links, 1);
+    }
+
+    #[test]
+    fn merging_links_with_different_targets_fails() {
+        let ours   = small_tree();
+        let theirs = small_tree();
+        ours.add_link(FSLink::new("l".into(), "a".into()));
+        theirs.add_link(FSLink::new("l".into(), "a/b".into()));
+        let conflict = MergeError::LinkTargetConflict { path   : "/l".into(), 
+                                                        ours   : "a".into(), 
+                                                        theirs : "a/b".into() };
+        assert_eq!(ours.merge(&theirs), Err(conflict));
+        theirs.remove("l");
+        theirs.add_file(FSFile::new("l".into(), 1));
+        assert_eq!(ours.merge(&theirs), 
+                   Err(MergeError::KindConflict("/l".into())));
+    }
+
+    /// Builds /home/user/deep/down with a 100-byte quota on /home, and 
+    /// quotas enforced.
+    fn quota_tree() -> (FSDir, FSDir) {
+        let root = FSDir::new("/".into());
+        let down = root.make_dirs("home/user/deep/down");
+        root.get_dir("home").unwrap().set_quota(100);
+        root.set_enforce_quotas(true);
+        (root, down)
+    }
+
+    #[test]
+    fn files_within_or_exactly_at_a_quota_are_added() {
+        let (root, down) = quota_tree();
+        down.try_add_file(FSFile::new("a".into(), 60)).unwrap();
+        down.try_add_file(FSFile::new("b".into(), 40)).unwrap();
+        assert_eq!(root.get_dir("home").unwrap().size(), 100);
+    }
+
+    #[test]
+    fn a_deep_descendant_cant_take_a_directory_over_its_quota() {
+        let (root, down) = quota_tree();
+        down.add_file(FSFile::new("a".into(), 60));
+        let err = down.try_add_file(FSFile::new("b".into(), 45)).unwrap_err();
+        assert_eq!(err, GrowthError::QuotaExceeded(QuotaExceeded { 
+            path: "/home".into(), quota: 100, overage: 5,
+        }));
+        assert!(!down.contains("b"));
+        assert_eq!(root.size(), 60);
+
+        let file = down.get_file("a").unwrap();
+        assert!(matches!(file.try_set_size(101), 
+                         Err(GrowthError::QuotaExceeded(_))));
+        assert_eq!(file.size(), 60);
+
+        let other = root.make_dirs("tmp");
+        other.add_file(FSFile::new("big".into(), 50));
+        assert!(matches!(other.move_child("big", &down), 
+                         Err(MoveError::QuotaExceeded(_))));
+        assert_eq!((other.size(), down.size()), (50, 60));
+
+        root.set_enforce_quotas(false);
+        down.try_add_file(FSFile::new("b".into(), 45)).unwrap();
+        assert_eq!(root.get_dir("home").unwrap().size(), 105);
+    }
+
+    #[test]
+    #[should_panic(expected = "'/home' would be 1 bytes over its quota")]
+    fn plain_adds_panic_over_an_enforced_quota() {
+        let (_root, down) = quota_tree();
+        down.add_file(FSFile::new("a".into(), 101));
+    }
+
+    #[test]
+    fn each_tree_has_its_own_quota_switch() {
+        let (root, down) = quota_tree();
+        let home  = root.get_dir("home").unwrap();
+        let other = FSDir::new("/".into());
+        let away  = other.make_dirs("away");
+        away.make_dirs("x").add_file(FSFile::new("f".into(), 20));
+        assert!(down.enforces_quotas() && !away.enforces_quotas());
+
+        // A subtree removed from a tree leaves its switch behind, and one 
+        // added takes the switch of the tree it joins.
+        let user = home.remove("user").unwrap();
+        assert!(!down.enforces_quotas());
+        away.insert_entry(user);
+        away.move_child("x", &home).unwrap();
+        let x = home.get_dir("x").unwrap();
+        assert!(x.enforces_quotas() && !down.enforces_quotas());
+        assert!(x.try_add_file(FSFile::new("g".into(), 81)).is_err());
+
+        // Switching it on in one tree leaves the others as they were.
+        other.set_enforce_quotas(true);
+        assert!(down.enforces_quotas() && x.enforces_quotas());
+        other.set_enforce_quotas(false);
+        assert!(!down.enforces_quotas() && x.enforces_quotas());
+        // Once its tree is dropped, /home/x is a tree of its own.
+        drop((root, home));
+        assert!(x.is_root() && !x.enforces_quotas());
+    }
+
+    #[test]
+    fn names_containing_the_needle_are_found_at_every_depth() {
+        let root = FSDir::new("/".into());
+        root.add_file_at_path("node_modules/x.js", 1);
+        root.add_file_at_path("a/b/node_modules/y.js", 2);
+        root.add_file_at_path("a/old_node_modules_2", 4);
+        root.add_file_at_path("a/b/c/node_module", 8);
+        let found = root.find_by_name("node_modules")
+                        .into_iter()
+                        .map(|(path, entry)| (path, entry.size()))
+                        .collect::<Vec<_>>();
+        assert_eq!(found, [("/a/b/node_modules".into(), 2),
+                           ("/a/old_node_modules_2".into(), 4),
+                           ("/node_modules".into(), 1)]);
+        assert!(root.find_by_name("Node").is_empty());
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn names_matching_the_pattern_are_found_at_every_depth() {
+        let root = FSDir::new("/".into());
+        root.add_file_at_path("node_modules/x.js", 1);
+        root.add_file_at_path("a/b/node_modules/y.js", 2);
+        root.add_file_at_path("a/my_node_modules_backup", 4);
+        root.add_file_at_path("a/b/c/node_module", 8);
+        let paths = |pattern| {
+            let re = regex::Regex::new(pattern).unwrap();
+            root.find_by_regex(&re)
+                .into_iter()
+                .map(|(path, _)| path)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(paths("^node_modules$"), ["/a/b/node_modules", 
+                                             "/node_modules"]);
+        assert_eq!(paths("node_modules"), ["/a/b/node_modules", 
+                                           "/a/my_node_modules_backup",
+                                           "/node_modules"]);
+        assert_eq!(paths(r"^\w+\.js$"), ["/a/b/node_modules/y.js",
+                                          "/node_modules/x.js"]);
+    }
+
+    #[test]
+    fn dropping_the_root_frees_the_tree() {
+        let root  = FSDir::new("/".into());
+        let file  = root.add_file_at_path("a/b/c/f", 10);
+        let b     = root.get_dir("a").unwrap().get_dir("b").unwrap();
+        let weaks = root.iter_dirs_recurs()
+                        .map(|dir| Rc::downgrade(&dir.pimpl))
+                        .chain([Rc::downgrade(&root.pimpl)])
+                        .collect::<Vec<_>>();
+        drop(root);
+        // Only what's still held is left: b, and through it, c and f.
+        let alive = weaks.iter().filter(|w| w.upgrade().is_some()).count();
+        assert_eq!(alive, 2);
+        assert_eq!(Rc::strong_count(&b.pimpl), 1);
+        assert!(b.parent().is_none());
+        assert_eq!(file.path(), "b/c/f");
+        assert_eq!(Rc::strong_count(&file.pimpl), 2);
+        drop(b);
+        assert_eq!(Rc::strong_count(&file.pimpl), 1);
+        assert!(file.parent().is_none());
+        assert!(weaks.iter().all(|w| w.upgrade().is_none()));
+    }
+
+    #[test]
+    fn adding_a_name_again_replaces_the_old_size() {
+        let root = FSDir::new("/".into());
+        let a    = root.make_dirs("a/b").parent().unwrap();
+        for size in [10, 500, 3, 0, 42] {
+            a.add_file(FSFile::new("f".into(), size));
+            assert_sizes_add_up(&root);
+            assert_eq!(root.size(), size);
+        }
+        // A directory replaced by a file takes its contents' size with it,
+        // and the other way round.
+        let dir = FSDir::new("f".into());
+        dir.add_file(FSFile::new("g".into(), 7));
+        a.add_dir(dir);
+        assert_eq!(root.size(), 7);
+        a.add_file(FSFile::new("b".into(), 1));
+        a.add_dir(FSDir::new("f".into()));
+        assert_sizes_add_up(&root);
+        assert_eq!((root.size(), a.len()), (1, 2));
+        a.try_add_file(FSFile::new("b".into(), 9)).unwrap();
+        assert_eq!(root.size(), 9);
+    }
+
+    #[test]
+    fn files_are_found_by_predicate_in_walk_order() {
+        let text = include_str!("../data/sample.txt");
+        let root = crate::parser::build_fs_from_str(text).unwrap();
+        let big  = root.find_files_recurs_by(|f| f.size() > 5_000_000)
+                       .iter()
+                       .map(|f| (f.path(), f.size()))
+                       .collect::<Vec<_>>();
+        assert_eq!(big, [("/b.txt".into(), 14848514),
+                         ("/c.dat".into(), 8504156),
+                         ("/d/d.ext".into(), 5626152),
+                         ("/d/d.log".into(), 8033020),
+                         ("/d/k".into(), 7214296)]);
+        let all  = root.find_files_recurs_by(|_| true)
+                       .iter()
+                       .map(|f| f.path())
+                       .collect::<Vec<_>>();
+        let walk = root.walk()
+                       .filter(|(_, e)| matches!(e, FSEntry::File(_)))
+                       .map(|(path, _)| path)
+                       .collect::<Vec<_>>();
+        assert_eq!(all, walk);
+        assert_eq!(root.find_files_by(|f| f.size() > 5_000_000).len(), 2);
+        assert!(root.find_files_recurs_by(|f| f.size() > 20_000_000)
+                    .is_empty());
+    }
+
+    #[test]
+    fn files_are_found_at_the_bottom_of_very_deep_trees() {
+        let root   = FSDir::new("/".into());
+        let bottom = root.make_dirs(&vec!["d"; 200_000].join("/"));
+        bottom.add_file(FSFile::new("f".into(), 1));
+        root.add_file(FSFile::new("top".into(), 2));
+        let found  = root.find_files_recurs_by(|_| true);
+        assert_eq!(found.iter().map(|f| f.size()).collect::<Vec<_>>(), 
+                   [1, 2]);
+    }
+
+    #[test]
+    fn very_deep_trees_drop_without_overflowing_the_stack() {
+        let path   = vec!["d"; 200_000].join("/");
+        let root   = FSDir::new("/".into());
+        let bottom = root.make_dirs(&path);
+        bottom.add_file(FSFile::new("f".into(), 1));
+        assert_eq!(root.size(), 1);
+        // A handle held halfway down keeps that half alive.
+        let middle = root.make_dirs(&path[..path.len() / 2]);
+        drop(bottom);
+        drop(root);
+        assert!(middle.parent().is_none());
+        assert_eq!(middle.size(), 1);
+        drop(middle);
+    }
+
+    #[test]
+    fn get_file_finds_only_files() {
+        let root = small_tree();
+        let b    = root.get_dir("a").unwrap().get_dir("b").unwrap();
+        assert_eq!(b.get_file("file.txt").unwrap().size(), 100);
+        assert!(b.get_file("missing").is_none());
+        assert!(root.get_file("a").is_none());
+        assert!(matches!(root.get("a"), Some(FSEntry::Dir(_))));
+        assert!(matches!(b.get("file.txt"), Some(FSEntry::File(_))));
+        assert!(root.get("missing").is_none());
+    }
+
+    #[test]
+    fn moves_keep_sizes_consistent() {
+        let root = FSDir::new("/".into());
+        root.add_file_at_path("logs/a.log", 30);
+        root.add_file_at_path("logs/old/b.log", 20);
+        root.add_file_at_path("src/main.rs", 5);
+        let logs = root.get_dir("logs").unwrap();
+        let src  = root.get_dir("src").unwrap();
+
+        // A move within the tree leaves the root's size alone.
+        root.move_child("logs", &src).unwrap();
+        assert_eq!((root.size(), src.size()), (55, 55));
+        assert_eq!(logs.path(), "/src/logs");
+        assert_sizes_add_up(&root);
+
+        // A move into a detached tree takes the size with it.
+        let mount = FSDir::new("mnt".into());
+        mount.add_file(FSFile::new("x".into(), 1));
+        src.move_child("logs", &mount).unwrap();
+        assert_eq!((root.size(), src.size(), mount.size()), (5, 5, 51));
+        assert_eq!(logs.path(), "mnt/logs");
+        assert_sizes_add_up(&root);
+        assert_sizes_add_up(&mount);
+    }
+
+    #[test]
+    fn moves_into_a_descendant_are_rejected() {
+        let root = small_tree();
+        let a    = root.get_dir("a").unwrap();
+        let b    = a.get_dir("b").unwrap();
+        for dest in [&a, &b] {
+            assert!(matches!(root.move_child("a", dest),
+                             Err(MoveError::IntoDescendant(_))));
+        }
+        assert!(matches!(root.move_child("nope", &b), 
+                         Err(MoveError::NotFound(_))));
+        b.add_dir(FSDir::new("b".into()));
+        assert!(matches!(a.move_child("b", &b),
+                         Err(MoveError::AlreadyExists(_))));
+        assert_eq!((root.size(), b.path()), (100, "/a/b".into()));
+        assert_sizes_add_up(&root);
+    }
+
+    #[test]
+    fn sizes_that_would_overflow_are_refused() {
+        let half = u64::MAX / 2 + 1;
+        let root = FSDir::new("/".into());
+        let dir  = root.make_dirs("a");
+        dir.add_file(FSFile::new("x".into(), half));
+        match dir.try_add_file(FSFile::new("y".into(), half)) {
+            Err(GrowthError::Overflow(e)) => {
+                assert_eq!((e.path.as_str(), e.size), ("/a", half));
+            },
+            other => panic!("expected an overflow, got {:?}", other),
+        }
+        assert_eq!((root.size(), dir.len()), (half, 1));
+        let file = dir.get_file("x").unwrap();
+        assert!(matches!(file.try_set_size(u64::MAX), Ok(())));
+        assert!(matches!(file.grow(1), Err(GrowthError::Overflow(_))));
+        assert_eq!(root.size(), u64::MAX);
+        let text = format!("$ cd /\n$ ls\n{} x\n{} y\n", half, half);
+        let err  = crate::parser::build_fs_from_str(&text).unwrap_err();
+        assert!(matches!(err, crate::parser::ParseError::SizeOverflow { 
+                                  line: 4, .. }), "{}", err);
+    }
+
+    #[test]
+    #[should_panic(expected = "overflow")]
+    fn plain_adds_panic_on_overflow() {
+        let root = FSDir::new("/".into());
+        root.add_file(FSFile::new("x".into(), u64::MAX / 2 + 1));
+        root.add_file(FSFile::new("y".into(), u64::MAX / 2 + 1));
+    }
+
+    #[test]
+    fn sizes_update_through_a_million_levels() {
+        let path   = vec!["d"; 1_000_000].join("/");
+        let root   = FSDir::new("/".into());
+        let bottom = root.make_dirs(&path);
+        let file   = bottom.add_file(FSFile::new("f".into(), 5));
+        assert_eq!(root.size(), 5);
+        file.set_size(7);
+        assert_eq!(root.size(), 7);
+        bottom.remove("f");
+        assert_eq!(root.size(), 0);
+    }
+
+    #[test]
+    fn iterating_a_directory_yields_its_children_in_name_order() {
+        let root = FSDir::new("/".into());
+        root.add_file(FSFile::new("c.txt".into(), 3));
+        root.make_dirs("b");
+        root.add_file(FSFile::new("a.txt".into(), 1));
+        root.make_dirs("B");
+        let mut seen = vec![];
+        for child in &root {
+            let kind = match child {
+                FSEntry::Dir(_)  => "dir",
+                FSEntry::File(_) => "file",
+                FSEntry::Link(_) => "link",
+            };
+            seen.push((child.name(), kind));
+        }
+        assert_eq!(seen, [("B".into(), "dir"), ("a.txt".into(), "file"),
+                          ("b".into(), "dir"), ("c.txt".into(), "file")]);
+        assert!((&FSDir::new("empty".into())).into_iter().next().is_none());
+    }
+
+    #[test]
+    fn child_returns_files_and_dirs() {
+        let root = small_tree();
+        let FSEntry::Dir(a) = root.child("a") else {
+            panic!("expected a directory");
+        };
+        assert_eq!(a.path(), "/a");
+        match a.get_dir("b").unwrap().child("file.txt") {
+            FSEntry::File(file) => assert_eq!(file.size(), 100),
+            other               => panic!("expected a file, got {:?}", other),
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "no entry named 'nope' in '/a'")]
+    fn child_names_what_is_missing() {
+        small_tree().get_dir("a").unwrap().child("nope");
+    }
+
+    #[test]
+    fn dot_dot_never_climbs_above_the_receiver() {
+        let root = small_tree();
+        let a    = root.get_dir("a").unwrap();
+        let path = |dir: &FSDir, path| dir.find_by_path(path).map(|e| e.path());
+        assert_eq!(path(&a, ".."), Some("/a".into()));
+        assert_eq!(path(&a, "../../b"), Some("/a/b".into()));
+        assert_eq!(path(&a, "b/../.."), Some("/a".into()));
+        assert_eq!(path(&a, "/b/file.txt"), Some("/a/b/file.txt".into()));
+        assert_eq!(path(&root, "a/b/../../.."), Some("/".into()));
+        // From below the receiver, ".." climbs up to it, and no further.
+        let b = a.get_dir("b").unwrap();
+        let from = |path| root.find_by_path_from(&b, path).map(|e| e.path());
+        assert_eq!(from(".."), Some("/a".into()));
+        assert_eq!(from("../../../a"), Some("/a".into()));
+        assert_eq!(from("/a/b"), Some("/a/b".into()));
+        assert_eq!(a.find_by_path_from(&b, "../../b").unwrap().path(), "/a/b");
+    }
+
+    #[test]
+    fn entries_of_the_same_size_sort_by_name() {
+        let root = FSDir::new("/".into());
+        root.add_file(FSFile::new("b".into(), 10));
+        root.add_file(FSFile::new("e".into(), 20));
+        root.add_file(FSFile::new("a".into(), 10));
+        root.add_file(FSFile::new("c".into(), 5));
+        root.add_dir(FSDir::new("d".into()))
+            .add_file(FSFile::new("x".into(), 10));
+        let names = |key, dir| root.children_sorted(key, dir).iter()
+                                   .map(|c| c.name())
+                                   .collect::<Vec<_>>();
+        assert_eq!(names(SortKey::Size, SortDir::Ascending),
+                   ["c", "a", "b", "d", "e"]);
+        assert_eq!(names(SortKey::Size, SortDir::Descending),
+                   ["e", "a", "b", "d", "c"]);
+        assert_eq!(names(SortKey::Name, SortDir::Ascending),
+                   ["a", "b", "c", "d", "e"]);
+        assert_eq!(names(SortKey::Name, SortDir::Descending),
+                   ["e", "d", "c", "b", "a"]);
+    }
+
+    #[test]
+    fn resizing_files_keeps_every_directory_summed() {
+        use crate::testing::*;
+        for seed in 0..20 {
+            let spec    = TreeSpec { nodes: 200, ..Default::default() };
+            let root    = FSDir::random(seed, spec);
+            let files   = root.find_files_recurs_by(|_| true);
+            let mut rng = SplitMix64::new(seed);
+            for _ in 0..300 {
+                let file = &files[rng.in_range(&(0..=files.len() - 1))];
+                let n    = rng.in_range_u64(&(0..=100_000));
+                match rng.in_range(&(0..=2)) {
+                    0 => file.set_size(n),
+                    1 => file.grow(n).unwrap(),
+                    _ => {
+                        let old = file.size();
+                        match file.shrink(n) {
+                            Ok(()) => assert_eq!(file.size(), old - n),
+                            Err(e) => {
+                                assert!(n > old);
+                                assert_eq!((e.size, file.size()), (old, old));
+                            },
+                        }
+                    },
+                }
+                assert_sizes_add_up(&root);
+            }
+        }
+        // A file with no parent just changes size.
+        let file = FSFile::new("loose".into(), 10);
+        file.set_size(3);
+        assert_eq!(file.size(), 3);
+    }
+
+    #[test]
+    fn lazy_sizes_are_marked_and_recomputed() {
+        let root = FSDir::new_lazy("/".into());
+        let b    = root.make_dirs("a/b");
+        b.add_file(FSFile::new("x".into(), 10));
+        b.add_file(FSFile::new("y".into(), 20));
+        root.make_dirs("c").add_file(FSFile::new("z".into(), 5));
+        assert!(root.get_ref().dirty && b.get_ref().dirty);
+        assert_eq!(root.size(), 35);
+        assert!(!root.get_ref().dirty && !b.get_ref().dirty);
+
+        root.get_dir("a").unwrap().remove("b");
+        assert!(root.get_ref().dirty);
+        assert_eq!(root.verify_sizes(), Ok(()));
+        assert_eq!(root.size(), 5);
+        assert_eq!(root.verify_sizes(), Ok(()));
+    }
+
+    #[test]
+    fn wrong_cached_sizes_are_found_and_fixed() {
+        let root = small_tree();
+        let b    = root.make_dirs("a/b");
+        root.make_dirs("c").add_file(FSFile::new("z".into(), 5));
+        b.get_mut().size = 7;
+        assert_eq!(root.verify_sizes(), Err(SizeMismatch { 
+            path: "/a/b".into(), cached: 7, actual: 100,
+        }));
+        // Marked out of date, it isn't checked, and is fixed on demand.
+        b.mark_dirty();
+        assert_eq!(root.verify_sizes(), Ok(()));
+        assert_eq!((b.size(), root.size()), (100, 105));
+
+        b.get_mut().size = 7;
+        root.recompute_sizes();
+        assert_eq!(root.verify_sizes(), Ok(()));
+        assert_eq!(b.size(), 100);
+    }
+
+    #[test]
+    fn directories_take_the_latest_mtime_below_them_unless_set() {
+        let root = small_tree();
+        assert_eq!(root.mtime(), None);
+        let file = root.find_files_recurs_by(|_| true).remove(0);
+        file.set_mtime(Some(100));
+        root.make_dirs("c/d").add_file(FSFile::new("g".into(), 1))
+            .set_mtime(Some(300));
+        root.add_file(FSFile::new("h".into(), 1)).set_mtime(Some(200));
+        let (a, c) = (root.get_dir("a").unwrap(), root.get_dir("c").unwrap());
+        assert_eq!((a.mtime(), c.mtime(), root.mtime()), 
+                   (Some(100), Some(300), Some(300)));
+        // A time set on a directory stands for everything below it, even if
+        // something below is newer.
+        c.set_mtime(Some(50));
+        assert_eq!((c.mtime(), c.own_mtime(), root.mtime()), 
+                   (Some(50), Some(50), Some(200)));
+        a.get_dir("b").unwrap().set_mtime(Some(400));
+        assert_eq!((a.mtime(), root.mtime()), (Some(400), Some(400)));
+        c.set_mtime(None);
+        assert_eq!(c.mtime(), Some(300));
+        assert_eq!(root.make_dirs("empty").mtime(), None);
+    }
+
+    #[test]
+    fn renaming_the_root_just_changes_its_name() {
+        let root = small_tree();
+        root.rename("top").unwrap();
+        assert_eq!((root.name(), root.path()), ("top".into(), "top".into()));
+        assert!(root.contains("a"));
+        assert_eq!(root.find_by_path("/a/b/file.txt").unwrap().size(), 100);
+        let file = FSFile::new("loose".into(), 1);
+        file.rename("free").unwrap();
+        assert_eq!(file.name(), "free");
+    }
+
+    #[test]
+    fn renamed_entries_are_found_under_their_new_names() {
+        let root = small_tree();
+        let a    = root.get_dir("a").unwrap();
+        root.add_file(FSFile::new("x".into(), 5));
+        a.rename("z").unwrap();
+        assert!(root.contains("z") && !root.contains("a"));
+        assert!(root.get_dir("z").unwrap().ptr_eq(&a));
+        assert!(root.get_dir("a").is_none());
+        assert_eq!(a.get_dir("b").unwrap().path(), "/z/b");
+        assert_eq!(root.size(), 105);
+
+        let file = root.find_files_recurs_by(|f| f.size() == 100).remove(0);
+        file.rename("moved.txt").unwrap();
+        let b    = root.find_by_path("/z/b").unwrap();
+        match b {
+            FSEntry::Dir(b) => {
+                assert!(b.contains("moved.txt") && !b.contains("file.txt"));
+                assert!(b.get_file("moved.txt").unwrap().ptr_eq(&file));
+            },
+            other => panic!("expected /z/b, got {:?}", other),
+        }
+
+        assert_eq!(a.rename("x"), Err(RenameError::AlreadyExists("x".into())));
+        assert_eq!(root.get_file("x").unwrap().rename("z"), 
+                   Err(RenameError::AlreadyExists("z".into())));
+        assert!(root.get_dir("z").unwrap().ptr_eq(&a));
+        assert_eq!(root.get_file("x").unwrap().size(), 5);
+        a.rename("z").unwrap();
+    }
+
+    #[test]
+    fn walk_bfs_visits_each_level_in_name_order() {
+        let root = FSDir::new("/".into());
+        for path in ["b/y/q/deep", "a/x/p", "a/w", "b/v", "c"] {
+            root.make_dirs(path);
+        }
+        root.make_dirs("a").add_file(FSFile::new("file".into(), 1));
+        let order = root.walk_bfs()
+                        .map(|(depth, dir)| (depth, dir.path()))
+                        .collect::<Vec<_>>();
+        assert_eq!(order, [(0, "/".to_string()),
+                           (1, "/a".into()), (1, "/b".into()), (1, "/c".into()),
+                           (2, "/a/w".into()), (2, "/a/x".into()), 
+                           (2, "/b/v".into()), (2, "/b/y".into()),
+                           (3, "/a/x/p".into()), (3, "/b/y/q".into()),
+                           (4, "/b/y/q/deep".into())]);
+    }
+
+    #[test]
+    fn stateful_predicates_see_each_entry_once() {
+        let root = FSDir::new("/".into());
+        for path in ["x/y/x", "z/x"] {
+            root.make_dirs(path);
+        }
+        root.add_file_at_path("x/y/f", 1);
+        root.add_file_at_path("z/g", 2);
+        let mut calls = 0;
+        let found     = root.find_dirs_with_paths_by(|dir| {
+            calls += 1;
+            dir.name_ref().as_ref() == "x"
+        });
+        assert_eq!(calls, 5);
+        let paths = found.iter().map(|(path, _)| path).collect::<Vec<_>>();
+        assert_eq!(paths, ["/x", "/x/y/x", "/z/x"]);
+        assert!(found.iter().all(|(path, dir)| *path == dir.path()));
+
+        let mut seen = vec![];
+        let dirs     = root.find_dirs_recurs_by(|dir| {
+            seen.push(dir.path());
+            seen.len() % 2 == 0
+        });
+        assert_eq!(seen, ["/x", "/x/y", "/x/y/x", "/z", "/z/x"]);
+        assert_eq!(dirs.iter().map(|d| d.path()).collect::<Vec<_>>(), 
+                   ["/x/y", "/z"]);
+
+        let mut calls = 0;
+        let first     = root.find_first_dir_by(|_| { calls += 1; calls == 3 });
+        assert_eq!((first.unwrap().path(), calls), ("/x/y/x".into(), 3));
+        let mut total = 0;
+        root.find_files_recurs_by(|file| { total += file.size(); false });
+        assert_eq!(total, 3);
+    }
+
+    #[test]
+    fn sizes_past_4_gib_are_exact() {
+        const GIB: u64 = 1 << 30;
+        let text = format!("$ cd /\n$ ls\ndir a\n{} big\n$ cd a\n$ ls\n\
+                            {} x\n{} y\n1 z\n", 
+                           3 * GIB, 3 * GIB, 5 * GIB + 7);
+        let root = crate::parser::build_fs_from_str(&text).unwrap();
+        let a    = root.get_dir("a").unwrap();
+        assert!(root.size() > u32::MAX as u64);
+        assert_eq!(a.size(), 8 * GIB + 8);
+        assert_eq!(root.size(), 11 * GIB + 8);
+        assert_eq!(root.size(), 11_811_160_072);
+        assert_eq!(crate::analysis::sum_dirs_at_most(&root, 9 * GIB), 
+                   8 * GIB + 8);
+
+        a.get_file("z").unwrap().set_size(GIB);
+        a.remove("x");
+        assert_eq!((a.size(), root.size()), (6 * GIB + 7, 9 * GIB + 7));
+        assert_eq!(root.verify_sizes(), Ok(()));
+    }
+
+    /// Builds /a/b/c/f (1), /a/b/h (4), /a/g (2) and /x/y (8).
+    fn depth_tree() -> FSDir {
+        let root = FSDir::new("/".into());
+        root.add_file_at_path("a/b/c/f", 1);
+        root.add_file_at_path("a/b/h", 4);
+        root.add_file_at_path("a/g", 2);
+        root.add_file_at_path("x/y", 8);
+        root
+    }
+
+    #[test]
+    fn walks_and_finds_to_a_depth_count_from_the_receiver() {
+        // Depths count from /a, at 0. Walks and finds never yield the 
+        // directory they start from, so a max_depth of 0, which allows 
+        // nothing deeper than it, gives nothing at all.
+        let root  = depth_tree();
+        let a     = root.get_dir("a").unwrap();
+        let walk  = |max| {
+            a.walk_to_depth(max).map(|(_, e)| e.path()).collect::<Vec<_>>()
+        };
+        let find  = |max| {
+            a.find_dirs_to_depth_by(max, |_| true).iter()
+             .map(|d| d.path()).collect::<Vec<_>>()
+        };
+        assert!(walk(0).is_empty());
+        assert_eq!(walk(1), ["/a/b", "/a/g"]);
+        assert_eq!(walk(2), ["/a/b", "/a/b/c", "/a/b/h", "/a/g"]);
+        assert!(find(0).is_empty());
+        assert_eq!(find(1), ["/a/b"]);
+        assert_eq!(find(2), ["/a/b", "/a/b/c"]);
+    }
+
+    #[test]
+    fn depth_counts_from_the_root_of_the_tree() {
+        // Unlike the depth limits above, depth() doesn't depend on where a
+        // walk started: /a is at 1 even when it's the one walked.
+        let root = depth_tree();
+        let a    = root.get_dir("a").unwrap();
+        assert_eq!((root.depth(), a.depth()), (0, 1));
+        let depths = a.walk().map(|(_, e)| (e.path(), e.depth()))
+                              .collect::<Vec<_>>();
+        assert_eq!(depths, [("/a/b".into(), 2), ("/a/b/c".into(), 3), 
+                            ("/a/b/c/f".into(), 4), ("/a/b/h".into(), 3), 
+                            ("/a/g".into(), 2)]);
+    }
+
+    #[test]
+    fn only_the_incl_self_search_finds_a_matching_receiver() {
+        let root = FSDir::new("/".into());
+        root.add_file_at_path("big", 100);
+        root.add_file_at_path("small/f", 1);
+        let big  = |d: &FSDir| d.size() >= 50;
+        assert!(root.find_dirs_recurs_by(big).is_empty());
+        let found = root.find_dirs_recurs_incl_self_by(big);
+        assert_eq!(found.len(), 1);
+        assert!(found[0].ptr_eq(&root));
+        // Below the receiver, the two agree.
+        let small = |d: &FSDir| d.size() < 50;
+        let paths = |dirs: Vec<FSDir>| {
+            dirs.iter().map(|d| d.path()).collect::<Vec<_>>()
+        };
+        assert_eq!(paths(root.find_dirs_recurs_by(small)), ["/small"]);
+        assert_eq!(paths(root.find_dirs_recurs_incl_self_by(small)), 
+                   ["/small"]);
+    }
+
+    #[test]
+    fn removed_subtrees_are_roots_of_their_own() {
+        let root = FSDir::new("/".into());
+        let c    = root.make_dirs("a/b/c");
+        let a    = root.get_dir("a").unwrap();
+        assert!(root.is_root() && !a.is_root() && !c.is_root());
+        assert!(root.root().ptr_eq(&root));
+        assert!(a.root().ptr_eq(&root) && c.root().ptr_eq(&root));
+
+        // Once /a is removed, it's the top of /a/b/c's tree.
+        root.remove("a");
+        assert!(a.is_root() && !c.is_root());
+        assert!(a.root().ptr_eq(&a) && c.root().ptr_eq(&a));
+        assert!(root.root().ptr_eq(&root));
+        assert_eq!(c.path(), "a/b/c");
+    }
+
+    #[test]
+    fn changing_a_deep_clone_leaves_the_original_alone() {
+        let root = FSDir::new("/".into());
+        root.add_file_at_path("a/f", 10);
+        root.add_file_at_path("a/b/g", 20);
+        root.add_file_at_path("h", 5);
+        let copy = root.deep_clone();
+        assert!(copy.structural_eq(&root));
+        let a    = copy.get_dir("a").unwrap();
+        assert!(a.remove("b").is_some());
+        assert!(copy.remove("h").is_some());
+        assert_eq!((a.size(), copy.size()), (10, 10));
+        // The original keeps its children and sizes.
+        let names = |dir: &FSDir| {
+            dir.children().iter().map(|e| e.name()).collect::<Vec<_>>()
+        };
+        assert_eq!(root.size(), 35);
+        assert_eq!(names(&root), ["a", "h"]);
+        assert_eq!(names(&root.get_dir("a").unwrap()), ["b", "f"]);
+        assert_eq!(root.get_dir("a").unwrap().size(), 30);
+        assert_eq!(root.verify_sizes(), Ok(()));
+    }
+
+    #[test]
+    fn make_dirs_never_climbs_above_the_receiver() {
+        let root = FSDir::new("/".into());
+        let a    = root.make_dirs("a");
+        assert!(a.make_dirs("..").ptr_eq(&a));
+        assert_eq!(a.make_dirs("../../b").path(), "/a/b");
+        assert_eq!(a.make_dirs("b/c/../../../d").path(), "/a/d");
+        assert_eq!(a.add_file_at_path("../f", 1).path(), "/a/f");
+        // Below the receiver, ".." goes up as usual.
+        assert_eq!(a.make_dirs("b/c/../e").path(), "/a/b/e");
+        assert_eq!(root.dir_count(), 5);
+        assert!(root.contains("a") && root.len() == 1);
     }
 }
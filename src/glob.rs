@@ -0,0 +1,307 @@
+//! This module implements Glob, a shell-style pattern matched against paths,
+//! for picking out entries like "**/*.log" or "**/node_modules" without
+//! writing a predicate. A pattern is split into components at each '/', and
+//! each component matches one component of the path:
+//! 
+//! * `*` matches any run of characters within a component.
+//! * `?` matches any one character.
+//! * `[abc]`, `[a-z]` match one character in the class; `[!abc]` or `[^abc]`
+//!   match one character not in it.
+//! * `**`, as a whole component, matches any number of components, none
+//!   included.
+//! * `\` matches the character after it literally.
+//! 
+//! Matching is anchored to the whole path. Paths in a tree all start at the
+//! root, so a leading '/' makes no difference, on the pattern or the path.
+
+use crate::fsobject::*;
+
+/// Options for Glob::new_with() and FSDir::glob_with().
+/// 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct GlobOptions {
+    /// Ignore case, so "*.LOG" matches "a.log".
+    pub fold_case: bool,
+}
+
+/// A compiled glob pattern. Any pattern is valid; a '[' without a closing
+/// ']' is taken literally.
+/// 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Glob {
+    pattern    : String,
+    components : Vec<Component>,
+    fold_case  : bool,
+}
+
+/// One component of a pattern.
+/// 
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Component {
+    /// `**`, any number of path components.
+    AnyDepth,
+    /// Anything else, matching one path component.
+    Name(Vec<Token>),
+}
+
+/// One piece of a pattern component.
+/// 
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    /// `*`, any run of characters.
+    Any,
+    /// `?`, any one character.
+    One,
+    /// A character matched literally.
+    Char(char),
+    /// A bracketed class of characters, as inclusive ranges.
+    Class { negated: bool, ranges: Vec<(char, char)> },
+}
+impl Token {
+    /// Returns true if this token, other than Any, matches `c`.
+    fn matches(&self, c: char) -> bool {
+        match self {
+            Token::Any | Token::One => true,
+            Token::Char(t)          => *t == c,
+            Token::Class { negated, ranges } => {
+                ranges.iter().any(|&(lo, hi)| lo <= c && c <= hi) != *negated
+            },
+        }
+    }
+}
+
+impl Glob {
+    /// Compiles `pattern`, matching case exactly.
+    pub fn new(pattern: &str) -> Self {
+        Self::new_with(pattern, GlobOptions::default())
+    }
+    /// Compiles `pattern` with the given options.
+    pub fn new_with(pattern: &str, opts: GlobOptions) -> Self {
+        let folded = if opts.fold_case {
+            pattern.to_lowercase()
+        } else {
+            pattern.to_string()
+        };
+        let components = folded.split('/')
+                               .filter(|c| !c.is_empty())
+                               .map(|c| match c {
+                                   "**" => Component::AnyDepth,
+                                   _    => Component::Name(tokens(c)),
+                               })
+                               .collect();
+        Self { pattern: pattern.to_string(),
+               components,
+               fold_case: opts.fold_case }
+    }
+    /// Returns the pattern as given.
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+    /// Returns true if the pattern matches the whole of `path`. A name with
+    /// no '/' in it is a path of one component.
+    /// 
+    pub fn matches(&self, path: &str) -> bool {
+        let folded;
+        let path = if self.fold_case {
+            folded = path.to_lowercase();
+            &folded
+        } else {
+            path
+        };
+        let parts = path.split('/')
+                        .filter(|p| !p.is_empty())
+                        .collect::<Vec<_>>();
+        wildcard_match(&self.components, &parts,
+                       |c| *c == Component::AnyDepth,
+                       |c, part| match c {
+                           Component::Name(tokens) => {
+                               let chars = part.chars().collect::<Vec<_>>();
+                               wildcard_match(tokens, &chars,
+                                              |t| *t == Token::Any,
+                                              |t, &c| t.matches(c))
+                           },
+                           Component::AnyDepth => unreachable!(),
+                       })
+    }
+}
+
+/// Splits one component of a pattern into tokens.
+/// 
+fn tokens(component: &str) -> Vec<Token> {
+    let chars      = component.chars().collect::<Vec<_>>();
+    let mut tokens = vec![];
+    let mut i      = 0;
+    while i < chars.len() {
+        let token = match chars[i] {
+            '*'  => Token::Any,
+            '?'  => Token::One,
+            '\\' if i + 1 < chars.len() => {
+                i += 1;
+                Token::Char(chars[i])
+            },
+            '['  => match class(&chars[i + 1..]) {
+                Some((token, len)) => {
+                    i += len;
+                    token
+                },
+                None => Token::Char('['),
+            },
+            c    => Token::Char(c),
+        };
+        // A run of '*' is the same as one.
+        if !(token == Token::Any && tokens.last() == Some(&Token::Any)) {
+            tokens.push(token);
+        }
+        i += 1;
+    }
+    tokens
+}
+
+/// Parses the class whose text, after the opening '[', starts `chars`.
+/// Returns the class and the number of characters it took up, closing ']'
+/// included, or None if it's never closed. A ']' straight after the '[', or
+/// after the '!' or '^' that negates the class, is part of the class.
+/// 
+fn class(chars: &[char]) -> Option<(Token, usize)> {
+    let negated    = matches!(chars.first(), Some('!' | '^'));
+    let mut i      = negated as usize;
+    let mut ranges = vec![];
+    let start      = i;
+    loop {
+        let lo = *chars.get(i)?;
+        if lo == ']' && i > start {
+            return Some((Token::Class { negated, ranges }, i + 1));
+        }
+        match (chars.get(i + 1), chars.get(i + 2)) {
+            (Some('-'), Some(&hi)) if hi != ']' => {
+                ranges.push((lo, hi));
+                i += 3;
+            },
+            _ => {
+                ranges.push((lo, lo));
+                i += 1;
+            },
+        }
+    }
+}
+
+/// Returns true if `pattern` matches the whole of `items`, where pattern
+/// elements for which `is_any` holds match any run of items, and the others
+/// match the one item for which `matches` holds.
+/// 
+fn wildcard_match<P, I, A, M>(pattern: &[P], items: &[I], is_any: A,
+                              matches: M)
+    -> bool
+where
+    A: Fn(&P) -> bool,
+    M: Fn(&P, &I) -> bool
+{
+    let (mut p, mut n) = (0, 0);
+    let mut backtrack  = None;
+
+    while n < items.len() {
+        match pattern.get(p) {
+            Some(pat) if is_any(pat) => {
+                backtrack = Some((p, n));
+                p += 1;
+            },
+            Some(pat) if matches(pat, &items[n]) => {
+                p += 1;
+                n += 1;
+            },
+            _ => match backtrack {
+                // Let the last wildcard swallow one more item and retry.
+                Some((bp, bn)) => {
+                    backtrack = Some((bp, bn + 1));
+                    p = bp + 1;
+                    n = bn + 1;
+                },
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(is_any)
+}
+
+impl FSDir {
+    /// Returns the entries below this directory whose full paths match the
+    /// glob `pattern`, with those paths, in the order walk() visits them.
+    /// 
+    pub fn glob(&self, pattern: &str) -> Vec<(String, FSEntry)> {
+        self.glob_with(pattern, GlobOptions::default())
+    }
+    /// Like glob(), with options, such as ignoring case.
+    pub fn glob_with(&self, pattern: &str, opts: GlobOptions)
+        -> Vec<(String, FSEntry)>
+    {
+        let glob = Glob::new_with(pattern, opts);
+        self.walk().filter(|(path, _)| glob.matches(path)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn patterns_match_whole_paths() {
+        let cases = [
+            ("**/*.log",           "/a/b/c.log",          true),
+            ("**/*.log",           "/c.log",              true),
+            ("**/*.log",           "/a/c.log.gz",         false),
+            ("*.log",              "/c.log",              true),
+            ("*.log",              "/a/c.log",            false),
+            ("/a/*/c",             "/a/b/c",              true),
+            ("/a/*/c",             "/a/b/x/c",            false),
+            ("/a/**/c",            "/a/c",                true),
+            ("/a/**/c",            "/a/b/x/c",            true),
+            ("**",                 "/any/depth/at/all",   true),
+            ("**/node_modules",    "/x/node_modules",     true),
+            ("**/node_modules",    "/x/node_modules/y",   false),
+            ("**/node_modules/**", "/x/node_modules/y/z", true),
+            ("a?c",                "/abc",                true),
+            ("a?c",                "/ac",                 false),
+            ("a?c",                "/a/c",                false),
+            ("[a-c]x",             "/bx",                 true),
+            ("[!a-c]x",            "/bx",                 false),
+            ("[^a-c]x",            "/dx",                 true),
+            ("[]]x",               "/]x",                 true),
+            ("[a",                 "/[a",                 true),
+            ("\\*",                "/*",                  true),
+            ("\\*",                "/a",                  false),
+            ("a*b*c",              "/aXbYbc",             true),
+            ("a*b*c",              "/aXbYbd",             false),
+            ("a***",               "/abc",                true),
+            ("a",                  "a",                   true),
+            ("*",                  "/a/b",                false),
+            ("*.LOG",              "/a.log",              false),
+        ];
+        for (pattern, path, expected) in cases {
+            assert_eq!(Glob::new(pattern).matches(path), expected, 
+                       "{} on {}", pattern, path);
+        }
+    }
+
+    #[test]
+    fn fold_case_ignores_case_on_both_sides() {
+        let opts = GlobOptions { fold_case: true };
+        assert!(Glob::new_with("*.LOG", opts).matches("/a.log"));
+        assert!(Glob::new_with("**/Node_Modules", opts)
+                    .matches("/x/NODE_MODULES"));
+        assert!(!Glob::new_with("[A-C]", opts).matches("/d"));
+    }
+
+    #[test]
+    fn glob_lists_matches_in_walk_order() {
+        let root = FSDir::new("/".into());
+        root.add_file_at_path("b/x.log", 1);
+        root.add_file_at_path("a/y.log", 2);
+        root.add_file_at_path("a/z.txt", 3);
+        let paths = root.glob("**/*.log")
+                        .into_iter()
+                        .map(|(path, _)| path)
+                        .collect::<Vec<_>>();
+        assert_eq!(paths, ["/a/y.log", "/b/x.log"]);
+        assert_eq!(root.glob("/a").len(), 1);
+    }
+}
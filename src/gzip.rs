@@ -0,0 +1,105 @@
+//! This module reads gzip-compressed session logs as they're decompressed, 
+//! so they don't have to be unpacked to disk first. It's built with the 
+//! `gzip` feature, and the decoding itself is done by flate2.
+//! 
+//! Files made of several gzip members one after the other, as `cat a.gz b.gz`
+//! produces, are read in turn, and each member's CRC-32 and length is checked.
+
+use std::io::{self, BufRead, BufReader};
+use flate2::bufread::MultiGzDecoder;
+
+/// The first two bytes of every gzip member.
+const MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Returns true if what's left to read from `reader` starts with the gzip 
+/// magic bytes. Nothing is consumed.
+/// 
+pub fn is_gzip<R: BufRead>(reader: &mut R) -> io::Result<bool> {
+    Ok(reader.fill_buf()?.starts_with(&MAGIC))
+}
+
+/// Returns `reader` wrapped in a gzip decoder if its content starts with the 
+/// gzip magic bytes, or as it is otherwise. Only the content is looked at, 
+/// not any file name, so this works for standard input too.
+/// 
+pub fn gunzip_if_gzip<'a, R>(mut reader: R) -> io::Result<Box<dyn BufRead + 'a>>
+where
+    R: BufRead + 'a
+{
+    if is_gzip(&mut reader)? {
+        Ok(Box::new(BufReader::new(MultiGzDecoder::new(reader))))
+    } else {
+        Ok(Box::new(reader))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::path::PathBuf;
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+    use crate::analysis::*;
+    use crate::fsobject::*;
+    use crate::parser::*;
+
+    fn fixture(name: &str) -> PathBuf {
+        [env!("CARGO_MANIFEST_DIR"), "data", name].iter().collect()
+    }
+
+    fn gunzip(bytes: &[u8]) -> io::Result<Vec<u8>> {
+        let mut out = vec![];
+        gunzip_if_gzip(bytes)?.read_to_end(&mut out)?;
+        Ok(out)
+    }
+
+    /// Returns the answers to both parts of the puzzle for the session in
+    /// the fixture `name`.
+    fn answers(name: &str) -> (u64, Option<u64>) {
+        let root = build_fs_from_path(&fixture(name)).unwrap();
+        (sum_dirs_at_most(&root, 100_000),
+         smallest_dir_to_free(&root, 70_000_000, 30_000_000).map(|d| d.size()))
+    }
+
+    #[test]
+    fn compressed_fixtures_give_the_same_answers() {
+        assert_eq!(answers("sample.txt.gz"), (95437, Some(24933642)));
+        assert_eq!(answers("sample.txt.gz"), answers("sample.txt"));
+        assert_eq!(answers("data.txt.gz"), answers("data.txt"));
+        let plain  = build_fs_from_path(&fixture("data.txt")).unwrap();
+        let packed = build_fs_from_path(&fixture("data.txt.gz")).unwrap();
+        assert!(packed.structural_eq(&plain));
+    }
+
+    #[test]
+    fn concatenated_members_are_read_in_turn() {
+        let packed = std::fs::read(fixture("sample.txt.gz")).unwrap();
+        let plain  = std::fs::read(fixture("sample.txt")).unwrap();
+        let twice  = [packed.as_slice(), &packed].concat();
+        let both   = [plain.as_slice(), &plain].concat();
+        assert_eq!(gunzip(&twice).unwrap(), both);
+    }
+
+    #[test]
+    fn corrupt_members_are_an_error() {
+        let data    = b"$ cd /\n$ ls\n12 a\n";
+        let mut enc = GzEncoder::new(vec![], Compression::default());
+        enc.write_all(data).unwrap();
+        let mut gz  = enc.finish().unwrap();
+        assert_eq!(gunzip(&gz).unwrap(), data);
+        let crc_at = gz.len() - 8;
+        gz[crc_at] ^= 1;
+        assert!(gunzip(&gz).is_err());
+    }
+
+    #[test]
+    fn plain_input_passes_through() {
+        let text       = b"$ cd /\n";
+        let mut reader = gunzip_if_gzip(&text[..]).unwrap();
+        let mut out    = vec![];
+        reader.read_to_end(&mut out).unwrap();
+        assert_eq!(out, text);
+        assert!(!is_gzip(&mut &text[..]).unwrap());
+    }
+}
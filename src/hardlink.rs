@@ -0,0 +1,127 @@
+//! This module implements sizes that count hard links once. A file with 
+//! several hard links is listed once per name, and each listing counts in
+//! full toward FSObject::size(), as `du --count-links` would count it. Files
+//! that share a link id (see FSFile::link_id()) are the same data, so the 
+//! functions here count each id once, the way `df` sees the space. Files 
+//! without a link id are taken to be unique.
+
+use std::collections::{HashMap, HashSet};
+use crate::fsobject::*;
+
+impl FSDir {
+    /// Returns the size of this directory with every link id below it
+    /// counted once, for the first file that has it in the order walk() 
+    /// visits them. It's the same as size() if no two files share an id.
+    /// 
+    pub fn deduplicated_size(&self) -> u64 {
+        let mut seen = HashSet::new();
+        self.fold(0, |total, _, entry| match entry {
+            FSEntry::File(file) => match file.link_id() {
+                Some(id) if !seen.insert(id) => total,
+                _                            => total + file.size(),
+            },
+            FSEntry::Link(link) => total + link.size(),
+            FSEntry::Dir(_)     => total,
+        })
+    }
+    /// Returns the `n` directories below this one with the largest 
+    /// deduplicated sizes, with those sizes, largest first, with ties broken
+    /// by path.
+    /// 
+    pub fn largest_dirs_deduplicated(&self, n: usize) -> Vec<(FSDir, u64)> {
+        let sizes = self.deduplicated_sizes();
+        top_n_by(n, self.walk().filter_map(|(path, entry)| match entry {
+            FSEntry::Dir(dir) => Some((path, sizes[&dir.id()], dir)),
+            _                 => None,
+        }))
+    }
+    /// Returns the `n` largest files below this directory, largest first, 
+    /// with ties broken by path, leaving out every file whose link id an 
+    /// earlier file in walk() order has, so each is listed under one name.
+    /// 
+    pub fn largest_files_deduplicated(&self, n: usize) -> Vec<(FSFile, u64)> {
+        let mut seen = HashSet::new();
+        top_n_by(n, self.walk().filter_map(|(path, entry)| match entry {
+            FSEntry::File(file) => match file.link_id() {
+                Some(id) if !seen.insert(id) => None,
+                _                            => Some((path, file.size(), file)),
+            },
+            _ => None,
+        }))
+    }
+    /// Returns the deduplicated size of every directory in the tree this
+    /// directory is in, from its root down, keyed by their ids. Each file 
+    /// is added to every directory above it that hasn't already counted its
+    /// link id.
+    /// 
+    pub(crate) fn deduplicated_sizes(&self) -> HashMap<usize, u64> {
+        let root      = self.root();
+        let mut sizes = HashMap::new();
+        let mut seen  = HashSet::new();
+        for (_, dir) in root.walk_bfs() {
+            sizes.entry(dir.id()).or_insert(0);
+            for child in dir.children() {
+                let (size, id) = match &child {
+                    FSEntry::File(file) => (file.size(), file.link_id()),
+                    FSEntry::Link(link) => (link.size(), None),
+                    FSEntry::Dir(_)     => continue,
+                };
+                let mut above = Some(dir.clone());
+                while let Some(d) = above {
+                    if id.is_none_or(|id| seen.insert((d.id(), id))) {
+                        *sizes.entry(d.id()).or_insert(0) += size;
+                    }
+                    above = d.parent();
+                }
+            }
+        }
+        sizes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::*;
+
+    /// Builds a tree where /a/x, /b/x2 and /b/c/x3 are hard links to one
+    /// 100 byte file, beside /a/y, /w and /z, which have no link id.
+    fn linked_tree() -> FSDir {
+        let root = FSDir::new("/".into());
+        let file = |dir: &FSDir, name: &str, size, id| {
+            dir.add_file(FSFile::new(name.into(), size)).set_link_id(id);
+        };
+        let a    = root.make_dirs("a");
+        file(&a, "x", 100, Some(7));
+        file(&a, "y", 10, None);
+        file(&root.make_dirs("b"), "x2", 100, Some(7));
+        file(&root.make_dirs("b/c"), "x3", 100, Some(7));
+        file(&root, "w", 5, None);
+        file(&root, "z", 5, None);
+        root
+    }
+
+    fn paths<T: FSObject>(ranked: Vec<(T, u64)>) -> Vec<(String, u64)> {
+        ranked.into_iter().map(|(obj, size)| (obj.path(), size)).collect()
+    }
+
+    #[test]
+    fn three_links_to_one_file_count_once() {
+        let root = linked_tree();
+        assert_eq!((root.size(), root.deduplicated_size()), (320, 120));
+        let b = root.get_dir("b").unwrap();
+        assert_eq!((b.size(), b.deduplicated_size()), (200, 100));
+        assert_eq!(paths(root.largest_dirs_deduplicated(10)), 
+                   [("/a".into(), 110), ("/b".into(), 100), 
+                    ("/b/c".into(), 100)]);
+        assert_eq!(paths(root.largest_files_deduplicated(10)),
+                   [("/a/x".into(), 100), ("/a/y".into(), 10), 
+                    ("/w".into(), 5), ("/z".into(), 5)]);
+
+        let opts = DuOptions { dedup_hardlinks: true, ..Default::default() };
+        assert_eq!(root.du_report_with(opts).unwrap(),
+                   "120\t/\n110\t/a\n100\t/b\n100\t/b/c\n");
+        assert_eq!(root.du_report_with(DuOptions::default()).unwrap(),
+                   "320\t/\n110\t/a\n200\t/b\n100\t/b/c\n");
+    }
+}
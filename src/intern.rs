@@ -0,0 +1,38 @@
+//! This module implements NameInterner, which hands out one shared Rc<str>
+//! per distinct name. Real trees repeat the same few names ("src", ".git",
+//! "Cargo.toml") over and over, and FSDir and FSFile store their names as
+//! Rc<str>, so building them from interned names stores each of those once.
+//! Interned names compare and sort exactly like any other.
+
+use std::collections::HashSet;
+use std::rc::Rc;
+
+/// A set of shared names. intern() returns the same Rc<str> for equal names.
+/// 
+#[derive(Debug, Clone, Default)]
+pub struct NameInterner {
+    names: HashSet<Rc<str>>,
+}
+impl NameInterner {
+    /// Creates an interner holding no names.
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Returns the shared copy of `name`, adding it first if it's new.
+    pub fn intern(&mut self, name: &str) -> Rc<str> {
+        if let Some(shared) = self.names.get(name) {
+            return shared.clone();
+        }
+        let shared = Rc::<str>::from(name);
+        self.names.insert(shared.clone());
+        shared
+    }
+    /// Returns the number of distinct names interned.
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+    /// Returns true if no names have been interned.
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+}
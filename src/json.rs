@@ -0,0 +1,438 @@
+//! This module implements a small JSON reader and writer, for the CLI's 
+//! `--json` output and for ncdu's export format (see the ncdu module). The
+//! reader is iterative, so deeply nested documents don't overflow the stack.
+//! 
+//! Saving and loading whole trees, with FSDir::to_json() and from_json(), is
+//! done with serde instead; see the persist module.
+
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use crate::fsobject::*;
+
+/// A parsed JSON value. Numbers are kept as their source text so that large
+/// integers survive without going through floating point.
+/// 
+#[derive(Debug, Clone, PartialEq)]
+pub enum JsonValue {
+    Null,
+    Bool(bool),
+    Number(String),
+    String(String),
+    Array(Vec<JsonValue>),
+    Object(Vec<(String, JsonValue)>),
+}
+impl JsonValue {
+    /// Parse a JSON document.
+    pub fn parse(text: &str) -> Result<JsonValue, JsonError> {
+        Parser { text: text.as_bytes(), pos: 0 }.parse()
+    }
+    /// Returns the value of the given key if this is an object that has it.
+    pub fn get(&self, key: &str) -> Option<&JsonValue> {
+        match self {
+            JsonValue::Object(members) => {
+                members.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+            },
+            _ => None,
+        }
+    }
+    /// Returns the string if this is a string.
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            JsonValue::String(s) => Some(s),
+            _ => None,
+        }
+    }
+    /// Returns the number if this is a non-negative integer that fits.
+    pub fn as_usize(&self) -> Option<usize> {
+        match self {
+            JsonValue::Number(n) => n.parse().ok(),
+            _ => None,
+        }
+    }
+    /// Returns the number if this is a non-negative integer that fits in a 
+    /// u64, as file sizes do.
+    /// 
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            JsonValue::Number(n) => n.parse().ok(),
+            _ => None,
+        }
+    }
+    /// Returns the elements if this is an array.
+    pub fn as_array(&self) -> Option<&[JsonValue]> {
+        match self {
+            JsonValue::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+}
+
+/// Values are torn down iteratively, since the default recursive drop would
+/// overflow the stack on deeply nested documents.
+/// 
+impl Drop for JsonValue {
+    fn drop(&mut self) {
+        fn take_children(value: &mut JsonValue, out: &mut Vec<JsonValue>) {
+            match value {
+                JsonValue::Array(items) => out.append(items),
+                JsonValue::Object(members) => {
+                    out.extend(members.drain(..).map(|(_, v)| v));
+                },
+                _ => {},
+            }
+        }
+        let mut stack = vec![];
+        take_children(self, &mut stack);
+        while let Some(mut value) = stack.pop() {
+            take_children(&mut value, &mut stack);
+        }
+    }
+}
+
+/// An error encountered while reading JSON, or while loading a tree from it.
+/// 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JsonError {
+    /// The text isn't valid JSON; `offset` is the byte where reading failed.
+    Syntax { offset: usize, message: String },
+    /// The document is valid JSON but doesn't have the expected shape.
+    Shape(String),
+    /// An entry couldn't be added to the tree being loaded, because a size
+    /// would overflow or a quota would be exceeded.
+    Growth(GrowthError),
+}
+impl JsonError {
+    /// A syntax error at byte `offset`.
+    fn at(offset: usize, message: &str) -> Self {
+        JsonError::Syntax { offset, message: message.into() }
+    }
+    /// A valid document that doesn't have the expected shape.
+    pub(crate) fn shape(message: &str) -> Self {
+        JsonError::Shape(message.into())
+    }
+}
+impl Display for JsonError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JsonError::Syntax { offset, message } => {
+                write!(f, "invalid JSON at byte {}: {}", offset, message)
+            },
+            JsonError::Shape(message) => {
+                write!(f, "unexpected JSON: {}", message)
+            },
+            JsonError::Growth(e) => write!(f, "can't load the tree: {}", e),
+        }
+    }
+}
+impl Error for JsonError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            JsonError::Growth(e) => Some(e),
+            _                    => None,
+        }
+    }
+}
+impl From<GrowthError> for JsonError {
+    fn from(e: GrowthError) -> Self {
+        JsonError::Growth(e)
+    }
+}
+
+/// Returns `text` as a quoted JSON string, with quotes, backslashes, and
+/// control characters escaped.
+/// 
+pub fn json_string(text: &str) -> String {
+    let mut out = String::with_capacity(text.len() + 2);
+    out.push('"');
+    for c in text.chars() {
+        match c {
+            '"'  => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                out.push_str(&format!("\\u{:04x}", c as u32));
+            },
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Returns `,"mtime":N` for a modification time, to follow the other members
+/// of an object, or nothing without one.
+/// 
+pub(crate) fn mtime_member(mtime: Option<u64>) -> String {
+    mtime.map(|t| format!(",\"mtime\":{}", t)).unwrap_or_default()
+}
+
+/// Returns the "mtime" member of the object `value`, if it has one. Fails if
+/// it isn't a whole number of seconds.
+/// 
+pub(crate) fn mtime_of(value: &JsonValue) -> Result<Option<u64>, JsonError> {
+    value.get("mtime")
+         .map(|t| t.as_u64().ok_or_else(|| {
+             JsonError::shape("'mtime' isn't a whole number")
+         }))
+         .transpose()
+}
+
+/// A container being filled in by the parser.
+enum Frame {
+    Array(Vec<JsonValue>),
+    Object(Vec<(String, JsonValue)>, String),
+}
+
+/// The state of the JSON parser.
+struct Parser<'a> {
+    text : &'a [u8],
+    pos  : usize,
+}
+impl Parser<'_> {
+    /// Parse the whole document. Containers are tracked on an explicit stack
+    /// rather than by recursion.
+    /// 
+    fn parse(mut self) -> Result<JsonValue, JsonError> {
+        let mut stack: Vec<Frame> = vec![];
+        loop {
+            // Read the start of a value. Containers are pushed and we go round
+            // again for their first element, unless they're empty.
+            self.skip_ws();
+            let mut value = match self.peek() {
+                Some(b'[') => {
+                    self.pos += 1;
+                    self.skip_ws();
+                    if self.eat(b']') {
+                        JsonValue::Array(vec![])
+                    } else {
+                        stack.push(Frame::Array(vec![]));
+                        continue;
+                    }
+                },
+                Some(b'{') => {
+                    self.pos += 1;
+                    self.skip_ws();
+                    if self.eat(b'}') {
+                        JsonValue::Object(vec![])
+                    } else {
+                        let key = self.key()?;
+                        stack.push(Frame::Object(vec![], key));
+                        continue;
+                    }
+                },
+                _ => self.scalar()?,
+            };
+            // Hand the finished value to its container, closing containers
+            // for as long as they end.
+            loop {
+                let Some(frame) = stack.last_mut() else {
+                    self.skip_ws();
+                    if self.pos < self.text.len() {
+                        return Err(self.error("trailing characters"));
+                    }
+                    return Ok(value);
+                };
+                self.skip_ws();
+                match frame {
+                    Frame::Array(items) => {
+                        items.push(value);
+                        if self.eat(b',') {
+                            break;
+                        }
+                        if !self.eat(b']') {
+                            return Err(self.error("expected ',' or ']'"));
+                        }
+                    },
+                    Frame::Object(members, key) => {
+                        members.push((std::mem::take(key), value));
+                        if self.eat(b',') {
+                            self.skip_ws();
+                            *key = self.key()?;
+                            break;
+                        }
+                        if !self.eat(b'}') {
+                            return Err(self.error("expected ',' or '}'"));
+                        }
+                    },
+                }
+                value = match stack.pop().unwrap() {
+                    Frame::Array(items)       => JsonValue::Array(items),
+                    Frame::Object(members, _) => JsonValue::Object(members),
+                };
+            }
+        }
+    }
+    /// Read an object key and the ':' after it.
+    fn key(&mut self) -> Result<String, JsonError> {
+        let key = self.string()?;
+        self.skip_ws();
+        if !self.eat(b':') {
+            return Err(self.error("expected ':'"));
+        }
+        Ok(key)
+    }
+    /// Read a string, number, or literal.
+    fn scalar(&mut self) -> Result<JsonValue, JsonError> {
+        match self.peek() {
+            Some(b'"') => Ok(JsonValue::String(self.string()?)),
+            Some(b't') => self.literal("true",  JsonValue::Bool(true)),
+            Some(b'f') => self.literal("false", JsonValue::Bool(false)),
+            Some(b'n') => self.literal("null",  JsonValue::Null),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            Some(_)    => Err(self.error("unexpected character")),
+            None       => Err(self.error("unexpected end of input")),
+        }
+    }
+    fn literal(&mut self, word: &str, value: JsonValue)
+        -> Result<JsonValue, JsonError>
+    {
+        if self.text[self.pos..].starts_with(word.as_bytes()) {
+            self.pos += word.len();
+            Ok(value)
+        } else {
+            Err(self.error("unknown literal"))
+        }
+    }
+    fn number(&mut self) -> Result<JsonValue, JsonError> {
+        let start = self.pos;
+        self.eat(b'-');
+        if self.digits() == 0 {
+            return Err(self.error("expected digits"));
+        }
+        if self.eat(b'.') && self.digits() == 0 {
+            return Err(self.error("expected digits after '.'"));
+        }
+        if self.eat(b'e') || self.eat(b'E') {
+            let _ = self.eat(b'+') || self.eat(b'-');
+            if self.digits() == 0 {
+                return Err(self.error("expected exponent digits"));
+            }
+        }
+        let text = std::str::from_utf8(&self.text[start..self.pos]).unwrap();
+        Ok(JsonValue::Number(text.into()))
+    }
+    /// Skip over digits, returning how many there were.
+    fn digits(&mut self) -> usize {
+        let start = self.pos;
+        while matches!(self.peek(), Some(b'0'..=b'9')) {
+            self.pos += 1;
+        }
+        self.pos - start
+    }
+    fn string(&mut self) -> Result<String, JsonError> {
+        if !self.eat(b'"') {
+            return Err(self.error("expected a string"));
+        }
+        let mut bytes = vec![];
+        loop {
+            match self.peek() {
+                None => return Err(self.error("unterminated string")),
+                Some(b'"') => {
+                    self.pos += 1;
+                    break;
+                },
+                Some(b'\\') => {
+                    self.pos += 1;
+                    let simple = match self.peek() {
+                        Some(b'"')  => Some('"'),
+                        Some(b'\\') => Some('\\'),
+                        Some(b'/')  => Some('/'),
+                        Some(b'b')  => Some('\u{8}'),
+                        Some(b'f')  => Some('\u{c}'),
+                        Some(b'n')  => Some('\n'),
+                        Some(b'r')  => Some('\r'),
+                        Some(b't')  => Some('\t'),
+                        Some(b'u')  => None,
+                        _ => return Err(self.error("bad escape")),
+                    };
+                    self.pos += 1;
+                    let c = match simple {
+                        Some(c) => c,
+                        None    => self.unicode_escape()?,
+                    };
+                    bytes.extend(c.encode_utf8(&mut [0; 4]).as_bytes());
+                },
+                Some(b) => {
+                    bytes.push(b);
+                    self.pos += 1;
+                },
+            }
+        }
+        String::from_utf8(bytes).map_err(|_| self.error("invalid UTF-8"))
+    }
+    /// Read the hex digits of a \u escape, and the low half of a surrogate
+    /// pair if there is one. Leaves the position just past the escape.
+    /// 
+    fn unicode_escape(&mut self) -> Result<char, JsonError> {
+        let hi = self.hex4()?;
+        if !(0xd800..0xdc00).contains(&hi) {
+            return char::from_u32(hi).ok_or_else(|| self.error("bad escape"));
+        }
+        if !(self.eat(b'\\') && self.eat(b'u')) {
+            return Err(self.error("unpaired surrogate"));
+        }
+        let lo = self.hex4()?;
+        if !(0xdc00..0xe000).contains(&lo) {
+            return Err(self.error("unpaired surrogate"));
+        }
+        let c = 0x10000 + ((hi - 0xd800) << 10) + (lo - 0xdc00);
+        char::from_u32(c).ok_or_else(|| self.error("bad escape"))
+    }
+    fn hex4(&mut self) -> Result<u32, JsonError> {
+        let digits = self.text.get(self.pos..self.pos + 4)
+                              .and_then(|d| std::str::from_utf8(d).ok())
+                              .and_then(|d| u32::from_str_radix(d, 16).ok())
+                              .ok_or_else(|| self.error("bad \\u escape"))?;
+        self.pos += 4;
+        Ok(digits)
+    }
+    fn skip_ws(&mut self) {
+        while matches!(self.peek(), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+            self.pos += 1;
+        }
+    }
+    fn peek(&self) -> Option<u8> {
+        self.text.get(self.pos).copied()
+    }
+    /// Consume `b` if it's next.
+    fn eat(&mut self, b: u8) -> bool {
+        if self.peek() == Some(b) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+    fn error(&self, message: &str) -> JsonError {
+        JsonError::at(self.pos, message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overflowing_sizes_are_an_error() {
+        let max  = u64::MAX;
+        let ncdu = format!("[1,0,{{}},[{{\"name\":\"/\"}},\
+                            {{\"name\":\"a\",\"asize\":{}}},\
+                            {{\"name\":\"b\",\"asize\":{}}}]]", max, max);
+        match FSDir::from_ncdu_json(&ncdu) {
+            Err(JsonError::Growth(GrowthError::Overflow(e))) => {
+                assert_eq!(e.path, "/");
+            },
+            other => panic!("expected an overflow, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn errors_say_where_reading_failed() {
+        assert!(matches!(JsonValue::parse("{\"a\":}"), 
+                         Err(JsonError::Syntax { offset: 5, .. })));
+        assert!(matches!(FSDir::from_ncdu_json("[]"), 
+                         Err(JsonError::Shape(_))));
+    }
+}
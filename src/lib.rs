@@ -0,0 +1,35 @@
+//! A library for rebuilding a file system from the terminal output of a 
+//! session of `cd` and `ls` commands, as in day 7 of the 2022 Advent of Code,
+//! and for asking questions about it.
+
+pub mod aggregate;
+pub mod analysis;
+pub mod arena;
+pub mod csv;
+pub mod dot;
+pub mod filter;
+pub mod flame;
+pub mod fsdiff;
+pub mod fsobject;
+pub mod glob;
+#[cfg(feature = "gzip")]
+pub mod gzip;
+pub mod hardlink;
+pub mod intern;
+pub mod json;
+pub mod ncdu;
+pub mod oplog;
+pub mod parser;
+#[cfg(feature = "serde")]
+pub mod persist;
+pub mod putback_iter;
+pub mod records;
+pub mod render;
+pub mod scan;
+pub mod shell;
+pub mod streaming;
+pub mod sync;
+pub mod testing;
+pub mod units;
+pub mod usage;
+pub mod visit;
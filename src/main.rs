@@ -1,127 +1,1126 @@
-/// Implements a solution for day 7 of the 2022 Advent of Code.
+//! Implements a solution for day 7 of the 2022 Advent of Code.
 
+use std::collections::HashSet;
+use std::env;
 use std::error::Error;
 use std::fs::File;
-use std::io::{BufReader, BufRead};
-use fsobject::*;
-use putback_iter::*;
+use std::io::{self, IsTerminal};
+use std::path::Path;
+use std::process;
+use std::str::FromStr;
+use no_space_left_on_device::analysis::*;
+use no_space_left_on_device::csv::*;
+use no_space_left_on_device::flame::*;
+use no_space_left_on_device::fsdiff::*;
+use no_space_left_on_device::fsobject::*;
+use no_space_left_on_device::glob::*;
+use no_space_left_on_device::json::*;
+use no_space_left_on_device::parser::*;
+use no_space_left_on_device::render::*;
+use no_space_left_on_device::shell::*;
+use no_space_left_on_device::usage::*;
 
-mod fsobject;
-mod putback_iter;
+/// The usage text printed for `--help`.
+const USAGE: &str = "\
+usage: no-space-left-on-device [COMMAND] [INPUT...] [OPTIONS]
 
-fn main() -> Result<(), Box<dyn Error>> {
-    println!("part_1: {:>10}", part_1()?);
-    println!("part_2: {:>10}", part_2()?);
-    Ok(())
+Rebuilds a file system from a terminal session of cd and ls commands in
+INPUT (default data/data.txt, or - for standard input) and reports on it.
+The sessions in several INPUTs are merged into one file system. With no
+command, both answers to the puzzle are printed.
+
+commands:
+  part1             print the answer to part 1
+  part2             print the answer to part 2
+  tree              print the tree, in the puzzle's format
+  du                print the size and path of each directory
+  bars              chart the sizes of the directories as bars
+  top               list the largest directories and files
+  diff OLD NEW      list what changed between two sessions, biggest first
+  dupes             list files that share a name and size, most wasteful first
+  find TEXT         list the directories and files whose names contain TEXT,
+                    with their sizes, and the total; TEXT with *, ? or [ in
+                    it is a glob, like *.log, matched against whole names,
+                    or whole paths if it has a / too, like /a/**/*.log
+  stats             summarize the tree: counts, sizes, depth, and how many
+                    files there are of each order of magnitude of size
+  stale             list the files last modified before --older-than, at
+                    least --min-size bytes, biggest and then oldest first
+  by-ext            total the sizes of files by extension, biggest first
+  shell             explore the tree with commands read from standard input,
+                    like cd, ls, du, and rm; try help once it's running
+  plan              list the directories to delete to free enough space for
+                    the update, deleting as little as possible
+
+options:
+  --threshold N     part 1: largest directory counted (default 100000)
+                    du: smallest directory listed (default 0)
+  --device-size N   size of the device in part 2 (default 70000000)
+  --update-size N   size of the update in part 2 (default 30000000)
+  --depth N         tree, du: don't go deeper than N levels below the root
+                    bars: chart N levels below the root (default 1)
+  --width N         bars: width of the longest bar (default 40)
+  --with-files      bars: chart files as well as directories
+  --percent         tree, du: show each entry's share of its parent and of
+                    the whole tree
+  --block-size N    du, top: report the space used on a disk that stores
+                    files in blocks of N bytes, not the bytes in the files
+  --dedup-hardlinks du, top: count each file once, however many hard links
+                    it has, where the input says which files are linked
+  --dirs N          top: list the N largest directories
+  --files N         top: list the N largest files
+  --skip-empty      dupes: leave out empty files
+  --only GLOB       leave out every file whose path doesn't match GLOB, like
+                    **/*.log, and directories left with nothing in them;
+                    * and ? match within a path component, [a-z] one
+                    character of a class, and ** any number of components
+  --regex           find: match names against TEXT as a regular expression,
+                    like ^node_modules$ (in builds with the regex feature)
+  --fold-case       by-ext: total extensions that differ only in case together
+                    --only, find with a glob: ignore case when matching
+  --need N          plan: bytes to free, instead of what the update needs
+  --mtimes          read files in ls output as SIZE MTIME NAME, with the
+                    time each was last modified in seconds since 1970
+  --older-than T    stale: list files last modified before T, in seconds
+                    since 1970
+  --min-size N      stale: leave out files smaller than N (default 0)
+  --top N           list the N largest directories and files, after the
+                    answers or with top (top lists 10 of each by default)
+  --what-if PATH    after the answers, show how much space deleting the
+                    directory at PATH would free, and whether the update
+                    would then fit
+  --csv PATH        also write every directory and file to PATH as CSV
+  --flame PATH      also write the tree to PATH as collapsed stacks, for
+                    flamegraph.pl or inferno
+  --sort KEY        tree, du, shell: order entries by KEY, name or size;
+                    by size, the largest come first (tree and du list by
+                    name by default, and the shell's ls by size)
+  --reverse         tree, du, shell: reverse the order
+  --name-sort       the same as --sort name
+  --progress        report how far parsing has got on standard error
+  --json            print the results as JSON, and nothing else
+  --help            print this help and exit
+
+An INPUT named like a command must be given as a path, like ./tree.";
+
+/// What the program was asked to do.
+/// 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum Command {
+    /// Print the answers to both parts.
+    #[default]
+    Answers,
+    /// Print the answer to part 1 alone.
+    Part1,
+    /// Print the answer to part 2 alone.
+    Part2,
+    /// Print the tree.
+    Tree,
+    /// Print a `du` style report of directory sizes.
+    Du,
+    /// Print a bar chart of directory sizes.
+    Bars,
+    /// Print tables of the largest directories and files.
+    Top,
+    /// Print the differences between two trees.
+    Diff,
+    /// Print a plan of directories to delete.
+    Plan,
+    /// Print the total size of files by extension.
+    ByExt,
+    /// Print groups of likely duplicate files.
+    Dupes,
+    /// Print the entries whose names contain some text.
+    Find,
+    /// Print a summary of the tree.
+    Stats,
+    /// Print the big files that haven't been modified for a while.
+    Stale,
+    /// Run an interactive shell over the tree.
+    Shell,
 }
 
-/// Find the total size of all directories that have a size less than or equal 
-/// to 100,000 and return the sum of their sizes.
+/// The settings given on the command line.
 /// 
-fn part_1() -> Result<usize, Box<dyn Error>> {
-    let root = build_fs()?;
-    let dirs = root.find_dirs_recurs_by(&|d| d.size() <= 100000);
-    let sum  = dirs.iter().map(|d| d.size()).sum();
-    Ok(sum)
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Config {
+    command     : Command,
+    paths       : Vec<String>,
+    threshold   : Option<u64>,
+    device_size : u64,
+    update_size : u64,
+    depth       : Option<usize>,
+    width       : usize,
+    with_files  : bool,
+    top_dirs    : Option<usize>,
+    top_files   : Option<usize>,
+    need        : Option<u64>,
+    older_than  : Option<u64>,
+    min_size    : u64,
+    mtimes      : bool,
+    fold_case   : bool,
+    skip_empty  : bool,
+    percent     : bool,
+    block_size  : Option<u64>,
+    dedup_links : bool,
+    sort        : Option<SortKey>,
+    reverse     : bool,
+    progress    : bool,
+    what_if     : Option<String>,
+    csv         : Option<String>,
+    flame       : Option<String>,
+    only        : Option<String>,
+    needle      : Option<String>,
+    regex       : bool,
+    json        : bool,
+    help        : bool,
+}
+impl Default for Config {
+    fn default() -> Self {
+        Self { command     : Command::default(),
+               paths       : vec!["data/data.txt".into()],
+               threshold   : None,
+               device_size : 70_000_000,
+               update_size : 30_000_000,
+               depth       : None,
+               width       : 40,
+               with_files  : false,
+               top_dirs    : None,
+               top_files   : None,
+               need        : None,
+               older_than  : None,
+               min_size    : 0,
+               mtimes      : false,
+               fold_case   : false,
+               skip_empty  : false,
+               percent     : false,
+               block_size  : None,
+               dedup_links : false,
+               sort        : None,
+               reverse     : false,
+               progress    : false,
+               what_if     : None,
+               csv         : None,
+               flame       : None,
+               only        : None,
+               needle      : None,
+               regex       : false,
+               json        : false,
+               help        : false }
+    }
 }
 
-/// Find the size of the smallest directory needs to be deleted to accommodate
-/// a 30MB update.
+fn main() {
+    let args   = env::args().skip(1).collect::<Vec<_>>();
+    let config = match parse_args(&args) {
+        Ok(config) => config,
+        Err(msg)   => {
+            eprintln!("error: {}", msg);
+            eprintln!("try --help for usage");
+            process::exit(2);
+        },
+    };
+    if config.help {
+        println!("{}", USAGE);
+        return;
+    }
+    if let Err(e) = run(&config) {
+        eprintln!("error: {}", e);
+        process::exit(1);
+    }
+}
+
+/// Parse the command line arguments, not including the program name, into a
+/// Config. Returns a message saying what's wrong if they can't be parsed.
 /// 
-fn part_2() -> Result<usize, Box<dyn Error>> {
-    let device_size = 70_000_000_usize;
-    let update_size = 30_000_000_usize;
+fn parse_args(args: &[String]) -> Result<Config, String> {
+    let mut config = Config::default();
+    let mut paths  = vec![];
+    let mut first  = true;
+    let mut args   = args.iter();
 
-    let root  = build_fs()?;
-    let taken = root.size();
-    let avail = device_size - taken;
-    let need  = update_size.saturating_sub(avail);
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next().ok_or_else(|| format!("{} needs a value", arg))
+        };
+        let command = match arg.as_str() {
+            "part1"  => Some(Command::Part1),
+            "part2"  => Some(Command::Part2),
+            "tree"   => Some(Command::Tree),
+            "du"     => Some(Command::Du),
+            "bars"   => Some(Command::Bars),
+            "top"    => Some(Command::Top),
+            "diff"   => Some(Command::Diff),
+            "plan"   => Some(Command::Plan),
+            "by-ext" => Some(Command::ByExt),
+            "dupes"  => Some(Command::Dupes),
+            "find"   => Some(Command::Find),
+            "stats"  => Some(Command::Stats),
+            "stale"  => Some(Command::Stale),
+            "shell"  => Some(Command::Shell),
+            _        => None,
+        };
+        match arg.as_str() {
+            _ if first && command.is_some() => {
+                config.command = command.unwrap();
+            },
+            "--threshold"   => config.threshold = Some(number(arg, value()?)?),
+            "--device-size" => config.device_size = number(arg, value()?)?,
+            "--update-size" => config.update_size = number(arg, value()?)?,
+            "--depth"       => config.depth     = Some(number(arg, value()?)?),
+            "--width"       => config.width     = number(arg, value()?)?,
+            "--dirs"        => config.top_dirs  = Some(number(arg, value()?)?),
+            "--files"       => config.top_files = Some(number(arg, value()?)?),
+            "--need"        => config.need      = Some(number(arg, value()?)?),
+            "--older-than"  => {
+                config.older_than = Some(number(arg, value()?)?);
+            },
+            "--min-size"    => config.min_size = number(arg, value()?)?,
+            "--mtimes"      => config.mtimes = true,
+            "--block-size"  => {
+                config.block_size = Some(number(arg, value()?)?);
+            },
+            "--top"         => {
+                let n = number(arg, value()?)?;
+                config.top_dirs  = Some(n);
+                config.top_files = Some(n);
+            },
+            "--what-if"     => config.what_if = Some(value()?.clone()),
+            "--csv"         => config.csv = Some(value()?.clone()),
+            "--flame"       => config.flame = Some(value()?.clone()),
+            "--only"        => config.only = Some(value()?.clone()),
+            "--fold-case"   => config.fold_case = true,
+            "--regex"       => config.regex = true,
+            "--skip-empty"  => config.skip_empty = true,
+            "--with-files"  => config.with_files = true,
+            "--percent"     => config.percent = true,
+            "--dedup-hardlinks" => config.dedup_links = true,
+            "--sort"        => config.sort = Some(sort_key(value()?)?),
+            "--reverse"     => config.reverse = true,
+            "--name-sort"   => config.sort = Some(SortKey::Name),
+            "--progress"    => config.progress = true,
+            "--json"        => config.json = true,
+            "--help" | "-h" => config.help = true,
+            flag if flag.starts_with("--") => {
+                return Err(format!("unknown option: {}", flag));
+            },
+            _ => paths.push(arg.clone()),
+        }
+        first = false;
+    }
+    if config.command == Command::Find {
+        if paths.is_empty() {
+            return Err("find needs the text to look for".into());
+        }
+        config.needle = Some(paths.remove(0));
+    }
+    if config.regex && !cfg!(feature = "regex") {
+        return Err("--regex needs a build with the regex feature".into());
+    }
+    if !paths.is_empty() {
+        config.paths = paths;
+    }
+    if config.command == Command::Diff && config.paths.len() != 2 {
+        return Err("diff needs an old and a new input".into());
+    }
+    if config.command == Command::Shell && config.paths.iter().any(|p| p == "-")
+    {
+        return Err("shell reads its commands from standard input, so its \
+                    input can't be -".into());
+    }
+    if config.command == Command::Shell && config.json {
+        return Err("shell has no JSON output".into());
+    }
+    #[cfg(not(feature = "serde"))]
+    if matches!(config.command, Command::Tree | Command::Bars) && config.json {
+        return Err("tree and bars need the serde feature for JSON output"
+                   .into());
+    }
+    if config.command == Command::Stale && config.older_than.is_none() {
+        return Err("stale needs --older-than".into());
+    }
+    if config.block_size == Some(0) {
+        return Err("--block-size needs at least 1 byte".into());
+    }
+    if config.block_size.is_some() && config.dedup_links {
+        return Err("--dedup-hardlinks can't be used with --block-size".into());
+    }
+    if config.command == Command::Top
+        && config.top_dirs.is_none() && config.top_files.is_none()
+    {
+        config.top_dirs  = Some(10);
+        config.top_files = Some(10);
+    }
+    Ok(config)
+}
 
-    let dirs  = root.find_dirs_recurs_by(&|d| d.size() >= need);
-    let dmin  = dirs.iter().min_by_key(|d| d.size()).unwrap();
+/// Parse the value of the option `flag` as a number of bytes or a count.
+/// 
+fn number<T: FromStr>(flag: &str, value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| {
+        format!("{} needs a whole number, not '{}'", flag, value)
+    })
+}
 
-    Ok(dmin.size())
+/// Parse the value of --sort.
+/// 
+fn sort_key(value: &str) -> Result<SortKey, String> {
+    match value {
+        "name" => Ok(SortKey::Name),
+        "size" => Ok(SortKey::Size),
+        _      => Err(format!("--sort needs name or size, not '{}'", value)),
+    }
 }
 
-/// Build the file system from the data file.
-/// 
-fn build_fs() -> Result<FSDir, Box<dyn Error>> {
-    let     file     = File::open("data/data.txt")?;
-    let     reader   = BufReader::new(file);
-    let     fs_root  = FSDir::new("/".into());
-    let mut fs_stack = vec![fs_root.clone()];
-    let mut cur_dir  = fs_root.clone();
-    let mut lines    = PutBack::new(reader.lines());
-
-    fn split(line: &str) -> Vec<&str> {
-        line.split_whitespace().collect()
-    }
-
-    while let Some(line) = lines.next() {
-        let line  = line?;
-        let parts = split(&line);
-        
-        match parts[0] {
-            "$" => {  // Command prompt.
-                match parts[1] {
-                    "cd" => {  // Change directory.
-                        match parts[2] {
-                            ".." => {  // Go up one directory.
-                                if cur_dir.name() != "/" {
-                                    fs_stack.pop().unwrap();
-                                    cur_dir = fs_stack.last().unwrap().clone();
-                                }
-                            },
-                            "/" => {  // Go to root directory.
-                                fs_stack.truncate(1);
-                                cur_dir = fs_root.clone();
-                            },
-                            name => {  // Go to a subdirectory.
-                                if let Some(d) = cur_dir.get_dir(name) {
-                                    fs_stack.push(d.clone());
-                                    cur_dir = d.clone();
-                                }
-                                else {
-                                    let d = FSDir::new(name.into());
-                                    cur_dir.add_dir(d.clone());
-                                    fs_stack.push(d.clone());
-                                    cur_dir = d;
-                                }
-                            },
-                        }
-                    },
-                    "ls" => {  // List directory contents.
-                        while let Some(line) = lines.next() {
-                            let line  = line?;
-                            let parts = split(&line);
-                            match parts[0] {
-                                "dir" => {  // Directory.
-                                    let name = parts[1];
-                                    if !cur_dir.contains(name) {
-                                        let d = FSDir::new(name.into());
-                                        cur_dir.add_dir(d);
-                                    }
-                                },
-                                "$" => {  // Go back to command prompt.
-                                    lines.put_back(Ok(line));
-                                    break;
-                                },
-                                size => {  // File.
-                                    let size = size.parse::<usize>()?;
-                                    let name = parts[1];
-                                    if !cur_dir.contains(name) {
-                                        let f = FSFile::new(name.into(), size);
-                                        cur_dir.add_file(f);
-                                    }
-                                },
-                            }
-                        }
-                    },
-                    _ => panic!("Unknown command: {}", parts[1]),
+/// Parse the input and print what the command asks for, along with any extra
+/// reports asked for on the command line.
+/// 
+fn run(config: &Config) -> Result<(), Box<dyn Error>> {
+    if config.command == Command::Diff {
+        return run_diff(config);
+    }
+    let root = load(&config.paths[0], config)?;
+    for path in &config.paths[1..] {
+        let other = load(path, config)?;
+        root.merge(&other).map_err(|e| ParseError::MergeConflict {
+            file: path.into(), source: e,
+        })?;
+    }
+    let root = only(root, config);
+    if config.json {
+        println!("{}", run_json(&root, config)?);
+    } else {
+        match config.command {
+            Command::Answers => {
+                println!("part_1: {:>10}", part_1(&root, config));
+                println!("part_2: {:>10}", part_2(&root, config)?);
+                if config.top_dirs.is_some() || config.top_files.is_some() {
+                    println!();
+                    print_top(&root, config)?;
+                }
+                if let Some(path) = &config.what_if {
+                    println!();
+                    print_what_if(&root, path, config)?;
+                }
+            },
+            Command::Part1 => println!("{}", part_1(&root, config)),
+            Command::Part2 => println!("{}", part_2(&root, config)?),
+            Command::Tree  => {
+                print!("{}", root.render(render_opts(config)));
+            },
+            Command::Du    => {
+                print!("{}", root.du_report_with(du_opts(config))?);
+            },
+            Command::Bars  => {
+                print!("{}", root.render_bars_with(config.width, 
+                                                   config.depth.unwrap_or(1),
+                                                   config.with_files));
+            },
+            Command::Top   => print_top(&root, config)?,
+            Command::Plan  => {
+                let plan = plan(&root, config)?;
+                for dir in &plan {
+                    println!("{:>10}  {}", dir.size(), dir.path());
+                }
+                let total = plan.iter().map(|d| d.size()).sum::<u64>();
+                println!("{:>10}  total", total);
+            },
+            Command::ByExt => {
+                println!("{:>12}  {:>7}  extension", "size", "files");
+                for (ext, stats) in by_ext(&root, config) {
+                    let ext = if ext == NO_EXTENSION { "(none)" } else { &ext };
+                    println!("{:>12}  {:>7}  {}", 
+                             stats.bytes, stats.files, ext);
+                }
+            },
+            Command::Dupes => {
+                for group in find_duplicates_with(&root, config.skip_empty) {
+                    println!("{:>12}  {} ({} copies of {})", 
+                             group.wasted(), group.name, group.paths.len(),
+                             group.size);
+                    for path in &group.paths {
+                        println!("{:>12}  {}", "", path);
+                    }
                 }
             },
-            _ => panic!("Unknown command: {}", parts[0])
+            Command::Find  => {
+                let found = find(&root, config)?;
+                for (path, entry) in &found {
+                    println!("{:>10}  {}", entry.size(), path);
+                }
+                println!("{:>10}  total", found_total(&found));
+            },
+            Command::Stats => print_stats(&root),
+            Command::Stale => {
+                for file in stale(&root, config) {
+                    println!("{:>10}  {:>10}  {}", file.size(), 
+                             file.mtime().unwrap_or(0), file.path());
+                }
+            },
+            Command::Shell => {
+                let stdin = io::stdin();
+                let (sort_key, sort_dir) = sort_order(config, SortKey::Size);
+                let opts  = ShellOptions { sort_key, 
+                                           sort_dir,
+                                           prompt : stdin.is_terminal() };
+                run_shell(&root, stdin.lock(), io::stdout().lock(), opts)?;
+            },
+            Command::Diff  => unreachable!(),
+        }
+    }
+    if let Some(out) = &config.csv {
+        root.to_csv(File::create(out)?, ExportKind::All)?;
+    }
+    if let Some(out) = &config.flame {
+        std::fs::write(out, root.to_collapsed_stacks(Leaf::File))?;
+    }
+    Ok(())
+}
+
+/// Returns the entries whose names contain the text given to find, or with
+/// --regex, match it, sorted by path. Text with '*', '?' or '[' in it is a 
+/// glob, matched against whole names, or against whole paths if it has a 
+/// '/' in it too. Fails if the pattern is invalid.
+/// 
+fn find(root: &FSDir, config: &Config) 
+    -> Result<Vec<(String, FSEntry)>, Box<dyn Error>> 
+{
+    let needle = config.needle.as_deref().unwrap_or_default();
+    #[cfg(feature = "regex")]
+    if config.regex {
+        let regex = regex::Regex::new(needle)?;
+        return Ok(root.find_by_regex(&regex));
+    }
+    if needle.contains(['*', '?', '[']) {
+        let opts      = GlobOptions { fold_case: config.fold_case };
+        let mut found = match needle.contains('/') {
+            true  => root.glob_with(needle, opts),
+            false => {
+                let glob = Glob::new_with(needle, opts);
+                root.walk().filter(|(_, e)| glob.matches(&e.name_ref()))
+                           .collect()
+            },
+        };
+        found.sort_by(|a, b| a.0.cmp(&b.0));
+        return Ok(found);
+    }
+    Ok(root.find_by_name(needle))
+}
+
+/// Returns the total size of the entries find() found, counting each byte
+/// once: entries inside a directory that was also found aren't added again.
+/// 
+fn found_total(found: &[(String, FSEntry)]) -> u64 {
+    let dirs = found.iter()
+                    .filter(|(_, e)| matches!(e, FSEntry::Dir(_)))
+                    .map(|(path, _)| path.as_str())
+                    .collect::<HashSet<_>>();
+    found.iter()
+         .filter(|(path, _)| {
+             !path.match_indices('/').any(|(i, _)| dirs.contains(&path[..i]))
+         })
+         .map(|(_, entry)| entry.size())
+         .sum()
+}
+
+/// The boundaries between the buckets of the file size histogram printed by
+/// stats.
+/// 
+const HISTOGRAM_BOUNDS: [u64; 5] = [1_000, 10_000, 100_000, 1_000_000, 
+                                    10_000_000];
+
+/// Returns the range of sizes the `i`th bucket of the stats histogram holds,
+/// as its least and, unless it's the last, its greatest size.
+/// 
+fn bucket(i: usize) -> (u64, Option<u64>) {
+    let lo = if i == 0 { 0 } else { HISTOGRAM_BOUNDS[i - 1] };
+    (lo, HISTOGRAM_BOUNDS.get(i).map(|hi| hi - 1))
+}
+
+/// Returns the files the `stale` command lists.
+/// 
+fn stale(root: &FSDir, config: &Config) -> Vec<FSFile> {
+    stale_candidates(root, config.older_than.unwrap_or(0), config.min_size)
+}
+
+/// Print a summary of the tree: how many directories and files it has, their
+/// total and average size, how deep it goes, and a histogram of file sizes.
+/// 
+fn print_stats(root: &FSDir) {
+    let stats   = root.stats();
+    let average = root.average_file_size()
+                      .map_or_else(|| "-".to_string(), |a| format!("{:.1}", a));
+    println!("directories   {:>12}", stats.dirs);
+    println!("files         {:>12}", stats.files);
+    println!("total bytes   {:>12}", root.total_file_bytes());
+    println!("average file  {:>12}", average);
+    println!("max depth     {:>12}", root.max_depth());
+    println!("file sizes:");
+    for (i, count) in root.size_histogram(&HISTOGRAM_BOUNDS).iter().enumerate()
+    {
+        let range = match bucket(i) {
+            (lo, Some(hi)) => format!("{} - {}", lo, hi),
+            (lo, None)     => format!("{} or more", lo),
+        };
+        println!("  {:>19}  {:>7}", range, count);
+    }
+}
+
+/// Returns the summary print_stats() prints, as JSON.
+/// 
+fn stats_json(root: &FSDir) -> String {
+    let stats   = root.stats();
+    let average = root.average_file_size()
+                      .map_or_else(|| "null".to_string(), |a| a.to_string());
+    let buckets = root.size_histogram(&HISTOGRAM_BOUNDS)
+                      .iter()
+                      .enumerate()
+                      .map(|(i, count)| {
+                          let (lo, hi) = bucket(i);
+                          let hi = hi.map_or_else(|| "null".to_string(), 
+                                                  |hi| hi.to_string());
+                          format!("{{\"min\":{},\"max\":{},\"files\":{}}}",
+                                  lo, hi, count)
+                      })
+                      .collect::<Vec<_>>();
+    format!("{{\"dirs\":{},\"files\":{},\"total_bytes\":{},\
+             \"average_file_size\":{},\"max_depth\":{},\"histogram\":[{}]}}",
+            stats.dirs, stats.files, root.total_file_bytes(), average,
+            root.max_depth(), buckets.join(","))
+}
+
+/// Returns `root` as it is, or, with `--only`, a copy of it holding just the
+/// files whose paths match the pattern, and the directories holding them.
+/// 
+fn only(root: FSDir, config: &Config) -> FSDir {
+    match &config.only {
+        Some(pattern) => {
+            let opts = GlobOptions { fold_case: config.fold_case };
+            let glob = Glob::new_with(pattern, opts);
+            root.filter_clone(|file| glob.matches(&file.path()))
+        },
+        None => root,
+    }
+}
+
+/// Compare the trees built from the two inputs and print their differences,
+/// largest first, with the change in size of each.
+/// 
+/// ```text
+///    +24933642  added    dir   /d
+///       -29116  removed  file  /a/f
+///       +10000  changed  dir   /a (below)
+/// ```
+/// 
+/// "(below)" marks directories whose own files didn't change. With `--json`,
+/// the differences are printed as an array of objects instead.
+/// 
+fn run_diff(config: &Config) -> Result<(), Box<dyn Error>> {
+    let old       = only(load(&config.paths[0], config)?, config);
+    let new       = only(load(&config.paths[1], config)?, config);
+    let mut diffs = diff(&old, &new);
+    diffs.sort_by(|a, b| {
+        b.delta().abs().cmp(&a.delta().abs()).then_with(|| a.path.cmp(&b.path))
+    });
+    let change = |d: &DiffEntry| match d.kind {
+        DiffKind::Added       => "added",
+        DiffKind::Removed     => "removed",
+        DiffKind::SizeChanged => "changed",
+    };
+    let kind   = |d: &DiffEntry| if d.is_dir { "dir" } else { "file" };
+    if config.json {
+        let items = diffs.iter()
+                         .map(|d| {
+                             format!("{{\"path\":{},\"change\":\"{}\",\
+                                      \"kind\":\"{}\",\"old_size\":{},\
+                                      \"new_size\":{},\
+                                      \"descendants_only\":{}}}",
+                                     json_string(&d.path), change(d), 
+                                     kind(d), d.old_size, d.new_size,
+                                     d.descendants_only)
+                         })
+                         .collect::<Vec<_>>();
+        println!("[{}]", items.join(","));
+    } else {
+        for d in &diffs {
+            let below = if d.descendants_only { " (below)" } else { "" };
+            println!("{:>+12}  {:<7}  {:<4}  {}{}", 
+                     d.delta(), change(d), kind(d), d.path, below);
+        }
+    }
+    Ok(())
+}
+
+/// Build the file system from the session in the file at `path`, or from 
+/// standard input if `path` is "-".
+/// 
+fn load(path: &str, config: &Config) -> Result<FSDir, Box<dyn Error>> {
+    let mut opts = ParserOptions { mtimes: config.mtimes, 
+                                   ..Default::default() };
+    if config.progress {
+        opts.progress = Some(Box::new(print_progress));
+    }
+    let outcome = if path == "-" {
+        let stdin = io::stdin().lock();
+        #[cfg(feature = "gzip")]
+        let stdin = no_space_left_on_device::gzip::gunzip_if_gzip(stdin)?;
+        build_fs_with(stdin, opts)
+    } else {
+        build_fs_from_path_with(Path::new(path), opts)
+    };
+    if config.progress {
+        // Clear the status line.
+        eprint!("\r\x1b[K");
+    }
+    Ok(outcome?.root)
+}
+
+/// Overwrites the status line on standard error with how far parsing has 
+/// got, like "12%, 1.2M lines, 48k dirs".
+/// 
+fn print_progress(info: ProgressInfo) {
+    fn abbrev(n: usize) -> String {
+        match n {
+            0..1_000         => n.to_string(),
+            1_000..1_000_000 => format!("{:.0}k", n as f64 / 1e3),
+            _                => format!("{:.1}M", n as f64 / 1e6),
+        }
+    }
+    let done = info.fraction_done()
+                   .map_or(String::new(), |f| format!("{:.0}%, ", f * 100.0));
+    eprint!("\r{}{} lines, {} dirs", done, abbrev(info.lines), 
+            abbrev(info.dirs));
+}
+
+/// Returns the results the command asks for as a single line of JSON. The 
+/// answers are an object, with the largest entries under "top" and the 
+/// outcome of a deletion under "what_if", if asked for. Its "input" is the 
+/// input path, or an array of them if there are several:
+/// 
+/// ```text
+/// {"part1":95437,"part2":24933642,"total_size":48381165,
+///  "input":"data/sample.txt"}
+/// ```
+/// 
+/// `du` and `top` give arrays of `{"path":..,"size":..}` objects, with a 
+/// "kind" of "dir" or "file" added for `top`, and `tree` and `bars` give the
+/// tree in the format FSDir::to_json() saves it in, with the `serde` 
+/// feature.
+/// 
+fn run_json(root: &FSDir, config: &Config) -> Result<String, Box<dyn Error>> {
+    let json = match config.command {
+        Command::Answers => {
+            let input    = match &config.paths[..] {
+                [path] => json_string(path),
+                paths  => {
+                    let paths = paths.iter()
+                                     .map(|p| json_string(p))
+                                     .collect::<Vec<_>>();
+                    format!("[{}]", paths.join(","))
+                },
+            };
+            let mut json = format!("{{\"part1\":{},\"part2\":{},\
+                                    \"total_size\":{},\"input\":{}",
+                                   part_1(root, config), 
+                                   part_2(root, config)?,
+                                   root.size(), 
+                                   input);
+            if config.top_dirs.is_some() || config.top_files.is_some() {
+                json.push_str(&format!(",\"top\":{}", top_json(root, config)?));
+            }
+            if let Some(path) = &config.what_if {
+                let outcome = simulate_delete_path(root, path, 
+                                                   config.device_size)?;
+                json.push_str(&format!(",\"what_if\":{{\"path\":{},\
+                                        \"freed\":{},\"used\":{},\
+                                        \"free\":{},\"fits\":{}}}",
+                                       json_string(path), outcome.freed,
+                                       outcome.used, outcome.free,
+                                       outcome.fits(config.update_size)));
+            }
+            json.push('}');
+            json
+        },
+        Command::Part1 => format!("{{\"part1\":{}}}", part_1(root, config)),
+        Command::Part2 => format!("{{\"part2\":{}}}", part_2(root, config)?),
+        #[cfg(feature = "serde")]
+        Command::Tree  | Command::Bars => root.to_json(),
+        #[cfg(not(feature = "serde"))]
+        Command::Tree  | Command::Bars => unreachable!("refused by parse_args"),
+        Command::Du    => {
+            let items = root.du_entries(du_opts(config))?
+                            .into_iter()
+                            .map(|(path, size)| {
+                                format!("{{\"path\":{},\"size\":{}}}",
+                                        json_string(&path), size)
+                            })
+                            .collect::<Vec<_>>();
+            format!("[{}]", items.join(","))
+        },
+        Command::Top   => top_json(root, config)?,
+        Command::Plan  => {
+            let items = plan(root, config)?
+                            .iter()
+                            .map(|d| {
+                                format!("{{\"path\":{},\"size\":{}}}",
+                                        json_string(&d.path()), d.size())
+                            })
+                            .collect::<Vec<_>>();
+            format!("[{}]", items.join(","))
+        },
+        Command::ByExt => {
+            let items = by_ext(root, config)
+                            .iter()
+                            .map(|(ext, stats)| {
+                                format!("{{\"extension\":{},\"size\":{},\
+                                         \"files\":{}}}",
+                                        json_string(ext), stats.bytes, 
+                                        stats.files)
+                            })
+                            .collect::<Vec<_>>();
+            format!("[{}]", items.join(","))
+        },
+        Command::Dupes => {
+            let items = find_duplicates_with(root, config.skip_empty)
+                            .iter()
+                            .map(|g| {
+                                let paths = g.paths.iter()
+                                                   .map(|p| json_string(p))
+                                                   .collect::<Vec<_>>();
+                                format!("{{\"name\":{},\"size\":{},\
+                                         \"wasted\":{},\"paths\":[{}]}}",
+                                        json_string(&g.name), g.size, 
+                                        g.wasted(), paths.join(","))
+                            })
+                            .collect::<Vec<_>>();
+            format!("[{}]", items.join(","))
+        },
+        Command::Find  => {
+            let found = find(root, config)?;
+            let items = found.iter()
+                             .map(|(path, entry)| {
+                                 let kind = match entry {
+                                     FSEntry::Dir(_)  => "dir",
+                                     FSEntry::File(_) => "file",
+                                     FSEntry::Link(_) => "link",
+                                 };
+                                 format!("{{\"path\":{},\"kind\":\"{}\",\
+                                          \"size\":{}}}",
+                                         json_string(path), kind,
+                                         entry.size())
+                             })
+                             .collect::<Vec<_>>();
+            format!("{{\"found\":[{}],\"total\":{}}}",
+                    items.join(","), found_total(&found))
+        },
+        Command::Stats => stats_json(root),
+        Command::Stale => {
+            let items = stale(root, config)
+                            .iter()
+                            .map(|file| {
+                                format!("{{\"path\":{},\"size\":{},\
+                                         \"mtime\":{}}}",
+                                        json_string(&file.path()), 
+                                        file.size(), 
+                                        file.mtime().unwrap_or(0))
+                            })
+                            .collect::<Vec<_>>();
+            format!("[{}]", items.join(","))
+        },
+        Command::Diff  | Command::Shell => unreachable!(),
+    };
+    Ok(json)
+}
+
+/// Returns the largest directories and then the largest files, as many of 
+/// each as the config asks for, as a JSON array.
+/// 
+fn top_json(root: &FSDir, config: &Config) -> Result<String, UsageOverflow> {
+    let (dirs, files) = top(root, config)?;
+    let dirs  = dirs.into_iter().map(|(d, size)| (d.path(), size, "dir"));
+    let files = files.into_iter().map(|(f, size)| (f.path(), size, "file"));
+    let items = dirs.chain(files)
+                    .map(|(path, size, kind)| {
+                        format!("{{\"path\":{},\"size\":{},\
+                                 \"kind\":\"{}\"}}",
+                                json_string(&path), size, kind)
+                    })
+                    .collect::<Vec<_>>();
+    Ok(format!("[{}]", items.join(",")))
+}
+
+/// Returns the RenderOptions for the `tree` command.
+/// 
+fn render_opts(config: &Config) -> RenderOptions {
+    let (sort_key, sort_dir) = sort_order(config, SortKey::Name);
+    RenderOptions { max_depth        : config.depth,
+                    show_percentages : config.percent,
+                    sort_key,
+                    sort_dir,
+                    ..Default::default() }
+}
+
+/// Returns the DuOptions for the `du` command.
+/// 
+fn du_opts(config: &Config) -> DuOptions {
+    let (sort_key, sort_dir) = sort_order(config, SortKey::Name);
+    DuOptions { threshold        : config.threshold.unwrap_or(0),
+                max_depth        : config.depth,
+                show_percentages : config.percent,
+                sort_key,
+                sort_dir,
+                disk_model       : config.block_size.map(DiskModel::blocks),
+                dedup_hardlinks  : config.dedup_links }
+}
+
+/// Returns the order to list entries in: by the --sort key, or `default`, 
+/// names first to last and sizes largest first, reversed by --reverse.
+/// 
+fn sort_order(config: &Config, default: SortKey) -> (SortKey, SortDir) {
+    let key = config.sort.unwrap_or(default);
+    let dir = match key {
+        SortKey::Name => SortDir::Ascending,
+        SortKey::Size => SortDir::Descending,
+    };
+    (key, if config.reverse { dir.reversed() } else { dir })
+}
+
+/// Find the total size of all directories that have a size less than or equal
+/// to the threshold, 100,000 by default, and return the sum of their sizes.
+/// 
+fn part_1(root: &FSDir, config: &Config) -> u64 {
+    sum_dirs_at_most(root, config.threshold.unwrap_or(100_000))
+}
+
+/// Find the size of the smallest directory needs to be deleted to accommodate
+/// the update, 30MB on a 70MB device by default. If there's already enough
+/// free space, this is 0.
+/// 
+fn part_2(root: &FSDir, config: &Config) -> Result<u64, Box<dyn Error>> {
+    let device_size = config.device_size;
+    let update_size = config.update_size;
+
+    if space_to_free(root, device_size, update_size) == 0 {
+        return Ok(0);
+    }
+    let dmin = smallest_dir_to_free(root, device_size, update_size)
+                   .ok_or("no directory is large enough to delete")?;
+
+    Ok(dmin.size())
+}
+
+/// Returns the total size and count of files by extension, biggest total 
+/// first.
+/// 
+fn by_ext(root: &FSDir, config: &Config) -> Vec<(String, ExtStats)> {
+    let mut stats = size_by_extension_with(root, config.fold_case)
+                        .into_iter()
+                        .collect::<Vec<_>>();
+    stats.sort_by(|a, b| b.1.bytes.cmp(&a.1.bytes).then_with(|| a.0.cmp(&b.0)));
+    stats
+}
+
+/// Plan which directories to delete to free the bytes given by `--need`, or
+/// else the bytes the update needs. Fails if no plan frees enough.
+/// 
+fn plan(root: &FSDir, config: &Config) -> Result<Vec<FSDir>, Box<dyn Error>> {
+    let need = config.need.unwrap_or_else(|| {
+        space_to_free(root, config.device_size, config.update_size)
+    });
+    let plan = deletion_plan(root, need, &|_| true);
+    if plan.is_empty() && need > 0 {
+        return Err("no set of directories is large enough to delete".into());
+    }
+    Ok(plan)
+}
+
+/// Print what the device would look like if the directory at `path` were
+/// deleted.
+/// 
+fn print_what_if(root: &FSDir, path: &str, config: &Config) 
+    -> Result<(), Box<dyn Error>> 
+{
+    let outcome = simulate_delete_path(root, path, config.device_size)?;
+    let fits    = if outcome.fits(config.update_size) { "yes" } else { "no" };
+    println!("if {} were deleted:", path);
+    println!("  freed: {:>10}", outcome.freed);
+    println!("  used:  {:>10}", outcome.used);
+    println!("  free:  {:>10}", outcome.free);
+    println!("  the update would fit: {}", fits);
+    Ok(())
+}
+
+/// Print ranked tables of the largest directories and files, as many of each
+/// as the config asks for. Tables are separated by a blank line.
+/// 
+fn print_top(root: &FSDir, config: &Config) -> Result<(), UsageOverflow> {
+    let (dirs, files) = top(root, config)?;
+    if config.top_dirs.is_some() {
+        println!("largest directories:");
+        for (i, (dir, size)) in dirs.iter().enumerate() {
+            println!("{:>4}. {:>10}  {}", i + 1, size, dir.path());
+        }
+    }
+    if config.top_dirs.is_some() && config.top_files.is_some() {
+        println!();
+    }
+    if config.top_files.is_some() {
+        println!("largest files:");
+        for (i, (file, size)) in files.iter().enumerate() {
+            println!("{:>4}. {:>10}  {}", i + 1, size, file.path());
+        }
+    }
+    Ok(())
+}
+
+/// Objects paired with their sizes, largest first.
+type Ranked<T> = Vec<(T, u64)>;
+
+/// Returns the largest directories and the largest files, as many of each as
+/// the config asks for, with their sizes, or the space they take up on disk
+/// if the config gives a block size. With --dedup-hardlinks, directories are
+/// sized with each hard link counted once, and each file is listed once.
+/// Fails if the space something takes up on disk doesn't fit in a u64.
+/// 
+fn top(root: &FSDir, config: &Config) 
+    -> Result<(Ranked<FSDir>, Ranked<FSFile>), UsageOverflow> 
+{
+    let n_dirs  = config.top_dirs.unwrap_or(0);
+    let n_files = config.top_files.unwrap_or(0);
+    Ok(match config.block_size {
+        Some(block_size) => {
+            let model = DiskModel::blocks(block_size);
+            (root.largest_dirs_on_disk(n_dirs, model)?,
+             root.largest_files_on_disk(n_files, model)?)
+        },
+        None if config.dedup_links => {
+            (root.largest_dirs_deduplicated(n_dirs),
+             root.largest_files_deduplicated(n_files))
+        },
+        None => {
+            (root.largest_dirs(n_dirs).into_iter().map(with_size).collect(),
+             root.largest_files(n_files).into_iter().map(with_size).collect())
+        },
+    })
+}
+
+/// Pairs `obj` with its size.
+/// 
+fn with_size<T: FSObject>(obj: T) -> (T, u64) {
+    let size = obj.size();
+    (obj, size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parses `line`, split at spaces, as the command line.
+    fn parse(line: &str) -> Result<Config, String> {
+        parse_args(&line.split_whitespace().map(String::from)
+                        .collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn no_arguments_give_the_defaults() {
+        assert_eq!(parse("").unwrap(), Config::default());
+    }
+
+    #[test]
+    fn commands_flags_and_paths_are_read() {
+        let config = parse("du --depth 2 --block-size 4096 --reverse \
+                            --name-sort a.txt").unwrap();
+        assert_eq!(config, Config { command    : Command::Du,
+                                    paths      : vec!["a.txt".into()],
+                                    depth      : Some(2),
+                                    block_size : Some(4096),
+                                    reverse    : true,
+                                    sort       : Some(SortKey::Name),
+                                    ..Config::default() });
+        let config = parse("find --fold-case node_modules x.txt").unwrap();
+        assert_eq!((config.command, config.needle.as_deref(), config.paths),
+                   (Command::Find, Some("node_modules"), vec!["x.txt".into()]));
+        let config = parse("--top 3 --sort size").unwrap();
+        assert_eq!((config.top_dirs, config.top_files, config.sort),
+                   (Some(3), Some(3), Some(SortKey::Size)));
+        let config = parse("top --dirs 2").unwrap();
+        assert_eq!((config.top_dirs, config.top_files), (Some(2), None));
+        assert_eq!(parse("top").unwrap().top_dirs, Some(10));
+        assert_eq!(parse("diff old.txt new.txt").unwrap().paths, 
+                   ["old.txt", "new.txt"]);
+    }
+
+    #[test]
+    fn a_command_name_only_counts_first() {
+        let config = parse("part1 tree").unwrap();
+        assert_eq!((config.command, config.paths), 
+                   (Command::Part1, vec!["tree".into()]));
+    }
+
+    #[test]
+    fn bad_arguments_say_what_is_wrong() {
+        let cases = [
+            ("--bogus",             "unknown option: --bogus"),
+            ("--depth",             "--depth needs a value"),
+            ("--depth two",         "--depth needs a whole number, not 'two'"),
+            ("--device-size -1",    "--device-size needs a whole number, \
+                                     not '-1'"),
+            ("--sort age",          "--sort needs name or size, not 'age'"),
+            ("find",                "find needs the text to look for"),
+            ("diff a.txt",          "diff needs an old and a new input"),
+            ("shell -",             "shell reads its commands from standard \
+                                     input, so its input can't be -"),
+            ("shell --json",        "shell has no JSON output"),
+            ("stale",               "stale needs --older-than"),
+            ("du --block-size 0",   "--block-size needs at least 1 byte"),
+            ("du --block-size 512 --dedup-hardlinks",
+                                    "--dedup-hardlinks can't be used with \
+                                     --block-size"),
+        ];
+        for (line, message) in cases {
+            assert_eq!(parse(line).unwrap_err(), message, "{}", line);
+        }
+    }
+
+    #[test]
+    fn dedup_hardlinks_counts_linked_files_once_in_du_and_top() {
+        let root = FSDir::new("/".into());
+        for path in ["a/x", "b/x", "b/c/x"] {
+            root.add_file_at_path(path, 100).set_link_id(Some(7));
+        }
+        root.add_file_at_path("a/y", 10);
+        let config = parse("du --dedup-hardlinks").unwrap();
+        assert_eq!(root.du_report_with(du_opts(&config)).unwrap(),
+                   "110\t/\n110\t/a\n100\t/b\n100\t/b/c\n");
+        let config = parse("top --dedup-hardlinks").unwrap();
+        let (dirs, files) = top(&root, &config).unwrap();
+        let dirs  = dirs.iter().map(|(d, size)| (d.path(), *size));
+        let files = files.iter().map(|(f, size)| (f.path(), *size));
+        assert_eq!(dirs.collect::<Vec<_>>(), 
+                   [("/a".into(), 110), ("/b".into(), 100), 
+                    ("/b/c".into(), 100)]);
+        assert_eq!(files.collect::<Vec<_>>(), 
+                   [("/a/x".into(), 100), ("/a/y".into(), 10)]);
+        let config = parse("top").unwrap();
+        let (dirs, _) = top(&root, &config).unwrap();
+        assert_eq!(dirs[0].1, 200);
+    }
+
+    #[test]
+    fn feature_flags_are_refused_without_the_feature() {
+        let regex = parse("find --regex ^a$");
+        let json  = parse("tree --json");
+        if cfg!(feature = "regex") {
+            assert!(regex.unwrap().regex);
+        } else {
+            assert_eq!(regex.unwrap_err(), 
+                       "--regex needs a build with the regex feature");
+        }
+        if cfg!(feature = "serde") {
+            assert!(json.unwrap().json);
+        } else {
+            assert_eq!(json.unwrap_err(), 
+                       "tree and bars need the serde feature for JSON output");
         }
     }
-    Ok(fs_root)
 }
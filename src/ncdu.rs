@@ -0,0 +1,116 @@
+//! This module implements export and import of trees in the JSON format ncdu
+//! uses for its `-o` and `-f` options, so parsed trees can be browsed in ncdu.
+//! 
+//! A directory is an array whose first element is an object describing the
+//! directory, followed by an object for each file and an array for each
+//! subdirectory. The whole document wraps the root directory with a format
+//! version and some metadata.
+//! 
+//! ```text
+//! [1,0,{"progname":"...","progver":"...","timestamp":0},
+//!  [{"name":"/"},{"name":"b.txt","asize":14848514,"dsize":14848514},
+//!   [{"name":"a"}, ...], ...]]
+//! ```
+
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::fsobject::*;
+use crate::json::*;
+
+impl FSDir {
+    /// Exports the tree rooted at this directory in ncdu's JSON format. Both
+    /// the apparent and disk sizes of files are set from their sizes. Empty
+    /// directories are kept, as arrays holding only their info object. 
+    /// Modification times are written as ncdu's "mtime" where they're known,
+    /// for directories only where they were set on the directory itself.
+    /// 
+    pub fn to_ncdu_json(&self) -> String {
+        enum Step { Dir(FSDir), Text(&'static str) }
+
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)
+                                         .map_or(0, |d| d.as_secs());
+        let mut out   = format!("[1,0,{{\"progname\":{},\"progver\":{},\
+                                 \"timestamp\":{}}},",
+                                json_string(env!("CARGO_PKG_NAME")),
+                                json_string(env!("CARGO_PKG_VERSION")),
+                                timestamp);
+        let mut stack = vec![Step::Text("]"), Step::Dir(self.clone())];
+        while let Some(step) = stack.pop() {
+            let dir = match step {
+                Step::Text(text) => { out.push_str(text); continue; },
+                Step::Dir(dir)   => dir,
+            };
+            out.push_str("[{\"name\":");
+            out.push_str(&json_string(&dir.name_ref()));
+            out.push_str(&mtime_member(dir.own_mtime()));
+            out.push('}');
+            for file in dir.find_files_by(|_| true) {
+                out.push_str(&format!(",{{\"name\":{},\"asize\":{},\
+                                       \"dsize\":{}{}}}",
+                                      json_string(&file.name_ref()),
+                                      file.size(), file.size(),
+                                      mtime_member(file.mtime())));
+            }
+            stack.push(Step::Text("]"));
+            for sub in dir.find_dirs_by(|_| true).into_iter().rev() {
+                stack.push(Step::Dir(sub));
+                stack.push(Step::Text(","));
+            }
+        }
+        out
+    }
+    /// Imports a tree from ncdu's JSON format, as written by `ncdu -o` or by
+    /// to_ncdu_json(). Directories and files are added with try_add_dir()
+    /// and try_add_file(), so a size that would overflow is an error. A 
+    /// file's apparent size is used; entries without one, such as those ncdu
+    /// excluded from its scan, are given a size of 0. The "mtime" ncdu 
+    /// writes in its extended mode is kept.
+    /// 
+    pub fn from_ncdu_json(text: &str) -> Result<FSDir, JsonError> {
+        fn name(value: &JsonValue) -> Result<String, JsonError> {
+            value.get("name")
+                 .and_then(JsonValue::as_str)
+                 .map(String::from)
+                 .ok_or_else(|| JsonError::shape("entry without a name"))
+        }
+        // Split a directory's array into its info object and its entries.
+        fn split(value: &JsonValue) 
+            -> Result<(&JsonValue, &[JsonValue]), JsonError> 
+        {
+            value.as_array()
+                 .and_then(|a| a.split_first())
+                 .ok_or_else(|| JsonError::shape("malformed directory"))
+        }
+        let value = JsonValue::parse(text)?;
+        let tree  = match value.as_array() {
+            Some([major, _, _, tree, ..]) if major.as_usize() == Some(1) => {
+                tree
+            },
+            Some([_, _, _, _, ..]) => {
+                return Err(JsonError::shape("unsupported ncdu format version"));
+            },
+            _ => return Err(JsonError::shape("not an ncdu export")),
+        };
+        let (info, entries) = split(tree)?;
+        let root      = FSDir::new(name(info)?);
+        root.set_mtime(mtime_of(info)?);
+        let mut stack = vec![(entries, root.clone())];
+        while let Some((entries, dir)) = stack.pop() {
+            for entry in entries {
+                if entry.as_array().is_some() {
+                    let (info, entries) = split(entry)?;
+                    let sub = dir.try_add_dir(FSDir::new(name(info)?))?;
+                    sub.set_mtime(mtime_of(info)?);
+                    stack.push((entries, sub));
+                } else {
+                    let size = entry.get("asize")
+                                    .and_then(JsonValue::as_u64)
+                                    .unwrap_or(0);
+                    let file = FSFile::new(name(entry)?, size);
+                    let file = dir.try_add_file(file)?;
+                    file.set_mtime(mtime_of(entry)?);
+                }
+            }
+        }
+        Ok(root)
+    }
+}
@@ -0,0 +1,254 @@
+//! This module implements OpLog, a log of changes to a tree that can be 
+//! undone. FSDir::begin_ops() starts one, and the changes made through it,
+//! removing, moving, resizing and renaming entries, are recorded with what
+//! it takes to reverse them. undo_last() reverses the latest, and undo_all()
+//! all of them, latest first, leaving the tree as it was when the log began:
+//! the same entries, sizes and parents, down to the handles, so FSFile and 
+//! FSDir handles taken before the changes are still good afterwards. 
+//! Dropping the log keeps the changes.
+//! 
+//! A log only knows about the changes made through it. Changing the tree 
+//! any other way while a log is live, whether directly or through a second
+//! log, can leave the log's records stale, and what undoing them then does
+//! is undefined.
+
+use crate::fsobject::*;
+
+/// A change recorded by an OpLog, with what's needed to reverse it.
+/// 
+#[derive(Debug)]
+enum Op {
+    /// `entry` was removed from `dir`.
+    Removed { dir: FSDir, entry: FSEntry },
+    /// The child named `name` was moved from `from` into `to`.
+    Moved   { from: FSDir, to: FSDir, name: String },
+    /// `file` was resized from `old` bytes.
+    Resized { file: FSFile, old: u64 },
+    /// `entry` was renamed from `old`.
+    Renamed { entry: FSEntry, old: String },
+}
+
+/// A log of changes to the tree it was begun on, which can be undone. See
+/// the module documentation.
+/// 
+#[derive(Debug)]
+pub struct OpLog {
+    root : FSDir,
+    ops  : Vec<Op>,
+}
+
+impl FSDir {
+    /// Begins a log of changes to the tree this directory is in. Changes made
+    /// through the log can be undone with OpLog::undo_last() and 
+    /// OpLog::undo_all().
+    /// 
+    pub fn begin_ops(&self) -> OpLog {
+        OpLog { root: self.root(), ops: Vec::new() }
+    }
+}
+
+impl OpLog {
+    /// Returns the root of the tree the log was begun on.
+    pub fn root(&self) -> &FSDir {
+        &self.root
+    }
+    /// Returns the number of changes that can be undone.
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+    /// Returns true if there's nothing to undo.
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+    /// Removes the child with the given name from `dir`, as FSDir::remove() 
+    /// does, and returns it. Nothing is recorded if there's no such child.
+    /// 
+    pub fn remove(&mut self, dir: &FSDir, name: &str) -> Option<FSEntry> {
+        let entry = dir.remove(name)?;
+        self.ops.push(Op::Removed { dir: dir.clone(), entry: entry.clone() });
+        Some(entry)
+    }
+    /// Moves the child with the given name from `dir` into `dest`, as 
+    /// FSDir::move_child() does. Nothing is recorded if the move fails.
+    /// 
+    pub fn move_child(&mut self, dir: &FSDir, name: &str, dest: &FSDir) 
+        -> Result<(), MoveError> 
+    {
+        dir.move_child(name, dest)?;
+        if !dir.ptr_eq(dest) {
+            self.ops.push(Op::Moved { from : dir.clone(), 
+                                      to   : dest.clone(), 
+                                      name : name.into() });
+        }
+        Ok(())
+    }
+    /// Changes the size of `file`, as FSFile::try_set_size() does. Nothing
+    /// is recorded if the change fails.
+    /// 
+    pub fn set_size(&mut self, file: &FSFile, size: u64) 
+        -> Result<(), GrowthError> 
+    {
+        let old = file.size();
+        file.try_set_size(size)?;
+        self.ops.push(Op::Resized { file: file.clone(), old });
+        Ok(())
+    }
+    /// Renames `entry`, as the rename() of the file, directory or link in it
+    /// does. Nothing is recorded if the rename fails.
+    /// 
+    pub fn rename(&mut self, entry: &FSEntry, new_name: &str) 
+        -> Result<(), RenameError> 
+    {
+        let old = entry.name();
+        rename(entry, new_name)?;
+        self.ops.push(Op::Renamed { entry: entry.clone(), old });
+        Ok(())
+    }
+    /// Reverses the latest change still in the log and drops it from the
+    /// log. Returns false if there was nothing to undo. Quotas aren't 
+    /// enforced while undoing, so putting the tree back never fails on them.
+    /// 
+    /// # Panics
+    /// If the tree was changed other than through this log in a way that
+    /// gets in the way, such as by adding an entry under the name a removed
+    /// one is to be put back under. See the module documentation.
+    /// 
+    pub fn undo_last(&mut self) -> bool {
+        let op = match self.ops.pop() {
+            Some(op) => op,
+            None     => return false,
+        };
+        let enforce = self.root.enforces_quotas();
+        self.root.set_enforce_quotas(false);
+        match op {
+            Op::Removed { dir, entry } => {
+                assert!(!dir.contains(&entry.name_ref()),
+                        "can't undo: '{}' has a new '{}'", 
+                        dir.path(), entry.name());
+                dir.insert_entry(entry);
+            },
+            Op::Moved { from, to, name } => {
+                to.move_child(&name, &from)
+                  .unwrap_or_else(|e| panic!("can't undo: {}", e));
+            },
+            Op::Resized { file, old } => {
+                file.try_set_size(old)
+                    .unwrap_or_else(|e| panic!("can't undo: {}", e));
+            },
+            Op::Renamed { entry, old } => {
+                rename(&entry, &old)
+                    .unwrap_or_else(|e| panic!("can't undo: {}", e));
+            },
+        }
+        self.root.set_enforce_quotas(enforce);
+        true
+    }
+    /// Reverses every change in the log, latest first, leaving the tree as 
+    /// it was when the log began, and empties the log.
+    /// 
+    /// # Panics
+    /// As undo_last() does.
+    /// 
+    pub fn undo_all(&mut self) {
+        while self.undo_last() {}
+    }
+}
+
+/// Renames a file, directory or link.
+/// 
+fn rename(entry: &FSEntry, new_name: &str) -> Result<(), RenameError> {
+    match entry {
+        FSEntry::File(file) => file.rename(new_name),
+        FSEntry::Dir(dir)   => dir.rename(new_name),
+        FSEntry::Link(link) => link.rename(new_name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::*;
+
+    /// Makes `count` random changes to the tree through `log`. Changes that
+    /// fail, like moving a directory into itself, are skipped.
+    fn random_changes(log: &mut OpLog, rng: &mut SplitMix64, count: usize) {
+        for i in 0..count {
+            let entries = log.root().walk()
+                                    .map(|(_, entry)| entry)
+                                    .collect::<Vec<_>>();
+            let mut dirs = entries.iter()
+                                  .filter_map(|e| match e {
+                                      FSEntry::Dir(dir) => Some(dir.clone()),
+                                      _                 => None,
+                                  })
+                                  .collect::<Vec<_>>();
+            dirs.push(log.root().clone());
+            if entries.is_empty() {
+                return;
+            }
+            let entry = &entries[rng.in_range(&(0..=entries.len() - 1))];
+            let dest  = &dirs[rng.in_range(&(0..=dirs.len() - 1))];
+            let name  = entry.name();
+            let dir   = entry.parent().unwrap();
+            match rng.in_range(&(0..=3)) {
+                0 => {
+                    log.remove(&dir, &name);
+                },
+                1 => {
+                    let _ = log.move_child(&dir, &name, dest);
+                },
+                2 => {
+                    if let FSEntry::File(file) = entry {
+                        let size = rng.in_range_u64(&(0..=1_000_000));
+                        log.set_size(file, size).unwrap();
+                    }
+                },
+                _ => {
+                    let _ = log.rename(entry, &format!("r{}", i % 7));
+                },
+            }
+        }
+    }
+
+    #[test]
+    fn undoing_everything_restores_the_tree() {
+        for seed in 0..20 {
+            let spec     = TreeSpec { nodes: 200, ..Default::default() };
+            let root     = FSDir::random(seed, spec);
+            let snapshot = root.deep_clone();
+            let before   = root.walk().collect::<Vec<_>>();
+            let mut rng  = SplitMix64::new(seed);
+            let mut log  = root.begin_ops();
+            random_changes(&mut log, &mut rng, 100);
+            assert!(!log.is_empty());
+            log.undo_all();
+            assert!(log.is_empty());
+            assert!(root.structural_eq(&snapshot), "seed {}", seed);
+            assert_eq!(root.size(), snapshot.size());
+            // The handles taken before the changes are the ones back in
+            // the tree, where they were.
+            for (path, entry) in &before {
+                assert_eq!(&entry.path(), path, "seed {}", seed);
+            }
+        }
+    }
+
+    #[test]
+    fn undo_last_reverses_one_change_at_a_time() {
+        let root = FSDir::new("/".into());
+        let file = root.add_file_at_path("a/f", 10);
+        let b    = root.make_dirs("b");
+        let mut log = root.begin_ops();
+        log.set_size(&file, 25).unwrap();
+        log.move_child(&root.get_dir("a").unwrap(), "f", &b).unwrap();
+        log.remove(&root, "a");
+        assert_eq!((root.size(), log.len()), (25, 3));
+        assert!(log.undo_last());
+        assert_eq!(file.path(), "/b/f");
+        assert!(log.undo_last());
+        assert_eq!((file.path().as_str(), root.size()), ("/a/f", 25));
+        assert!(log.undo_last());
+        assert_eq!(root.size(), 10);
+        assert!(!log.undo_last());
+    }
+}
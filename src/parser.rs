@@ -0,0 +1,2062 @@
+//! This module implements the parser that rebuilds the file system from the
+//! terminal output of a session of `cd` and `ls` commands.
+
+use std::error::Error;
+use std::fmt::{Debug, Display, Formatter};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufReader, BufRead};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use crate::arena::*;
+use crate::fsobject::*;
+use crate::intern::*;
+use crate::units::*;
+use crate::visit::*;
+
+/// The errors that can occur while parsing terminal output. Each carries the
+/// 1-based number of the line it occurred on, and, where there is one, the 
+/// text of that line and the path of the directory the session was in.
+/// 
+#[derive(Debug)]
+pub enum ParseError {
+    /// A command other than `cd` or `ls`, unless ParserOptions says to skip
+    /// or collect them.
+    UnknownCommand   { line: usize, text: String, dir: String },
+    /// A command or listing entry that's missing a required argument.
+    MissingArgument  { line: usize, text: String, dir: String },
+    /// A file listing whose size isn't a number.
+    BadSize          { line: usize, text: String, dir: String, 
+                       source: ParseSizeError },
+    /// A file listing whose modification time isn't a whole number of 
+    /// seconds, with ParserOptions::mtimes set.
+    BadMtime         { line: usize, text: String, dir: String },
+    /// Opening or reading the input failed. Failures to open are reported
+    /// as line 0.
+    Io               { line: usize, source: io::Error },
+    /// A line that's neither a command nor part of an `ls` listing.
+    UnexpectedOutput { line: usize, text: String, dir: String },
+    /// A `cd` into the directory at `path`, which no `ls` had listed, with 
+    /// UnknownCd::Error set.
+    CdIntoUnknown    { line: usize, text: String, dir: String, path: String },
+    /// A `cd ..` at the root, with AboveRoot::Error set.
+    CdAboveRoot      { line: usize, text: String },
+    /// An `ls` given more than one directory to list.
+    TooManyArguments { line: usize, text: String, dir: String },
+    /// The entry at `path` was listed as `first` on line `first_line`, but
+    /// as `second` on line `line`. A `cd` into a directory that was listed as
+    /// a file or a link is always an error, as links aren't followed; 
+    /// conflicting `ls` output only is unless 
+    /// ParserOptions::conflicts_as_warnings is set. `first_line` is 0 if the
+    /// entry wasn't added by the parser, but through SessionParser::root().
+    ConflictingEntry { line: usize, dir: String, path: String, 
+                       first: Listing, first_line: usize, second: Listing },
+    /// A file listing that would make the size of a directory overflow.
+    SizeOverflow     { line: usize, text: String, dir: String, 
+                       source: SizeOverflow },
+    /// A listing that would take a directory over its quota, with quotas 
+    /// enforced on the tree being built (see SessionParser::root()).
+    QuotaExceeded    { line: usize, text: String, dir: String, 
+                       source: QuotaExceeded },
+    /// Going on would take the tree over `limit`, one of the limits set in
+    /// ParserOptions.
+    LimitExceeded    { line: usize, text: String, dir: String, limit: Limit },
+    /// The tree built from the session in `file` conflicts with the trees 
+    /// built from the sessions before it. It isn't tied to a line, so the
+    /// line is reported as 0.
+    MergeConflict    { file: PathBuf, source: MergeError },
+}
+impl ParseError {
+    /// Returns the error for a listing on line `line`, read while in the 
+    /// directory at `dir`, that couldn't be added to the tree.
+    /// 
+    fn growth(e: GrowthError, line: usize, text: &str, dir: String) -> Self {
+        let text = text.into();
+        match e {
+            GrowthError::Overflow(source) => {
+                ParseError::SizeOverflow { line, text, dir, source }
+            },
+            GrowthError::QuotaExceeded(source) => {
+                ParseError::QuotaExceeded { line, text, dir, source }
+            },
+        }
+    }
+    /// Returns the 1-based number of the line the error occurred on.
+    pub fn line_number(&self) -> usize {
+        match self {
+            ParseError::UnknownCommand   { line, .. } => *line,
+            ParseError::MissingArgument  { line, .. } => *line,
+            ParseError::BadSize          { line, .. } => *line,
+            ParseError::BadMtime         { line, .. } => *line,
+            ParseError::Io               { line, .. } => *line,
+            ParseError::UnexpectedOutput { line, .. } => *line,
+            ParseError::CdIntoUnknown    { line, .. } => *line,
+            ParseError::CdAboveRoot      { line, .. } => *line,
+            ParseError::TooManyArguments { line, .. } => *line,
+            ParseError::ConflictingEntry { line, .. } => *line,
+            ParseError::SizeOverflow     { line, .. } => *line,
+            ParseError::QuotaExceeded    { line, .. } => *line,
+            ParseError::LimitExceeded    { line, .. } => *line,
+            ParseError::MergeConflict    { .. }       => 0,
+        }
+    }
+    /// Returns the path of the directory the session was in when the error
+    /// occurred, or None for errors that aren't tied to a line of it.
+    /// 
+    pub fn dir(&self) -> Option<&str> {
+        match self {
+            ParseError::UnknownCommand   { dir, .. } => Some(dir),
+            ParseError::MissingArgument  { dir, .. } => Some(dir),
+            ParseError::BadSize          { dir, .. } => Some(dir),
+            ParseError::BadMtime         { dir, .. } => Some(dir),
+            ParseError::UnexpectedOutput { dir, .. } => Some(dir),
+            ParseError::CdIntoUnknown    { dir, .. } => Some(dir),
+            ParseError::CdAboveRoot      { .. }      => Some("/"),
+            ParseError::TooManyArguments { dir, .. } => Some(dir),
+            ParseError::ConflictingEntry { dir, .. } => Some(dir),
+            ParseError::SizeOverflow     { dir, .. } => Some(dir),
+            ParseError::QuotaExceeded    { dir, .. } => Some(dir),
+            ParseError::LimitExceeded    { dir, .. } => Some(dir),
+            ParseError::Io               { .. }      => None,
+            ParseError::MergeConflict    { .. }      => None,
+        }
+    }
+}
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseError::UnknownCommand { line, text, dir } => {
+                write!(f, "line {} in {}: unknown command: {}", 
+                       line, dir, text)
+            },
+            ParseError::MissingArgument { line, text, dir } => {
+                write!(f, "line {} in {}: missing argument: {}", 
+                       line, dir, text)
+            },
+            ParseError::BadSize { line, text, dir, source } => {
+                write!(f, "line {} in {}: bad size ({}): {}", 
+                       line, dir, source, text)
+            },
+            ParseError::BadMtime { line, text, dir } => {
+                write!(f, "line {} in {}: bad modification time: {}", 
+                       line, dir, text)
+            },
+            ParseError::Io { line: 0, source } => {
+                write!(f, "open failed: {}", source)
+            },
+            ParseError::Io { line, source } => {
+                write!(f, "line {}: read failed: {}", line, source)
+            },
+            ParseError::UnexpectedOutput { line, text, dir } => {
+                write!(f, "line {} in {}: unexpected output: {}", 
+                       line, dir, text)
+            },
+            ParseError::CdIntoUnknown { line, text, dir, path } => {
+                write!(f, "line {} in {}: cd into unlisted directory {}: {}",
+                       line, dir, path, text)
+            },
+            ParseError::CdAboveRoot { line, text } => {
+                write!(f, "line {} in /: cd above the root: {}", line, text)
+            },
+            ParseError::TooManyArguments { line, text, dir } => {
+                write!(f, "line {} in {}: too many arguments: {}", 
+                       line, dir, text)
+            },
+            ParseError::ConflictingEntry { line, dir, path, first, 
+                                           first_line, second } => {
+                write!(f, "line {} in {}: {} is a {}, but line {} had it as \
+                           a {}", line, dir, path, second, first_line, first)
+            },
+            ParseError::SizeOverflow { line, text, dir, source } => {
+                write!(f, "line {} in {}: {}: {}", line, dir, source, text)
+            },
+            ParseError::QuotaExceeded { line, text, dir, source } => {
+                write!(f, "line {} in {}: {}: {}", line, dir, source, text)
+            },
+            ParseError::LimitExceeded { line, text, dir, limit } => {
+                write!(f, "line {} in {}: over the {}: {}", 
+                       line, dir, limit, text)
+            },
+            ParseError::MergeConflict { file, source } => {
+                write!(f, "{}: can't merge: {}", file.display(), source)
+            },
+        }
+    }
+}
+impl Error for ParseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ParseError::BadSize       { source, .. } => Some(source),
+            ParseError::SizeOverflow  { source, .. } => Some(source),
+            ParseError::QuotaExceeded { source, .. } => Some(source),
+            ParseError::Io            { source, .. } => Some(source),
+            ParseError::MergeConflict { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// A limit on the size of the tree, set in ParserOptions, that a session went
+/// over.
+/// 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    /// ParserOptions::max_depth, the deepest a directory may be.
+    Depth(usize),
+    /// ParserOptions::max_nodes, the most entries the tree may have.
+    Nodes(usize),
+}
+impl Display for Limit {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Limit::Depth(max) => write!(f, "depth limit of {} levels", max),
+            Limit::Nodes(max) => write!(f, "limit of {} entries", max),
+        }
+    }
+}
+
+/// What the parser does when a `cd`, or an `ls` of another directory, names a
+/// directory that no `ls` has listed. In a real capture, that means part of
+/// the session is missing.
+/// 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UnknownCd {
+    /// Create the directory and carry on.
+    #[default]
+    CreateSilently,
+    /// Fail with ParseError::CdIntoUnknown.
+    Error,
+    /// Create the directory, and record a ParseWarning::CdIntoUnknown.
+    CreateAndWarn,
+}
+
+/// What the parser does when a `cd ..` is given at the root.
+/// 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AboveRoot {
+    /// Stay at the root and carry on.
+    #[default]
+    Ignore,
+    /// Stay at the root, and record a ParseWarning::CdAboveRoot.
+    ClampAndWarn,
+    /// Fail with ParseError::CdAboveRoot.
+    Error,
+}
+
+/// What the parser does on a command other than `cd` or `ls`, like the odd
+/// `cat` or `du` in a real capture.
+/// 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UnknownCommands {
+    /// Fail with ParseError::UnknownCommand.
+    #[default]
+    Error,
+    /// Skip the command and everything it printed, up to the next command.
+    SkipSilently,
+    /// Skip the command and what it printed, but keep them as a 
+    /// CollectedCommand in the ParseOutcome.
+    Collect,
+}
+
+/// How far a parse has got, as passed to ParserOptions::progress.
+/// 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProgressInfo {
+    /// The number of lines parsed.
+    pub lines       : usize,
+    /// The number of bytes parsed, counting one for each line ending.
+    pub bytes       : u64,
+    /// The size of the whole input in bytes, if it's known.
+    pub total_bytes : Option<u64>,
+    /// The number of directories in the tree so far, not counting the root.
+    pub dirs        : usize,
+    /// The total size of the files in the tree so far.
+    pub total_size  : u64,
+}
+impl ProgressInfo {
+    /// Returns the share of the input parsed, from 0.0 to 1.0, if the size of
+    /// the input is known.
+    /// 
+    pub fn fraction_done(&self) -> Option<f64> {
+        self.total_bytes.filter(|&total| total > 0)
+                        .map(|total| self.bytes as f64 / total as f64)
+                        .map(|fraction| fraction.min(1.0))
+    }
+}
+
+/// Options controlling how build_fs_with() recognizes commands, how 
+/// strictly it treats sessions that don't add up, and how it reports its 
+/// progress. The defaults are what build_fs() uses.
+/// 
+pub struct ParserOptions {
+    /// The prompt that starts each command line, as a separate word, like 
+    /// "$" in "$ cd /". Ignored if there's a `prompt_matcher`.
+    pub prompt                : String,
+    /// Decides whether a line is a command line, returning the command after
+    /// the prompt if it is. See match_dollar_suffix() for one that accepts
+    /// prompts like "user@host:~$".
+    pub prompt_matcher        : Option<fn(&str) -> Option<&str>>,
+    /// What to do on a `cd` into a directory no `ls` has listed.
+    pub on_unknown_cd         : UnknownCd,
+    /// What to do on a `cd ..` at the root.
+    pub on_cd_above_root      : AboveRoot,
+    /// On an `ls` that lists an entry differently from an earlier one, as a
+    /// file instead of a directory or with a different size, keep the first
+    /// listing and record a ParseWarning::ConflictingEntry, instead of 
+    /// failing with ParseError::ConflictingEntry.
+    pub conflicts_as_warnings : bool,
+    /// What to do on a command other than `cd` or `ls`.
+    pub on_unknown_command    : UnknownCommands,
+    /// How file sizes are written in `ls` output.
+    pub size_format           : SizeSyntax,
+    /// Read files in `ls` output as "SIZE MTIME NAME", with the time the file
+    /// was last modified, in seconds since the Unix epoch, between the size
+    /// and the name. Without it, files are read as "SIZE NAME".
+    /// 
+    pub mtimes                : bool,
+    /// Called every `progress_every` lines with how far the parse has got.
+    /// It only sees a copy of the numbers, never the tree.
+    pub progress              : Option<Box<dyn FnMut(ProgressInfo)>>,
+    /// How many lines to parse between calls to `progress`. With 0, it's
+    /// never called.
+    pub progress_every        : usize,
+    /// The size of the input in bytes, if it's known, for `progress`. 
+    /// build_fs_from_path_with() fills this in from the file's size.
+    pub input_size            : Option<u64>,
+    /// The deepest a directory may be below the root, which is at depth 0.
+    /// A `cd` or `ls` that would make one deeper fails with 
+    /// ParseError::LimitExceeded. None for no limit.
+    pub max_depth             : Option<usize>,
+    /// The most files and directories the tree may have, not counting the
+    /// root. One more fails with ParseError::LimitExceeded. None for no 
+    /// limit. Symbolic links count as files here.
+    pub max_nodes             : Option<usize>,
+    /// Share one allocation between all the entries with the same name, 
+    /// through a NameInterner. Names compare and sort the same either way.
+    /// 
+    pub intern_names          : bool,
+    /// How symbolic links are written in `ls` output, if the session has
+    /// any. With None, a line like "link a -> b" is read as a file, and 
+    /// fails as one with ParseError::BadSize.
+    /// 
+    pub links                 : Option<LinkSyntax>,
+    /// How much each symbolic link adds to the size of the directories 
+    /// above it.
+    /// 
+    pub link_size             : LinkSize,
+}
+impl Debug for ParserOptions {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let progress = self.progress.as_ref().map(|_| "FnMut(ProgressInfo)");
+        f.debug_struct("ParserOptions")
+            .field("prompt", &self.prompt)
+            .field("prompt_matcher", &self.prompt_matcher)
+            .field("on_unknown_cd", &self.on_unknown_cd)
+            .field("on_cd_above_root", &self.on_cd_above_root)
+            .field("conflicts_as_warnings", &self.conflicts_as_warnings)
+            .field("on_unknown_command", &self.on_unknown_command)
+            .field("size_format", &self.size_format)
+            .field("mtimes", &self.mtimes)
+            .field("progress", &progress)
+            .field("progress_every", &self.progress_every)
+            .field("input_size", &self.input_size)
+            .field("max_depth", &self.max_depth)
+            .field("max_nodes", &self.max_nodes)
+            .field("intern_names", &self.intern_names)
+            .field("links", &self.links)
+            .field("link_size", &self.link_size)
+            .finish()
+    }
+}
+impl Default for ParserOptions {
+    fn default() -> Self {
+        Self { prompt                : "$".into(),
+               prompt_matcher        : None,
+               on_unknown_cd         : UnknownCd::default(),
+               on_cd_above_root      : AboveRoot::default(),
+               conflicts_as_warnings : false,
+               on_unknown_command    : UnknownCommands::default(),
+               size_format           : SizeSyntax::default(),
+               mtimes                : false,
+               progress              : None,
+               progress_every        : 100_000,
+               input_size            : None,
+               max_depth             : None,
+               max_nodes             : None,
+               intern_names          : true,
+               links                 : None,
+               link_size             : LinkSize::default() }
+    }
+}
+impl ParserOptions {
+    /// Returns the command on `line`, without its prompt, or None if `line`
+    /// isn't a command line.
+    /// 
+    pub fn command<'a>(&self, line: &'a str) -> Option<&'a str> {
+        match self.prompt_matcher {
+            Some(matcher) => matcher(line),
+            None          => match_prompt_token(line, &self.prompt),
+        }
+    }
+}
+
+/// How a symbolic link is written in `ls` output: a keyword as the first 
+/// word, then the link's name, then an arrow, then the target. The default
+/// reads lines like "link name -> target", which is also how to_session() 
+/// writes links.
+/// 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkSyntax {
+    /// The first word of a link's line.
+    pub keyword : String,
+    /// What separates the name from the target, spaces included.
+    pub arrow   : String,
+}
+impl Default for LinkSyntax {
+    fn default() -> Self {
+        Self { keyword: "link".into(), arrow: " -> ".into() }
+    }
+}
+impl LinkSyntax {
+    /// Returns the name and target of the link on `line`, or None if it 
+    /// isn't a link's line, or is missing the arrow, the name, or the target.
+    /// 
+    pub fn parse<'a>(&self, line: &'a str) -> Option<(&'a str, &'a str)> {
+        match head_tail(line) {
+            (head, Some(rest)) if head == self.keyword => {
+                rest.split_once(self.arrow.as_str())
+                    .filter(|(name, target)| {
+                        !name.is_empty() && !target.is_empty()
+                    })
+            },
+            _ => None,
+        }
+    }
+    /// Returns the line for a link named `name` to `target`.
+    pub fn format(&self, name: &str, target: &str) -> String {
+        format!("{} {}{}{}", self.keyword, name, self.arrow, target)
+    }
+}
+
+/// A prompt matcher that takes lines whose first word is exactly `token` as
+/// command lines, returning the rest of the line, which may be empty.
+/// 
+pub fn match_prompt_token<'a>(line: &'a str, token: &str) -> Option<&'a str> {
+    match head_tail(line) {
+        (head, rest) if head == token => Some(rest.unwrap_or("")),
+        _                             => None,
+    }
+}
+
+/// A prompt matcher that takes lines whose first word ends in '$' as command
+/// lines, so prompts like "$" and "user@host:~$" both work. Returns the rest
+/// of the line, which may be empty.
+/// 
+pub fn match_dollar_suffix(line: &str) -> Option<&str> {
+    match head_tail(line) {
+        (head, rest) if head.ends_with('$') => Some(rest.unwrap_or("")),
+        _                                   => None,
+    }
+}
+
+/// A command other than `cd` or `ls`, kept with UnknownCommands::Collect.
+/// 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CollectedCommand {
+    /// The number of the line the command was on.
+    pub line    : usize,
+    /// The command, without its prompt.
+    pub command : String,
+    /// The lines the command printed, leaving out blank ones.
+    pub output  : Vec<String>,
+}
+
+/// What build_fs_with() returns: the tree, along with anything the options 
+/// asked it to note rather than fail on.
+/// 
+#[derive(Debug)]
+pub struct ParseOutcome {
+    /// The root of the tree.
+    pub root     : FSDir,
+    /// What was suspicious about the session, in the order it was found.
+    pub warnings : Vec<ParseWarning>,
+    /// The commands other than `cd` and `ls`, with UnknownCommands::Collect.
+    pub commands : Vec<CollectedCommand>,
+}
+
+/// What an `ls` said an entry was.
+/// 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Listing {
+    /// A directory.
+    Dir,
+    /// A file of the given size.
+    File(u64),
+    /// A symbolic link.
+    Link,
+}
+impl Display for Listing {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Listing::Dir        => write!(f, "directory"),
+            Listing::File(size) => write!(f, "file of size {}", size),
+            Listing::Link       => write!(f, "symbolic link"),
+        }
+    }
+}
+
+/// Something suspicious build_fs_with() was asked to note rather than fail 
+/// on.
+/// 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseWarning {
+    /// A `cd` on line `line` into the directory at `path`, which no `ls` had
+    /// listed.
+    CdIntoUnknown    { line: usize, path: String },
+    /// A `cd ..` at the root on line `line`.
+    CdAboveRoot      { line: usize },
+    /// An `ls` on line `line` listed the entry at `path` as `second`, but 
+    /// line `first_line` had listed it as `first`, which was kept. 
+    /// `first_line` is 0 if the entry wasn't added by the parser.
+    ConflictingEntry { line: usize, path: String, first: Listing, 
+                       first_line: usize, second: Listing },
+}
+impl Display for ParseWarning {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseWarning::CdIntoUnknown { line, path } => {
+                write!(f, "line {}: cd into unlisted directory {}", line, path)
+            },
+            ParseWarning::CdAboveRoot { line } => {
+                write!(f, "line {}: cd above the root", line)
+            },
+            ParseWarning::ConflictingEntry { line, path, first, first_line, 
+                                             second } => {
+                write!(f, "line {}: {} is a {}, but line {} had it as a {}",
+                       line, path, second, first_line, first)
+            },
+        }
+    }
+}
+
+/// Split `text` at its first space into the leading token and the rest, if 
+/// any. The rest is kept as is, so names can contain spaces.
+/// 
+pub(crate) fn head_tail(text: &str) -> (&str, Option<&str>) {
+    match text.split_once(' ') {
+        Some((head, rest)) => (head, Some(rest)),
+        None               => (text, None),
+    }
+}
+
+/// Tidies a line of terminal output read as line `line_no`: a UTF-8 byte 
+/// order mark at the start of the first line is dropped, as is a '\r' left
+/// at the end by Windows line endings. Returns None for lines that are empty
+/// or only whitespace, which are skipped wherever they appear.
+/// 
+pub(crate) fn clean_line(line: &str, line_no: usize) -> Option<&str> {
+    let line = match line_no {
+        1 => line.strip_prefix('\u{feff}').unwrap_or(line),
+        _ => line,
+    };
+    let line = line.strip_suffix('\r').unwrap_or(line);
+    (!line.trim().is_empty()).then_some(line)
+}
+
+/// Returns the argument, or a MissingArgument error for line `line`, read 
+/// while in the directory whose path `dir` returns, if it's absent or empty.
+/// 
+pub(crate) fn need_arg<'a, D>(arg  : Option<&'a str>, 
+                              line : usize, 
+                              text : &str, 
+                              dir  : D) 
+    -> Result<&'a str, ParseError> 
+where
+    D: FnOnce() -> String
+{
+    arg.filter(|a| !a.is_empty()).ok_or_else(|| {
+        ParseError::MissingArgument { line, text: text.into(), dir: dir() }
+    })
+}
+
+/// A command, as read_command() reads it.
+/// 
+enum Command<'a> {
+    /// A `cd` to the target.
+    Cd(&'a str),
+    /// An `ls`, of the target if there is one.
+    Ls(Option<&'a str>),
+    /// Any other command.
+    Other(&'a str),
+}
+
+/// Reads `command`, the part of line `line_no`, whose text is `line`, after
+/// the prompt. `dir` returns the path of the directory the session is in, 
+/// for errors.
+/// 
+fn read_command<'a, D>(command : &'a str, 
+                       line_no : usize, 
+                       line    : &str, 
+                       dir     : D) 
+    -> Result<Command<'a>, ParseError> 
+where
+    D: Fn() -> String
+{
+    let command = need_arg(Some(command), line_no, line, &dir)?;
+    match head_tail(command) {
+        ("cd", target) => {
+            Ok(Command::Cd(need_arg(target, line_no, line, &dir)?))
+        },
+        ("ls", target) => {
+            match target.map(str::trim).filter(|t| !t.is_empty()) {
+                Some(t) if t.contains(char::is_whitespace) => {
+                    Err(ParseError::TooManyArguments {
+                        line: line_no, text: line.into(), dir: dir(),
+                    })
+                },
+                target => Ok(Command::Ls(target)),
+            }
+        },
+        _ => Ok(Command::Other(command)),
+    }
+}
+
+/// An entry of an `ls` listing, as read_entry() reads it.
+/// 
+enum Entry<'a> {
+    /// A directory.
+    Dir(&'a str),
+    /// A file, with its modification time if the options ask for them.
+    File { name: &'a str, size: u64, mtime: Option<u64> },
+    /// A symbolic link, if the options ask for them.
+    Link { name: &'a str, target: &'a str },
+}
+
+/// Reads line `line_no` of an `ls` listing, whose text is `line`, in the 
+/// format `opts` describes. `dir` returns the path of the directory the 
+/// session is in, for errors.
+/// 
+fn read_entry<'a, D>(line    : &'a str, 
+                     opts    : &ParserOptions, 
+                     line_no : usize, 
+                     dir     : D) 
+    -> Result<Entry<'a>, ParseError> 
+where
+    D: Fn() -> String
+{
+    let unexpected = || ParseError::UnexpectedOutput {
+        line: line_no, text: line.into(), dir: dir(),
+    };
+    match (head_tail(line), &opts.links) {
+        ((head, _), Some(syntax)) if head == syntax.keyword => {
+            let (name, target) = syntax.parse(line).ok_or_else(unexpected)?;
+            Ok(Entry::Link { name, target })
+        },
+        (("dir", name), _) => {
+            Ok(Entry::Dir(need_arg(name, line_no, line, &dir)?))
+        },
+        (("", _), _) => Err(unexpected()),
+        ((size, rest), _) => {
+            let rest = need_arg(rest, line_no, line, &dir)?;
+            let size = parse_size(size, opts.size_format).map_err(|e| {
+                ParseError::BadSize { 
+                    line: line_no, text: line.into(), dir: dir(), source: e,
+                }
+            })?;
+            if !opts.mtimes {
+                return Ok(Entry::File { name: rest, size, mtime: None });
+            }
+            let (mtime, name) = head_tail(rest);
+            let mtime = mtime.parse::<u64>().map_err(|_| {
+                ParseError::BadMtime { 
+                    line: line_no, text: line.into(), dir: dir(),
+                }
+            })?;
+            let name  = need_arg(name, line_no, line, &dir)?;
+            Ok(Entry::File { name, size, mtime: Some(mtime) })
+        },
+    }
+}
+
+/// Build the file system from the terminal output in the file at `path`. With
+/// the `gzip` feature, gzip-compressed files are decompressed as they're 
+/// read; they're recognized by their content, whatever they're named.
+/// 
+pub fn build_fs_from_path(path: &Path) -> Result<FSDir, ParseError> {
+    build_fs_from_path_with(path, ParserOptions::default()).map(|out| out.root)
+}
+
+/// Like build_fs_from_path(), with the options of build_fs_with(). Unless 
+/// `opts` already has an `input_size`, it's set to the size of the file, 
+/// so long as the file isn't compressed.
+/// 
+pub fn build_fs_from_path_with(path: &Path, mut opts: ParserOptions) 
+    -> Result<ParseOutcome, ParseError> 
+{
+    let open_err = |e| ParseError::Io { line: 0, source: e };
+    let file     = File::open(path).map_err(open_err)?;
+    let size     = file.metadata().map_err(open_err)?.len();
+    let reader   = BufReader::new(file);
+    // The size of a compressed file says little about how much it holds.
+    #[cfg(feature = "gzip")]
+    let (reader, compressed) = {
+        let mut reader = reader;
+        let compressed = crate::gzip::is_gzip(&mut reader).map_err(open_err)?;
+        (crate::gzip::gunzip_if_gzip(reader).map_err(open_err)?, compressed)
+    };
+    #[cfg(not(feature = "gzip"))]
+    let compressed = false;
+    if !compressed {
+        opts.input_size = opts.input_size.or(Some(size));
+    }
+    build_fs_with(reader, opts)
+}
+
+/// Build one file system from the terminal output in each of the files at 
+/// `paths`, merged together with FSDir::merge(). Fails with MergeConflict if
+/// the trees disagree about the kind or size of something at the same path.
+/// 
+pub fn build_fs_multi(paths: &[PathBuf]) -> Result<FSDir, ParseError> {
+    let root = FSDir::new("/".into());
+    for path in paths {
+        let tree = build_fs_from_path(path)?;
+        root.merge(&tree).map_err(|e| ParseError::MergeConflict {
+            file: path.clone(), source: e,
+        })?;
+    }
+    Ok(root)
+}
+
+/// Build the file system from terminal output held in a string.
+/// 
+pub fn build_fs_from_str(text: &str) -> Result<FSDir, ParseError> {
+    build_fs(text.as_bytes())
+}
+
+/// Build the file system from the terminal output read from `reader`.
+/// 
+pub fn build_fs<R: BufRead>(reader: R) -> Result<FSDir, ParseError> {
+    build_fs_with(reader, ParserOptions::default()).map(|out| out.root)
+}
+
+/// Like build_fs(), but `opts` decides what the prompt on command lines 
+/// looks like, and whether a `cd` into a directory no `ls` has listed, a 
+/// `cd ..` at the root, conflicting `ls` output, or a command other than 
+/// `cd` and `ls` is let pass, noted in the returned ParseOutcome, or an 
+/// error.
+/// 
+pub fn build_fs_with<R: BufRead>(reader: R, opts: ParserOptions)
+    -> Result<ParseOutcome, ParseError>
+{
+    let mut parser = SessionParser::new(opts);
+    feed_lines(reader, &mut parser.parser)?;
+    parser.into_outcome()
+}
+
+/// Like build_fs(), but builds the tree as an FsArena, for big sessions. The
+/// session is parsed as build_fs() parses it, by the same code, and fails
+/// with the same errors, but the lines go straight into the arena, so no
+/// FSDir tree is ever built. An arena holds no links, so with options that
+/// read them they're left out, as FsArena::from_fsdir() leaves them out.
+/// 
+pub fn build_fs_arena<R: BufRead>(reader: R) -> Result<FsArena, ParseError> {
+    let mut parser = Parser::new(ParserOptions::default(), ArenaTree::new());
+    feed_lines(reader, &mut parser)?;
+    Ok(parser.finish().tree.arena)
+}
+
+/// Feeds the lines read from `reader` to `parser`, one at a time.
+/// 
+fn feed_lines<R: BufRead, T: SessionTree>(reader: R, parser: &mut Parser<T>)
+    -> Result<(), ParseError>
+{
+    for line in reader.lines() {
+        let line = line.map_err(|e| ParseError::Io {
+            line: parser.line_no + 1, source: e,
+        })?;
+        parser.feed_line(&line)?;
+    }
+    Ok(())
+}
+
+/// What a session builds its tree through, so that one parser can build
+/// either an FSDir tree or an FsArena. Directories are handed around as
+/// `Dir`s, which only mean something to the tree they came from. The tree
+/// also keeps the line each entry was added on, for conflicts.
+/// 
+trait SessionTree {
+    type Dir: Clone;
+
+    /// Returns the root directory.
+    fn root(&self) -> Self::Dir;
+    /// Returns the directory holding `dir`, or None for the root.
+    fn parent(&self, dir: &Self::Dir) -> Option<Self::Dir>;
+    /// Returns the full path of `dir`.
+    fn path(&self, dir: &Self::Dir) -> String;
+    /// Returns the size of the root.
+    fn size(&self) -> u64;
+    /// Returns the subdirectory of `dir` named `name`, if there is one.
+    fn get_dir(&self, dir: &Self::Dir, name: &str) -> Option<Self::Dir>;
+    /// Returns what the child of `dir` named `name` is, if there is one.
+    fn listing(&self, dir: &Self::Dir, name: &str) -> Option<Listing>;
+    /// Returns the line the child of `dir` named `name` was added on, or 0 if
+    /// it wasn't added by the parser.
+    /// 
+    fn first_line(&self, dir: &Self::Dir, name: &str) -> usize;
+    /// Adds a subdirectory named `name`, seen first on line `line`, to `dir`
+    /// and returns it.
+    /// 
+    fn add_dir(&mut self, dir: &Self::Dir, name: &str, line: usize)
+        -> Self::Dir;
+    /// Adds a file named `name`, listed on line `line`, to `dir`. Fails,
+    /// leaving the tree as it was, if a size would overflow or a quota be
+    /// exceeded.
+    /// 
+    fn add_file(&mut self,
+                dir   : &Self::Dir,
+                name  : &str,
+                size  : u64,
+                mtime : Option<u64>,
+                line  : usize)
+        -> Result<(), GrowthError>;
+    /// Like add_file(), for a symbolic link to `target` that adds `size` to
+    /// the directories above it.
+    /// 
+    fn add_link(&mut self,
+                dir    : &Self::Dir,
+                name   : &str,
+                target : &str,
+                size   : LinkSize,
+                line   : usize)
+        -> Result<(), GrowthError>;
+}
+
+/// The FSDir tree a SessionParser builds, with the names interned so far, if
+/// the options ask for interning, and the line each entry was added on,
+/// keyed by the id of its directory and its name.
+/// 
+struct DirTree {
+    root  : FSDir,
+    names : Option<NameInterner>,
+    seen  : HashMap<(usize, String), usize>,
+}
+impl DirTree {
+    /// Returns `name` for a new entry, shared with the other entries of that
+    /// name if the options ask for interning.
+    /// 
+    fn name(&mut self, name: &str) -> Rc<str> {
+        match &mut self.names {
+            Some(names) => names.intern(name),
+            None        => name.into(),
+        }
+    }
+}
+impl SessionTree for DirTree {
+    type Dir = FSDir;
+
+    fn root(&self) -> FSDir {
+        self.root.clone()
+    }
+    fn parent(&self, dir: &FSDir) -> Option<FSDir> {
+        dir.parent()
+    }
+    fn path(&self, dir: &FSDir) -> String {
+        dir.path()
+    }
+    fn size(&self) -> u64 {
+        self.root.size()
+    }
+    fn get_dir(&self, dir: &FSDir, name: &str) -> Option<FSDir> {
+        dir.get_dir(name)
+    }
+    fn listing(&self, dir: &FSDir, name: &str) -> Option<Listing> {
+        match dir.get(name)? {
+            FSEntry::Dir(_)     => Some(Listing::Dir),
+            FSEntry::File(file) => Some(Listing::File(file.size())),
+            FSEntry::Link(_)    => Some(Listing::Link),
+        }
+    }
+    fn first_line(&self, dir: &FSDir, name: &str) -> usize {
+        self.seen.get(&(dir.id(), name.to_string())).copied().unwrap_or(0)
+    }
+    fn add_dir(&mut self, dir: &FSDir, name: &str, line: usize) -> FSDir {
+        self.seen.insert((dir.id(), name.to_string()), line);
+        let name = self.name(name);
+        dir.add_dir(FSDir::new_shared(name))
+    }
+    fn add_file(&mut self,
+                dir   : &FSDir,
+                name  : &str,
+                size  : u64,
+                mtime : Option<u64>,
+                line  : usize)
+        -> Result<(), GrowthError>
+    {
+        let file = dir.try_add_file(FSFile::new_shared(self.name(name), size))?;
+        file.set_mtime(mtime);
+        self.seen.insert((dir.id(), name.to_string()), line);
+        Ok(())
+    }
+    fn add_link(&mut self,
+                dir    : &FSDir,
+                name   : &str,
+                target : &str,
+                size   : LinkSize,
+                line   : usize)
+        -> Result<(), GrowthError>
+    {
+        dir.try_add_link(FSLink::new_with(name.into(), target.into(), size))?;
+        self.seen.insert((dir.id(), name.to_string()), line);
+        Ok(())
+    }
+}
+
+/// The FsArena build_fs_arena() builds, with the line each node was added
+/// on, by index. The arena interns its names whatever the options say, and
+/// has no links, so they're left out.
+/// 
+struct ArenaTree {
+    arena : FsArena,
+    seen  : Vec<usize>,
+}
+impl ArenaTree {
+    /// Creates an arena holding only the root, added on no line.
+    fn new() -> Self {
+        Self { arena: FsArena::new(), seen: vec![0] }
+    }
+}
+impl SessionTree for ArenaTree {
+    type Dir = NodeId;
+
+    fn root(&self) -> NodeId {
+        self.arena.root()
+    }
+    fn parent(&self, dir: &NodeId) -> Option<NodeId> {
+        self.arena.parent(*dir)
+    }
+    fn path(&self, dir: &NodeId) -> String {
+        self.arena.path(*dir)
+    }
+    fn size(&self) -> u64 {
+        self.arena.size(self.arena.root())
+    }
+    fn get_dir(&self, dir: &NodeId, name: &str) -> Option<NodeId> {
+        self.arena.get(*dir, name).filter(|&id| self.arena.is_dir(id))
+    }
+    fn listing(&self, dir: &NodeId, name: &str) -> Option<Listing> {
+        let id = self.arena.get(*dir, name)?;
+        match self.arena.is_dir(id) {
+            true  => Some(Listing::Dir),
+            false => Some(Listing::File(self.arena.size(id))),
+        }
+    }
+    fn first_line(&self, dir: &NodeId, name: &str) -> usize {
+        self.arena.get(*dir, name).map_or(0, |id| self.seen[id.index()])
+    }
+    fn add_dir(&mut self, dir: &NodeId, name: &str, line: usize) -> NodeId {
+        self.seen.push(line);
+        self.arena.dir_entry(*dir, name)
+    }
+    fn add_file(&mut self,
+                dir  : &NodeId,
+                name : &str,
+                size : u64,
+                _    : Option<u64>,
+                line : usize)
+        -> Result<(), GrowthError>
+    {
+        self.arena.try_add_file(*dir, name, size)?;
+        self.seen.push(line);
+        Ok(())
+    }
+    fn add_link(&mut self, _: &NodeId, _: &str, _: &str, _: LinkSize, _: usize)
+        -> Result<(), GrowthError>
+    {
+        Ok(())
+    }
+}
+
+/// What a SessionParser expects the next line to be.
+/// 
+enum State<D> {
+    /// A command line.
+    AwaitingCommand,
+    /// A command line, or an entry of the listing of `dir`, which is `depth`
+    /// levels below the root.
+    InLsOutput { dir: D, depth: usize },
+    /// A command line, or output of a command other than `cd` or `ls`, which
+    /// is kept in `collected` if the options ask for it.
+    InOtherOutput { collected: Option<CollectedCommand> },
+}
+
+/// A parser that's fed a session a line at a time, for when the lines arrive
+/// piecemeal, as over a socket. The tree is built as the lines come, and can
+/// be looked at between them. build_fs() and build_fs_with() are built on
+/// this.
+/// 
+/// ```text
+/// let mut parser = SessionParser::new(ParserOptions::default());
+/// for line in lines {
+///     parser.feed_line(&line)?;
+/// }
+/// let root = parser.finish()?;
+/// ```
+/// 
+pub struct SessionParser {
+    parser: Parser<DirTree>,
+}
+impl SessionParser {
+    /// Creates a parser for a session in the format `opts` describes, with
+    /// an empty root directory named "/".
+    /// 
+    pub fn new(opts: ParserOptions) -> Self {
+        let tree = DirTree { root  : FSDir::new("/".into()),
+                             names : opts.intern_names.then(NameInterner::new),
+                             seen  : HashMap::new() };
+        Self { parser: Parser::new(opts, tree) }
+    }
+    /// Returns the root of the tree built so far.
+    pub fn root(&self) -> &FSDir {
+        &self.parser.tree.root
+    }
+    /// Returns the 1-based number of the line most recently fed, or 0 if
+    /// none has been.
+    /// 
+    pub fn line_number(&self) -> usize {
+        self.parser.line_no
+    }
+    /// Returns the warnings so far.
+    pub fn warnings(&self) -> &[ParseWarning] {
+        &self.parser.warnings
+    }
+    /// Parses the next line of the session, which shouldn't include its line
+    /// ending. After an error, parsing can carry on with the next line.
+    /// 
+    pub fn feed_line(&mut self, line: &str) -> Result<(), ParseError> {
+        self.parser.feed_line(line)
+    }
+    /// Finishes parsing the session, and returns the tree.
+    /// 
+    pub fn finish(self) -> Result<FSDir, ParseError> {
+        self.into_outcome().map(|out| out.root)
+    }
+    /// Finishes parsing the session, and returns the tree along with the
+    /// warnings and collected commands.
+    /// 
+    pub fn into_outcome(self) -> Result<ParseOutcome, ParseError> {
+        let parser = self.parser.finish();
+        Ok(ParseOutcome { root     : parser.tree.root,
+                          warnings : parser.warnings,
+                          commands : parser.commands })
+    }
+}
+
+
+/// What SessionParser and build_fs_arena() parse with: the options, the
+/// tree being built, where the session is in it and what's expected next,
+/// the warnings and commands collected so far, and the number of 
+/// directories and files made.
+/// 
+struct Parser<T: SessionTree> {
+    opts      : ParserOptions,
+    tree      : T,
+    cur_dir   : T::Dir,
+    cur_depth : usize,
+    state     : State<T::Dir>,
+    warnings  : Vec<ParseWarning>,
+    commands  : Vec<CollectedCommand>,
+    dirs      : usize,
+    files     : usize,
+    line_no   : usize,
+    bytes     : u64,
+}
+impl<T: SessionTree> Parser<T> {
+    /// Creates a parser for a session in the format `opts` describes, that
+    /// builds on `tree`, starting at its root.
+    /// 
+    fn new(opts: ParserOptions, tree: T) -> Self {
+        Self { opts,
+               cur_dir   : tree.root(),
+               cur_depth : 0,
+               tree,
+               state     : State::AwaitingCommand,
+               warnings  : vec![],
+               commands  : vec![],
+               dirs      : 0,
+               files     : 0,
+               line_no   : 0,
+               bytes     : 0 }
+    }
+    /// Parses the next line of the session, as SessionParser::feed_line()
+    /// does.
+    /// 
+    fn feed_line(&mut self, line: &str) -> Result<(), ParseError> {
+        self.line_no += 1;
+        self.bytes   += line.len() as u64 + 1;
+        let result    = self.parse_line(line, self.line_no);
+
+        let opts  = &mut self.opts;
+        let every = opts.progress_every;
+        if every > 0 && self.line_no.is_multiple_of(every) {
+            if let Some(progress) = &mut opts.progress {
+                progress(ProgressInfo { lines       : self.line_no,
+                                        bytes       : self.bytes,
+                                        total_bytes : opts.input_size,
+                                        dirs        : self.dirs,
+                                        total_size  : self.tree.size() });
+            }
+        }
+        result
+    }
+    /// Parses line `line_no` of the session.
+    /// 
+    fn parse_line(&mut self, line: &str, line_no: usize)
+        -> Result<(), ParseError>
+    {
+        let line = match clean_line(line, line_no) {
+            Some(line) => line,
+            None       => return Ok(()),
+        };
+        let command = self.opts.command(line);
+        match (&mut self.state, command) {
+            (State::InLsOutput { dir, depth }, None) => {
+                let (dir, depth) = (dir.clone(), *depth);
+                self.list_entry(&dir, depth, line, line_no)
+            },
+            (State::InOtherOutput { collected }, None) => {
+                if let Some(collected) = collected {
+                    collected.output.push(line.into());
+                }
+                Ok(())
+            },
+            (_, Some(command)) => {
+                self.end_output();
+                self.run_command(command, line, line_no)
+            },
+            (State::AwaitingCommand, None) => {
+                Err(ParseError::UnexpectedOutput {
+                    line: line_no, text: line.into(), dir: self.here(),
+                })
+            },
+        }
+    }
+    /// Finishes parsing the session, wrapping up the output of the last
+    /// command.
+    /// 
+    fn finish(mut self) -> Self {
+        self.end_output();
+        self
+    }
+    /// Returns the path of the directory the session is in, for errors.
+    fn here(&self) -> String {
+        self.tree.path(&self.cur_dir)
+    }
+    /// Wraps up the output of the last command, keeping it if it was
+    /// collected, and goes back to awaiting a command.
+    /// 
+    fn end_output(&mut self) {
+        let state = std::mem::replace(&mut self.state, State::AwaitingCommand);
+        if let State::InOtherOutput { collected: Some(collected) } = state {
+            self.commands.push(collected);
+        }
+    }
+    /// Runs `command`, the part of command line `line` after the prompt.
+    /// 
+    fn run_command(&mut self, command: &str, line: &str, line_no: usize)
+        -> Result<(), ParseError>
+    {
+        match read_command(command, line_no, line, || self.here())? {
+            Command::Cd(target) => {  // Change directory.
+                let (dir, depth) = self.follow(target, line_no, line)?;
+                self.cur_dir   = dir;
+                self.cur_depth = depth;
+            },
+            Command::Ls(target) => {  // List directory contents.
+                // List the target, if any, without going there.
+                let (dir, depth) = match target {
+                    None         => (self.cur_dir.clone(), self.cur_depth),
+                    Some(target) => self.follow(target, line_no, line)?,
+                };
+                self.state = State::InLsOutput { dir, depth };
+            },
+            Command::Other(command) => {
+                let collected = match self.opts.on_unknown_command {
+                    UnknownCommands::Error => {
+                        return Err(ParseError::UnknownCommand {
+                            line: line_no, text: line.into(), dir: self.here(),
+                        });
+                    },
+                    UnknownCommands::SkipSilently => None,
+                    UnknownCommands::Collect      => Some(CollectedCommand {
+                        line: line_no, command: command.into(), output: vec![],
+                    }),
+                };
+                // Pass over whatever the command prints.
+                self.state = State::InOtherOutput { collected };
+            },
+        }
+        Ok(())
+    }
+    /// Follows the `cd` target `target`, given on line `line_no` whose text is
+    /// `line`, from the directory the session is in. Returns the directory 
+    /// the target leads to and its depth. Missing directories are created on
+    /// the way, as the options allow.
+    /// 
+    fn follow(&mut self, target: &str, line_no: usize, line: &str)
+        -> Result<(T::Dir, usize), ParseError>
+    {
+        let (mut dir, mut depth) = match target.starts_with('/') {
+            true  => (self.tree.root(), 0),  // Start from root.
+            false => (self.cur_dir.clone(), self.cur_depth),
+        };
+        for part in target.split('/') {
+            match part {
+                "" | "." => {},
+                ".." if depth > 0 => {  // Go up one directory.
+                    dir    = self.tree.parent(&dir)
+                                      .expect("the parser holds the root");
+                    depth -= 1;
+                },
+                ".." => match self.opts.on_cd_above_root {
+                    AboveRoot::Ignore       => {},
+                    AboveRoot::ClampAndWarn => {
+                        let line = line_no;
+                        self.warnings.push(ParseWarning::CdAboveRoot { line });
+                    },
+                    AboveRoot::Error        => {
+                        return Err(ParseError::CdAboveRoot {
+                            line: line_no, text: line.into(),
+                        });
+                    },
+                },
+                name => {  // Go to a subdirectory.
+                    dir = match self.tree.get_dir(&dir, name) {
+                        Some(sub) => sub,
+                        None      => self.cd_into_unknown(&dir, name, depth,
+                                                          line_no, line)?,
+                    };
+                    depth += 1;
+                },
+            }
+        }
+        Ok((dir, depth))
+    }
+    /// Makes the subdirectory of `dir`, which is `depth` levels below the 
+    /// root, named `name`, that a `cd` on line `line_no` goes into without
+    /// an `ls` having listed it, as the options allow, and returns it.
+    /// 
+    fn cd_into_unknown(&mut self, 
+                       dir     : &T::Dir, 
+                       name    : &str, 
+                       depth   : usize,
+                       line_no : usize, 
+                       line    : &str)
+        -> Result<T::Dir, ParseError>
+    {
+        let path = join_path(&self.tree.path(dir), name);
+        if let Some(first) = self.tree.listing(dir, name) {
+            return Err(ParseError::ConflictingEntry {
+                first,
+                first_line : self.tree.first_line(dir, name),
+                second     : Listing::Dir,
+                line       : line_no,
+                dir        : self.here(),
+                path,
+            });
+        }
+        self.check_limits(Listing::Dir, depth + 1)
+            .map_err(|limit| ParseError::LimitExceeded {
+                line: line_no, text: line.into(), dir: self.here(), limit,
+            })?;
+        match self.opts.on_unknown_cd {
+            UnknownCd::CreateSilently => {},
+            UnknownCd::CreateAndWarn  => {
+                self.warnings.push(ParseWarning::CdIntoUnknown {
+                    line: line_no, path,
+                });
+            },
+            UnknownCd::Error          => {
+                return Err(ParseError::CdIntoUnknown {
+                    line: line_no, text: line.into(), dir: self.here(), path,
+                });
+            },
+        }
+        self.dirs += 1;
+        Ok(self.tree.add_dir(dir, name, line_no))
+    }
+    /// Checks that making an entry listed as `listing`, `depth` levels below
+    /// the root, keeps the tree within the limits the options set. Returns
+    /// the limit it would go over if not.
+    /// 
+    fn check_limits(&self, listing: Listing, depth: usize)
+        -> Result<(), Limit>
+    {
+        if let Some(max) = self.opts.max_nodes {
+            if self.dirs + self.files >= max {
+                return Err(Limit::Nodes(max));
+            }
+        }
+        if let (Some(max), Listing::Dir) = (self.opts.max_depth, listing) {
+            if depth > max {
+                return Err(Limit::Depth(max));
+            }
+        }
+        Ok(())
+    }
+    /// Adds the entry on line `line` of the listing of `dir`, which is
+    /// `depth` levels below the root.
+    /// 
+    fn list_entry(&mut self, dir: &T::Dir, depth: usize, line: &str,
+                  line_no: usize)
+        -> Result<(), ParseError>
+    {
+        let entry = read_entry(line, &self.opts, line_no, || self.here())?;
+        let (listing, name, mtime) = match entry {
+            Entry::Link { name, target } => {
+                return self.list_link(dir, depth, name, target, line_no, 
+                                      line);
+            },
+            Entry::Dir(name) => (Listing::Dir, name, None),
+            Entry::File { name, size, mtime } => {
+                (Listing::File(size), name, mtime)
+            },
+        };
+        let first = match self.tree.listing(dir, name) {
+            None        => {
+                self.check_limits(listing, depth + 1).map_err(|limit| {
+                    ParseError::LimitExceeded {
+                        line: line_no, text: line.into(), dir: self.here(), 
+                        limit,
+                    }
+                })?;
+                return self.add(dir, name, listing, mtime, line_no, line);
+            },
+            Some(first) => first,
+        };
+        self.relist(dir, name, first, listing, line_no)
+    }
+    /// Adds the new entry named `name` that an `ls` on line `line_no`, whose
+    /// text is `line`, listed as `listing` to `dir`. A new file is given 
+    /// `mtime`.
+    /// 
+    fn add(&mut self, 
+           dir     : &T::Dir, 
+           name    : &str, 
+           listing : Listing, 
+           mtime   : Option<u64>,
+           line_no : usize,
+           line    : &str)
+        -> Result<(), ParseError>
+    {
+        match listing {
+            Listing::Dir => {
+                self.tree.add_dir(dir, name, line_no);
+                self.dirs += 1;
+            },
+            Listing::File(size) => {
+                self.tree.add_file(dir, name, size, mtime, line_no)
+                    .map_err(|e| {
+                        ParseError::growth(e, line_no, line, self.here())
+                    })?;
+                self.files += 1;
+            },
+            Listing::Link => unreachable!("links are added by list_link()"),
+        }
+        Ok(())
+    }
+    /// Checks the entry named `name` in `dir`, first listed as `first`, 
+    /// against an `ls` on line `line_no` that lists it as `second`. If they
+    /// differ, either the first listing is kept and a warning recorded, or
+    /// the conflict is returned, as the options say.
+    /// 
+    fn relist(&mut self, 
+              dir     : &T::Dir, 
+              name    : &str, 
+              first   : Listing, 
+              second  : Listing, 
+              line_no : usize)
+        -> Result<(), ParseError>
+    {
+        if first == second {
+            return Ok(());
+        }
+        let first_line = self.tree.first_line(dir, name);
+        let path       = join_path(&self.tree.path(dir), name);
+        if !self.opts.conflicts_as_warnings {
+            return Err(ParseError::ConflictingEntry {
+                line: line_no, dir: self.here(), path,
+                first, first_line, second,
+            });
+        }
+        self.warnings.push(ParseWarning::ConflictingEntry {
+            line: line_no, path, first, first_line, second,
+        });
+        Ok(())
+    }
+    /// Like list_entry(), for a symbolic link named `name` to `target`. A 
+    /// link listed again is kept as first listed, whatever its target.
+    /// 
+    fn list_link(&mut self,
+                 dir     : &T::Dir,
+                 depth   : usize,
+                 name    : &str,
+                 target  : &str,
+                 line_no : usize,
+                 line    : &str)
+        -> Result<(), ParseError>
+    {
+        if let Some(first) = self.tree.listing(dir, name) {
+            return self.relist(dir, name, first, Listing::Link, line_no);
+        }
+        self.check_limits(Listing::Link, depth + 1).map_err(|limit| {
+            ParseError::LimitExceeded { 
+                line: line_no, text: line.into(), dir: self.here(), limit,
+            }
+        })?;
+        let size = self.opts.link_size;
+        self.tree.add_link(dir, name, target, size, line_no).map_err(|e| {
+            ParseError::growth(e, line_no, line, self.here())
+        })?;
+        self.files += 1;
+        Ok(())
+    }
+}
+
+impl FSDir {
+    /// Writes out a terminal session of `cd` and `ls` commands that build_fs()
+    /// turns back into this tree. It starts with `$ cd /`, then lists each 
+    /// directory and visits its subdirectories depth-first in name order, 
+    /// with `$ cd name` on the way in and `$ cd ..` on the way out.
+    /// 
+    /// The rebuilt tree's root is always named "/". Names containing '/', or
+    /// that are "." or "..", can't be written as `cd` targets, so subtrees
+    /// under such directories don't survive the round trip. Symbolic links
+    /// are written in the LinkSyntax::default() form, which build_fs_with()
+    /// only reads with ParserOptions::links set. Modification times aren't
+    /// written.
+    /// 
+    pub fn to_session(&self) -> String {
+        let mut writer = SessionWriter { out: String::from("$ cd /\n"), 
+                                         depth: 0 };
+        self.accept(&mut writer);
+        writer.out
+    }
+}
+
+/// The Visitor behind FSDir::to_session().
+/// 
+struct SessionWriter {
+    out   : String,
+    depth : usize,
+}
+impl Visitor for SessionWriter {
+    fn enter_dir(&mut self, _path: &str, dir: &FSDir) -> VisitFlow {
+        if self.depth > 0 {
+            self.out.push_str(&format!("$ cd {}\n", dir.name()));
+        }
+        self.depth += 1;
+        self.out.push_str("$ ls\n");
+        for child in dir {
+            match child {
+                FSEntry::Dir(sub)   => {
+                    self.out.push_str(&format!("dir {}\n", sub.name()));
+                },
+                FSEntry::File(file) => {
+                    self.out.push_str(&format!("{} {}\n", file.size(), 
+                                               file.name()));
+                },
+                FSEntry::Link(link) => {
+                    let syntax = LinkSyntax::default();
+                    let line   = syntax.format(&link.name(), &link.target());
+                    self.out.push_str(&line);
+                    self.out.push('\n');
+                },
+            }
+        }
+        VisitFlow::Continue
+    }
+    fn leave_dir(&mut self, _path: &str, _dir: &FSDir) -> VisitFlow {
+        self.depth -= 1;
+        if self.depth > 0 {
+            self.out.push_str("$ cd ..\n");
+        }
+        VisitFlow::Continue
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::*;
+
+    #[test]
+    fn errors_report_the_current_directory() {
+        let cases = [
+            ("$ cd /\n$ bogus\n",                          "/"),
+            ("$ cd /\n$ ls\ndir a\n$ cd a\n$ bogus\n",     "/a"),
+            ("$ cd /\n$ ls\ndir a\n$ cd a\n$ ls\ndir b\n\
+              $ cd b\n$ ls\nbig b.txt\n",                  "/a/b"),
+        ];
+        for (text, dir) in cases {
+            let err = build_fs_from_str(text).unwrap_err();
+            assert_eq!(err.dir(), Some(dir), "{}", err);
+            assert!(err.to_string().contains(&format!(" in {}:", dir)));
+        }
+    }
+
+    #[test]
+    fn cd_into_a_listed_link_is_a_conflict() {
+        let opts = ParserOptions { links: Some(LinkSyntax::default()), 
+                                   ..Default::default() };
+        let text = "$ cd /\n$ ls\ndir a\nlink l -> a\n$ cd l\n$ ls\n1 x\n";
+        match build_fs_with(text.as_bytes(), opts) {
+            Err(ParseError::ConflictingEntry { path, first, second, 
+                                               first_line, .. }) => {
+                assert_eq!(path, "/l");
+                assert_eq!((first, second), (Listing::Link, Listing::Dir));
+                assert_eq!(first_line, 4);
+            },
+            other => panic!("expected a conflict, got {:?}", other.err()),
+        }
+    }
+
+    /// Goes above the root on line 2 and into an unlisted directory on line
+    /// 3, then lists it.
+    const UNLISTED: &str = "$ cd /\n$ cd ..\n$ cd x\n$ ls\n5 f\n";
+
+    #[test]
+    fn unknown_cds_are_created_warned_about_or_refused() {
+        for mode in [UnknownCd::CreateSilently, UnknownCd::CreateAndWarn] {
+            let opts = ParserOptions { on_unknown_cd: mode, 
+                                       ..Default::default() };
+            let out  = build_fs_with(UNLISTED.as_bytes(), opts).unwrap();
+            assert_eq!(out.root.find_by_path("/x/f").unwrap().size(), 5);
+            let expected = match mode {
+                UnknownCd::CreateAndWarn => vec![
+                    ParseWarning::CdIntoUnknown { line: 3, path: "/x".into() },
+                ],
+                _ => vec![],
+            };
+            assert_eq!(out.warnings, expected);
+        }
+        let opts = ParserOptions { on_unknown_cd: UnknownCd::Error, 
+                                   ..Default::default() };
+        match build_fs_with(UNLISTED.as_bytes(), opts) {
+            Err(ParseError::CdIntoUnknown { line, path, dir, .. }) => {
+                assert_eq!((line, path.as_str(), dir.as_str()), (3, "/x", "/"));
+            },
+            other => panic!("expected CdIntoUnknown, got {:?}", other),
+        }
+        // An `ls` of an unlisted directory follows the same policy.
+        let opts = ParserOptions { on_unknown_cd: UnknownCd::Error, 
+                                   ..Default::default() };
+        let err  = build_fs_with("$ cd /\n$ ls y\n".as_bytes(), opts);
+        assert!(matches!(err, Err(ParseError::CdIntoUnknown { line: 2, .. })));
+    }
+
+    #[test]
+    fn cds_above_the_root_are_ignored_warned_about_or_refused() {
+        for mode in [AboveRoot::Ignore, AboveRoot::ClampAndWarn] {
+            let opts = ParserOptions { on_cd_above_root: mode, 
+                                       ..Default::default() };
+            let out  = build_fs_with(UNLISTED.as_bytes(), opts).unwrap();
+            assert_eq!(out.root.find_by_path("/x/f").unwrap().size(), 5);
+            let expected = match mode {
+                AboveRoot::ClampAndWarn => {
+                    vec![ParseWarning::CdAboveRoot { line: 2 }]
+                },
+                _ => vec![],
+            };
+            assert_eq!(out.warnings, expected);
+        }
+        let opts = ParserOptions { on_cd_above_root : AboveRoot::Error, 
+                                   on_unknown_cd    : UnknownCd::Error,
+                                   ..Default::default() };
+        match build_fs_with(UNLISTED.as_bytes(), opts) {
+            Err(ParseError::CdAboveRoot { line, text }) => {
+                assert_eq!((line, text.as_str()), (2, "$ cd .."));
+            },
+            other => panic!("expected CdAboveRoot, got {:?}", other),
+        }
+        // Warnings of both kinds are kept in the order they're found.
+        let opts = ParserOptions { on_cd_above_root : AboveRoot::ClampAndWarn, 
+                                   on_unknown_cd    : UnknownCd::CreateAndWarn,
+                                   ..Default::default() };
+        let out  = build_fs_with(UNLISTED.as_bytes(), opts).unwrap();
+        assert_eq!(out.warnings, [
+            ParseWarning::CdAboveRoot { line: 2 },
+            ParseWarning::CdIntoUnknown { line: 3, path: "/x".into() },
+        ]);
+    }
+
+    #[test]
+    fn crlf_bom_and_blank_line_fixtures_read_like_the_sample() {
+        let sample = build_fs_from_str(include_str!("../data/sample.txt"))
+                         .unwrap();
+        let names  = sample.walk().map(|(path, _)| path).collect::<Vec<_>>();
+        for text in [include_str!("../data/sample-crlf.txt"), 
+                     include_str!("../data/sample-blank.txt")] {
+            let root = build_fs_from_str(text).unwrap();
+            assert!(root.structural_eq(&sample));
+            assert_eq!(root.walk().map(|(path, _)| path).collect::<Vec<_>>(),
+                       names);
+            assert_eq!(crate::analysis::sum_dirs_at_most(&root, 100_000), 
+                       95437);
+            assert_eq!(root.size(), 48381165);
+        }
+    }
+
+    #[test]
+    fn ls_with_a_target_lists_it_without_moving() {
+        let text = "$ cd /\n$ ls\ndir a\ndir b\n\
+                    $ cd a\n$ ls ../b\n5 x\ndir c\n\
+                    $ ls\n7 y\n\
+                    $ ls /b/c\n3 z\n\
+                    $ cd ..\n$ ls\n1 w\n\
+                    $ cd b/c\n$ ls .\n4 u\n$ cd ..\n$ ls\n2 v\n";
+        let root = build_fs_from_str(text).unwrap();
+        let files = root.walk()
+                        .filter(|(_, entry)| matches!(entry, FSEntry::File(_)))
+                        .map(|(path, entry)| (path, entry.size()))
+                        .collect::<Vec<_>>();
+        assert_eq!(files, [("/a/y".to_string(), 7), 
+                           ("/b/c/u".into(), 4),
+                           ("/b/c/z".into(), 3),
+                           ("/b/v".into(), 2),
+                           ("/b/x".into(), 5),
+                           ("/w".into(), 1)]);
+
+        match build_fs_from_str("$ cd /\n$ ls a b\n") {
+            Err(ParseError::TooManyArguments { line, dir, .. }) => {
+                assert_eq!((line, dir.as_str()), (2, "/"));
+            },
+            other => panic!("expected TooManyArguments, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn progress_is_reported_every_so_many_lines() {
+        use std::cell::RefCell;
+        let sample = include_str!("../data/sample.txt");
+        let lines  = sample.lines().count();
+        for every in [0, 1, 5, 100] {
+            let calls = Rc::new(RefCell::new(vec![]));
+            let seen  = calls.clone();
+            let opts  = ParserOptions { 
+                progress       : Some(Box::new(move |info| {
+                    seen.borrow_mut().push(info)
+                })),
+                progress_every : every,
+                input_size     : Some(sample.len() as u64),
+                ..Default::default()
+            };
+            build_fs_with(sample.as_bytes(), opts).unwrap();
+            let calls    = calls.borrow();
+            let expected = lines.checked_div(every).unwrap_or(0);
+            assert_eq!(calls.len(), expected, "every {}", every);
+            for (n, info) in calls.iter().enumerate() {
+                assert_eq!(info.lines, (n + 1) * every);
+                assert_eq!(info.total_bytes, Some(sample.len() as u64));
+            }
+            if every == 1 {
+                let last = calls.last().unwrap();
+                assert_eq!((last.dirs, last.total_size), (3, 48381165));
+                assert_eq!(last.fraction_done(), Some(1.0));
+            }
+        }
+    }
+
+    #[test]
+    fn listing_the_same_entries_again_is_not_a_conflict() {
+        let text = "$ cd /\n$ ls\ndir a\n5 f\n$ ls\n5 f\ndir a\n\
+                    $ cd a\n$ cd ..\n$ ls /\ndir a\n5 f\n";
+        let opts = ParserOptions::default();
+        let out  = build_fs_with(text.as_bytes(), opts).unwrap();
+        assert_eq!((out.root.size(), out.root.len()), (5, 2));
+        assert!(out.warnings.is_empty());
+    }
+
+    #[test]
+    fn conflicting_listings_are_errors_or_warnings() {
+        // The text, the path, the line and listing kept, and the line and 
+        // listing that conflict with it.
+        let cases = [
+            ("$ cd /\n$ ls\ndir a\n$ ls\n5 a\n", 
+             "/a", (3, Listing::Dir), (5, Listing::File(5))),
+            ("$ cd /\n$ ls\n5 a\n$ ls\ndir a\n", 
+             "/a", (3, Listing::File(5)), (5, Listing::Dir)),
+            ("$ cd /\n$ ls\n5 a\n$ ls\n6 a\n", 
+             "/a", (3, Listing::File(5)), (5, Listing::File(6))),
+            ("$ cd /\n$ ls\ndir d\n$ cd d\n$ ls\n5 a\n$ cd /\n\
+              $ ls d\n6 a\n", 
+             "/d/a", (6, Listing::File(5)), (9, Listing::File(6))),
+        ];
+        for (text, path, (first_line, first), (line, second)) in cases {
+            let err = build_fs_from_str(text).unwrap_err();
+            match &err {
+                ParseError::ConflictingEntry { line: l, path: p, first: f, 
+                                               first_line: fl, second: s, 
+                                               .. } => {
+                    assert_eq!((p.as_str(), *fl, *f, *l, *s), 
+                               (path, first_line, first, line, second));
+                },
+                other => panic!("expected a conflict, got {:?}", other),
+            }
+            let opts = ParserOptions { conflicts_as_warnings: true, 
+                                       ..Default::default() };
+            let out  = build_fs_with(text.as_bytes(), opts).unwrap();
+            assert_eq!(out.warnings, [ParseWarning::ConflictingEntry {
+                line, path: path.into(), first, first_line, second,
+            }]);
+            let kept = match out.root.find_by_path(path).unwrap() {
+                FSEntry::Dir(_)     => Listing::Dir,
+                FSEntry::File(file) => Listing::File(file.size()),
+                FSEntry::Link(_)    => Listing::Link,
+            };
+            assert_eq!(kept, first);
+        }
+    }
+
+    #[test]
+    fn entries_added_through_root_conflict_from_line_0() {
+        let mut parser = SessionParser::new(ParserOptions::default());
+        parser.feed_line("$ cd /").unwrap();
+        parser.root().add_file(FSFile::new("f".into(), 5));
+        parser.feed_line("$ ls").unwrap();
+        match parser.feed_line("6 f") {
+            Err(ParseError::ConflictingEntry { line, first_line, .. }) => {
+                assert_eq!((line, first_line), (3, 0));
+            },
+            other => panic!("expected a conflict, got {:?}", other),
+        }
+        match parser.feed_line("$ cd f") {
+            Err(ParseError::ConflictingEntry { line, first_line, .. }) => {
+                assert_eq!((line, first_line), (4, 0));
+            },
+            other => panic!("expected a conflict, got {:?}", other),
+        }
+        parser.feed_line("$ ls").unwrap();
+        parser.feed_line("5 f").unwrap();
+        assert_eq!(parser.finish().unwrap().size(), 5);
+    }
+
+    #[test]
+    fn sessions_written_from_trees_build_them_again() {
+        let empty = FSDir::new("/".into());
+        let deep  = FSDir::new("/".into());
+        deep.make_dirs("a/b/c/d").add_file(FSFile::new("f".into(), 1));
+        deep.make_dirs("a/e");
+        deep.make_dirs("x y").add_file(FSFile::new("with space".into(), 2));
+        let sample = build_fs_from_str(include_str!("../data/sample.txt"))
+                         .unwrap();
+        let random = (0..20).map(|seed| {
+            FSDir::random(seed, TreeSpec { nodes: 200, ..Default::default() })
+        });
+        for tree in [empty, deep, sample].into_iter().chain(random) {
+            let text = tree.to_session();
+            assert!(text.starts_with("$ cd /\n"));
+            let root = build_fs_from_str(&text).unwrap();
+            assert!(root.structural_eq(&tree), "{}", text);
+        }
+    }
+
+    #[test]
+    fn files_are_read_with_or_without_mtimes() {
+        let plain = "$ cd /\n$ ls\ndir a\n5 f g\n$ cd a\n$ ls\n7 h\n";
+        let timed = "$ cd /\n$ ls\ndir a\n5 1700000000 f g\n$ cd a\n\
+                     $ ls\n7 1600000000 h\n";
+        let mtimes = |text: &str, on| {
+            let opts = ParserOptions { mtimes: on, ..Default::default() };
+            let root = build_fs_with(text.as_bytes(), opts)?.root;
+            Ok::<_, ParseError>(root.walk().map(|(path, entry)| match entry {
+                FSEntry::File(f) => (path, f.size(), f.mtime()),
+                FSEntry::Dir(d)  => (path, d.size(), d.mtime()),
+                FSEntry::Link(_) => unreachable!(),
+            }).collect::<Vec<_>>())
+        };
+        assert_eq!(mtimes(plain, false).unwrap(), 
+                   [("/a".into(), 7, None), ("/a/h".into(), 7, None), 
+                    ("/f g".into(), 5, None)]);
+        assert_eq!(mtimes(timed, true).unwrap(), 
+                   [("/a".into(), 7, Some(1600000000)), 
+                    ("/a/h".into(), 7, Some(1600000000)), 
+                    ("/f g".into(), 5, Some(1700000000))]);
+        // Without the option the time is taken as part of the name, and with
+        // it, a name is taken as the time.
+        let paths = mtimes(timed, false).unwrap()
+                                        .into_iter()
+                                        .map(|(path, _, _)| path)
+                                        .collect::<Vec<_>>();
+        assert_eq!(paths, ["/1700000000 f g", "/a", "/a/1600000000 h"]);
+        match mtimes(plain, true) {
+            Err(ParseError::BadMtime { line, text, .. }) => {
+                assert_eq!((line, text.as_str()), (4, "5 f g"));
+            },
+            other => panic!("expected BadMtime, got {:?}", other),
+        }
+        assert!(matches!(mtimes("$ ls\n5 1700000000\n", true), 
+                         Err(ParseError::MissingArgument { line: 2, .. })));
+    }
+
+    #[test]
+    fn arena_reads_sessions_like_the_tree_parser() {
+        let sample = include_str!("../data/sample.txt");
+        let tree   = build_fs_from_str(sample).unwrap();
+        let arena  = build_fs_arena(sample.as_bytes()).unwrap();
+        assert_eq!(arena.size(arena.root()), tree.size());
+        assert!(arena.to_fsdir(arena.root()).structural_eq(&tree));
+
+        let bad = ["$ cd /\n$ rm x\n", "$ ls a b\n", "$ ls\nabc x\n",
+                   "$ ls\n10 a\n$ cd a\n", "$ ls\ndir\n", "stray\n"];
+        for text in bad {
+            let tree  = build_fs_from_str(text).unwrap_err();
+            let arena = build_fs_arena(text.as_bytes()).unwrap_err();
+            assert_eq!(arena.to_string(), tree.to_string());
+        }
+    }
+
+    #[test]
+    fn lines_fed_one_at_a_time_or_in_chunks_build_the_same_tree() {
+        let sample     = include_str!("../data/sample.txt");
+        let tree       = build_fs_from_str(sample).unwrap();
+        let mut parser = SessionParser::new(ParserOptions::default());
+        for line in sample.lines() {
+            parser.feed_line(line).unwrap();
+        }
+        assert!(parser.finish().unwrap().structural_eq(&tree));
+
+        // As the text would arrive over a socket: in chunks that split lines
+        // anywhere, with a line fed once its newline has come.
+        for seed in 0..20 {
+            let mut rng     = SplitMix64::new(seed);
+            let mut parser  = SessionParser::new(ParserOptions::default());
+            let mut pending = String::new();
+            let mut rest    = sample;
+            let mut size    = 0;
+            while !rest.is_empty() {
+                let len = rng.in_range(&(1..=24)).min(rest.len());
+                let (chunk, tail) = rest.split_at(len);
+                rest = tail;
+                pending.push_str(chunk);
+                while let Some(end) = pending.find('\n') {
+                    let line = pending.drain(..=end).collect::<String>();
+                    parser.feed_line(line.trim_end_matches('\n')).unwrap();
+                }
+                assert!(parser.root().size() >= size);
+                size = parser.root().size();
+            }
+            if !pending.is_empty() {
+                parser.feed_line(&pending).unwrap();
+            }
+            let root = parser.finish().unwrap();
+            assert!(root.structural_eq(&tree), "seed {}", seed);
+            assert_eq!(root.size(), 48381165);
+        }
+    }
+
+    /// Returns a session that goes `depth` directories deep, one `cd` at a
+    /// time, and lists a 1 byte file at the bottom.
+    fn deep_session(depth: usize) -> String {
+        let mut text = String::from("$ cd /\n");
+        for _ in 0..depth {
+            text.push_str("$ ls\ndir d\n$ cd d\n");
+        }
+        text.push_str("$ ls\n1 f\n");
+        text
+    }
+
+    #[test]
+    fn deep_sessions_parse_without_a_limit() {
+        let root = build_fs_from_str(&deep_session(100_000)).unwrap();
+        assert_eq!(root.size(), 1);
+        assert_eq!(root.iter_dirs_recurs().count(), 100_000);
+    }
+
+    #[test]
+    fn limits_stop_deep_sessions() {
+        let text = deep_session(100_000);
+        let opts = ParserOptions { max_depth: Some(1000), 
+                                   ..Default::default() };
+        match build_fs_with(text.as_bytes(), opts) {
+            Err(ParseError::LimitExceeded { line, text, limit, .. }) => {
+                // The listing of the 1001st directory, on its `dir` line.
+                assert_eq!((line, text.as_str()), (3 + 3 * 1000, "dir d"));
+                assert_eq!(limit, Limit::Depth(1000));
+            },
+            other => panic!("expected the limit, got {:?}", other.err()),
+        }
+        let opts = ParserOptions { max_nodes: Some(500), 
+                                   ..Default::default() };
+        let err  = build_fs_with(text.as_bytes(), opts).unwrap_err();
+        assert!(matches!(err, ParseError::LimitExceeded { 
+                                  limit: Limit::Nodes(500), .. }), "{}", err);
+        let opts = ParserOptions { max_depth: Some(100_000), 
+                                   ..Default::default() };
+        assert!(build_fs_with(text.as_bytes(), opts).is_ok());
+    }
+
+    #[test]
+    fn ten_thousand_files_of_one_name_share_it() {
+        // 100 directories "0".."99", each holding 100 more that each hold a
+        // "data.bin".
+        let dirs: String = (0..100).map(|i| format!("dir {}\n", i)).collect();
+        let mut text = format!("$ cd /\n$ ls\n{}", dirs);
+        for i in 0..100 {
+            text.push_str(&format!("$ cd {}\n$ ls\n{}", i, dirs));
+            for j in 0..100 {
+                text.push_str(&format!("$ cd {}\n$ ls\n1 data.bin\n$ cd ..\n", j));
+            }
+            text.push_str("$ cd ..\n");
+        }
+        let root  = build_fs_from_str(&text).unwrap();
+        let stats = root.memory_stats();
+        assert_eq!(root.stats().files, 10_000);
+        // "/", "0".."99" and "data.bin", each allocated once.
+        assert_eq!((stats.nodes, stats.unique_names, stats.name_allocs), 
+                   (20_101, 102, 102));
+        let opts  = ParserOptions { intern_names: false, ..Default::default() };
+        let plain = build_fs_with(text.as_bytes(), opts).unwrap().root;
+        let plain_stats = plain.memory_stats();
+        assert_eq!((plain_stats.unique_names, plain_stats.name_allocs), 
+                   (102, 20_101));
+        assert!(plain_stats.approx_bytes > stats.approx_bytes);
+        assert!(plain.structural_eq(&root));
+    }
+
+    #[test]
+    fn overlapping_sessions_merge_into_one_tree() {
+        let sample = include_str!("../data/sample.txt");
+        let lines  = sample.lines().collect::<Vec<_>>();
+        // The first session stops after listing "/a/e", the second lists 
+        // "/" and "/a" again and then "/d" instead.
+        let first  = lines[..15].join("\n");
+        let second = [&lines[..12], &["$ cd .."], &lines[17..]].concat();
+        let second = second.join("\n");
+        let dir    = std::env::temp_dir();
+        let pid    = std::process::id();
+        let paths  = ["first", "second", "conflict"].map(|name| {
+            dir.join(format!("nsl-merge-{}-{}.txt", pid, name))
+        });
+        std::fs::write(&paths[0], &first).unwrap();
+        std::fs::write(&paths[1], &second).unwrap();
+        std::fs::write(&paths[2], second.replace("29116 f", "999 f")).unwrap();
+        let merged   = build_fs_multi(&paths[..2]);
+        let conflict = build_fs_multi(&[paths[0].clone(), paths[2].clone()]);
+        for path in &paths {
+            let _ = std::fs::remove_file(path);
+        }
+        let merged = merged.unwrap();
+        assert!(merged.structural_eq(&build_fs_from_str(sample).unwrap()));
+        assert_eq!(merged.size(), 48381165);
+
+        let err = conflict.unwrap_err();
+        match &err {
+            ParseError::MergeConflict { file, source } => {
+                assert_eq!(file, &paths[2]);
+                assert_eq!(source, &MergeError::SizeConflict { 
+                    path: "/a/f".into(), ours: 29116, theirs: 999 
+                });
+            },
+            e => panic!("expected a merge conflict, got {:?}", e),
+        }
+        assert_eq!(err.to_string(), 
+                   format!("{}: can't merge: '/a/f' has size 29116 in one \
+                            tree and 999 in the other", paths[2].display()));
+    }
+
+    #[test]
+    fn other_prompts_read_like_the_dollar() {
+        let sample = include_str!("../data/sample.txt");
+        let arrows = sample.replace("$ ", "➜ ");
+        let tree   = build_fs_from_str(sample).unwrap();
+        let opts   = ParserOptions { prompt: "➜".into(), ..Default::default() };
+        let root   = build_fs_with(arrows.as_bytes(), opts).unwrap().root;
+        assert!(root.structural_eq(&tree));
+        fn arrow(line: &str) -> Option<&str> {
+            match_prompt_token(line, "➜")
+        }
+        let opts = ParserOptions { prompt_matcher : Some(arrow), 
+                                   ..Default::default() };
+        let root = build_fs_with(arrows.as_bytes(), opts).unwrap().root;
+        assert!(root.structural_eq(&tree));
+        // With the default prompt, the first "➜" line isn't a command.
+        let err = build_fs_from_str(&arrows).unwrap_err();
+        assert!(matches!(err, ParseError::UnexpectedOutput { line: 1, .. }), 
+                "{:?}", err);
+    }
+
+    #[test]
+    fn unknown_commands_mid_session_are_skipped_collected_or_refused() {
+        // A `du` run from /a, between the `cd ..` out of /a/e and the one 
+        // out of /a.
+        let sample    = include_str!("../data/sample.txt");
+        let mut lines = sample.lines().collect::<Vec<_>>();
+        lines.splice(16..16, ["$ du -sh *", "584\te", "29K\tf"]);
+        let text      = lines.join("\n");
+        let tree      = build_fs_from_str(sample).unwrap();
+        let with      = |on_unknown_command| {
+            let opts = ParserOptions { on_unknown_command, 
+                                       ..Default::default() };
+            build_fs_with(text.as_bytes(), opts)
+        };
+        let skipped = with(UnknownCommands::SkipSilently).unwrap();
+        assert!(skipped.root.structural_eq(&tree));
+        assert!(skipped.warnings.is_empty() && skipped.commands.is_empty());
+
+        let collected = with(UnknownCommands::Collect).unwrap();
+        assert!(collected.root.structural_eq(&tree));
+        assert_eq!(collected.commands, [CollectedCommand { 
+            line    : 17, 
+            command : "du -sh *".into(),
+            output  : vec!["584\te".into(), "29K\tf".into()],
+        }]);
+
+        let err = with(UnknownCommands::Error).unwrap_err();
+        assert_eq!(err.to_string(), 
+                   "line 17 in /a: unknown command: $ du -sh *");
+    }
+
+    #[test]
+    fn names_keep_their_spaces() {
+        let text = "$ cd /\n$ ls\ndir My Documents\ndir  lead\n\
+                    12 my report.txt\n7 trail \n\
+                    $ cd My Documents\n$ ls\n3 a  b\n$ cd ..\n\
+                    $ cd  lead\n$ ls\n4 x\n";
+        let root = build_fs_from_str(text).unwrap();
+        assert!(root.contains("my report.txt") && root.contains("trail "));
+        assert!(!root.contains("my") && !root.contains("trail"));
+        let docs = root.get_dir("My Documents").unwrap();
+        assert!(docs.contains("a  b") && !docs.contains("a b"));
+        assert_eq!(docs.path(), "/My Documents");
+        let lead = root.get_dir(" lead").unwrap();
+        assert!(root.get_dir("lead").is_none());
+        assert_eq!((lead.size(), root.size()), (4, 26));
+        // Written back out and read again, the names come back the same.
+        let again = build_fs_from_str(&root.to_session()).unwrap();
+        assert!(again.structural_eq(&root));
+    }
+
+    #[test]
+    fn cd_targets_can_be_paths() {
+        let text = "$ cd /\n$ ls\ndir a\n$ cd a\n$ ls\ndir b\n\
+                    $ cd b\n$ ls\ndir c\n$ cd /\n\
+                    $ cd a/b/c\n$ ls\n1 f\n\
+                    $ cd ..\n$ ls\n2 g\n\
+                    $ cd /a/b/c/\n$ ls\n3 h\n\
+                    $ cd /\n$ cd ./a\n$ ls\n4 i\n\
+                    $ cd ..\n$ ls\n5 j\n\
+                    $ cd a/b/\n$ cd ..\n$ ls\n6 k\n\
+                    $ cd ./b/./c/../\n$ ls\n7 l\n";
+        let root  = build_fs_from_str(text).unwrap();
+        let files = root.walk().filter_map(|(_, entry)| match entry {
+            FSEntry::File(f) => Some((f.path(), f.size())),
+            _                => None,
+        }).collect::<Vec<_>>();
+        // A lone `cd ..` after a multi-part `cd` goes up one level.
+        assert_eq!(files, [("/a/b/c/f".into(), 1), ("/a/b/c/h".into(), 3), 
+                           ("/a/b/g".into(), 2), ("/a/b/l".into(), 7), 
+                           ("/a/i".into(), 4), ("/a/k".into(), 6), 
+                           ("/j".into(), 5)]);
+        assert_eq!(root.dir_count(), 3);
+    }
+}
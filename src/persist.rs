@@ -0,0 +1,258 @@
+//! This module saves and loads file system trees as JSON, through serde and
+//! serde_json. It's built with the `serde` feature.
+//! 
+//! A tree is stored as nested directory objects. Parent links and directory
+//! sizes aren't stored; they're rebuilt on load.
+//! 
+//! ```text
+//! {"name":"/","files":[{"name":"b.txt","size":14848514}],"dirs":[...]}
+//! ```
+//! 
+//! Files and directories with a modification time (see FSFile::mtime())
+//! carry it as an "mtime" member, in seconds since the Unix epoch. For a
+//! directory, it's only the time set on it, not one taken from its files.
+//! 
+//! A directory with symbolic links lists them in a "links" member, after its
+//! files, as `{"name":"l","target":"../a"}`. A link that counts toward the
+//! sizes above it (see LinkSize) has a "size" member too, the length of its
+//! target.
+//! 
+//! Documents nest as deeply as the tree does. serde_json's recursion limit is
+//! turned off, and to_json() and from_json() run through serde_stacker,
+//! which moves to a fresh stack on the heap when the current one runs low,
+//! so deep trees don't overflow the stack.
+
+use std::borrow::Cow;
+use std::fmt;
+use serde::de::{self, Deserializer, IgnoredAny, MapAccess, Visitor};
+use serde::ser::{SerializeStruct, Serializer};
+use serde::{Deserialize, Serialize};
+use crate::fsobject::*;
+
+/// A file as it's saved.
+#[derive(Serialize, Deserialize)]
+struct FileRecord<'a> {
+    #[serde(borrow)]
+    name  : Cow<'a, str>,
+    size  : u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    mtime : Option<u64>,
+}
+
+/// A symbolic link as it's saved. `size` is only there for links that count
+/// toward the sizes above them.
+/// 
+#[derive(Serialize, Deserialize)]
+struct LinkRecord<'a> {
+    #[serde(borrow)]
+    name   : Cow<'a, str>,
+    #[serde(borrow)]
+    target : Cow<'a, str>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    size   : Option<u64>,
+}
+
+/// Serializes the directory and everything under it, in the format described
+/// in the module docs. Subdirectories are serialized in turn as FSDirs, so
+/// the nesting goes as deep as the tree; see to_json().
+/// 
+impl Serialize for FSDir {
+    fn serialize<S: Serializer>(&self, serializer: S)
+        -> Result<S::Ok, S::Error>
+    {
+        let mtime = self.own_mtime();
+        let files = self.find_files_by(|_| true)
+                        .iter()
+                        .map(|file| FileRecord {
+                            name  : file.name_ref().to_string().into(),
+                            size  : file.size(),
+                            mtime : file.mtime(),
+                        })
+                        .collect::<Vec<_>>();
+        let links = self.children()
+                        .into_iter()
+                        .filter_map(|c| match c {
+                            FSEntry::Link(link) => Some(LinkRecord {
+                                name   : link.name_ref().to_string().into(),
+                                target : link.target().into(),
+                                size   : Some(link.size()).filter(|&n| n > 0),
+                            }),
+                            _ => None,
+                        })
+                        .collect::<Vec<_>>();
+        let len   = 3 + mtime.is_some() as usize + !links.is_empty() as usize;
+        let mut s = serializer.serialize_struct("FSDir", len)?;
+        s.serialize_field("name", &*self.name_ref())?;
+        match mtime {
+            Some(_) => s.serialize_field("mtime", &mtime)?,
+            None    => s.skip_field("mtime")?,
+        }
+        s.serialize_field("files", &files)?;
+        match links.is_empty() {
+            true  => s.skip_field("links")?,
+            false => s.serialize_field("links", &links)?,
+        }
+        s.serialize_field("dirs", &self.find_dirs_by(|_| true))?;
+        s.end()
+    }
+}
+
+/// Rebuilds a directory saved by its Serialize impl. Each directory is built
+/// once its members have been read, with its files, links and subdirectories
+/// added by try_add_file(), try_add_link() and try_add_dir(), so parent links
+/// and sizes are rebuilt and a size that would overflow is an error.
+/// Modification times and links are optional, and unknown members are
+/// skipped.
+/// 
+impl<'de> Deserialize<'de> for FSDir {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D)
+        -> Result<Self, D::Error>
+    {
+        deserializer.deserialize_struct("FSDir", FIELDS, DirVisitor)
+    }
+}
+
+/// The members of a saved directory.
+const FIELDS: &[&str] = &["name", "mtime", "files", "links", "dirs"];
+
+/// Reads a directory object into an FSDir.
+struct DirVisitor;
+
+impl<'de> Visitor<'de> for DirVisitor {
+    type Value = FSDir;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a directory object")
+    }
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A)
+        -> Result<FSDir, A::Error>
+    {
+        let mut name  = None;
+        let mut mtime = None;
+        let mut files = Vec::<FileRecord>::new();
+        let mut links = Vec::<LinkRecord>::new();
+        let mut dirs  = Vec::<FSDir>::new();
+        while let Some(key) = map.next_key::<Cow<str>>()? {
+            match &*key {
+                "name"  => name  = Some(map.next_value::<String>()?),
+                "mtime" => mtime = map.next_value()?,
+                "files" => files = map.next_value()?,
+                "links" => links = map.next_value()?,
+                "dirs"  => dirs  = map.next_value()?,
+                _       => { map.next_value::<IgnoredAny>()?; },
+            }
+        }
+        let dir = FSDir::new(name.ok_or_else(|| {
+            de::Error::missing_field("name")
+        })?);
+        dir.set_mtime(mtime);
+        for FileRecord { name, size, mtime } in files {
+            let file = FSFile::new(name.into_owned(), size);
+            let file = dir.try_add_file(file).map_err(de::Error::custom)?;
+            file.set_mtime(mtime);
+        }
+        for LinkRecord { name, target, size } in links {
+            let size = match size {
+                None | Some(0) => LinkSize::Zero,
+                Some(n) if n == target.len() as u64 => LinkSize::TargetLength,
+                Some(_) => {
+                    return Err(de::Error::custom("link size isn't 0 or the \
+                                                  target's length"));
+                },
+            };
+            let link = FSLink::new_with(name.into_owned(),
+                                        target.into_owned(), size);
+            dir.try_add_link(link).map_err(de::Error::custom)?;
+        }
+        for sub in dirs {
+            dir.try_add_dir(sub).map_err(de::Error::custom)?;
+        }
+        Ok(dir)
+    }
+}
+
+impl FSDir {
+    /// Saves the tree rooted at this directory as JSON, in the format
+    /// described in the module docs.
+    /// 
+    pub fn to_json(&self) -> String {
+        let mut out = vec![];
+        let mut ser = serde_json::Serializer::new(&mut out);
+        self.serialize(serde_stacker::Serializer::new(&mut ser))
+            .expect("a tree always serializes");
+        String::from_utf8(out).expect("serde_json writes UTF-8")
+    }
+    /// Loads a tree saved by to_json(), as the Deserialize impl does. Fails
+    /// if the text isn't JSON in that format, or if a size would overflow.
+    /// 
+    pub fn from_json(text: &str) -> Result<FSDir, serde_json::Error> {
+        let mut de = serde_json::Deserializer::from_str(text);
+        de.disable_recursion_limit();
+        let root   = FSDir::deserialize(serde_stacker::Deserializer::new(
+                                            &mut de))?;
+        de.end()?;
+        Ok(root)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::*;
+
+    #[test]
+    fn the_sample_survives_the_round_trip() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/data/sample.txt");
+        let root = build_fs_from_path(path.as_ref()).unwrap();
+        let json = serde_json::to_string(&root).unwrap();
+        let back = serde_json::from_str::<FSDir>(&json).unwrap();
+        assert!(back.structural_eq(&root), "{}", json);
+        assert_eq!(back.size(), root.size());
+        assert_eq!(back.size(), 48381165);
+        assert_eq!(back.get_dir("a").unwrap().size(), 94853);
+        assert_eq!(FSDir::from_json(&root.to_json()).unwrap().size(),
+                   48381165);
+    }
+
+    #[test]
+    fn links_survive_the_round_trip() {
+        let root = FSDir::new("/".into());
+        let a    = root.make_dirs("a");
+        a.add_file(FSFile::new("f".into(), 10));
+        root.add_link(FSLink::new("plain".into(), "a/f".into()));
+        a.add_link(FSLink::new_with("sized".into(), "../a".into(),
+                                    LinkSize::TargetLength));
+        let json = root.to_json();
+        let back = FSDir::from_json(&json).unwrap();
+        assert!(back.structural_eq(&root), "{}", json);
+        assert_eq!(back.size(), 14);
+        let link = back.get_link("plain").unwrap();
+        assert_eq!(back.resolve_link(&link).unwrap().path(), "/a/f");
+    }
+
+    #[test]
+    fn deep_trees_survive_the_round_trip() {
+        let root = FSDir::new("/".into());
+        let mut dir = root.clone();
+        for _ in 0..100_000 {
+            dir = dir.add_dir(FSDir::new("d".into()));
+        }
+        dir.add_file(FSFile::new("f".into(), 7));
+        let back = FSDir::from_json(&root.to_json()).unwrap();
+        assert_eq!(back.size(), 7);
+        assert_eq!(back.iter_dirs_recurs().count(), 100_000);
+    }
+
+    #[test]
+    fn bad_documents_are_an_error() {
+        let max  = u64::MAX;
+        let json = format!("{{\"name\":\"/\",\"files\":[\
+                            {{\"name\":\"a\",\"size\":{}}},\
+                            {{\"name\":\"b\",\"size\":{}}}]}}", max, max);
+        let e = FSDir::from_json(&json).unwrap_err();
+        assert!(e.to_string().contains("overflow"), "{}", e);
+        assert!(FSDir::from_json("[]").is_err());
+        assert!(FSDir::from_json("{\"files\":[]}").is_err());
+        assert!(FSDir::from_json("{\"name\":\"/\"} x").is_err());
+    }
+}
@@ -1,10 +1,16 @@
-/// This file implements a simple iterator that allows you to put back items.
-/// Other iterators don't allow this, so this is a simple wrapper around them.
+//! This file implements a simple iterator that allows you to put back items.
+//! Other iterators don't allow this, so this is a simple wrapper around them.
 
 use std::collections::VecDeque;
+use std::io::{self, BufRead, Lines};
+use std::iter::Enumerate;
 
-/// A simple iterator that allows you to put back items.
+/// A simple iterator that allows you to put back items. Put back items are
+/// returned before any more are taken from the wrapped iterator, most 
+/// recently put back first, like a stack. So putting back A then B yields B
+/// then A.
 /// 
+#[derive(Debug, Clone)]
 pub struct PutBack<I, T> {
     iter : I,
     buf  : VecDeque<T>,
@@ -15,10 +21,51 @@ impl<I: Iterator<Item=T>, T> PutBack<I, T> {
         Self { iter, buf: VecDeque::new() }
     }
     /// Put an item back into the iterator. It will be returned the next time
-    /// next() is called.
+    /// next() is called, ahead of any items put back before it.
     /// 
     pub fn put_back(&mut self, item: T) {
-        self.buf.push_back(item);
+        self.buf.push_front(item);
+    }
+    /// Put a run of items back into the iterator. They will be returned in 
+    /// the same order they're given in, ahead of any items put back before.
+    /// 
+    pub fn put_back_all<J>(&mut self, items: J) 
+    where
+        J: IntoIterator<Item=T>
+    {
+        let items = items.into_iter().collect::<Vec<_>>();
+        for item in items.into_iter().rev() {
+            self.buf.push_front(item);
+        }
+    }
+    /// Returns a reference to the item the next call to next() will return,
+    /// without consuming it. Returns None if the iterator is exhausted.
+    /// 
+    pub fn peek(&mut self) -> Option<&T> {
+        self.fill();
+        self.buf.front()
+    }
+    /// Like peek(), but returns a mutable reference, so the item can be 
+    /// modified before it's returned by next().
+    /// 
+    pub fn peek_mut(&mut self) -> Option<&mut T> {
+        self.fill();
+        self.buf.front_mut()
+    }
+    /// Consumes the PutBack, returning the items that have been put back or
+    /// peeked at, in the order they would have been returned, along with the
+    /// wrapped iterator.
+    /// 
+    pub fn into_inner(self) -> (VecDeque<T>, I) {
+        (self.buf, self.iter)
+    }
+    /// Makes sure the buffer holds the next item, if there is one.
+    fn fill(&mut self) {
+        if self.buf.is_empty() {
+            if let Some(item) = self.iter.next() {
+                self.buf.push_back(item);
+            }
+        }
     }
 }
 impl<I: Iterator<Item=T>, T> Iterator for PutBack<I, T> {
@@ -32,4 +79,186 @@ impl<I: Iterator<Item=T>, T> Iterator for PutBack<I, T> {
             self.iter.next()
         }
     }
+    /// The wrapped iterator's hint, plus the number of buffered items.
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (lo, hi) = self.iter.size_hint();
+        let n        = self.buf.len();
+        (lo.saturating_add(n), hi.and_then(|hi| hi.checked_add(n)))
+    }
+}
+impl<I: ExactSizeIterator<Item=T>, T> ExactSizeIterator for PutBack<I, T> {}
+
+/// A PutBack over the lines of a reader. Lines are put back as plain Strings,
+/// so read errors can't be put back by mistake, and the number of each line is
+/// tracked for error reporting.
+/// 
+pub struct LinesPutBack<R> {
+    iter    : PutBack<Enumerate<Lines<R>>, (usize, io::Result<String>)>,
+    line_no : usize,
+}
+impl<R: BufRead> LinesPutBack<R> {
+    /// Create a new LinesPutBack over the lines of `reader`.
+    pub fn new(reader: R) -> Self {
+        Self { iter: PutBack::new(reader.lines().enumerate()), line_no: 0 }
+    }
+    /// Returns the 1-based number of the line most recently returned by 
+    /// next(), or 0 if no line has been returned yet.
+    /// 
+    pub fn line_number(&self) -> usize {
+        self.line_no
+    }
+    /// Put the line most recently returned by next() back. It will be 
+    /// returned again, with its original line number, the next time next() 
+    /// is called. Putting back several lines in the reverse of the order they
+    /// were read keeps their numbers right.
+    /// 
+    pub fn put_back(&mut self, line: String) {
+        // Lines are stored with their 0-based index, as enumerate() gives it.
+        self.line_no = self.line_no.saturating_sub(1);
+        self.iter.put_back((self.line_no, Ok(line)));
+    }
+    /// Returns a reference to the line the next call to next() will return,
+    /// without consuming it.
+    /// 
+    pub fn peek(&mut self) -> Option<&io::Result<String>> {
+        self.iter.peek().map(|(_, line)| line)
+    }
+}
+impl<R: BufRead> Iterator for LinesPutBack<R> {
+    type Item = io::Result<String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (i, line) = self.iter.next()?;
+        self.line_no  = i + 1;
+        Some(line)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn size_hint_counts_the_buffer_and_the_inner_hint() {
+        let mut iter = PutBack::new(vec![1, 2, 3].into_iter());
+        assert_eq!(iter.size_hint(), (3, Some(3)));
+        iter.put_back(0);
+        iter.put_back_all([7, 8]);
+        assert_eq!(iter.size_hint(), (6, Some(6)));
+        assert_eq!(iter.len(), 6);
+        assert_eq!(iter.next(), Some(7));
+        assert_eq!(iter.size_hint(), (5, Some(5)));
+        iter.peek();
+        assert_eq!(iter.size_hint(), (5, Some(5)));
+        // An unbounded inner iterator keeps the upper bound unknown.
+        let mut iter = PutBack::new(0..);
+        iter.put_back(5);
+        assert_eq!(iter.size_hint(), (usize::MAX, None));
+        let mut iter = PutBack::new(std::iter::empty());
+        assert_eq!(iter.size_hint(), (0, Some(0)));
+        iter.put_back(1);
+        assert_eq!(iter.size_hint(), (1, Some(1)));
+    }
+
+    #[test]
+    fn interleaved_put_backs_come_out_last_in_first_out() {
+        enum Op { Next, One(i32), All(&'static [i32]) }
+        use Op::*;
+        let cases: &[(&[Op], &[i32])] = &[
+            (&[One(10), One(20)],                     &[20, 10, 1, 2, 3]),
+            (&[All(&[10, 20])],                       &[10, 20, 1, 2, 3]),
+            (&[One(10), All(&[20, 30])],              &[20, 30, 10, 1, 2, 3]),
+            (&[All(&[10, 20]), One(30)],              &[30, 10, 20, 1, 2, 3]),
+            (&[All(&[10, 20]), All(&[30, 40])],       &[30, 40, 10, 20, 1, 2, 3]),
+            (&[Next, One(10), Next, One(20)],         &[1, 10, 20, 2, 3]),
+            (&[Next, Next, All(&[10, 20]), Next],     &[1, 2, 10, 20, 3]),
+            (&[One(10), Next, All(&[20]), One(30)],   &[10, 30, 20, 1, 2, 3]),
+            (&[All(&[]), One(10), All(&[])],          &[10, 1, 2, 3]),
+            (&[Next, Next, Next, All(&[10, 20])],     &[1, 2, 3, 10, 20]),
+        ];
+        for (ops, expected) in cases {
+            let mut iter = PutBack::new(vec![1, 2, 3].into_iter());
+            let mut out  = vec![];
+            for op in ops.iter() {
+                match op {
+                    Next       => out.extend(iter.next()),
+                    One(item)  => iter.put_back(*item),
+                    All(items) => iter.put_back_all(items.iter().copied()),
+                }
+            }
+            out.extend(iter);
+            assert_eq!(&out, expected);
+        }
+    }
+
+    #[test]
+    fn putting_back_after_the_end_resumes() {
+        let mut iter = PutBack::new(vec![1].into_iter());
+        assert_eq!((iter.next(), iter.next()), (Some(1), None));
+        iter.put_back(1);
+        assert_eq!((iter.next(), iter.next()), (Some(1), None));
+    }
+
+    #[test]
+    fn peeked_items_come_next() {
+        let mut iter = PutBack::new(vec![1, 2].into_iter());
+        assert_eq!(iter.peek(), Some(&1));
+        assert_eq!(iter.peek(), Some(&1));
+        assert_eq!(iter.next(), Some(1));
+        assert_eq!(iter.peek(), Some(&2));
+        assert_eq!(iter.next(), Some(2));
+        // Peeking at the end finds nothing, and doesn't stop a put back item
+        // from coming out after it.
+        assert_eq!(iter.peek(), None);
+        assert_eq!(iter.peek_mut(), None);
+        assert_eq!(iter.next(), None);
+        iter.put_back(3);
+        assert_eq!(iter.peek(), Some(&3));
+        assert_eq!(iter.next(), Some(3));
+        assert_eq!(iter.next(), None);
+    }
+
+    #[test]
+    fn peeking_sees_the_last_item_put_back() {
+        let mut iter = PutBack::new(vec![1, 2].into_iter());
+        assert_eq!(iter.peek(), Some(&1));
+        iter.put_back(10);
+        iter.put_back(20);
+        iter.put_back(30);
+        assert_eq!(iter.peek(), Some(&30));
+        assert_eq!(iter.collect::<Vec<_>>(), [30, 20, 10, 1, 2]);
+    }
+
+    #[test]
+    fn edits_through_peek_mut_show_in_next() {
+        let mut iter = PutBack::new(vec![1, 2].into_iter());
+        *iter.peek_mut().unwrap() += 100;
+        assert_eq!(iter.peek(), Some(&101));
+        assert_eq!(iter.next(), Some(101));
+        iter.put_back(5);
+        *iter.peek_mut().unwrap() = 6;
+        assert_eq!(iter.collect::<Vec<_>>(), [6, 2]);
+    }
+
+    #[test]
+    fn put_back_lines_keep_their_numbers() {
+        let mut lines = LinesPutBack::new("a\nb\nc\n".as_bytes());
+        assert_eq!(lines.next().unwrap().unwrap(), "a");
+        assert_eq!(lines.next().unwrap().unwrap(), "b");
+        assert_eq!(lines.line_number(), 2);
+        lines.put_back("b".into());
+        assert_eq!(lines.line_number(), 1);
+        assert_eq!(lines.peek().unwrap().as_ref().unwrap(), "b");
+        assert_eq!(lines.next().unwrap().unwrap(), "b");
+        assert_eq!(lines.line_number(), 2);
+        // Two lines put back in reverse order come back numbered as before.
+        assert_eq!(lines.next().unwrap().unwrap(), "c");
+        lines.put_back("c".into());
+        lines.put_back("b".into());
+        assert_eq!(lines.next().unwrap().unwrap(), "b");
+        assert_eq!(lines.line_number(), 2);
+        assert_eq!(lines.next().unwrap().unwrap(), "c");
+        assert_eq!(lines.line_number(), 3);
+        assert!(lines.next().is_none());
+    }
 }
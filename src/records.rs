@@ -0,0 +1,188 @@
+//! This module implements building a file system tree from a flat list of
+//! file paths and sizes, such as the output of 
+//! `find / -type f -printf '%p %s\n'`, without going through a transcript of
+//! `cd` and `ls` commands.
+
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use crate::fsobject::*;
+use crate::intern::*;
+
+/// Returned when a tree can't be built from a list of records. Each variant
+/// holds the path, from the root, of the entry at fault.
+/// 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BuildError {
+    /// A record's path has no components, like "" or "/".
+    NoName(String),
+    /// A record's path runs through something an earlier record made a file.
+    FileInPath(String),
+    /// A record names a path an earlier record made a directory.
+    IsDirectory(String),
+    /// A record's file would overflow the size of a directory above it.
+    Overflow { path: String, source: SizeOverflow },
+}
+impl Display for BuildError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BuildError::NoName(path) => {
+                write!(f, "record has no file name: '{}'", path)
+            },
+            BuildError::FileInPath(path) => {
+                write!(f, "'{}' is a file, but is used as a directory", path)
+            },
+            BuildError::IsDirectory(path) => {
+                write!(f, "'{}' is a directory, but is used as a file", path)
+            },
+            BuildError::Overflow { path, source } => {
+                write!(f, "can't add '{}': {}", path, source)
+            },
+        }
+    }
+}
+impl Error for BuildError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            BuildError::Overflow { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl FSDir {
+    /// Builds a tree from `(path, size)` records, one per file. Paths are
+    /// taken from the root, named "/", whether or not they start with '/'.
+    /// Missing directories are created as needed, so records can come in any
+    /// order. A later record for the same file replaces its size. Empty and
+    /// "." path components are skipped; other components, ".." included, are
+    /// taken as names. Equal names share one allocation.
+    /// 
+    pub fn from_records<I>(iter: I) -> Result<FSDir, BuildError>
+    where
+        I: IntoIterator<Item = (String, u64)>
+    {
+        FSDir::from_linked_records(iter.into_iter()
+                                       .map(|(path, size)| (path, size, None)))
+    }
+    /// Like from_records(), but each record also carries the file's link id,
+    /// if it has one, like the device and inode numbers from
+    /// `find / -type f -printf '%p %s %D:%i\n'`, so hard links to the same
+    /// file can be counted once. See FSFile::link_id().
+    /// 
+    pub fn from_linked_records<I>(iter: I) -> Result<FSDir, BuildError>
+    where
+        I: IntoIterator<Item = (String, u64, Option<u64>)>
+    {
+        let root      = FSDir::new("/".into());
+        let mut names = NameInterner::new();
+        for (path, size, link_id) in iter {
+            let mut parts  = path.split('/')
+                                 .filter(|p| !p.is_empty() && *p != ".");
+            let name       = match parts.next_back() {
+                Some(name) => name,
+                None       => return Err(BuildError::NoName(path)),
+            };
+            let mut dir    = root.clone();
+            let mut so_far = String::new();
+            for part in parts {
+                so_far.push('/');
+                so_far.push_str(part);
+                dir = match dir.get(part) {
+                    Some(FSEntry::Dir(sub)) => sub,
+                    Some(_)                 => {
+                        return Err(BuildError::FileInPath(so_far));
+                    },
+                    None => dir.add_dir(FSDir::new_shared(names.intern(part))),
+                };
+            }
+            if dir.get_dir(name).is_some() {
+                return Err(BuildError::IsDirectory(format!("{}/{}", 
+                                                           so_far, name)));
+            }
+            let file = FSFile::new_shared(names.intern(name), size);
+            file.set_link_id(link_id);
+            dir.try_add_file(file).map_err(|e| match e {
+                GrowthError::Overflow(source) => BuildError::Overflow {
+                    path: format!("{}/{}", so_far, name), source,
+                },
+                GrowthError::QuotaExceeded(_) => {
+                    unreachable!("a new tree has no quotas")
+                },
+            })?;
+        }
+        Ok(root)
+    }
+}
+
+/// Collects `(path, size)` records into a tree, as FSDir::from_records() does.
+/// 
+/// # Panics
+/// If the records conflict, with a path used both as a file and a directory,
+/// if a path has no file name, or if the sizes overflow. Use FSDir::from_records() to handle these
+/// as errors.
+/// 
+impl FromIterator<(String, u64)> for FSDir {
+    fn from_iter<I>(iter: I) -> Self 
+    where
+        I: IntoIterator<Item = (String, u64)>
+    {
+        FSDir::from_records(iter).unwrap_or_else(|e| panic!("{}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::*;
+
+    #[test]
+    fn records_in_any_order_build_the_sample() {
+        let sample  = build_fs_from_str(include_str!("../data/sample.txt"))
+                          .unwrap();
+        // The deepest files come first, and /a/f before anything else in /a.
+        let records = [("/a/e/i", 584), ("d/k", 7214296), ("/a/f", 29116),
+                       ("/d/j", 4060174), ("b.txt", 14848514), 
+                       ("/a/./h.lst", 62596), ("/d/d.log", 8033020),
+                       ("/c.dat", 8504156), ("//a/g", 2557), 
+                       ("/d/d.ext", 5626152)];
+        let records = records.map(|(path, size)| (path.to_string(), size));
+        let root    = FSDir::from_records(records.clone()).unwrap();
+        assert!(root.structural_eq(&sample));
+        assert_eq!(root.size(), 48381165);
+        assert!(records.into_iter().rev().collect::<FSDir>()
+                       .structural_eq(&sample));
+    }
+
+    #[test]
+    fn a_path_used_as_a_file_and_a_directory_is_an_error() {
+        let cases = [
+            (vec![("/a/b", 1), ("/a/b/c", 2)], 
+             BuildError::FileInPath("/a/b".into())),
+            (vec![("/a/b/c", 2), ("/a/b", 1)], 
+             BuildError::IsDirectory("/a/b".into())),
+            (vec![("/", 1)], BuildError::NoName("/".into())),
+        ];
+        for (records, err) in cases {
+            let records = records.into_iter()
+                                 .map(|(path, size)| (path.to_string(), size));
+            assert_eq!(FSDir::from_records(records).unwrap_err(), err);
+        }
+    }
+
+    #[test]
+    fn sizes_that_overflow_are_an_error() {
+        let records = [("/a/big", u64::MAX), ("/b/one", 1)];
+        let records = records.map(|(path, size)| (path.to_string(), size));
+        let err     = FSDir::from_records(records).unwrap_err();
+        assert_eq!(err, BuildError::Overflow { 
+            path   : "/b/one".into(), 
+            source : SizeOverflow { path: "/".into(), size: 1 },
+        });
+        assert_eq!(err.to_string(), "can't add '/b/one': adding 1 bytes would \
+                                     overflow the size of '/'");
+        // Replacing a file's size isn't growth on top of the old one.
+        let records = [("/a/big", u64::MAX), ("/a/big", u64::MAX - 1)];
+        let records = records.map(|(path, size)| (path.to_string(), size));
+        assert_eq!(FSDir::from_records(records).unwrap().size(), u64::MAX - 1);
+    }
+}
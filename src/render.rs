@@ -0,0 +1,660 @@
+//! This module implements text renderings of the file system tree. The plain
+//! rendering matches the format used in the puzzle description.
+
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use crate::fsobject::*;
+use crate::units::*;
+use crate::usage::*;
+use crate::visit::*;
+
+/// Options controlling how FSDir::render() draws the tree.
+/// 
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RenderOptions {
+    /// Don't show entries deeper than this. The directory being rendered is
+    /// at depth 0, so 0 shows it alone, and 1 adds its children.
+    /// 
+    pub max_depth        : Option<usize>,
+    /// What each directory's children are ordered by.
+    pub sort_key         : SortKey,
+    /// Which way each directory's children are ordered.
+    pub sort_dir         : SortDir,
+    /// The units sizes are shown in.
+    pub size_format      : SizeFormat,
+    /// Follow each size with the entry's share of its parent and of the
+    /// whole tree.
+    pub show_percentages : bool,
+}
+
+/// Options controlling which directories FSDir::du_report_with() lists and in
+/// what order.
+/// 
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DuOptions {
+    /// Only list directories at least this big.
+    pub threshold        : u64,
+    /// What the directories are ordered by. SortKey::Name orders them by 
+    /// path, and ties on size are broken by path.
+    /// 
+    pub sort_key         : SortKey,
+    /// Which way the directories are ordered.
+    pub sort_dir         : SortDir,
+    /// Don't list directories deeper than this. The directory being reported
+    /// on is at depth 0, so 0 lists it alone, and 1 adds the directories in
+    /// it.
+    /// 
+    pub max_depth        : Option<usize>,
+    /// Follow each line with the directory's share of its parent and of the
+    /// whole tree.
+    pub show_percentages : bool,
+    /// Report the space directories take up on a disk allocating space this
+    /// way, instead of the bytes in their files. The threshold, the order, 
+    /// and the percentages go by that space too.
+    /// 
+    pub disk_model       : Option<DiskModel>,
+    /// Report directory sizes with each hard link counted once, as 
+    /// FSDir::deduplicated_size() counts them. Ignored with a `disk_model`.
+    /// 
+    pub dedup_hardlinks  : bool,
+}
+
+impl FSDir {
+    /// Renders the tree rooted at this directory in the indented format of
+    /// the puzzle description, two spaces per level:
+    /// 
+    /// ```text
+    /// - / (dir, size=48381165)
+    ///   - a (dir, size=94853)
+    ///     - e (dir, size=584)
+    ///       - i (file, size=584)
+    /// ```
+    /// 
+    pub fn render(&self, opts: RenderOptions) -> String {
+        let mut out      = String::new();
+        let mut stack    = vec![(0, FSEntry::Dir(self.clone()))];
+        let mut children = vec![];
+
+        while let Some((depth, entry)) = stack.pop() {
+            let size = opts.size_format.format(entry.size());
+            let kind = match entry {
+                FSEntry::File(_) => "file",
+                FSEntry::Dir(_)  => "dir",
+                FSEntry::Link(_) => "link",
+            };
+            let shares = if opts.show_percentages {
+                format!(", {}", percentages(&entry))
+            } else {
+                String::new()
+            };
+            out.push_str(&format!("{:indent$}- {} ({}, size={}{})\n",
+                                  "", entry.name(), kind, size, shares,
+                                  indent = depth * 2));
+
+            if let FSEntry::Dir(dir) = entry {
+                if opts.max_depth.is_some_and(|max| depth >= max) {
+                    continue;
+                }
+                dir.children_sorted_into(&mut children, opts.sort_key, 
+                                         opts.sort_dir);
+                for child in children.drain(..).rev() {
+                    stack.push((depth + 1, child));
+                }
+            }
+        }
+        out
+    }
+}
+
+impl FSDir {
+    /// Draws the tree rooted at this directory with box-drawing connectors,
+    /// like the `tree` command, followed by a right-aligned column of sizes
+    /// in bytes.
+    /// 
+    /// ```text
+    /// /              48381165
+    /// ├── a             94853
+    /// │   ├── e           584
+    /// │   │   └── i       584
+    /// ...
+    /// ```
+    /// 
+    pub fn render_tree(&self) -> String {
+        self.render_tree_with(|size| size.to_string())
+    }
+    /// Like render_tree(), but the sizes are formatted with `fmt_size`.
+    /// 
+    pub fn render_tree_with<F>(&self, fmt_size: F) -> String
+    where
+        F: Fn(u64) -> String
+    {
+        self.render_tree_sorted_with(SortKey::Name, SortDir::Ascending, 
+                                     fmt_size)
+    }
+    /// Like render_tree_with(), but each directory's children are ordered by
+    /// `key`, in the direction `dir`, as FSDir::children_sorted() orders 
+    /// them.
+    /// 
+    pub fn render_tree_sorted_with<F>(&self, 
+                                      key      : SortKey, 
+                                      dir      : SortDir, 
+                                      fmt_size : F) 
+        -> String
+    where
+        F: Fn(u64) -> String
+    {
+        self.render_tree_impl(key, dir, None, &fmt_size)
+    }
+    /// Like render_tree(), but entries deeper than `max_depth` are left out.
+    /// This directory is at depth 0 and its children at depth 1, so a 
+    /// `max_depth` of 0 draws this directory alone, and 1 adds its children.
+    /// Sizes still count everything below.
+    /// 
+    pub fn render_tree_to_depth(&self, max_depth: usize) -> String {
+        self.render_tree_impl(SortKey::Name, SortDir::Ascending, 
+                              Some(max_depth), &|size| size.to_string())
+    }
+    /// Does the work of the render_tree functions.
+    /// 
+    fn render_tree_impl(&self, 
+                        key       : SortKey, 
+                        dir       : SortDir, 
+                        max_depth : Option<usize>,
+                        fmt_size  : &dyn Fn(u64) -> String) 
+        -> String
+    {
+        let mut lines    = vec![];
+        let mut stack    = vec![(String::new(), String::new(), 
+                                 FSEntry::Dir(self.clone()), 0)];
+        let mut children = vec![];
+
+        while let Some((prefix, child_prefix, entry, depth)) = stack.pop() {
+            let label = format!("{}{}", prefix, entry.name());
+            lines.push((label, fmt_size(entry.size())));
+
+            if let FSEntry::Dir(sub) = entry {
+                if max_depth.is_some_and(|max| depth >= max) {
+                    continue;
+                }
+                sub.children_sorted_into(&mut children, key, dir);
+                let last = children.len().saturating_sub(1);
+                for (i, child) in children.drain(..).enumerate().rev() {
+                    let (branch, guide) = if i == last {
+                        ("└── ", "    ")
+                    } else {
+                        ("├── ", "│   ")
+                    };
+                    stack.push((format!("{}{}", child_prefix, branch),
+                                format!("{}{}", child_prefix, guide),
+                                child, depth + 1));
+                }
+            }
+        }
+        let label_width = lines.iter()
+                               .map(|(l, _)| l.chars().count())
+                               .max()
+                               .unwrap_or(0);
+        let size_width  = lines.iter()
+                               .map(|(_, s)| s.chars().count())
+                               .max()
+                               .unwrap_or(0);
+        let mut out = String::new();
+        for (label, size) in lines {
+            let pad = label_width - label.chars().count();
+            out.push_str(&format!("{}{:pad$}  {:>sw$}\n", 
+                                  label, "", size, 
+                                  pad = pad, sw = size_width));
+        }
+        out
+    }
+}
+
+impl FSDir {
+    /// Draws a bar chart of the directories below this one, down to `depth`
+    /// levels, like ncdu does. Each line shows a directory's size, a bar of
+    /// '#' scaled so the largest of its siblings fills `width` characters,
+    /// and its share of its parent. Siblings are listed largest first.
+    /// 
+    /// ```text
+    /// 24933642  [####################]   51.5%  d/
+    ///    94853  [#                   ]    0.2%  a/
+    ///      584  [#                   ]    0.6%    e/
+    /// ```
+    /// 
+    pub fn render_bars(&self, width: usize, depth: usize) -> String {
+        self.render_bars_with(width, depth, false)
+    }
+    /// Like render_bars(), but with `with_files` set, files are charted too,
+    /// with bars of '=' so they stand out from directories.
+    /// 
+    pub fn render_bars_with(&self, 
+                            width      : usize, 
+                            depth      : usize, 
+                            with_files : bool) 
+        -> String 
+    {
+        let children = |dir: &FSDir| {
+            let mut children = dir.children()
+                                  .into_iter()
+                                  .filter(|c| with_files 
+                                              || matches!(c, FSEntry::Dir(_)))
+                                  .collect::<Vec<_>>();
+            children.sort_by_key(|c| std::cmp::Reverse(c.size()));
+            let largest = children.first().map_or(0, |c| c.size());
+            children.into_iter()
+                    .rev()
+                    .map(|c| (largest, dir.size(), c))
+                    .collect::<Vec<_>>()
+        };
+        let mut lines = vec![];
+        let mut stack = children(self).into_iter()
+                                      .map(|c| (1, c))
+                                      .collect::<Vec<_>>();
+
+        while let Some((level, (largest, parent_size, entry))) = stack.pop() {
+            let size = entry.size();
+            let fill = match entry {
+                FSEntry::File(_) => '=',
+                FSEntry::Dir(_)  => '#',
+                FSEntry::Link(_) => '-',
+            };
+            // Round to the nearest character, but never hide a nonzero size.
+            let len = match size {
+                0 => 0,
+                _ => ((size as f64 / largest as f64 * width as f64).round() 
+                      as usize).max(1),
+            };
+            let share = match parent_size {
+                0 => format_percent(0.0),
+                _ => format_percent(size as f64 / parent_size as f64),
+            };
+            let mut label = format!("{:indent$}{}", "", entry.name(),
+                                    indent = (level - 1) * 2);
+            if let FSEntry::Dir(dir) = entry {
+                label.push('/');
+                if level < depth {
+                    stack.extend(children(&dir).into_iter()
+                                               .map(|c| (level + 1, c)));
+                }
+            }
+            lines.push((size.to_string(), fill.to_string().repeat(len),
+                        share, label));
+        }
+        let size_width = lines.iter()
+                              .map(|(s, ..)| s.len())
+                              .max()
+                              .unwrap_or(0);
+        let mut out = String::new();
+        for (size, bar, share, label) in lines {
+            out.push_str(&format!("{:>sw$}  [{:<w$}]  {:>6}  {}\n",
+                                  size, bar, share, label,
+                                  sw = size_width, w = width));
+        }
+        out
+    }
+}
+
+impl FSDir {
+    /// Returns the path and size of this directory and every directory below
+    /// it that passes the filters in `opts`, in the order `opts` asks for.
+    /// 
+    pub fn du_entries(&self, opts: DuOptions) 
+        -> Result<Vec<(String, u64)>, UsageOverflow> 
+    {
+        let usages = self.du_usages(opts)?;
+        Ok(self.du_dirs(opts, &usages)
+               .into_iter()
+               .map(|(path, _, size)| (path, size))
+               .collect())
+    }
+    /// Returns the directories du_entries() reports on, with their paths and
+    /// the sizes it reports, taken from `usages` if there are any.
+    /// 
+    fn du_dirs(&self, opts: DuOptions, usages: &Option<HashMap<usize, u64>>)
+        -> Vec<(String, FSDir, u64)>
+    {
+        let mut collector = DuCollector { opts, 
+                                          usages, 
+                                          depth   : 0, 
+                                          entries : vec![] };
+        self.accept(&mut collector);
+        let mut entries = collector.entries;
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        match (opts.sort_key, opts.sort_dir) {
+            (SortKey::Name, SortDir::Ascending)  => {},
+            (SortKey::Name, SortDir::Descending) => entries.reverse(),
+            (SortKey::Size, SortDir::Ascending)  => {
+                entries.sort_by_key(|(_, _, size)| *size);
+            },
+            (SortKey::Size, SortDir::Descending) => {
+                entries.sort_by_key(|(_, _, size)| std::cmp::Reverse(*size));
+            },
+        }
+        entries
+    }
+    /// Returns the disk usage, or the deduplicated size, of every directory
+    /// in the tree this directory is in, from its root down, if `opts` asks
+    /// for either. Fails if a disk usage doesn't fit in a u64.
+    /// 
+    fn du_usages(&self, opts: DuOptions) 
+        -> Result<Option<HashMap<usize, u64>>, UsageOverflow> 
+    {
+        Ok(match opts.disk_model {
+            Some(model) => Some(self.root().disk_usages(model)?),
+            None        => opts.dedup_hardlinks
+                               .then(|| self.deduplicated_sizes()),
+        })
+    }
+    /// Produces a `du --threshold` style report with one `size<TAB>path` line
+    /// for this directory and each directory below it that's at least 
+    /// `threshold` bytes, sorted by path.
+    /// 
+    pub fn du_report(&self, threshold: u64) -> String {
+        let opts = DuOptions { threshold, ..Default::default() };
+        self.du_lines(opts, &None)
+    }
+    /// Like du_report(), with full control over filtering and ordering. With
+    /// `show_percentages` set, lines read "14204\t/a/e  29.4% of parent, 
+    /// 0.1% of total". Fails if `opts` has a `disk_model` and the usage of
+    /// something doesn't fit in a u64.
+    /// 
+    pub fn du_report_with(&self, opts: DuOptions) 
+        -> Result<String, UsageOverflow> 
+    {
+        let usages = self.du_usages(opts)?;
+        Ok(self.du_lines(opts, &usages))
+    }
+    /// Returns the lines of du_report_with(), with sizes taken from `usages`
+    /// if there are any.
+    /// 
+    fn du_lines(&self, opts: DuOptions, usages: &Option<HashMap<usize, u64>>)
+        -> String
+    {
+        self.du_dirs(opts, usages)
+            .into_iter()
+            .map(|(path, dir, size)| {
+                if opts.show_percentages {
+                    format!("{}\t{}  {}\n", size, path, 
+                            dir_shares(&dir, usages))
+                } else {
+                    format!("{}\t{}\n", size, path)
+                }
+            })
+            .collect()
+    }
+}
+
+/// The Visitor behind FSDir::du_dirs(). It collects the directories big
+/// enough to list, and doesn't go into any that are too small or too deep,
+/// as nothing below them could be listed.
+/// 
+struct DuCollector<'a> {
+    opts    : DuOptions,
+    usages  : &'a Option<HashMap<usize, u64>>,
+    depth   : usize,
+    entries : Vec<(String, FSDir, u64)>,
+}
+impl Visitor for DuCollector<'_> {
+    fn enter_dir(&mut self, path: &str, dir: &FSDir) -> VisitFlow {
+        let size  = du_size(dir, self.usages);
+        let depth = self.depth;
+        self.depth += 1;
+        if size < self.opts.threshold {
+            return VisitFlow::SkipSubtree;
+        }
+        self.entries.push((path.to_string(), dir.clone(), size));
+        if self.opts.max_depth.is_some_and(|max| depth >= max) {
+            return VisitFlow::SkipSubtree;
+        }
+        VisitFlow::Continue
+    }
+    fn leave_dir(&mut self, _path: &str, _dir: &FSDir) -> VisitFlow {
+        self.depth -= 1;
+        VisitFlow::Continue
+    }
+}
+
+/// Returns the size du reports for `dir`: its disk usage from `usages`, if 
+/// there are any, or else its size.
+/// 
+fn du_size(dir: &FSDir, usages: &Option<HashMap<usize, u64>>) -> u64 {
+    match usages {
+        Some(usages) => usages[&dir.id()],
+        None         => dir.size(),
+    }
+}
+
+/// Like percentages(), for a directory in a du report, whose size is taken
+/// as du_size() takes it.
+/// 
+fn dir_shares(dir: &FSDir, usages: &Option<HashMap<usize, u64>>) -> String {
+    let size      = du_size(dir, usages);
+    let root      = dir.root();
+    let share     = |whole: u64| {
+        (whole != 0).then(|| size as f64 / whole as f64)
+    };
+    let of_parent = dir.parent().and_then(|p| share(du_size(&p, usages)));
+    let of_root   = share(du_size(&root, usages)).unwrap_or(0.0);
+    shares(of_parent, of_root)
+}
+
+/// Describes an entry's share of its parent and of the root of its tree, as
+/// "29.4% of parent, 0.1% of total". The share of the parent is left out
+/// for the root, and when the parent is empty.
+/// 
+fn percentages(entry: &FSEntry) -> String {
+    let (of_parent, of_root) = match entry {
+        FSEntry::Dir(dir)   => (dir.fraction_of_parent(), 
+                                dir.fraction_of_root()),
+        _                   => match entry.parent() {
+            Some(parent) if parent.size() != 0 => {
+                let share = entry.size() as f64 / parent.size() as f64;
+                (Some(share), share * parent.fraction_of_root())
+            },
+            _ => (None, 0.0),
+        },
+    };
+    shares(of_parent, of_root)
+}
+
+/// Formats an entry's share of its parent, if it has one, and of the root of
+/// its tree, for percentages().
+/// 
+fn shares(of_parent: Option<f64>, of_root: f64) -> String {
+    match of_parent {
+        Some(share) => format!("{} of parent, {} of total",
+                               format_percent(share), format_percent(of_root)),
+        None        => format!("{} of total", format_percent(of_root)),
+    }
+}
+
+impl Display for FSDir {
+    /// Renders the tree with the default RenderOptions.
+    /// 
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.render(RenderOptions::default()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::*;
+
+    fn sample() -> FSDir {
+        build_fs_from_str(include_str!("../data/sample.txt")).unwrap()
+    }
+
+    #[test]
+    fn the_sample_tree_renders_like_tree_does() {
+        assert_eq!(sample().render_tree(), "\
+/              48381165
+├── a             94853
+│   ├── e           584
+│   │   └── i       584
+│   ├── f         29116
+│   ├── g          2557
+│   └── h.lst     62596
+├── b.txt      14848514
+├── c.dat       8504156
+└── d          24933642
+    ├── d.ext   5626152
+    ├── d.log   8033020
+    ├── j       4060174
+    └── k       7214296
+");
+    }
+
+    #[test]
+    fn guides_end_below_the_last_child_at_every_level() {
+        let root = FSDir::new("/".into());
+        root.make_dirs("p");
+        root.add_file_at_path("q/r/t/u", 1);
+        root.add_file_at_path("q/r/v", 2);
+        root.add_file_at_path("q/s", 4);
+        assert_eq!(root.render_tree_with(|size| format!("{}B", size)), "\
+/                  7B
+├── p              0B
+└── q              7B
+    ├── r          3B
+    │   ├── t      1B
+    │   │   └── u  1B
+    │   └── v      2B
+    └── s          4B
+");
+    }
+
+    #[test]
+    fn the_sample_charts_at_width_20() {
+        let root = sample();
+        assert_eq!(root.render_bars(20, 3), "\
+24933642  [####################]   51.5%  d/
+   94853  [#                   ]    0.2%  a/
+     584  [####################]    0.6%    e/
+");
+        assert_eq!(root.render_bars_with(20, 3, true), "\
+24933642  [####################]   51.5%  d/
+ 8033020  [====================]   32.2%    d.log
+ 7214296  [==================  ]   28.9%    k
+ 5626152  [==============      ]   22.6%    d.ext
+ 4060174  [==========          ]   16.3%    j
+14848514  [============        ]   30.7%  b.txt
+ 8504156  [=======             ]   17.6%  c.dat
+   94853  [#                   ]    0.2%  a/
+   62596  [====================]   66.0%    h.lst
+   29116  [=========           ]   30.7%    f
+    2557  [=                   ]    2.7%    g
+     584  [#                   ]    0.6%    e/
+     584  [====================]    100%      i
+");
+        assert_eq!(root.render_bars(20, 1), "\
+24933642  [####################]   51.5%  d/
+   94853  [#                   ]    0.2%  a/
+");
+    }
+
+    #[test]
+    fn nonzero_sizes_get_at_least_one_character() {
+        let root = FSDir::new("/".into());
+        root.add_file_at_path("big/f", 1_000_000);
+        root.add_file_at_path("tiny/f", 1);
+        assert_eq!(root.render_bars(10, 1), "\
+1000000  [##########]   99.9%  big/
+      1  [#         ]    0.0%  tiny/
+");
+    }
+
+    #[test]
+    fn zero_size_directories_get_an_empty_bar() {
+        let root = FSDir::new("/".into());
+        root.make_dirs("x/y");
+        root.make_dirs("z");
+        assert_eq!(root.render_bars(5, 2), "\
+0  [     ]    0.0%  x/
+0  [     ]    0.0%    y/
+0  [     ]    0.0%  z/
+");
+        root.add_file_at_path("z/f", 8);
+        assert_eq!(root.render_bars(4, 1), "\
+8  [####]    100%  z/
+0  [    ]    0.0%  x/
+");
+    }
+
+    #[test]
+    fn shares_of_the_sample_match_its_sizes() {
+        let root = sample();
+        let a    = root.get_dir("a").unwrap();
+        let e    = a.get_dir("e").unwrap();
+        assert_eq!((e.size(), a.size()), (584, 94853));
+        assert_eq!(e.fraction_of_parent(), Some(584.0 / 94853.0));
+        assert_eq!(e.fraction_of_root(), 584.0 / 48381165.0);
+        assert_eq!(root.fraction_of_parent(), None);
+        assert_eq!(root.fraction_of_root(), 1.0);
+        let opts = DuOptions { show_percentages: true, ..Default::default() };
+        assert_eq!(root.du_report_with(opts).unwrap(), "\
+48381165\t/  100% of total
+94853\t/a  0.2% of parent, 0.2% of total
+584\t/a/e  0.6% of parent, 0.0% of total
+24933642\t/d  51.5% of parent, 51.5% of total
+");
+        let opts = RenderOptions { show_percentages: true, 
+                                   ..Default::default() };
+        let tree = root.render(opts);
+        let e    = tree.lines().find(|l| l.contains("- e ")).unwrap();
+        assert_eq!(e, "    - e (dir, size=584, 0.6% of parent, 0.0% of total)");
+        // /a/e/i is all of /a/e, so it's the one share shown as 100%.
+        let i    = tree.lines().find(|l| l.contains("- i ")).unwrap();
+        assert_eq!(i, "      - i (file, size=584, 100% of parent, \
+                       0.0% of total)");
+    }
+
+    #[test]
+    fn only_equal_sizes_read_as_100_percent() {
+        let root = FSDir::new("/".into());
+        root.add_file_at_path("big/f", 9999);
+        root.add_file_at_path("tiny/f", 1);
+        let big  = root.get_dir("big").unwrap();
+        assert_eq!(big.fraction_of_parent(), Some(0.9999));
+        assert_eq!(format_percent(big.fraction_of_parent().unwrap()), "99.9%");
+        assert_eq!(format_percent(root.fraction_of_root()), "100%");
+        // Nothing is a share of an empty directory.
+        let empty = FSDir::new("/".into());
+        let sub   = empty.make_dirs("x");
+        assert_eq!(sub.fraction_of_parent(), None);
+        assert_eq!(sub.fraction_of_root(), 0.0);
+    }
+
+    #[test]
+    fn depth_limits_count_from_the_directory_drawn() {
+        // /a is at depth 0 and is always drawn or listed itself, so a 
+        // max_depth of 0, which allows nothing deeper, gives /a alone.
+        let root = FSDir::new("/".into());
+        root.add_file_at_path("a/b/c/f", 1);
+        root.add_file_at_path("a/b/h", 4);
+        root.add_file_at_path("a/g", 2);
+        root.add_file_at_path("x/y", 8);
+        let a    = root.get_dir("a").unwrap();
+        assert_eq!(a.render_tree_to_depth(0), "a  7\n");
+        assert_eq!(a.render_tree_to_depth(1), "\
+a      7
+├── b  5
+└── g  2
+");
+        assert_eq!(a.render_tree_to_depth(2), "\
+a          7
+├── b      5
+│   ├── c  1
+│   └── h  4
+└── g      2
+");
+        let du   = |max| {
+            a.du_entries(DuOptions { max_depth: Some(max), 
+                                     ..Default::default() }).unwrap()
+        };
+        assert_eq!(du(0), [("/a".into(), 7)]);
+        assert_eq!(du(1), [("/a".into(), 7), ("/a/b".into(), 5)]);
+        assert_eq!(du(2), [("/a".into(), 7), ("/a/b".into(), 5), 
+                           ("/a/b/c".into(), 1)]);
+    }
+}
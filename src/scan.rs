@@ -0,0 +1,385 @@
+//! This module implements building a file system tree by scanning a real
+//! directory on disk, so the analysis functions can be run on actual data,
+//! and the reverse, creating a tree's directories and files on disk.
+
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use crate::fsobject::*;
+use crate::glob::*;
+
+/// Options controlling how FSDir::from_real_path() scans the disk.
+/// 
+#[derive(Debug, Clone, Default)]
+pub struct ScanOptions {
+    /// Follow symbolic links into the directories and files they point to. 
+    /// When off, a link is added as an FSLink to the path it holds, marked
+    /// with whether that path led anywhere at the time of the scan.
+    /// 
+    pub follow_symlinks : bool,
+    /// Don't scan deeper than this. The directory being scanned is at depth
+    /// 0. Directories at the limit are added, but empty, so what's below them
+    /// isn't counted in any size.
+    pub max_depth       : Option<usize>,
+    /// Skip entries that match any of these glob patterns (see Glob). A
+    /// pattern with no '/' in it is matched against each entry's name, and
+    /// one with a '/' against its path in the tree, so "*.o" skips object
+    /// files anywhere and "/target" only the directory at the top.
+    pub ignore          : Vec<String>,
+    /// How much each link adds to the size of the directories above it, when
+    /// links aren't followed.
+    /// 
+    pub link_size       : LinkSize,
+}
+
+/// Something that was skipped during a scan because it couldn't be read.
+/// 
+#[derive(Debug)]
+pub struct ScanWarning {
+    /// The path of the entry that was skipped.
+    pub path  : PathBuf,
+    /// Why it couldn't be read.
+    pub error : io::Error,
+}
+
+/// How FSDir::materialize() fills in the files it creates.
+/// 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FillMode {
+    /// Create every file empty, whatever its recorded size.
+    #[default]
+    Empty,
+    /// Set each file's length to its recorded size without writing to it, so
+    /// file systems that support it leave holes rather than allocate blocks.
+    Sparse,
+    /// Write each file out in full with zero bytes.
+    ZeroFilled,
+}
+
+/// What FSDir::materialize() created.
+/// 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MaterializeStats {
+    /// The number of directories created, not counting the target.
+    pub dirs  : usize,
+    /// The number of files created.
+    pub files : usize,
+    /// The number of symbolic links created.
+    pub links : usize,
+    /// The total length of the files created.
+    pub bytes : u64,
+}
+
+impl FSDir {
+    /// Builds a tree by scanning the directory at `path`. The root of the 
+    /// tree is named "/" and stands for `path`, as with the other builders.
+    /// Entries that can't be read are skipped; use scan_real_path() to find
+    /// out which. Fails only if `path` itself can't be read. On Unix, files
+    /// with more than one hard link are given a link id (see 
+    /// FSFile::link_id()), so FSDir::deduplicated_size() can count them once.
+    /// Files and directories get their modification times.
+    /// 
+    pub fn from_real_path(path: &Path, opts: ScanOptions) 
+        -> io::Result<FSDir> 
+    {
+        FSDir::scan_real_path(path, opts).map(|(root, _)| root)
+    }
+    /// Like from_real_path(), but also returns warnings for the entries that
+    /// were skipped because they couldn't be read, such as directories the
+    /// user has no permission to list. Each directory is scanned at most
+    /// once, so links that loop back on themselves are harmless.
+    /// 
+    pub fn scan_real_path(path: &Path, opts: ScanOptions) 
+        -> io::Result<(FSDir, Vec<ScanWarning>)> 
+    {
+        let root         = FSDir::new("/".into());
+        let mut warnings = vec![];
+        let mut visited  = HashSet::new();
+        let mut stack    = vec![(path.to_path_buf(), root.clone(), 0)];
+
+        visited.insert(fs::canonicalize(path)?);
+        root.set_mtime(fs::metadata(path).ok().and_then(|m| mtime(&m)));
+
+        let ignore = opts.ignore.iter()
+                                .map(|p| (Glob::new(p), p.contains('/')))
+                                .collect::<Vec<_>>();
+
+        while let Some((dir_path, dir, depth)) = stack.pop() {
+            if opts.max_depth.is_some_and(|max| depth >= max) {
+                continue;
+            }
+            let entries = match fs::read_dir(&dir_path) {
+                Ok(entries) => entries,
+                Err(error) if depth == 0 => return Err(error),
+                Err(error)  => {
+                    warnings.push(ScanWarning { path: dir_path, error });
+                    continue;
+                },
+            };
+            for entry in entries {
+                let entry = match entry {
+                    Ok(entry) => entry,
+                    Err(error) => {
+                        warnings.push(ScanWarning { path: dir_path.clone(),
+                                                    error });
+                        continue;
+                    },
+                };
+                let name = entry.file_name().to_string_lossy().into_owned();
+                if ignore.iter().any(|(glob, by_path)| if *by_path {
+                    glob.matches(&format!("{}/{}", dir.path(), name))
+                } else {
+                    glob.matches(&name)
+                }) {
+                    continue;
+                }
+                let path = entry.path();
+                let meta = if opts.follow_symlinks {
+                    fs::metadata(&path)
+                } else {
+                    fs::symlink_metadata(&path)
+                };
+                let meta = match meta {
+                    Ok(meta)   => meta,
+                    Err(error) => {
+                        warnings.push(ScanWarning { path, error });
+                        continue;
+                    },
+                };
+                if meta.is_dir() {
+                    match fs::canonicalize(&path) {
+                        Ok(real) => if !visited.insert(real) {
+                            continue;  // Already scanned through a link.
+                        },
+                        Err(error) => {
+                            warnings.push(ScanWarning { path, error });
+                            continue;
+                        },
+                    }
+                    let sub = dir.add_dir(FSDir::new(name));
+                    sub.set_mtime(mtime(&meta));
+                    stack.push((path, sub, depth + 1));
+                } else if meta.file_type().is_symlink() {
+                    let target = match fs::read_link(&path) {
+                        Ok(target) => target.to_string_lossy().into_owned(),
+                        Err(error) => {
+                            warnings.push(ScanWarning { path, error });
+                            continue;
+                        },
+                    };
+                    let link = FSLink::new_with(name, target, opts.link_size);
+                    link.set_resolved(Some(fs::metadata(&path).is_ok()));
+                    dir.add_link(link);
+                } else {
+                    let size = meta.len() as u64;
+                    let file = dir.add_file(FSFile::new(name, size));
+                    file.set_link_id(link_id(&meta));
+                    file.set_mtime(mtime(&meta));
+                }
+            }
+        }
+        Ok((root, warnings))
+    }
+    /// Creates the directories and files of the tree rooted at this directory
+    /// under `target`, which stands for this directory and is created if it
+    /// doesn't exist. Existing files are overwritten. Files are filled as 
+    /// `fill` says. Symbolic links are created holding their targets as 
+    /// given, where the platform allows; elsewhere, they fail with an 
+    /// Unsupported error.
+    /// 
+    /// Every name in the tree is checked before anything is created. A name
+    /// that's empty, "." or "..", or that contains a path separator, would
+    /// place an entry outside of where it belongs, possibly outside of 
+    /// `target`, so it fails the whole call with an InvalidInput error.
+    /// 
+    pub fn materialize(&self, target: &Path, fill: FillMode) 
+        -> io::Result<MaterializeStats> 
+    {
+        for (path, entry) in self.walk() {
+            let name = entry.name();
+            if name.is_empty() || name == "." || name == ".." 
+                || name.chars().any(std::path::is_separator) 
+            {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("unsafe name for '{}': '{}'", path, name)));
+            }
+        }
+        let mut stats = MaterializeStats::default();
+        let mut stack = vec![(target.to_path_buf(), self.clone())];
+
+        fs::create_dir_all(target)?;
+        while let Some((dir_path, dir)) = stack.pop() {
+            for child in dir.children() {
+                let path = dir_path.join(child.name());
+                match child {
+                    FSEntry::Dir(sub) => {
+                        if !path.is_dir() {
+                            fs::create_dir(&path)?;
+                        }
+                        stats.dirs += 1;
+                        stack.push((path, sub));
+                    },
+                    FSEntry::File(file) => {
+                        let size  = file.size();
+                        let mut f = fs::File::create(&path)?;
+                        match fill {
+                            FillMode::Empty      => {},
+                            FillMode::Sparse     => f.set_len(size)?,
+                            FillMode::ZeroFilled => {
+                                io::copy(&mut io::repeat(0).take(size),
+                                         &mut f)?;
+                            },
+                        }
+                        if fill != FillMode::Empty {
+                            stats.bytes += size;
+                        }
+                        stats.files += 1;
+                    },
+                    FSEntry::Link(link) => {
+                        if fs::symlink_metadata(&path).is_ok() {
+                            fs::remove_file(&path)?;
+                        }
+                        symlink(&link.target(), &path)?;
+                        stats.links += 1;
+                    },
+                }
+            }
+        }
+        Ok(stats)
+    }
+}
+
+/// Returns the modification time in `meta` in seconds since the Unix epoch,
+/// if the platform records one and it's not before the epoch.
+/// 
+fn mtime(meta: &fs::Metadata) -> Option<u64> {
+    let modified = meta.modified().ok()?;
+    modified.duration_since(UNIX_EPOCH).ok().map(|d| d.as_secs())
+}
+
+/// Returns the identity a file shares with its other hard links: its device
+/// and inode numbers, hashed together. Files with a single link get none, as
+/// there's nothing to share it with.
+/// 
+#[cfg(unix)]
+fn link_id(meta: &fs::Metadata) -> Option<u64> {
+    use std::hash::{Hash, Hasher};
+    use std::os::unix::fs::MetadataExt;
+
+    (meta.nlink() > 1).then(|| {
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        (meta.dev(), meta.ino()).hash(&mut hasher);
+        hasher.finish()
+    })
+}
+
+/// Hard links can only be told apart on Unix.
+/// 
+#[cfg(not(unix))]
+fn link_id(_meta: &fs::Metadata) -> Option<u64> {
+    None
+}
+
+/// Creates a symbolic link at `path` holding `target`.
+/// 
+#[cfg(unix)]
+fn symlink(target: &str, path: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, path)
+}
+
+/// Symbolic links can only be created on Unix.
+/// 
+#[cfg(not(unix))]
+fn symlink(_target: &str, path: &Path) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported,
+                       format!("can't create a link at '{}'", path.display())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A directory under the system's temp directory, removed when dropped.
+    struct TempDir(PathBuf);
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir()
+                           .join(format!("nsl-{}-{}", name, std::process::id()));
+            let _    = fs::remove_dir_all(&path);
+            fs::create_dir_all(&path).unwrap();
+            TempDir(path)
+        }
+    }
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// Returns the path and size of each entry below `root`, in walk order.
+    fn sizes(root: &FSDir) -> Vec<(String, u64)> {
+        root.walk().map(|(path, entry)| (path, entry.size())).collect()
+    }
+
+    #[test]
+    fn scanning_a_directory_gives_its_sizes() {
+        let tmp = TempDir::new("scan");
+        fs::create_dir_all(tmp.0.join("a/b")).unwrap();
+        fs::write(tmp.0.join("a/one.txt"), [1; 100]).unwrap();
+        fs::write(tmp.0.join("a/b/two.txt"), [2; 5]).unwrap();
+        fs::write(tmp.0.join("c.o"), [3; 9]).unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink("..", tmp.0.join("a/b/up")).unwrap();
+
+        let root = FSDir::from_real_path(&tmp.0, ScanOptions::default())
+                       .unwrap();
+        assert_eq!(root.size(), 114);
+        assert_eq!(root.find_by_path("/a/b").unwrap().size(), 5);
+        assert_eq!(root.find_by_path("/a/one.txt").unwrap().size(), 100);
+        assert_eq!(root.find_by_path("/c.o").unwrap().size(), 9);
+
+        let opts = ScanOptions { ignore    : vec!["*.o".into()], 
+                                 max_depth : Some(1),
+                                 ..Default::default() };
+        let root = FSDir::from_real_path(&tmp.0, opts).unwrap();
+        assert_eq!(sizes(&root), [("/a".into(), 0)]);
+
+        // The link back up to /a is followed, but /a isn't scanned twice.
+        let opts = ScanOptions { follow_symlinks: true, ..Default::default() };
+        let (root, warnings) = FSDir::scan_real_path(&tmp.0, opts).unwrap();
+        assert!(warnings.is_empty(), "{:?}", warnings);
+        assert_eq!(root.size(), 114);
+        assert!(FSDir::from_real_path(&tmp.0.join("missing"), 
+                                      ScanOptions::default()).is_err());
+    }
+
+    #[test]
+    fn the_sample_survives_materializing_and_rescanning() {
+        let text   = include_str!("../data/sample.txt");
+        let sample = crate::parser::build_fs_from_str(text).unwrap();
+        let tmp    = TempDir::new("materialize");
+        let stats  = sample.materialize(&tmp.0, FillMode::Sparse).unwrap();
+        assert_eq!(stats, MaterializeStats { dirs: 3, files: 10, links: 0, 
+                                             bytes: 48381165 });
+        let root   = FSDir::from_real_path(&tmp.0, ScanOptions::default())
+                         .unwrap();
+        assert_eq!(sizes(&root), sizes(&sample));
+        assert!(root.structural_eq(&sample));
+    }
+
+    #[test]
+    fn names_that_would_escape_the_target_are_refused() {
+        let tmp = TempDir::new("escape");
+        for name in ["..", ".", "a/b", "../x", ""] {
+            let root = FSDir::new("/".into());
+            root.make_dirs("d").add_file(FSFile::new(name.into(), 1));
+            let err  = root.materialize(&tmp.0.join("out"), FillMode::Empty)
+                           .unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput, "{:?}", name);
+            assert!(!tmp.0.join("out").exists(), "{:?}", name);
+        }
+    }
+}
@@ -0,0 +1,467 @@
+//! This module implements an interactive shell for exploring a parsed tree.
+//! Commands are read a line at a time and run against a current directory,
+//! which starts at the root:
+//! 
+//! ```text
+//! cd PATH      change to PATH, absolute or relative; "cd" alone goes to /
+//! ls [--name-sort | --size-sort] [--reverse]
+//!              list the current directory, largest first or by name
+//! du [N]       list the directories below, no deeper than N levels
+//! pwd          print the path of the current directory
+//! find SUBSTR  list the entries below whose names contain SUBSTR
+//! find GLOB    list the entries below whose paths match GLOB, like **/*.log
+//! top [N]      list the N largest directories and files below (default 10)
+//! rm PATH      remove the entry at PATH, and everything below it
+//! mv PATH DIR  move the entry at PATH into the directory DIR
+//! undo         undo the latest rm or mv still standing
+//! help         list the commands
+//! exit         leave the shell, as does the end of the input
+//! ```
+//! 
+//! Mistakes, like unknown commands or missing directories, are reported and
+//! the shell carries on. `rm` and `mv` change the tree for real, through an
+//! OpLog begun when the shell starts, so `undo` can take them back one at a
+//! time; whatever isn't undone stays changed when the shell ends. If `rm` 
+//! takes away the current directory, the shell goes back to the root.
+
+use std::io::{self, BufRead, Write};
+use crate::fsobject::*;
+use crate::glob::*;
+use crate::oplog::*;
+use crate::render::*;
+
+/// Options controlling how run_shell() behaves.
+/// 
+#[derive(Debug, Clone, Copy)]
+pub struct ShellOptions {
+    /// What `ls` orders entries by when it isn't told. The default is size.
+    pub sort_key : SortKey,
+    /// Which way `ls` orders entries when it isn't told. The default is 
+    /// descending, so the largest come first.
+    /// 
+    pub sort_dir : SortDir,
+    /// Print a prompt with the current path before reading each command. 
+    /// This is only useful when a person is typing the commands.
+    pub prompt   : bool,
+}
+impl Default for ShellOptions {
+    fn default() -> Self {
+        Self { sort_key : SortKey::Size,
+               sort_dir : SortDir::Descending,
+               prompt   : false }
+    }
+}
+
+/// The commands run_shell() understands, as printed by `help`.
+/// 
+const HELP: &str = "\
+cd PATH           change to PATH; cd alone goes to /
+ls [--name-sort | --size-sort] [--reverse]
+                  list the current directory, largest first or by name
+du [N]            list the directories below, no deeper than N levels
+pwd               print the path of the current directory
+find SUBSTR       list the entries below whose names contain SUBSTR
+find GLOB         list the entries below whose paths match GLOB, a pattern
+                  with *, ? or [ in it, like **/*.log; it's relative to the
+                  current directory unless it starts with /
+top [N]           list the N largest directories and files (default 10)
+rm PATH           remove the entry at PATH, and everything below it
+mv PATH DIR       move the entry at PATH into the directory DIR
+undo              undo the latest rm or mv still standing
+help              print this list
+exit              leave the shell
+";
+
+/// Runs the shell over the tree rooted at `root`, reading commands from 
+/// `input` until it ends or an `exit` command, and writing what they print to
+/// `output`. Only errors reading the input or writing the output are 
+/// returned; errors in the commands are written to `output` as lines 
+/// starting "error: ".
+/// 
+pub fn run_shell<R, W>(root       : &FSDir, 
+                       input      : R, 
+                       mut output : W, 
+                       opts       : ShellOptions) 
+    -> io::Result<()>
+where
+    R: BufRead,
+    W: Write
+{
+    let mut cwd   = root.clone();
+    let mut lines = input.lines();
+    let mut log   = root.begin_ops();
+
+    loop {
+        if opts.prompt {
+            write!(output, "{}> ", cwd.path())?;
+            output.flush()?;
+        }
+        let line = match lines.next() {
+            Some(line) => line?,
+            None       => break,
+        };
+        let words = line.split_whitespace().collect::<Vec<_>>();
+        let (&command, args) = match words.split_first() {
+            Some(split) => split,
+            None        => continue,
+        };
+        let result = match command {
+            "exit" | "quit" => break,
+            "cd"   => cd(root, &mut cwd, args),
+            "ls"   => ls(&cwd, args, opts),
+            "du"   => du(&cwd, args),
+            "pwd"  => Ok(format!("{}\n", cwd.path())),
+            "find" => find(&cwd, args),
+            "top"  => top(&cwd, args),
+            "rm"   => rm(root, &cwd, &mut log, args),
+            "mv"   => mv(root, &cwd, &mut log, args),
+            "undo" => undo(&mut log, args),
+            "help" => Ok(HELP.to_string()),
+            _      => Err(format!("unknown command '{}'; try help", command)),
+        };
+        match result {
+            Ok(text) => write!(output, "{}", text)?,
+            Err(msg) => writeln!(output, "error: {}", msg)?,
+        }
+        if !cwd.root().ptr_eq(root) {
+            cwd = root.clone();
+        }
+    }
+    output.flush()
+}
+
+/// Changes `cwd` to the directory at the path in `args`. Absolute paths are
+/// resolved from `root`, and ".." at the root stays at the root.
+/// 
+fn cd(root: &FSDir, cwd: &mut FSDir, args: &[&str]) 
+    -> Result<String, String> 
+{
+    let path = match args {
+        []     => "/",
+        [path] => path,
+        _      => return Err("cd takes one path".into()),
+    };
+    *cwd = dir_at(root, cwd, path)?;
+    Ok(String::new())
+}
+
+/// Returns the directory at `path`, resolved from `root` if it's absolute 
+/// and from `cwd` if not, following a link at the end of it.
+/// 
+fn dir_at(root: &FSDir, cwd: &FSDir, path: &str) -> Result<FSDir, String> {
+    match root.find_by_path_from(cwd, path) {
+        Some(FSEntry::Dir(dir)) => Ok(dir),
+        Some(FSEntry::File(_))  => Err(format!("'{}' is a file", path)),
+        Some(FSEntry::Link(link)) => match root.resolve_link(&link) {
+            Some(FSEntry::Dir(dir)) => Ok(dir),
+            _ => Err(format!("'{}' doesn't lead to a directory", path)),
+        },
+        None                    => Err(format!("no such directory: '{}'", 
+                                               path)),
+    }
+}
+
+/// Returns the entry at `path`, resolved as dir_at() resolves it but without
+/// following a link at the end, along with the directory it's in. The root
+/// isn't in a directory, so it's refused.
+/// 
+fn entry_at(root: &FSDir, cwd: &FSDir, path: &str) 
+    -> Result<(FSDir, FSEntry), String> 
+{
+    let entry = root.find_by_path_from(cwd, path)
+                    .ok_or_else(|| format!("no such entry: '{}'", path))?;
+    match entry.parent() {
+        Some(dir) => Ok((dir, entry)),
+        None      => Err("the root can't be removed or moved".into()),
+    }
+}
+
+/// Removes the entry at the path in `args` through `log`.
+/// 
+fn rm(root: &FSDir, cwd: &FSDir, log: &mut OpLog, args: &[&str]) 
+    -> Result<String, String> 
+{
+    let path = match args {
+        [path] => path,
+        _      => return Err("rm takes one path".into()),
+    };
+    let (dir, entry) = entry_at(root, cwd, path)?;
+    let removed      = format!("removed {} ({} bytes)\n", 
+                               entry.path(), entry.size());
+    log.remove(&dir, &entry.name_ref());
+    Ok(removed)
+}
+
+/// Moves the entry at the first path in `args` into the directory at the 
+/// second through `log`.
+/// 
+fn mv(root: &FSDir, cwd: &FSDir, log: &mut OpLog, args: &[&str]) 
+    -> Result<String, String> 
+{
+    let (path, dest) = match args {
+        [path, dest] => (path, dest),
+        _            => return Err("mv takes a path and a directory".into()),
+    };
+    let (dir, entry) = entry_at(root, cwd, path)?;
+    let dest         = dir_at(root, cwd, dest)?;
+    log.move_child(&dir, &entry.name_ref(), &dest)
+       .map_err(|e| e.to_string())?;
+    Ok(format!("moved to {}\n", entry.path()))
+}
+
+/// Undoes the latest change in `log`.
+/// 
+fn undo(log: &mut OpLog, args: &[&str]) -> Result<String, String> {
+    if !args.is_empty() {
+        return Err("undo takes no arguments".into());
+    }
+    if log.undo_last() {
+        Ok(String::new())
+    } else {
+        Err("nothing to undo".into())
+    }
+}
+
+/// Lists the children of `cwd` with their sizes and kinds, in the order 
+/// `opts` gives unless `args` asks for another. `--name-sort` lists them by
+/// name, `--size-sort` largest first, and `--reverse` the other way round.
+/// 
+fn ls(cwd: &FSDir, args: &[&str], opts: ShellOptions) 
+    -> Result<String, String> 
+{
+    let (mut key, mut dir) = (opts.sort_key, opts.sort_dir);
+    let mut reverse        = false;
+    for &arg in args {
+        match arg {
+            "--name-sort" => (key, dir) = (SortKey::Name, SortDir::Ascending),
+            "--size-sort" => (key, dir) = (SortKey::Size, SortDir::Descending),
+            "--reverse"   => reverse = true,
+            _             => {
+                return Err(format!("ls doesn't take '{}'; it takes \
+                                    --name-sort, --size-sort, and --reverse",
+                                   arg));
+            },
+        }
+    }
+    if reverse {
+        dir = dir.reversed();
+    }
+    let mut out = String::new();
+    for child in cwd.children_sorted(key, dir) {
+        let kind = match child {
+            FSEntry::File(_) => "file",
+            FSEntry::Dir(_)  => "dir",
+            FSEntry::Link(_) => "link",
+        };
+        out.push_str(&format!("{:>10}  {:<4}  {}\n", 
+                              child.size(), kind, child.name()));
+    }
+    Ok(out)
+}
+
+/// Reports the sizes of `cwd` and the directories below it, no deeper than
+/// the number of levels in `args`, if any.
+/// 
+fn du(cwd: &FSDir, args: &[&str]) -> Result<String, String> {
+    let max_depth = match args {
+        []  => None,
+        [n] => Some(count(n)?),
+        _   => return Err("du takes at most one depth".into()),
+    };
+    cwd.du_report_with(DuOptions { max_depth, ..Default::default() })
+       .map_err(|e| e.to_string())
+}
+
+/// Lists the entries below `cwd` whose names contain the text in `args`, or,
+/// if the text has any of the glob characters '*', '?' or '[' in it, whose
+/// paths match it as a glob. A glob not starting with '/' is relative to
+/// `cwd`.
+/// 
+fn find(cwd: &FSDir, args: &[&str]) -> Result<String, String> {
+    let text = match args {
+        [text] => text,
+        _      => return Err("find takes the text to look for".into()),
+    };
+    let found = if text.contains(['*', '?', '[']) {
+        let glob = if text.starts_with('/') {
+            Glob::new(text)
+        } else {
+            Glob::new(&format!("{}/{}", cwd.path(), text))
+        };
+        cwd.walk().filter(|(path, _)| glob.matches(path)).collect()
+    } else {
+        cwd.walk()
+           .filter(|(_, entry)| entry.name_ref().contains(text))
+           .collect::<Vec<_>>()
+    };
+    let mut out = String::new();
+    for (path, entry) in found {
+        out.push_str(&format!("{:>10}  {}\n", entry.size(), path));
+    }
+    Ok(out)
+}
+
+/// Lists the largest directories and files below `cwd`, as many of each as
+/// `args` asks for, or 10.
+/// 
+fn top(cwd: &FSDir, args: &[&str]) -> Result<String, String> {
+    let n = match args {
+        []  => 10,
+        [n] => count(n)?,
+        _   => return Err("top takes at most one count".into()),
+    };
+    let mut out = String::from("largest directories:\n");
+    for (i, dir) in cwd.largest_dirs(n).iter().enumerate() {
+        out.push_str(&format!("{:>4}. {:>10}  {}\n", 
+                              i + 1, dir.size(), dir.path()));
+    }
+    out.push_str("\nlargest files:\n");
+    for (i, file) in cwd.largest_files(n).iter().enumerate() {
+        out.push_str(&format!("{:>4}. {:>10}  {}\n", 
+                              i + 1, file.size(), file.path()));
+    }
+    Ok(out)
+}
+
+/// Parses a command's count argument.
+/// 
+fn count(arg: &str) -> Result<usize, String> {
+    arg.parse().map_err(|_| format!("expected a number, not '{}'", arg))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::*;
+
+    fn sample() -> FSDir {
+        build_fs_from_str(include_str!("../data/sample.txt")).unwrap()
+    }
+
+    /// Runs the commands in `script` over `root` and returns what the shell
+    /// printed.
+    /// 
+    fn run(root: &FSDir, script: &str) -> String {
+        let mut out = vec![];
+        run_shell(root, script.as_bytes(), &mut out, ShellOptions::default())
+            .unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn cd_above_the_root_stays_at_the_root() {
+        let root = sample();
+        assert_eq!(run(&root, "cd ..\npwd\ncd /a/e\npwd\ncd ../../..\npwd\n\
+                               cd a\ncd\npwd\n"),
+                   "/\n/a/e\n/\n/\n");
+    }
+
+    #[test]
+    fn mistakes_are_reported_and_the_shell_carries_on() {
+        let root = sample();
+        let out  = run(&root, "frob x\ncd nowhere\ncd b.txt\ntop x\n\
+                               ls --bogus\n\npwd\nexit\npwd\n");
+        assert_eq!(out, "error: unknown command 'frob'; try help\n\
+                         error: no such directory: 'nowhere'\n\
+                         error: 'b.txt' is a file\n\
+                         error: expected a number, not 'x'\n\
+                         error: ls doesn't take '--bogus'; it takes \
+                         --name-sort, --size-sort, and --reverse\n\
+                         /\n");
+    }
+
+    #[test]
+    fn ls_lists_by_size_or_by_name() {
+        let root = sample();
+        assert_eq!(run(&root, "ls\n").lines().collect::<Vec<_>>(), 
+                   ["  24933642  dir   d",
+                    "  14848514  file  b.txt",
+                    "   8504156  file  c.dat",
+                    "     94853  dir   a"]);
+        assert_eq!(run(&root, "ls --name-sort\n").lines().collect::<Vec<_>>(),
+                   ["     94853  dir   a",
+                    "  14848514  file  b.txt",
+                    "   8504156  file  c.dat",
+                    "  24933642  dir   d"]);
+        let out = run(&root, "cd d\nls --name-sort --reverse\n");
+        assert_eq!(out.lines().collect::<Vec<_>>(), 
+                   ["   7214296  file  k",
+                    "   4060174  file  j",
+                    "   8033020  file  d.log",
+                    "   5626152  file  d.ext"]);
+    }
+
+    #[test]
+    fn du_stops_at_the_depth_asked_for() {
+        let root = sample();
+        assert_eq!(run(&root, "du 1\n"), 
+                   "48381165\t/\n94853\t/a\n24933642\t/d\n");
+        assert_eq!(run(&root, "du\n"), 
+                   "48381165\t/\n94853\t/a\n584\t/a/e\n24933642\t/d\n");
+        assert_eq!(run(&root, "cd a\ndu 0\n"), "94853\t/a\n");
+    }
+
+    #[test]
+    fn top_lists_as_many_as_asked_for() {
+        let root = sample();
+        assert_eq!(run(&root, "top 2\n").lines().collect::<Vec<_>>(), 
+                   ["largest directories:",
+                    "   1.   24933642  /d",
+                    "   2.      94853  /a",
+                    "",
+                    "largest files:",
+                    "   1.   14848514  /b.txt",
+                    "   2.    8504156  /c.dat"]);
+        assert_eq!(run(&root, "cd a\ntop 1\n").lines().collect::<Vec<_>>(), 
+                   ["largest directories:",
+                    "   1.        584  /a/e",
+                    "",
+                    "largest files:",
+                    "   1.      62596  /a/h.lst"]);
+    }
+
+    #[test]
+    fn undo_restores_what_rm_and_mv_changed() {
+        let root = sample();
+        let out  = run(&root, "rm b.txt\nmv a d\nls --name-sort\n\
+                               undo\nls --name-sort\nundo\nls --name-sort\n\
+                               undo\n");
+        assert_eq!(out.lines().collect::<Vec<_>>(), 
+                   ["removed /b.txt (14848514 bytes)",
+                    "moved to /d/a",
+                    "   8504156  file  c.dat",
+                    "  25028495  dir   d",
+                    // After undoing the mv.
+                    "     94853  dir   a",
+                    "   8504156  file  c.dat",
+                    "  24933642  dir   d",
+                    // After undoing the rm.
+                    "     94853  dir   a",
+                    "  14848514  file  b.txt",
+                    "   8504156  file  c.dat",
+                    "  24933642  dir   d",
+                    "error: nothing to undo"]);
+        assert!(root.structural_eq(&sample()));
+    }
+
+    #[test]
+    fn undo_brings_back_a_removed_current_directory() {
+        let root = sample();
+        let out  = run(&root, "cd /a/e\nrm /a\npwd\nls --name-sort\nundo\n\
+                               ls --name-sort\ncd /a/e\npwd\nls\n");
+        assert_eq!(out.lines().collect::<Vec<_>>(), 
+                   ["removed /a (94853 bytes)",
+                    // The shell went back to the root.
+                    "/",
+                    "  14848514  file  b.txt",
+                    "   8504156  file  c.dat",
+                    "  24933642  dir   d",
+                    "     94853  dir   a",
+                    "  14848514  file  b.txt",
+                    "   8504156  file  c.dat",
+                    "  24933642  dir   d",
+                    "/a/e",
+                    "       584  file  i"]);
+        assert!(root.structural_eq(&sample()));
+    }
+}
@@ -0,0 +1,221 @@
+//! This module implements a solver that answers both parts of the puzzle in a
+//! single pass over the terminal output, without building a tree. Only a stack
+//! of running directory sizes is kept, plus the sizes of the directories that
+//! have been left for good.
+//! 
+//! Unlike the tree-based parser, this relies on each directory being entered
+//! and listed only once, as it is in the puzzle input. Revisited directories
+//! are counted again.
+
+use std::io::BufRead;
+use crate::fsobject::*;
+use crate::parser::*;
+use crate::units::*;
+
+/// Parse the terminal output from `reader` and return the answers to both
+/// parts of the puzzle: the sum of the sizes of the directories no bigger than
+/// `threshold`, and the size of the smallest directory that frees enough space
+/// on a `device` byte device for an `update` byte update. The second answer is
+/// 0 if nothing needs deleting or no directory is big enough. As with the
+/// tree-based solution, the root directory is left out of both. Sizes that
+/// would overflow are a ParseError::SizeOverflow, as they are to the parser.
+/// 
+pub fn solve_streaming<R: BufRead>(reader    : R,
+                                   threshold : u64,
+                                   device    : u64,
+                                   update    : u64)
+    -> Result<(u64, u64), ParseError>
+{
+    let mut stack   = vec![0_u64];
+    let mut done    = vec![];
+    let mut path    = String::from("/");
+    let mut in_ls   = false;
+    let mut line_no = 0;
+
+    // Leave the current directory, at `path`, adding its size to its 
+    // parent's and moving `path` up to the parent.
+    fn leave(stack: &mut Vec<u64>, done: &mut Vec<u64>, path: &mut String)
+        -> Result<(), SizeOverflow>
+    {
+        let size = stack.pop().unwrap();
+        parent_path(path);
+        let top  = stack.last_mut().unwrap();
+        *top = top.checked_add(size)
+                  .ok_or_else(|| SizeOverflow { path: path.clone(), size })?;
+        done.push(size);
+        Ok(())
+    }
+    // Drop the last name from a path, leaving "/" at the root.
+    fn parent_path(path: &mut String) {
+        let end = path.rfind('/').unwrap_or(0).max(1);
+        path.truncate(end);
+    }
+    // The error for a size that overflowed while reading `line`.
+    let overflow = |line_no, line: &str, source: SizeOverflow| {
+        ParseError::SizeOverflow { line: line_no, text: line.into(), 
+                                   dir: source.path.clone(), source }
+    };
+
+    for line in reader.lines() {
+        line_no += 1;
+        let raw  = line.map_err(|e| ParseError::Io { line: line_no,
+                                                     source: e })?;
+        let line = match clean_line(&raw, line_no) {
+            Some(line) => line,
+            None       => continue,
+        };
+        let here = || path.clone();
+        match head_tail(line) {
+            ("$", command) => {  // Command prompt.
+                in_ls = false;
+                let command = need_arg(command, line_no, line, here)?;
+                match head_tail(command) {
+                    ("cd", target) => {  // Change directory.
+                        let target = need_arg(target, line_no, line, here)?;
+                        if target.starts_with('/') {
+                            while stack.len() > 1 {
+                                leave(&mut stack, &mut done, &mut path)
+                                    .map_err(|e| overflow(line_no, line, e))?;
+                            }
+                        }
+                        for part in target.split('/') {
+                            match part {
+                                "" | "." => {},
+                                ".." => {
+                                    if stack.len() > 1 {
+                                        leave(&mut stack, &mut done, 
+                                              &mut path)
+                                            .map_err(|e| {
+                                                overflow(line_no, line, e)
+                                            })?;
+                                    }
+                                },
+                                name => {
+                                    stack.push(0);
+                                    if path.len() > 1 {
+                                        path.push('/');
+                                    }
+                                    path.push_str(name);
+                                },
+                            }
+                        }
+                    },
+                    ("ls", _) => in_ls = true,
+                    _ => {
+                        return Err(ParseError::UnknownCommand {
+                            line: line_no, text: line.into(), dir: path,
+                        });
+                    },
+                }
+            },
+            ("dir", name) if in_ls => {  // Directory, counted on entry.
+                need_arg(name, line_no, line, here)?;
+            },
+            (size, name) if in_ls && !size.is_empty() => {  // File.
+                need_arg(name, line_no, line, here)?;
+                let size = parse_size(size, SizeSyntax::Plain)
+                    .map_err(|e| ParseError::BadSize {
+                        line: line_no, text: line.into(), dir: path.clone(),
+                        source: e,
+                    })?;
+                let top = stack.last_mut().unwrap();
+                *top = top.checked_add(size).ok_or_else(|| {
+                    overflow(line_no, line, SizeOverflow { path: path.clone(),
+                                                           size })
+                })?;
+            },
+            _ => {
+                return Err(ParseError::UnexpectedOutput {
+                    line: line_no, text: line.into(), dir: path,
+                });
+            },
+        }
+    }
+    // The transcript may end deep in the tree. An overflow here is blamed
+    // on the last line.
+    while stack.len() > 1 {
+        leave(&mut stack, &mut done, &mut path)
+            .map_err(|e| overflow(line_no, "", e))?;
+    }
+    let taken = stack[0];
+    let need  = if taken > device {
+        update.saturating_add(taken - device)
+    } else {
+        update.saturating_sub(device - taken)
+    };
+    let part_1 = done.iter().filter(|&&s| s <= threshold).sum();
+    let part_2 = if need == 0 {
+        0
+    } else {
+        done.iter().copied().filter(|&s| s >= need).min().unwrap_or(0)
+    };
+    Ok((part_1, part_2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::*;
+    use crate::testing::*;
+
+    /// The answers to both parts of the puzzle, worked out on the tree.
+    fn tree_answers(root: &FSDir) -> (u64, u64) {
+        (sum_dirs_at_most(root, 100_000),
+         smallest_dir_to_free(root, 70_000_000, 30_000_000)
+             .map_or(0, |d| d.size()))
+    }
+
+    fn streaming_answers(text: &str) -> Result<(u64, u64), ParseError> {
+        solve_streaming(text.as_bytes(), 100_000, 70_000_000, 30_000_000)
+    }
+
+    #[test]
+    fn the_sample_gives_the_tree_answers() {
+        let text = include_str!("../data/sample.txt");
+        let root = build_fs_from_str(text).unwrap();
+        assert_eq!(streaming_answers(text).unwrap(), (95437, 24933642));
+        assert_eq!(streaming_answers(text).unwrap(), tree_answers(&root));
+    }
+
+    #[test]
+    fn a_million_line_session_gives_the_tree_answers() {
+        let tree = FSDir::random(1, TreeSpec { max_depth : 8, 
+                                               children  : 5..=30,
+                                               nodes     : 800_000,
+                                               ..Default::default() });
+        let text = tree.to_session();
+        assert!(text.lines().count() >= 1_000_000);
+        let root = build_fs_from_str(&text).unwrap();
+        assert_eq!(streaming_answers(&text).unwrap(), tree_answers(&root));
+    }
+
+    #[test]
+    fn sessions_ending_deep_in_the_tree_are_flushed() {
+        let text = "$ cd /\n$ ls\ndir a\n$ cd a\n$ ls\ndir b\n\
+                    $ cd b\n$ ls\n50 x\n";
+        assert_eq!(solve_streaming(text.as_bytes(), 100, 1000, 0).unwrap(),
+                   (100, 0));
+    }
+
+    #[test]
+    fn sizes_that_would_overflow_are_an_error() {
+        let big   = u64::MAX - 1;
+        let cases = [
+            (format!("$ cd /\n$ ls\n{} x\n2 y\n", big), 4, "/"),
+            (format!("$ cd /\n$ ls\n{} x\ndir a\n$ cd a\n$ ls\n2 y\n\
+                      $ cd ..\n", big), 8, "/"),
+            (format!("$ cd /\n$ ls\n{} x\ndir a\n$ cd a\n$ ls\n2 y\n", 
+                     big), 7, "/"),
+        ];
+        for (text, line, dir) in cases {
+            match streaming_answers(&text) {
+                Err(ParseError::SizeOverflow { line: l, source, .. }) => {
+                    assert_eq!((l, source.path.as_str()), (line, dir));
+                },
+                other => panic!("expected an overflow, got {:?}", other),
+            }
+            assert!(matches!(build_fs_from_str(&text), 
+                             Err(ParseError::SizeOverflow { .. })));
+        }
+    }
+}
@@ -0,0 +1,590 @@
+//! This module implements SyncFSDir and SyncFSFile, thread-safe counterparts
+//! of FSDir and FSFile for querying one tree from several threads at once.
+//! They're backed by Arc<RwLock<...>> instead of Rc<RefCell<...>>, and are
+//! Send and Sync. FSDir::into_sync() and FSDir::from_sync() deep-copy trees
+//! between the two.
+//! 
+//! Queries take read locks, so any number of threads can run them at once:
+//! name(), size(), parent(), path(), contains(), get(), get_dir(),
+//! get_file(), children(), len(), is_empty() and the find functions.
+//! Mutations take write locks: add_dir(), add_file(), dir_entry() and
+//! remove() lock the directory being changed, then each of its parents in
+//! turn to update their sizes. A thread never holds more than one lock at a
+//! time, so there's no lock ordering to get wrong, and predicates passed to
+//! the find functions are free to lock whatever they like. The flip side is
+//! that a query racing a mutation may see the new entry before its size has
+//! reached the root. Once the mutations are done, though, the sizes are 
+//! exact: adding an entry swaps out any child of the same name under the 
+//! one lock, so each replaced size is taken off exactly once, and an entry
+//! being added or removed has its size read under the lock that changes its
+//! parent, so growth below it racing the move is counted above it once.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard, Weak};
+use crate::fsobject::*;
+
+/// A file or a directory in a thread-safe tree.
+/// 
+#[derive(Debug, Clone)]
+pub enum SyncFSEntry {
+    File(SyncFSFile),
+    Dir(SyncFSDir),
+}
+impl SyncFSEntry {
+    /// Returns the name of the entry.
+    pub fn name(&self) -> String {
+        self.name_ref().to_string()
+    }
+    /// Returns a shared handle to the name of the entry, without allocating.
+    pub fn name_ref(&self) -> Arc<str> {
+        match self {
+            SyncFSEntry::File(file) => file.name_ref(),
+            SyncFSEntry::Dir(dir)   => dir.name_ref(),
+        }
+    }
+    /// Returns the size of the entry.
+    pub fn size(&self) -> u64 {
+        match self {
+            SyncFSEntry::File(file) => file.size(),
+            SyncFSEntry::Dir(dir)   => dir.size(),
+        }
+    }
+    /// Sets the parent of the entry and returns its size, under the one 
+    /// write lock. A size change making its way up from below the entry 
+    /// either gets here first, and is in the size returned, or after, and 
+    /// goes on to the new parent, so it's counted above the entry once.
+    /// 
+    fn set_parent(&self, parent: Option<Weak<RwLock<SyncDirImpl>>>) -> u64 {
+        match self {
+            SyncFSEntry::File(file) => {
+                let mut pimpl = file.write();
+                pimpl.parent  = parent;
+                pimpl.size
+            },
+            SyncFSEntry::Dir(dir) => {
+                let mut pimpl = dir.write();
+                pimpl.parent  = parent;
+                pimpl.size
+            },
+        }
+    }
+}
+
+/// The impl data for a thread-safe directory.
+/// 
+#[derive(Debug)]
+struct SyncDirImpl {
+    name     : Arc<str>,
+    size     : u64,
+    children : BTreeMap<Arc<str>, SyncFSEntry>,
+    parent   : Option<Weak<RwLock<SyncDirImpl>>>,
+    mtime    : Option<u64>,
+}
+
+/// A directory in a thread-safe tree. Like FSDir, cloning it clones the
+/// handle, not the directory.
+/// 
+#[derive(Debug, Clone)]
+pub struct SyncFSDir {
+    pimpl: Arc<RwLock<SyncDirImpl>>,
+}
+impl SyncFSDir {
+    /// Create a new directory with the given name.
+    pub fn new(name: String) -> Self {
+        Self {
+            pimpl: Arc::new(RwLock::new(SyncDirImpl {
+                name     : name.into(),
+                children : BTreeMap::new(),
+                parent   : None,
+                size     : 0,
+                mtime    : None,
+            })),
+        }
+    }
+    /// Returns true if `self` and `other` are handles to the same directory.
+    pub fn ptr_eq(&self, other: &SyncFSDir) -> bool {
+        Arc::ptr_eq(&self.pimpl, &other.pimpl)
+    }
+    /// Returns the name of the directory.
+    pub fn name(&self) -> String {
+        self.name_ref().to_string()
+    }
+    /// Returns a shared handle to the name of the directory, without
+    /// allocating.
+    /// 
+    pub fn name_ref(&self) -> Arc<str> {
+        self.read().name.clone()
+    }
+    /// Returns the total size of the files below the directory.
+    pub fn size(&self) -> u64 {
+        self.read().size
+    }
+    /// Returns the parent of the directory, or None for the root.
+    pub fn parent(&self) -> Option<SyncFSDir> {
+        let parent = self.read().parent.as_ref()?.upgrade()?;
+        Some(SyncFSDir { pimpl: parent })
+    }
+    /// Returns the full path of the directory, like FSObject::path().
+    pub fn path(&self) -> String {
+        path_from(self.name_ref(), self.parent())
+    }
+    /// Returns the time set on this directory with set_mtime(), if any, like
+    /// FSDir::own_mtime(). Unlike FSDir, there's no falling back on the 
+    /// children's times.
+    /// 
+    pub fn own_mtime(&self) -> Option<u64> {
+        self.read().mtime
+    }
+    /// Sets when this directory was last modified, in seconds since the Unix
+    /// epoch.
+    /// 
+    pub fn set_mtime(&self, mtime: Option<u64>) {
+        self.write().mtime = mtime;
+    }
+    /// Returns true if the directory has a child with the given name.
+    pub fn contains(&self, name: &str) -> bool {
+        self.read().children.contains_key(name)
+    }
+    /// Returns the child with the given name.
+    pub fn get(&self, name: &str) -> Option<SyncFSEntry> {
+        self.read().children.get(name).cloned()
+    }
+    /// Returns the child directory with the given name.
+    pub fn get_dir(&self, name: &str) -> Option<SyncFSDir> {
+        match self.get(name)? {
+            SyncFSEntry::Dir(dir) => Some(dir),
+            SyncFSEntry::File(_)  => None,
+        }
+    }
+    /// Returns the child file with the given name.
+    pub fn get_file(&self, name: &str) -> Option<SyncFSFile> {
+        match self.get(name)? {
+            SyncFSEntry::File(file) => Some(file),
+            SyncFSEntry::Dir(_)     => None,
+        }
+    }
+    /// Returns the children of the directory in name order.
+    pub fn children(&self) -> Vec<SyncFSEntry> {
+        self.read().children.values().cloned().collect()
+    }
+    /// Returns the number of children.
+    pub fn len(&self) -> usize {
+        self.read().children.len()
+    }
+    /// Returns true if the directory has no children.
+    pub fn is_empty(&self) -> bool {
+        self.read().children.is_empty()
+    }
+    /// Adds a directory to this directory and returns a handle to it. As with
+    /// FSDir::add_dir(), a child with the same name is replaced.
+    /// 
+    /// # Panics
+    /// If the size of this directory or one of its parents would overflow.
+    /// 
+    pub fn add_dir(&self, dir: SyncFSDir) -> SyncFSDir {
+        self.insert(SyncFSEntry::Dir(dir.clone()));
+        dir
+    }
+    /// Adds a file to this directory and returns a handle to it. As with
+    /// FSDir::add_file(), a child with the same name is replaced.
+    /// 
+    /// # Panics
+    /// If the size of this directory or one of its parents would overflow.
+    /// 
+    pub fn add_file(&self, file: SyncFSFile) -> SyncFSFile {
+        self.insert(SyncFSEntry::File(file.clone()));
+        file
+    }
+    /// Returns the child directory with the given name, creating it first if
+    /// it doesn't exist.
+    /// 
+    /// # Panics
+    /// If this directory has a file with the given name.
+    /// 
+    pub fn dir_entry(&self, name: &str) -> SyncFSDir {
+        match self.get(name) {
+            Some(SyncFSEntry::Dir(dir)) => dir,
+            Some(SyncFSEntry::File(_))  => {
+                panic!("'{}' is a file, not a directory", name);
+            },
+            None => self.add_dir(SyncFSDir::new(name.into())),
+        }
+    }
+    /// Removes the child with the given name, subtracting its size from this
+    /// directory and its parents, and returns it detached.
+    /// 
+    pub fn remove(&self, name: &str) -> Option<SyncFSEntry> {
+        let entry = self.write().children.remove(name)?;
+        self.sub_size(entry.set_parent(None));
+        Some(entry)
+    }
+    /// Returns the subdirectories of this directory that match the
+    /// predicate, in name order.
+    /// 
+    pub fn find_dirs_by<F>(&self, mut pred: F) -> Vec<SyncFSDir>
+    where
+        F: FnMut(&SyncFSDir) -> bool
+    {
+        self.subdirs().into_iter().filter(|d| pred(d)).collect()
+    }
+    /// Returns all the directories below this one that match the predicate,
+    /// depth-first in name order, like FSDir::find_dirs_recurs_by().
+    /// 
+    pub fn find_dirs_recurs_by<F>(&self, mut pred: F) -> Vec<SyncFSDir>
+    where
+        F: FnMut(&SyncFSDir) -> bool
+    {
+        let mut dirs  = vec![];
+        let mut stack = self.subdirs();
+        stack.reverse();
+        while let Some(dir) = stack.pop() {
+            let start = stack.len();
+            stack.extend(dir.subdirs());
+            stack[start..].reverse();
+            if pred(&dir) {
+                dirs.push(dir);
+            }
+        }
+        dirs
+    }
+    /// Returns all the files below this directory that match the predicate,
+    /// depth-first in name order.
+    /// 
+    pub fn find_files_recurs_by<F>(&self, mut pred: F) -> Vec<SyncFSFile>
+    where
+        F: FnMut(&SyncFSFile) -> bool
+    {
+        let mut files = vec![];
+        let mut stack = vec![SyncFSEntry::Dir(self.clone())];
+        while let Some(entry) = stack.pop() {
+            match entry {
+                SyncFSEntry::Dir(dir) => {
+                    stack.extend(dir.children().into_iter().rev());
+                },
+                SyncFSEntry::File(file) => {
+                    if pred(&file) {
+                        files.push(file);
+                    }
+                },
+            }
+        }
+        files
+    }
+    /// Adds `entry` to this directory, replacing any child with the same 
+    /// name under the same write lock, so two threads adding the same name
+    /// can't both miss the child they replace. The sizes above are then 
+    /// changed by the difference between the two. Each size is read under
+    /// the lock that sets or clears the entry's parent; see set_parent().
+    /// 
+    fn insert(&self, entry: SyncFSEntry) {
+        let added = entry.set_parent(Some(Arc::downgrade(&self.pimpl)));
+        let old   = self.write().children.insert(entry.name_ref(), 
+                                                  entry.clone());
+        let same  = |old: &SyncFSEntry| match (old, &entry) {
+            (SyncFSEntry::File(a), SyncFSEntry::File(b)) => a.ptr_eq(b),
+            (SyncFSEntry::Dir(a),  SyncFSEntry::Dir(b))  => a.ptr_eq(b),
+            _                                            => false,
+        };
+        // Adding an entry again changes nothing above it.
+        let removed = match old {
+            Some(old) if same(&old) => added,
+            Some(old)               => old.set_parent(None),
+            None                    => 0,
+        };
+        if added >= removed {
+            self.add_size(added - removed);
+        } else {
+            self.sub_size(removed - added);
+        }
+    }
+    /// Returns the subdirectories of this directory in name order.
+    fn subdirs(&self) -> Vec<SyncFSDir> {
+        self.read().children.values().filter_map(|child| match child {
+            SyncFSEntry::Dir(dir) => Some(dir.clone()),
+            SyncFSEntry::File(_)  => None,
+        }).collect()
+    }
+    /// Adds `size` to the size of this directory and each of its parents,
+    /// locking one at a time.
+    /// 
+    fn add_size(&self, size: u64) {
+        let mut cur = Some(self.clone()).filter(|_| size > 0);
+        while let Some(dir) = cur {
+            let mut pimpl = dir.write();
+            pimpl.size = pimpl.size.checked_add(size).unwrap_or_else(|| {
+                panic!("adding {} bytes overflows the size of '{}'",
+                       size, pimpl.name)
+            });
+            cur = pimpl.parent.as_ref()
+                              .and_then(Weak::upgrade)
+                              .map(|pimpl| SyncFSDir { pimpl });
+        }
+    }
+    /// Subtracts `size` from the size of this directory and each of its
+    /// parents, locking one at a time.
+    /// 
+    fn sub_size(&self, size: u64) {
+        let mut cur = Some(self.clone()).filter(|_| size > 0);
+        while let Some(dir) = cur {
+            let mut pimpl = dir.write();
+            pimpl.size -= size;
+            cur = pimpl.parent.as_ref()
+                              .and_then(Weak::upgrade)
+                              .map(|pimpl| SyncFSDir { pimpl });
+        }
+    }
+    /// Takes a read lock on the directory. A panic in another thread while it
+    /// held the lock doesn't stop the tree from being read.
+    /// 
+    fn read(&self) -> RwLockReadGuard<'_, SyncDirImpl> {
+        self.pimpl.read().unwrap_or_else(|e| e.into_inner())
+    }
+    /// Takes a write lock on the directory.
+    fn write(&self) -> RwLockWriteGuard<'_, SyncDirImpl> {
+        self.pimpl.write().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// The impl data for a thread-safe file.
+/// 
+#[derive(Debug)]
+struct SyncFileImpl {
+    name    : Arc<str>,
+    size    : u64,
+    parent  : Option<Weak<RwLock<SyncDirImpl>>>,
+    mtime   : Option<u64>,
+    link_id : Option<u64>,
+}
+
+/// A file in a thread-safe tree.
+/// 
+#[derive(Debug, Clone)]
+pub struct SyncFSFile {
+    pimpl: Arc<RwLock<SyncFileImpl>>,
+}
+impl SyncFSFile {
+    /// Create a new file with the given name and size.
+    pub fn new(name: String, size: u64) -> Self {
+        Self {
+            pimpl: Arc::new(RwLock::new(SyncFileImpl { name    : name.into(), 
+                                                       size,
+                                                       parent  : None,
+                                                       mtime   : None,
+                                                       link_id : None })),
+        }
+    }
+    /// Returns the name of the file.
+    pub fn name(&self) -> String {
+        self.name_ref().to_string()
+    }
+    /// Returns a shared handle to the name of the file, without allocating.
+    pub fn name_ref(&self) -> Arc<str> {
+        self.read().name.clone()
+    }
+    /// Returns the size of the file.
+    pub fn size(&self) -> u64 {
+        self.read().size
+    }
+    /// Returns the directory holding the file, if any.
+    pub fn parent(&self) -> Option<SyncFSDir> {
+        let parent = self.read().parent.as_ref()?.upgrade()?;
+        Some(SyncFSDir { pimpl: parent })
+    }
+    /// Returns the full path of the file, like FSObject::path().
+    pub fn path(&self) -> String {
+        path_from(self.name_ref(), self.parent())
+    }
+    /// Returns true if `self` and `other` are handles to the same file.
+    pub fn ptr_eq(&self, other: &SyncFSFile) -> bool {
+        Arc::ptr_eq(&self.pimpl, &other.pimpl)
+    }
+    /// Returns when the file was last modified, like FSFile::mtime().
+    pub fn mtime(&self) -> Option<u64> {
+        self.read().mtime
+    }
+    /// Sets when the file was last modified, in seconds since the Unix epoch.
+    pub fn set_mtime(&self, mtime: Option<u64>) {
+        self.write().mtime = mtime;
+    }
+    /// Returns the identity the file shares with its other hard links, like
+    /// FSFile::link_id().
+    /// 
+    pub fn link_id(&self) -> Option<u64> {
+        self.read().link_id
+    }
+    /// Sets the identity the file shares with its other hard links.
+    pub fn set_link_id(&self, link_id: Option<u64>) {
+        self.write().link_id = link_id;
+    }
+    /// Takes a read lock on the file.
+    fn read(&self) -> RwLockReadGuard<'_, SyncFileImpl> {
+        self.pimpl.read().unwrap_or_else(|e| e.into_inner())
+    }
+    /// Takes a write lock on the file.
+    fn write(&self) -> RwLockWriteGuard<'_, SyncFileImpl> {
+        self.pimpl.write().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Builds the path of an entry named `name` in the directory `parent`, by
+/// walking up the parents.
+/// 
+fn path_from(name: Arc<str>, parent: Option<SyncFSDir>) -> String {
+    let mut names = vec![name];
+    let mut cur   = parent;
+    while let Some(dir) = cur {
+        names.push(dir.name_ref());
+        cur = dir.parent();
+    }
+    join_names(names.iter().rev())
+}
+
+impl FSDir {
+    /// Deep-copies the tree rooted at this directory into a thread-safe
+    /// tree, which can be shared between threads. Modification times and 
+    /// hard-link ids come along. The thread-safe tree has no symbolic links
+    /// or quotas, so they're left out, and the sizes above links don't 
+    /// count them.
+    /// 
+    pub fn into_sync(self) -> SyncFSDir {
+        let root      = SyncFSDir::new(self.name());
+        let mut stack = vec![(self, root.clone())];
+        while let Some((dir, copy)) = stack.pop() {
+            copy.set_mtime(dir.own_mtime());
+            for child in dir.children() {
+                match child {
+                    FSEntry::Dir(sub) => {
+                        let sub_copy = SyncFSDir::new(sub.name());
+                        stack.push((sub, copy.add_dir(sub_copy)));
+                    },
+                    FSEntry::File(file) => {
+                        let copy = copy.add_file(SyncFSFile::new(file.name(),
+                                                                 file.size()));
+                        copy.set_mtime(file.mtime());
+                        copy.set_link_id(file.link_id());
+                    },
+                    FSEntry::Link(_) => {},
+                }
+            }
+        }
+        root
+    }
+    /// Deep-copies the thread-safe tree rooted at `dir` into a new FSDir
+    /// tree, modification times and hard-link ids included.
+    /// 
+    pub fn from_sync(dir: &SyncFSDir) -> FSDir {
+        let root      = FSDir::new(dir.name());
+        let mut stack = vec![(dir.clone(), root.clone())];
+        while let Some((dir, copy)) = stack.pop() {
+            copy.set_mtime(dir.own_mtime());
+            for child in dir.children() {
+                match child {
+                    SyncFSEntry::Dir(sub) => {
+                        let sub_copy = FSDir::new(sub.name());
+                        stack.push((sub, copy.add_dir(sub_copy)));
+                    },
+                    SyncFSEntry::File(file) => {
+                        let copy = copy.add_file(FSFile::new(file.name(), 
+                                                             file.size()));
+                        copy.set_mtime(file.mtime());
+                        copy.set_link_id(file.link_id());
+                    },
+                }
+            }
+        }
+        root
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn racing_adds_of_one_name_keep_sizes_exact() {
+        let root = SyncFSDir::new("/".into());
+        let dir  = root.add_dir(SyncFSDir::new("d".into()));
+        let handles = (1..=8).map(|t| {
+            let dir = dir.clone();
+            thread::spawn(move || {
+                for i in 0..500 {
+                    dir.add_file(SyncFSFile::new("f".into(), t * 1000 + i));
+                }
+            })
+        }).collect::<Vec<_>>();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        let file = dir.get_file("f").unwrap();
+        assert_eq!(dir.len(), 1);
+        assert_eq!(dir.size(), file.size());
+        assert_eq!(root.size(), file.size());
+    }
+
+    #[test]
+    fn threads_finding_at_once_agree() {
+        use crate::testing::*;
+        let tree = FSDir::random(7, TreeSpec { nodes: 2000,
+                                               ..Default::default() });
+        let want = tree.find_dirs_recurs_by(|d| d.size() > 1_000_000)
+                       .iter()
+                       .map(|d| d.path())
+                       .collect::<Vec<_>>();
+        let sync = tree.into_sync();
+        let handles = (0..4).map(|_| {
+            let sync = sync.clone();
+            thread::spawn(move || {
+                sync.find_dirs_recurs_by(|d| d.size() > 1_000_000)
+                    .iter()
+                    .map(|d| d.path())
+                    .collect::<Vec<_>>()
+            })
+        }).collect::<Vec<_>>();
+        assert!(!want.is_empty());
+        for handle in handles {
+            assert_eq!(handle.join().unwrap(), want);
+        }
+    }
+
+    #[test]
+    fn adds_racing_a_move_of_their_directory_keep_sizes_exact() {
+        let root = SyncFSDir::new("/".into());
+        let d    = root.add_dir(SyncFSDir::new("d".into()));
+        let sub  = d.add_dir(SyncFSDir::new("sub".into()));
+        let mover = {
+            let (root, d) = (root.clone(), d.clone());
+            thread::spawn(move || {
+                for _ in 0..2000 {
+                    root.remove("d");
+                    root.add_dir(d.clone());
+                }
+            })
+        };
+        for i in 0..2000 {
+            sub.add_file(SyncFSFile::new(i.to_string(), 1));
+        }
+        mover.join().unwrap();
+        assert_eq!((sub.size(), d.size(), root.size()), (2000, 2000, 2000));
+    }
+
+    #[test]
+    fn times_and_link_ids_survive_the_round_trip() {
+        let root = FSDir::new("/".into());
+        let file = root.make_dirs("a").add_file(FSFile::new("f".into(), 7));
+        file.set_mtime(Some(1_700_000_000));
+        file.set_link_id(Some(42));
+        root.get_dir("a").unwrap().set_mtime(Some(5));
+
+        let sync = root.deep_clone().into_sync();
+        let copy = sync.get_dir("a").unwrap().get_file("f").unwrap();
+        assert_eq!(copy.mtime(), Some(1_700_000_000));
+        assert_eq!(copy.link_id(), Some(42));
+
+        let back = FSDir::from_sync(&sync);
+        let dir  = back.get_dir("a").unwrap();
+        assert_eq!(dir.own_mtime(), Some(5));
+        assert_eq!(dir.get_file("f").unwrap().link_id(), Some(42));
+        assert!(back.structural_eq(&root));
+    }
+}
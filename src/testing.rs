@@ -0,0 +1,196 @@
+//! This module implements a generator of random trees, for benchmarks and for
+//! checking operations against many shapes of tree. It uses a small built-in
+//! generator of pseudo-random numbers, so the same seed always gives the same
+//! tree, on any platform.
+
+use std::ops::RangeInclusive;
+use crate::fsobject::*;
+
+/// A small, fast generator of pseudo-random numbers (SplitMix64). It's not
+/// suitable for anything that has to be unpredictable.
+/// 
+#[derive(Debug, Clone)]
+pub struct SplitMix64 {
+    state: u64,
+}
+impl SplitMix64 {
+    /// Create a generator that yields the sequence for `seed`.
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+    /// Returns the next number in the sequence.
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z  = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+    /// Returns a number in `range`, which must not be empty.
+    pub fn in_range(&mut self, range: &RangeInclusive<usize>) -> usize {
+        let range = *range.start() as u64..=*range.end() as u64;
+        self.in_range_u64(&range) as usize
+    }
+    /// Like in_range(), for u64s such as file sizes.
+    pub fn in_range_u64(&mut self, range: &RangeInclusive<u64>) -> u64 {
+        let (lo, hi) = (*range.start(), *range.end());
+        assert!(lo <= hi, "empty range");
+        let span = hi - lo;
+        if span == u64::MAX {
+            return self.next_u64();
+        }
+        lo + self.next_u64() % (span + 1)
+    }
+}
+
+/// The shape of the trees FSDir::random() generates.
+/// 
+#[derive(Debug, Clone)]
+pub struct TreeSpec {
+    /// The deepest a directory can be. The root is at depth 0, so with a 
+    /// max_depth of 0 the root holds only files.
+    pub max_depth : usize,
+    /// How many children each directory is given.
+    pub children  : RangeInclusive<usize>,
+    /// The sizes files are given.
+    pub file_size : RangeInclusive<u64>,
+    /// The exact number of directories and files below the root.
+    pub nodes     : usize,
+}
+impl Default for TreeSpec {
+    fn default() -> Self {
+        Self { max_depth : 8, 
+               children  : 0..=10, 
+               file_size : 1..=300_000, 
+               nodes     : 1000 }
+    }
+}
+
+/// The extensions given to generated file names.
+const EXTENSIONS: [&str; 6] = ["txt", "dat", "log", "lst", "ext", "bin"];
+
+impl FSDir {
+    /// Generates a random tree, named "/", with exactly `spec.nodes` 
+    /// directories and files below the root. The same seed and spec always 
+    /// give the same tree. Names are unique among siblings.
+    /// 
+    /// Directories are filled in a random order, each with a number of 
+    /// children from `spec.children`, about a third of them directories, 
+    /// until the budget runs out, so some may be left with fewer. If every
+    /// directory has been filled and budget is left, because the ranges or
+    /// depth limit don't allow for that many nodes, the rest are added to 
+    /// random directories as extra files.
+    /// 
+    pub fn random(seed: u64, spec: TreeSpec) -> FSDir {
+        let mut rng     = SplitMix64::new(seed);
+        let     root    = FSDir::new("/".into());
+        let mut pending = vec![(root.clone(), 0)];
+        let mut filled  = vec![];
+        let mut budget  = spec.nodes;
+
+        while budget > 0 && !pending.is_empty() {
+            let i            = rng.in_range(&(0..=pending.len() - 1));
+            let (dir, depth) = pending.swap_remove(i);
+            let mut count    = rng.in_range(&spec.children).min(budget);
+            // The last dir left to fill is given at least one child, and its
+            // last child is a directory while depth allows, so the budget 
+            // isn't stranded.
+            if pending.is_empty() {
+                count = count.max(1);
+            }
+            for n in 0..count {
+                let last   = pending.is_empty() && n == count - 1 
+                                                && budget > 1;
+                let is_dir = depth < spec.max_depth 
+                             && (last || rng.next_u64().is_multiple_of(3));
+                if is_dir {
+                    let sub = dir.add_dir(FSDir::new(format!("d{}", n)));
+                    pending.push((sub, depth + 1));
+                } else {
+                    add_random_file(&mut rng, &dir, n, &spec);
+                }
+                budget -= 1;
+            }
+            filled.push(dir);
+        }
+        filled.extend(pending.into_iter().map(|(dir, _)| dir));
+        while budget > 0 {
+            let i   = rng.in_range(&(0..=filled.len() - 1));
+            let dir = &filled[i];
+            add_random_file(&mut rng, dir, dir.len(), &spec);
+            budget -= 1;
+        }
+        root
+    }
+}
+
+/// Add a file of random size and extension to `dir`, numbered `n`, which must
+/// be unique in `dir`.
+/// 
+fn add_random_file(rng: &mut SplitMix64, dir: &FSDir, n: usize, 
+                   spec: &TreeSpec) 
+{
+    let ext  = EXTENSIONS[rng.in_range(&(0..=EXTENSIONS.len() - 1))];
+    let size = rng.in_range_u64(&spec.file_size);
+    dir.add_file(FSFile::new(format!("f{}.{}", n, ext), size));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the number of entries below `root` and the sum of its files'
+    /// sizes.
+    fn count_and_total(root: &FSDir) -> (usize, u64) {
+        root.walk().fold((0, 0), |(count, total), (_, entry)| match entry {
+            FSEntry::File(file) => (count + 1, total + file.size()),
+            _                   => (count + 1, total),
+        })
+    }
+
+    #[test]
+    fn trees_have_the_budget_and_add_up() {
+        for seed in 0..50 {
+            let spec  = TreeSpec { nodes: 500, ..Default::default() };
+            let root  = FSDir::random(seed, spec.clone());
+            let (count, total) = count_and_total(&root);
+            assert_eq!(count, spec.nodes, "seed {}", seed);
+            assert_eq!(root.size(), total, "seed {}", seed);
+            for (path, entry) in root.walk() {
+                // The depth of the directory holding the entry.
+                let depth = path.matches('/').count() - 1;
+                match entry {
+                    FSEntry::Dir(_)     => assert!(depth < spec.max_depth),
+                    FSEntry::File(file) => {
+                        assert!(spec.file_size.contains(&file.size()));
+                    },
+                    FSEntry::Link(_)    => panic!("no links are generated"),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn the_same_seed_gives_the_same_tree() {
+        let a = FSDir::random(7, TreeSpec::default());
+        let b = FSDir::random(7, TreeSpec::default());
+        let c = FSDir::random(8, TreeSpec::default());
+        assert!(a.structural_eq(&b));
+        assert!(!a.structural_eq(&c));
+    }
+
+    #[test]
+    fn budget_left_over_goes_to_extra_files() {
+        // With no subdirectories allowed, at most 2 files fit in the root
+        // before the rest are added as extras.
+        let spec = TreeSpec { max_depth : 0, 
+                              children  : 0..=2, 
+                              file_size : 5..=5, 
+                              nodes     : 50 };
+        let root = FSDir::random(1, spec);
+        assert_eq!(root.len(), 50);
+        assert_eq!(root.size(), 250);
+        let empty = TreeSpec { nodes: 0, ..Default::default() };
+        assert!(FSDir::random(1, empty).is_empty());
+    }
+}
@@ -0,0 +1,268 @@
+//! This module implements formatting of byte counts in human readable units,
+//! and parsing them back.
+
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+/// The units used to format a byte count.
+/// 
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SizeFormat {
+    /// Plain byte counts, e.g. "31337".
+    #[default]
+    Bytes,
+    /// Powers of 1024, e.g. "30.6 KiB".
+    Binary,
+    /// Powers of 1000, e.g. "31.3 KB".
+    Decimal,
+}
+impl SizeFormat {
+    /// Formats `bytes` in these units. Values smaller than one unit are
+    /// printed as plain bytes with a "B" suffix, everything else is rounded to
+    /// one decimal place.
+    /// 
+    pub fn format(self, bytes: u64) -> String {
+        const BINARY  : [&str; 6] = ["KiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
+        const DECIMAL : [&str; 6] = ["KB",  "MB",  "GB",  "TB",  "PB",  "EB" ];
+
+        let (base, units) = match self {
+            SizeFormat::Bytes   => return bytes.to_string(),
+            SizeFormat::Binary  => (1024.0, BINARY),
+            SizeFormat::Decimal => (1000.0, DECIMAL),
+        };
+        if (bytes as f64) < base {
+            return format!("{} B", bytes);
+        }
+        let mut value = bytes as f64 / base;
+        let mut unit  = 0;
+
+        // Step up a unit whenever the rounded value would read as a full
+        // unit, so we print "1.0 MiB" rather than "1024.0 KiB".
+        while round_1(value) >= base && unit < units.len() - 1 {
+            value /= base;
+            unit  += 1;
+        }
+        format!("{:.1} {}", value, units[unit])
+    }
+}
+
+/// Formats `bytes` in binary units, e.g. "29.9 MiB".
+/// 
+pub fn human_size(bytes: u64) -> String {
+    SizeFormat::Binary.format(bytes)
+}
+
+/// Rounds to one decimal place.
+fn round_1(value: f64) -> f64 {
+    (value * 10.0).round() / 10.0
+}
+
+/// Formats a fraction from 0.0 to 1.0 as a percentage rounded to one decimal
+/// place, e.g. "29.4%". Only a fraction of exactly 1.0 reads as "100%"; 
+/// anything just short of it is shown as "99.9%".
+/// 
+pub fn format_percent(fraction: f64) -> String {
+    if fraction == 1.0 {
+        return "100%".to_string();
+    }
+    let percent = round_1(fraction * 100.0).min(99.9);
+    format!("{:.1}%", percent)
+}
+
+/// How sizes are written in `ls` output, for parse_size().
+/// 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SizeSyntax {
+    /// Plain byte counts only, e.g. "31337".
+    #[default]
+    Plain,
+    /// Byte counts, or values with a unit suffix in powers of 1024, e.g. 
+    /// "30.6K", "30.6KB", or "30.6KiB".
+    HumanBinary,
+    /// Byte counts, or values with a unit suffix in powers of 1000, e.g.
+    /// "31.3K" or "31.3KB". A suffix with an 'i', like "KiB", still means
+    /// powers of 1024.
+    HumanDecimal,
+    /// Byte counts, or values with a unit suffix, read the way `ls -h` and 
+    /// `ls --si` write them: "K" and "KiB" are powers of 1024, "KB" powers of
+    /// 1000.
+    Auto,
+}
+
+/// Returned when parse_size() can't make sense of a size.
+/// 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseSizeError {
+    /// The size isn't a number, or has a unit where none is allowed.
+    Invalid(String),
+    /// The number is followed by something that isn't a unit.
+    UnknownUnit(String),
+    /// The size doesn't fit in a u64.
+    Overflow(String),
+}
+impl Display for ParseSizeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseSizeError::Invalid(text) => {
+                write!(f, "'{}' isn't a size", text)
+            },
+            ParseSizeError::UnknownUnit(text) => {
+                write!(f, "'{}' has an unknown unit", text)
+            },
+            ParseSizeError::Overflow(text) => {
+                write!(f, "'{}' is too big", text)
+            },
+        }
+    }
+}
+impl Error for ParseSizeError {}
+
+/// Parses `text` as a number of bytes written in `syntax`. Sizes with a unit
+/// may have a fractional part, and are rounded to the nearest byte, so 
+/// "1.5K" is 1536 bytes in binary units. The units are K, M, G, T, P, and E,
+/// in either case, optionally followed by 'i', 'B', or "iB".
+/// 
+pub fn parse_size(text: &str, syntax: SizeSyntax) 
+    -> Result<u64, ParseSizeError> 
+{
+    const UNITS: &str = "KMGTPE";
+
+    let invalid  = || ParseSizeError::Invalid(text.into());
+    let overflow = || ParseSizeError::Overflow(text.into());
+    let plain    = |digits: &str| {
+        if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(invalid());
+        }
+        digits.parse::<u64>().map_err(|_| overflow())
+    };
+    if syntax == SizeSyntax::Plain {
+        return plain(text);
+    }
+    // Split the number from its unit, and the unit into its letter and any
+    // 'i' or 'B' after it.
+    let split          = text.find(|c: char| !c.is_ascii_digit() && c != '.')
+                             .unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    if number.is_empty() {
+        return Err(invalid());
+    }
+    let (letter, rest) = match unit.chars().next() {
+        None | Some('B') => (None, unit),
+        Some(c)          => (Some(c.to_ascii_uppercase()), &unit[1..]),
+    };
+    let binary = match rest {
+        "" | "B" | "b" => false,
+        "i" | "iB"     => true,
+        _              => return Err(ParseSizeError::UnknownUnit(text.into())),
+    };
+    let Some(letter) = letter else {
+        return plain(number);
+    };
+    let power = UNITS.find(letter)
+                     .ok_or_else(|| ParseSizeError::UnknownUnit(text.into()))?;
+    let base: u128 = match syntax {
+        _ if binary                         => 1024,
+        SizeSyntax::HumanBinary             => 1024,
+        SizeSyntax::Auto if rest.is_empty() => 1024,
+        _                                   => 1000,
+    };
+    let scale = base.pow(power as u32 + 1);
+
+    // Work in integers, so large values are exact and rounding is to the 
+    // nearest byte. Digits past the 20th after the point can't move the 
+    // result by a byte, even in exabytes, so they're dropped.
+    let (whole, fraction) = number.split_once('.').unwrap_or((number, ""));
+    if number == "." || !fraction.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(invalid());
+    }
+    let whole    = if whole.is_empty() { 
+        0 
+    } else { 
+        whole.parse::<u128>().map_err(|_| overflow())? 
+    };
+    let fraction = &fraction[..fraction.len().min(20)];
+    let divisor  = 10_u128.pow(fraction.len() as u32);
+    let fraction = fraction.parse::<u128>().unwrap_or(0);
+    let bytes    = whole.checked_mul(scale)
+                        .and_then(|b| {
+                            b.checked_add((fraction * scale + divisor / 2) 
+                                          / divisor)
+                        })
+                        .ok_or_else(overflow)?;
+    u64::try_from(bytes).map_err(|_| overflow())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// What parse_size() is expected to give for some text: a size, or the
+    /// kind of error.
+    /// 
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum Expect { Size(u64), Invalid, Unit, Overflow }
+    use Expect::*;
+
+    fn outcome(text: &str, syntax: SizeSyntax) -> Expect {
+        match parse_size(text, syntax) {
+            Ok(size)                            => Size(size),
+            Err(ParseSizeError::Invalid(_))     => Invalid,
+            Err(ParseSizeError::UnknownUnit(_)) => Unit,
+            Err(ParseSizeError::Overflow(_))    => Overflow,
+        }
+    }
+
+    #[test]
+    fn sizes_parse_in_every_syntax() {
+        const GI: u64 = 1 << 30;
+        const G:  u64 = 1_000_000_000;
+        const E:  u64 = 1_000_000_000_000_000_000;
+        // Plain, HumanBinary, HumanDecimal, Auto.
+        let cases = [
+            ("0",       [Size(0),     Size(0),     Size(0),     Size(0)]),
+            ("31337",   [Size(31337), Size(31337), Size(31337), Size(31337)]),
+            ("100B",    [Invalid,     Size(100),   Size(100),   Size(100)]),
+            ("1.5K",    [Invalid,     Size(1536),  Size(1500),  Size(1536)]),
+            ("1.5KB",   [Invalid,     Size(1536),  Size(1500),  Size(1500)]),
+            ("1.5KiB",  [Invalid,     Size(1536),  Size(1536),  Size(1536)]),
+            (".5K",     [Invalid,     Size(512),   Size(500),   Size(512)]),
+            ("2k",      [Invalid,     Size(2048),  Size(2000),  Size(2048)]),
+            ("1.0001K", [Invalid,     Size(1024),  Size(1000),  Size(1024)]),
+            ("3G",      [Invalid,     Size(3*GI),  Size(3*G),   Size(3*GI)]),
+            ("3GB",     [Invalid,     Size(3*GI),  Size(3*G),   Size(3*G)]),
+            ("3GiB",    [Invalid,     Size(3*GI),  Size(3*GI),  Size(3*GI)]),
+            ("16E",     [Invalid,     Overflow,    Size(16*E),  Overflow]),
+            ("15.999E", [Invalid,     Size(18445591152204944769), 
+                                      Size(15_999 * (E / 1000)),
+                                      Size(18445591152204944769)]),
+            ("18446744073709551615", [Size(u64::MAX); 4]),
+            ("18446744073709551616", [Overflow; 4]),
+            ("1.5X",    [Invalid,     Unit,        Unit,        Unit]),
+            ("1KX",     [Invalid,     Unit,        Unit,        Unit]),
+            ("0.5",     [Invalid; 4]),
+            ("",        [Invalid; 4]),
+            ("K",       [Invalid; 4]),
+            ("-1",      [Invalid; 4]),
+            ("1.2.3K",  [Invalid; 4]),
+        ];
+        let syntaxes = [SizeSyntax::Plain, SizeSyntax::HumanBinary,
+                        SizeSyntax::HumanDecimal, SizeSyntax::Auto];
+        for (text, expected) in cases {
+            for (syntax, expected) in syntaxes.into_iter().zip(expected) {
+                assert_eq!(outcome(text, syntax), expected, "{:?} in {:?}", 
+                           text, syntax);
+            }
+        }
+    }
+
+    #[test]
+    fn parse_errors_quote_the_text() {
+        let e = parse_size("1.5X", SizeSyntax::Auto).unwrap_err();
+        assert_eq!(e.to_string(), "'1.5X' has an unknown unit");
+        let e = parse_size("1.5K", SizeSyntax::Plain).unwrap_err();
+        assert_eq!(e.to_string(), "'1.5K' isn't a size");
+        let e = parse_size("99999999999999999999", SizeSyntax::Plain)
+                    .unwrap_err();
+        assert_eq!(e.to_string(), "'99999999999999999999' is too big");
+    }
+}
@@ -0,0 +1,204 @@
+//! This module works out how much space a tree would take on a real disk,
+//! where files are stored in whole blocks, so a 1 byte file takes up 4096
+//! bytes on most file systems. The puzzle, and FSObject::size(), count
+//! bytes exactly; disk usage is computed separately and never cached.
+//! 
+//! Rounding up can take a size that fits in a u64 past u64::MAX, so disk 
+//! usage is returned as a Result, failing with UsageOverflow.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use crate::fsobject::*;
+
+/// Returned when the space something takes up on disk doesn't fit in a u64.
+/// 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UsageOverflow {
+    /// The path of the file or directory whose usage overflows.
+    pub path       : String,
+    /// The block size of the model.
+    pub block_size : u64,
+}
+impl Display for UsageOverflow {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "the disk usage of '{}' in blocks of {} bytes is too big \
+                   to count", self.path, self.block_size)
+    }
+}
+impl Error for UsageOverflow {}
+
+/// How a file system allocates space, for FSDir::disk_usage_with().
+/// 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiskModel {
+    /// The size of a block. Each file takes up a whole number of blocks.
+    pub block_size   : u64,
+    /// The space each directory takes up itself, apart from its contents.
+    pub dir_overhead : u64,
+}
+impl Default for DiskModel {
+    fn default() -> Self {
+        Self { block_size: 4096, dir_overhead: 0 }
+    }
+}
+impl DiskModel {
+    /// Returns a model with blocks of `block_size` bytes and no directory
+    /// overhead.
+    /// 
+    pub fn blocks(block_size: u64) -> Self {
+        Self { block_size, dir_overhead: 0 }
+    }
+    /// Returns the space a file of `size` bytes takes up: `size` rounded up
+    /// to a whole number of blocks. An empty file takes up none. Returns 
+    /// None if the rounded size doesn't fit in a u64.
+    /// 
+    /// # Panics
+    /// If the block size is 0.
+    /// 
+    pub fn file_usage(&self, size: u64) -> Option<u64> {
+        assert!(self.block_size > 0, "the block size must be at least 1");
+        size.div_ceil(self.block_size).checked_mul(self.block_size)
+    }
+}
+
+impl FSDir {
+    /// Returns the space the tree rooted at this directory takes up on a
+    /// disk with blocks of `block_size` bytes, each file being rounded up to
+    /// a whole number of blocks. Fails if the usage of a file or directory
+    /// doesn't fit in a u64.
+    /// 
+    /// # Panics
+    /// If `block_size` is 0.
+    /// 
+    pub fn disk_usage(&self, block_size: u64) -> Result<u64, UsageOverflow> {
+        self.disk_usage_with(DiskModel::blocks(block_size))
+    }
+    /// Like disk_usage(), but with full control over the model, including a
+    /// fixed overhead for each directory, this one included.
+    /// 
+    pub fn disk_usage_with(&self, model: DiskModel) 
+        -> Result<u64, UsageOverflow> 
+    {
+        Ok(self.disk_usages(model)?[&self.id()])
+    }
+    /// Returns the `n` directories below this one that take up the most
+    /// space under `model`, with that space, largest first, with ties
+    /// broken by path.
+    /// 
+    pub fn largest_dirs_on_disk(&self, n: usize, model: DiskModel)
+        -> Result<Vec<(FSDir, u64)>, UsageOverflow>
+    {
+        let usages = self.disk_usages(model)?;
+        Ok(top_n_by(n, self.walk().filter_map(|(path, entry)| match entry {
+            FSEntry::Dir(dir) => Some((path, usages[&dir.id()], dir)),
+            _                 => None,
+        })))
+    }
+    /// Returns the `n` files below this directory that take up the most
+    /// space under `model`, with that space, largest first, with ties broken
+    /// by path.
+    /// 
+    pub fn largest_files_on_disk(&self, n: usize, model: DiskModel)
+        -> Result<Vec<(FSFile, u64)>, UsageOverflow>
+    {
+        let mut files = vec![];
+        for (path, entry) in self.walk() {
+            if let FSEntry::File(file) = entry {
+                let usage = model.file_usage(file.size()).ok_or_else(|| {
+                    UsageOverflow { path       : path.clone(), 
+                                    block_size : model.block_size }
+                })?;
+                files.push((path, usage, file));
+            }
+        }
+        Ok(top_n_by(n, files.into_iter()))
+    }
+    /// Returns the space this directory and every directory below it take up
+    /// under `model`, keyed by their ids, worked out in one bottom-up pass.
+    /// Fails if a file's or a directory's usage doesn't fit in a u64.
+    /// 
+    /// # Panics
+    /// If the block size is 0.
+    /// 
+    pub(crate) fn disk_usages(&self, model: DiskModel) 
+        -> Result<HashMap<usize, u64>, UsageOverflow> 
+    {
+        let dirs       = self.walk_bfs().map(|(_, d)| d).collect::<Vec<_>>();
+        let mut usages = HashMap::with_capacity(dirs.len());
+        let overflow   = |path: String| {
+            UsageOverflow { path, block_size: model.block_size }
+        };
+        for dir in dirs.iter().rev() {
+            let mut total = model.dir_overhead;
+            for child in dir.children() {
+                let usage = match &child {
+                    FSEntry::File(file) => model.file_usage(file.size()),
+                    FSEntry::Dir(sub)   => Some(usages[&sub.id()]),
+                    FSEntry::Link(link) => model.file_usage(link.size()),
+                };
+                let usage = usage.ok_or_else(|| overflow(child.path()))?;
+                total     = total.checked_add(usage)
+                                 .ok_or_else(|| overflow(dir.path()))?;
+            }
+            usages.insert(dir.id(), total);
+        }
+        Ok(usages)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn files_are_rounded_up_to_whole_blocks() {
+        let model = DiskModel::blocks(4096);
+        for (size, usage) in [(0, 0), (1, 4096), (4095, 4096), (4096, 4096), 
+                              (4097, 8192), (8192, 8192), (8193, 12288)] {
+            assert_eq!(model.file_usage(size), Some(usage), "{} bytes", size);
+        }
+        assert_eq!(DiskModel::blocks(1).file_usage(4097), Some(4097));
+    }
+
+    #[test]
+    fn disk_usage_sums_the_rounded_files() {
+        let root = FSDir::new("/".into());
+        root.add_file_at_path("a/exact", 4096);
+        root.add_file_at_path("a/over", 4097);
+        root.add_file_at_path("b/empty", 0);
+        root.add_file_at_path("b/c/one", 1);
+        assert_eq!(root.size(), 8194);
+        assert_eq!(root.disk_usage(4096), Ok(4096 + 8192 + 4096));
+        let model = DiskModel { block_size: 4096, dir_overhead: 10 };
+        // Four directories, the root included.
+        assert_eq!(root.disk_usage_with(model), Ok(16384 + 40));
+        let b = root.get_dir("b").unwrap();
+        assert_eq!(b.disk_usage(4096), Ok(4096));
+        let files = root.largest_files_on_disk(2, DiskModel::blocks(4096))
+                        .unwrap();
+        let files = files.iter()
+                         .map(|(f, usage)| (f.name(), *usage))
+                         .collect::<Vec<_>>();
+        assert_eq!(files, [("over".into(), 8192), ("exact".into(), 4096)]);
+    }
+
+    #[test]
+    fn usage_past_u64_max_is_an_error() {
+        let model = DiskModel::blocks(4096);
+        assert_eq!(model.file_usage(u64::MAX - 1), None);
+        assert_eq!(model.file_usage(u64::MAX - 4095), Some(u64::MAX - 4095));
+        let root = FSDir::new("/".into());
+        root.add_file_at_path("a/big", u64::MAX - 1);
+        let e = root.disk_usage(4096).unwrap_err();
+        assert_eq!((e.path.as_str(), e.block_size), ("/a/big", 4096));
+        assert!(root.largest_files_on_disk(1, model).is_err());
+        assert!(root.largest_dirs_on_disk(1, model).is_err());
+        // Files that each fit, but not together.
+        let root = FSDir::new("/".into());
+        root.add_file_at_path("a/x", u64::MAX / 2);
+        root.add_file_at_path("a/y", u64::MAX / 2);
+        assert_eq!(root.disk_usage(4096).unwrap_err().path, "/a");
+        assert_eq!(root.disk_usage(1), Ok(u64::MAX - 1));
+    }
+}
@@ -0,0 +1,181 @@
+//! This module implements the Visitor trait and FSDir::accept(), a depth-first
+//! traversal with a callback on the way into each directory, one on the way
+//! out, and one for each file. That suits work that needs to know when a
+//! directory is finished, like totalling something per directory or writing
+//! out nested output, which the find functions can't express.
+
+use crate::fsobject::*;
+
+/// What a Visitor tells FSDir::accept() to do next.
+/// 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum VisitFlow {
+    /// Carry on with the traversal.
+    #[default]
+    Continue,
+    /// From enter_dir(), don't visit anything in the directory, but carry on
+    /// after it. leave_dir() is still called for it. Elsewhere, the same as
+    /// Continue.
+    /// 
+    SkipSubtree,
+    /// End the traversal at once. No more callbacks are made, leave_dir()
+    /// for the directories that were entered included.
+    /// 
+    Stop,
+}
+
+/// Callbacks for FSDir::accept(). Each gets the full path of the entry along
+/// with the entry. They all do nothing by default, so a visitor only needs to
+/// implement the ones it cares about.
+/// 
+pub trait Visitor {
+    /// Called on the way into a directory, before anything in it.
+    fn enter_dir(&mut self, _path: &str, _dir: &FSDir) -> VisitFlow {
+        VisitFlow::Continue
+    }
+    /// Called on the way out of a directory, after everything in it, for
+    /// every directory enter_dir() was called for, unless the traversal was
+    /// stopped.
+    /// 
+    fn leave_dir(&mut self, _path: &str, _dir: &FSDir) -> VisitFlow {
+        VisitFlow::Continue
+    }
+    /// Called for each file.
+    fn visit_file(&mut self, _path: &str, _file: &FSFile) -> VisitFlow {
+        VisitFlow::Continue
+    }
+    /// Called for each symbolic link. Links aren't followed.
+    fn visit_link(&mut self, _path: &str, _link: &FSLink) -> VisitFlow {
+        VisitFlow::Continue
+    }
+}
+
+impl FSDir {
+    /// Takes `visitor` through the tree rooted at this directory, depth-first,
+    /// starting with this directory, and visiting the children of each
+    /// directory in name order, whatever their kind. Returns Stop if the 
+    /// visitor stopped the traversal, and Continue otherwise.
+    /// 
+    pub fn accept(&self, visitor: &mut dyn Visitor) -> VisitFlow {
+        enum Step { Enter(String, FSEntry), Leave(String, FSDir) }
+
+        let mut stack = vec![Step::Enter(self.path(),
+                                         FSEntry::Dir(self.clone()))];
+        while let Some(step) = stack.pop() {
+            let flow = match step {
+                Step::Enter(path, FSEntry::File(file)) => {
+                    visitor.visit_file(&path, &file)
+                },
+                Step::Enter(path, FSEntry::Link(link)) => {
+                    visitor.visit_link(&path, &link)
+                },
+                Step::Enter(path, FSEntry::Dir(dir)) => {
+                    let flow     = visitor.enter_dir(&path, &dir);
+                    let children = match flow {
+                        VisitFlow::Continue => dir.children(),
+                        _                   => vec![],
+                    };
+                    let base = path.clone();
+                    stack.push(Step::Leave(path, dir));
+                    for child in children.into_iter().rev() {
+                        let path = join_path(&base, &child.name_ref());
+                        stack.push(Step::Enter(path, child));
+                    }
+                    flow
+                },
+                Step::Leave(path, dir) => visitor.leave_dir(&path, &dir),
+            };
+            if flow == VisitFlow::Stop {
+                return VisitFlow::Stop;
+            }
+        }
+        VisitFlow::Continue
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Records every callback. Answers SkipSubtree on entering the directory
+    /// at `skip`, and Stop on reaching the entry at `stop`.
+    #[derive(Default)]
+    struct Recorder {
+        calls : Vec<String>,
+        skip  : Option<&'static str>,
+        stop  : Option<&'static str>,
+    }
+    impl Recorder {
+        fn record(&mut self, call: &str, path: &str) -> VisitFlow {
+            self.calls.push(format!("{} {}", call, path));
+            if self.stop == Some(path) {
+                VisitFlow::Stop
+            } else {
+                VisitFlow::Continue
+            }
+        }
+    }
+    impl Visitor for Recorder {
+        fn enter_dir(&mut self, path: &str, _dir: &FSDir) -> VisitFlow {
+            match self.record("enter", path) {
+                VisitFlow::Continue if self.skip == Some(path) => {
+                    VisitFlow::SkipSubtree
+                },
+                flow => flow,
+            }
+        }
+        fn leave_dir(&mut self, path: &str, _dir: &FSDir) -> VisitFlow {
+            self.calls.push(format!("leave {}", path));
+            VisitFlow::Continue
+        }
+        fn visit_file(&mut self, path: &str, _file: &FSFile) -> VisitFlow {
+            self.record("file", path)
+        }
+    }
+
+    /// Builds /a/x, /a/b/y, /c and /d/z.
+    fn tree() -> FSDir {
+        let root = FSDir::new("/".into());
+        for path in ["a/x", "a/b/y", "c", "d/z"] {
+            root.add_file_at_path(path, 1);
+        }
+        root
+    }
+
+    fn run(skip: Option<&'static str>, stop: Option<&'static str>) 
+        -> (VisitFlow, Vec<String>) 
+    {
+        let mut visitor = Recorder { skip, stop, ..Default::default() };
+        let flow        = tree().accept(&mut visitor);
+        (flow, visitor.calls)
+    }
+
+    #[test]
+    fn every_entry_is_visited_in_order() {
+        let (flow, calls) = run(None, None);
+        assert_eq!(flow, VisitFlow::Continue);
+        assert_eq!(calls, ["enter /", "enter /a", "enter /a/b", 
+                           "file /a/b/y", "leave /a/b", "file /a/x", 
+                           "leave /a", "file /c", "enter /d", "file /d/z", 
+                           "leave /d", "leave /"]);
+    }
+
+    #[test]
+    fn a_skipped_subtree_is_entered_and_left_but_not_visited() {
+        let (flow, calls) = run(Some("/a"), None);
+        assert_eq!(flow, VisitFlow::Continue);
+        assert_eq!(calls, ["enter /", "enter /a", "leave /a", "file /c", 
+                           "enter /d", "file /d/z", "leave /d", "leave /"]);
+    }
+
+    #[test]
+    fn stopping_ends_the_traversal_at_once() {
+        let (flow, calls) = run(None, Some("/a/b/y"));
+        assert_eq!(flow, VisitFlow::Stop);
+        assert_eq!(calls, ["enter /", "enter /a", "enter /a/b", 
+                           "file /a/b/y"]);
+        let (flow, calls) = run(None, Some("/d"));
+        assert_eq!(flow, VisitFlow::Stop);
+        assert_eq!(calls.last().unwrap(), "enter /d");
+    }
+}
@@ -0,0 +1,218 @@
+//! Runs the built program on the puzzle's sample session, data/sample.txt,
+//! and checks what each command prints, and how mistakes are reported.
+
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+use no_space_left_on_device::json::*;
+
+/// Runs the program with `args` from the crate's directory.
+fn run(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_no-space-left-on-device"))
+        .args(args)
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .output()
+        .expect("can't run the program")
+}
+
+/// Runs the program with `args`, like run(), with `input` piped into its 
+/// standard input.
+/// 
+fn run_piped(args: &[&str], input: &[u8]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_no-space-left-on-device"))
+        .args(args)
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("can't run the program");
+    child.stdin.take().unwrap().write_all(input).unwrap();
+    child.wait_with_output().expect("can't run the program")
+}
+
+/// Runs `command` on the sample, checks it succeeded and printed nothing on
+/// standard error, and returns what it printed.
+/// 
+fn sample(command: &str) -> String {
+    let out = run(&[command, "data/sample.txt"]);
+    assert!(out.status.success(), "{:?}", out);
+    assert!(out.stderr.is_empty(), "{:?}", out);
+    String::from_utf8(out.stdout).unwrap()
+}
+
+#[test]
+fn part1_and_part2_print_the_answers() {
+    assert_eq!(sample("part1"), "95437\n");
+    assert_eq!(sample("part2"), "24933642\n");
+    let out = run(&["data/sample.txt"]);
+    assert_eq!(String::from_utf8(out.stdout).unwrap(),
+               "part_1:      95437\npart_2:   24933642\n");
+}
+
+#[test]
+fn a_dash_reads_the_session_from_standard_input() {
+    let sample = std::fs::read("data/sample.txt").unwrap();
+    let out    = run_piped(&["-"], &sample);
+    assert!(out.status.success() && out.stderr.is_empty(), "{:?}", out);
+    assert_eq!(String::from_utf8(out.stdout).unwrap(),
+               "part_1:      95437\npart_2:   24933642\n");
+    // Nothing piped in is an empty session, which leaves an empty root.
+    let out = run_piped(&["du", "-"], b"");
+    assert!(out.status.success() && out.stderr.is_empty(), "{:?}", out);
+    assert_eq!(String::from_utf8(out.stdout).unwrap(), "0\t/\n");
+}
+
+#[test]
+fn tree_prints_the_whole_tree() {
+    assert_eq!(sample("tree"), "\
+- / (dir, size=48381165)
+  - a (dir, size=94853)
+    - e (dir, size=584)
+      - i (file, size=584)
+    - f (file, size=29116)
+    - g (file, size=2557)
+    - h.lst (file, size=62596)
+  - b.txt (file, size=14848514)
+  - c.dat (file, size=8504156)
+  - d (dir, size=24933642)
+    - d.ext (file, size=5626152)
+    - d.log (file, size=8033020)
+    - j (file, size=4060174)
+    - k (file, size=7214296)
+");
+}
+
+#[test]
+fn du_prints_every_directory() {
+    assert_eq!(sample("du"), 
+               "48381165\t/\n94853\t/a\n584\t/a/e\n24933642\t/d\n");
+}
+
+#[test]
+fn find_takes_text_or_a_glob() {
+    let find = |args: &[&str]| {
+        let out = run(&[&["find"], args, &["data/sample.txt"]].concat());
+        assert!(out.status.success() && out.stderr.is_empty(), "{:?}", out);
+        String::from_utf8(out.stdout).unwrap()
+    };
+    // Plain text is looked for anywhere in a name.
+    assert_eq!(find(&["h.l"]), "     62596  /a/h.lst\n     62596  total\n");
+    // A glob matches whole names, at any depth.
+    assert_eq!(find(&["*.log"]), 
+               "   8033020  /d/d.log\n   8033020  total\n");
+    assert_eq!(find(&["[bc]*"]), 
+               "  14848514  /b.txt\n   8504156  /c.dat\n  23352670  total\n");
+    assert_eq!(find(&["*.LOG"]), "         0  total\n");
+    assert_eq!(find(&["--fold-case", "*.LOG"]), 
+               "   8033020  /d/d.log\n   8033020  total\n");
+    // With a '/', it matches whole paths.
+    assert_eq!(find(&["/a/*/?"]), "       584  /a/e/i\n       584  total\n");
+}
+
+#[test]
+fn top_prints_the_largest_entries() {
+    assert_eq!(sample("top"), "\
+largest directories:
+   1.   24933642  /d
+   2.      94853  /a
+   3.        584  /a/e
+
+largest files:
+   1.   14848514  /b.txt
+   2.    8504156  /c.dat
+   3.    8033020  /d/d.log
+   4.    7214296  /d/k
+   5.    5626152  /d/d.ext
+   6.    4060174  /d/j
+   7.      62596  /a/h.lst
+   8.      29116  /a/f
+   9.       2557  /a/g
+  10.        584  /a/e/i
+");
+}
+
+#[test]
+fn flame_writes_the_tree_as_collapsed_stacks() {
+    let path = std::env::temp_dir()
+                   .join(format!("nsl-flame-{}.txt", std::process::id()));
+    let out  = run(&["part1", "--flame", path.to_str().unwrap(), 
+                     "data/sample.txt"]);
+    let text = std::fs::read_to_string(&path);
+    let _    = std::fs::remove_file(&path);
+    assert!(out.status.success(), "{:?}", out);
+    assert_eq!(String::from_utf8(out.stdout).unwrap(), "95437\n");
+    let text = text.unwrap();
+    assert_eq!(text.lines().next(), Some("/;b.txt 14848514"));
+    let total = text.lines()
+                    .map(|line| line.rsplit_once(' ').unwrap().1)
+                    .map(|size| size.parse::<u64>().unwrap())
+                    .sum::<u64>();
+    assert_eq!((text.lines().count(), total), (10, 48381165));
+}
+
+/// Runs `args` with --json, checks it printed one line of JSON and nothing
+/// else, and returns it parsed.
+/// 
+fn json(args: &[&str]) -> JsonValue {
+    let out  = run(&[args, &["--json"]].concat());
+    assert!(out.status.success() && out.stderr.is_empty(), "{:?}", out);
+    let text = String::from_utf8(out.stdout).unwrap();
+    assert_eq!(text.lines().count(), 1, "{}", text);
+    JsonValue::parse(&text).unwrap()
+}
+
+/// Returns the path and size of each object in `array`.
+fn paths_and_sizes(array: &JsonValue) -> Vec<(String, u64)> {
+    array.as_array().unwrap().iter().map(|item| {
+        (item.get("path").and_then(JsonValue::as_str).unwrap().to_string(),
+         item.get("size").and_then(JsonValue::as_u64).unwrap())
+    }).collect()
+}
+
+#[test]
+fn json_output_parses() {
+    let answers = json(&["data/sample.txt"]);
+    let number  = |key| answers.get(key).and_then(JsonValue::as_u64);
+    assert_eq!((number("part1"), number("part2"), number("total_size")),
+               (Some(95437), Some(24933642), Some(48381165)));
+    assert_eq!(answers.get("input").and_then(JsonValue::as_str), 
+               Some("data/sample.txt"));
+
+    assert_eq!(paths_and_sizes(&json(&["du", "data/sample.txt"])),
+               [("/".into(), 48381165), ("/a".into(), 94853), 
+                ("/a/e".into(), 584), ("/d".into(), 24933642)]);
+    let top = json(&["top", "--dirs", "1", "--files", "2", 
+                     "data/sample.txt"]);
+    assert_eq!(paths_and_sizes(&top), 
+               [("/d".into(), 24933642), ("/b.txt".into(), 14848514),
+                ("/c.dat".into(), 8504156)]);
+    let kinds = top.as_array().unwrap().iter()
+                   .map(|item| item.get("kind").and_then(JsonValue::as_str))
+                   .collect::<Vec<_>>();
+    assert_eq!(kinds, [Some("dir"), Some("file"), Some("file")]);
+}
+
+#[test]
+fn json_strings_are_escaped() {
+    let name = r#"say "hi" \ bye"#;
+    let path = std::env::temp_dir()
+                   .join(format!("nsl-json-{}.txt", std::process::id()));
+    std::fs::write(&path, format!("$ cd /\n$ ls\ndir {}\n$ cd {}\n$ ls\n\
+                                   5 x\n", name, name)).unwrap();
+    let du = json(&["du", path.to_str().unwrap()]);
+    let _  = std::fs::remove_file(&path);
+    assert_eq!(paths_and_sizes(&du), 
+               [("/".into(), 5), (format!("/{}", name), 5)]);
+}
+
+#[test]
+fn mistakes_are_reported_with_an_exit_code() {
+    let out = run(&["--bogus"]);
+    assert_eq!(out.status.code(), Some(2));
+    assert_eq!(String::from_utf8(out.stderr).unwrap(),
+               "error: unknown option: --bogus\ntry --help for usage\n");
+    let out = run(&["part1", "data/no-such-file.txt"]);
+    assert_eq!(out.status.code(), Some(1));
+    assert!(out.stdout.is_empty());
+    assert!(String::from_utf8(out.stderr).unwrap().starts_with("error: "));
+}
@@ -0,0 +1,96 @@
+//! Exercises the library's public API end to end on the puzzle's sample 
+//! session, data/sample.txt, as another crate would use it.
+
+use std::path::Path;
+use no_space_left_on_device::analysis::*;
+use no_space_left_on_device::fsobject::*;
+use no_space_left_on_device::parser::*;
+use no_space_left_on_device::putback_iter::*;
+
+fn sample_path() -> &'static Path {
+    Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/data/sample.txt"))
+}
+
+fn sample() -> FSDir {
+    build_fs_from_path(sample_path()).unwrap()
+}
+
+#[test]
+fn the_sample_builds_the_tree_from_the_puzzle() {
+    let root = sample();
+    assert_eq!(root.path(), "/");
+    assert_eq!(root.size(), 48381165);
+    let sizes = root.walk()
+                    .map(|(path, entry)| (path, entry.size()))
+                    .collect::<Vec<_>>();
+    assert_eq!(sizes, [("/a".to_string(), 94853),
+                       ("/a/e".into(), 584),
+                       ("/a/e/i".into(), 584),
+                       ("/a/f".into(), 29116),
+                       ("/a/g".into(), 2557),
+                       ("/a/h.lst".into(), 62596),
+                       ("/b.txt".into(), 14848514),
+                       ("/c.dat".into(), 8504156),
+                       ("/d".into(), 24933642),
+                       ("/d/d.ext".into(), 5626152),
+                       ("/d/d.log".into(), 8033020),
+                       ("/d/j".into(), 4060174),
+                       ("/d/k".into(), 7214296)]);
+    let e = root.get_dir("a").and_then(|a| a.get_dir("e")).unwrap();
+    assert_eq!(e.get_file("i").unwrap().size(), 584);
+    assert_eq!(e.parent().unwrap().path(), "/a");
+    match root.find_by_path("/d/k") {
+        Some(FSEntry::File(k)) => assert_eq!(k.size(), 7214296),
+        other                  => panic!("expected /d/k, got {:?}", other),
+    }
+}
+
+#[test]
+fn the_sample_gives_the_puzzle_answers() {
+    let root = sample();
+    assert_eq!(sum_dirs_at_most(&root, 100_000), 95437);
+    assert_eq!(space_to_free(&root, 70_000_000, 30_000_000), 8381165);
+    let dir = smallest_dir_to_free(&root, 70_000_000, 30_000_000).unwrap();
+    assert_eq!((dir.path(), dir.size()), ("/d".into(), 24933642));
+}
+
+#[test]
+fn every_way_of_reading_the_sample_agrees() {
+    let text = std::fs::read_to_string(sample_path()).unwrap();
+    let root = sample();
+    assert!(build_fs_from_str(&text).unwrap().structural_eq(&root));
+    assert!(build_fs(text.as_bytes()).unwrap().structural_eq(&root));
+    let outcome = build_fs_with(text.as_bytes(), ParserOptions::default())
+                      .unwrap();
+    assert!(outcome.root.structural_eq(&root));
+    assert!(outcome.warnings.is_empty());
+}
+
+#[test]
+fn sessions_can_be_read_a_line_at_a_time_with_put_back() {
+    let text      = std::fs::read_to_string(sample_path()).unwrap();
+    let mut lines = LinesPutBack::new(text.as_bytes());
+    let first     = lines.next().unwrap().unwrap();
+    assert_eq!((first.as_str(), lines.line_number()), ("$ cd /", 1));
+    lines.put_back(first);
+    assert_eq!(lines.count(), text.lines().count());
+}
+
+#[test]
+fn errors_say_where_the_session_went_wrong() {
+    let err = build_fs_from_str("$ cd /\n$ ls\nnot-a-size x\n").unwrap_err();
+    assert_eq!(err.line_number(), 3);
+    assert!(err.to_string().contains("line 3"), "{}", err);
+}
+
+#[test]
+fn the_common_ancestor_of_two_entries_is_the_deepest_shared_directory() {
+    let root   = sample();
+    let parent = |path| root.find_by_path(path).unwrap().parent().unwrap();
+    let (i, f) = (parent("/a/e/i"), parent("/a/f"));
+    assert_eq!(FSDir::common_ancestor(&i, &f).unwrap().path(), "/a");
+    assert_eq!(FSDir::common_ancestor(&f, &i).unwrap().path(), "/a");
+    let d = root.get_dir("d").unwrap();
+    assert!(FSDir::common_ancestor(&i, &d).unwrap().ptr_eq(&root));
+    assert!(FSDir::common_ancestor(&i, &sample()).is_none());
+}